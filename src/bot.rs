@@ -3,23 +3,42 @@ use base64::Engine;
 use sqlx::SqlitePool;
 use std::sync::Arc;
 use teloxide::prelude::*;
-use teloxide::types::{MediaKind, MessageKind, ParseMode, PhotoSize, Voice};
+use teloxide::types::{MediaKind, MessageKind, ParseMode, PhotoSize, ThreadId, UpdateKind, Voice};
 
 use crate::config;
+use crate::confirm::ConfirmationGate;
 use crate::db;
 use crate::llm::LlmClient;
 use crate::mcp::McpManager;
-use crate::rag::RagEngine;
+use crate::progress::TurnProgress;
+use crate::rag::{EmbeddingModelConfig, IndexJob, RagEngine, SOURCE_TYPES};
+use crate::rag_queue::IndexQueue;
+use crate::rag_rerank::Reranker;
+use crate::secrets;
+use crate::tools::{ToolContext, ToolRegistry};
+use crate::turns::TurnQueue;
 use crate::types::*;
 
 const MAX_TELEGRAM_MSG_LEN: usize = 4096;
 const DEFAULT_HISTORY_LIMIT: i64 = 50;
 
+/// A message waiting for its chat's next LLM turn
+struct QueuedMessage {
+    msg: Message,
+    user_id: i64,
+    content: MessageContent,
+}
+
 struct BotState {
     pool: SqlitePool,
     llm: LlmClient,
-    rag: RagEngine,
+    tools: ToolRegistry,
+    rag: Arc<RagEngine>,
+    indexer: IndexQueue,
     mcp: McpManager,
+    confirm: ConfirmationGate,
+    /// Keyed by chat and forum topic
+    turns: TurnQueue<(i64, Option<i32>), QueuedMessage>,
     bot_name: String,
     bot_username: String,
     bot_user_id: i64,
@@ -49,7 +68,8 @@ pub async fn run(pool: SqlitePool) -> Result<()> {
             - `http://...` / `https://...` => transport inferred as http\n\
             - explicit `transport='stdio'` => requires non-empty command\n\
             - explicit `transport='http'`/`sse`/`streamable_http` => requires non-empty endpoint URL.\n\
-            You can manage files via `crud_file` with actions: create/read/list/update/delete. File operations are restricted to the `files` directory by default (or `config set files_root <path>` to override). \
+            You can manage files via `crud_file` with actions: create/read/list/update/delete/stat/search/move/copy/mkdir/send. File operations are restricted to the `files` directory by default (or `config set files_root <path>` to override), \
+            split into per-chat (`chat/<id>/`), per-user (`person/<id>/`) and `shared/` namespaces; plain paths go to the current chat's namespace. \
             Use `read` with `offset` (0-based) and optional `line_count` (default 200) for paging. \
             Use `update` with `mode` (`replace`, `insert`, `delete`, `append`), and optional `offset`/`line_count` for patch-style updates. \
             `offset` defaults to 0 when meaningful; `line_count` defaults to 1 for partial replace/delete and 200 for read. File paths must be relative and cannot include path traversal segments.\n\
@@ -70,7 +90,30 @@ pub async fn run(pool: SqlitePool) -> Result<()> {
     let trigger_keywords = db::trigger_keywords_list(&pool).await?;
 
     // Initialize RAG engine
-    let rag = RagEngine::init(&std::path::PathBuf::from("rag_data")).await?;
+    let rag_model = EmbeddingModelConfig::load(&pool).await?;
+    let reranker = Reranker::load(&pool).await?;
+    let rag = Arc::new(
+        RagEngine::init(&std::path::PathBuf::from("rag_data"), rag_model, reranker).await?,
+    );
+    if rag.model_changed() {
+        let policy = config::get_or_default(&pool, "rag_on_model_change", "reindex").await?;
+        if policy.trim().eq_ignore_ascii_case("refuse") {
+            anyhow::bail!(
+                "The embedding model changed since the RAG index was built. Run `astartebot rag reindex --full` or set rag_on_model_change to reindex."
+            );
+        }
+        tracing::warn!("Embedding model changed, rebuilding RAG index");
+        rag.sync(&pool, &SOURCE_TYPES, true, &|p| {
+            tracing::info!(
+                source = p.source,
+                done = p.done,
+                total = p.total,
+                "RAG rebuild progress"
+            );
+        })
+        .await?;
+    }
+    let indexer = IndexQueue::start(rag.clone())?;
 
     tracing::info!(
         bot_name = %bot_name,
@@ -80,11 +123,16 @@ pub async fn run(pool: SqlitePool) -> Result<()> {
         "Starting Telegram bot"
     );
 
+    let scheduled_pool = pool.clone();
     let state = Arc::new(BotState {
         pool,
         llm: LlmClient::new(llm_token, llm_model),
+        tools: ToolRegistry::builtin(),
         rag,
+        indexer,
         mcp: McpManager::new(),
+        confirm: ConfirmationGate::new(),
+        turns: TurnQueue::new(),
         bot_name,
         bot_username,
         bot_user_id,
@@ -95,10 +143,16 @@ pub async fn run(pool: SqlitePool) -> Result<()> {
     // Start hourly background backups
     let _backup_handle = crate::backup::start_hourly_backup();
 
-    let handler = Update::filter_message().endpoint(handle_message);
+    // Send scheduled send_message messages when they are due
+    let _outbox_handle = crate::outbox::start_scheduled_sender(bot.clone(), scheduled_pool);
+
+    let handler = dptree::entry()
+        .branch(Update::filter_message().endpoint(handle_message))
+        .branch(Update::filter_callback_query().endpoint(handle_callback_query));
 
     Dispatcher::builder(bot, handler)
         .default_handler(|_upd| async {})
+        .distribution_function(distribution_key)
         .dependencies(dptree::deps![state])
         .enable_ctrlc_handler()
         .build()
@@ -108,6 +162,177 @@ pub async fn run(pool: SqlitePool) -> Result<()> {
     Ok(())
 }
 
+/// Updates of one chat are handled in order, except callback queries: an
+/// approval answer must get through while that chat's turn is waiting for it.
+fn distribution_key(update: &Update) -> Option<ChatId> {
+    match update.kind {
+        UpdateKind::CallbackQuery(_) => None,
+        _ => update.chat().map(|c| c.id),
+    }
+}
+
+async fn handle_callback_query(
+    bot: Bot,
+    query: CallbackQuery,
+    state: Arc<BotState>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let user_id = query.from.id.0 as i64;
+    let notice = query
+        .data
+        .as_deref()
+        .and_then(|data| state.confirm.resolve(data, user_id));
+
+    let mut answer = bot.answer_callback_query(query.id.clone());
+    if let Some(text) = notice {
+        answer = answer.text(text);
+    }
+    answer.await?;
+    Ok(())
+}
+
+/// Forum topic of a message; None outside forums and in the General topic
+fn topic_of(msg: &Message) -> Option<ThreadId> {
+    if msg.is_topic_message {
+        msg.thread_id
+    } else {
+        None
+    }
+}
+
+/// A text message to the chat, and forum topic, `msg` came from
+fn send_to_origin(
+    bot: &Bot,
+    msg: &Message,
+    text: impl Into<String>,
+) -> <Bot as Requester>::SendMessage {
+    let request = bot.send_message(msg.chat.id, text);
+    match topic_of(msg) {
+        Some(thread_id) => request.message_thread_id(thread_id),
+        None => request,
+    }
+}
+
+/// Text after `command` (or `command@bot`), if the message is that command
+fn command_args<'a>(text: &'a str, command: &str, bot_username: &str) -> Option<&'a str> {
+    let text = text.trim_start();
+    let (first, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let matches = first == command
+        || first
+            .strip_prefix(command)
+            .and_then(|s| s.strip_prefix('@'))
+            .is_some_and(|name| name.eq_ignore_ascii_case(bot_username));
+    matches.then_some(rest.trim())
+}
+
+/// `/secret set NAME VALUE`, `/secret hosts NAME HOST,...`, `/secret mcp
+/// NAME SERVER,...`, `/secret share NAME CHAT_ID,...`, `/secret delete
+/// NAME`, `/secret list`, for admins in a private chat. Messages carrying a
+/// value are deleted.
+async fn handle_secret_command(
+    bot: &Bot,
+    msg: &Message,
+    state: &BotState,
+    user_id: i64,
+    args: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut parts = args.splitn(3, char::is_whitespace);
+    let action = parts.next().unwrap_or("");
+    let name = parts.next().unwrap_or("").trim();
+    let value = parts.next().unwrap_or("").trim();
+
+    if action == "set" && bot.delete_message(msg.chat.id, msg.id).await.is_err() {
+        tracing::warn!(
+            chat_id = msg.chat.id.0,
+            "Could not delete a /secret set message"
+        );
+    }
+
+    let reply = if !msg.chat.is_private() || !config::is_admin(&state.pool, user_id).await? {
+        "Secrets can only be managed by admins in a private chat.".to_string()
+    } else {
+        match (action, name.is_empty(), value.is_empty()) {
+            ("set", false, false) => {
+                match secrets::set(&state.pool, name, value, Some(user_id)).await {
+                    Ok(()) => {
+                        tracing::info!(user_id, secret = name, "Secret stored");
+                        format!(
+                            "Stored secret '{}'. Tools can use it as {{{{secret:{}}}}}; \
+                             allow HTTP hosts for it with /secret hosts {} HOST,... \
+                             and MCP servers with /secret mcp {} SERVER,...",
+                            name, name, name, name
+                        )
+                    }
+                    Err(e) => format!("Failed to store secret: {}", e),
+                }
+            }
+            ("hosts", false, _) => match secrets::set_hosts(&state.pool, name, value).await? {
+                true if value.is_empty() => {
+                    format!("Secret '{}' can no longer be sent over HTTP.", name)
+                }
+                true => format!("Secret '{}' can be sent to: {}.", name, value),
+                false => format!("Secret '{}' not found.", name),
+            },
+            ("mcp", false, _) => match secrets::set_mcp_servers(&state.pool, name, value).await? {
+                true if value.is_empty() => {
+                    format!("Secret '{}' can no longer be sent to MCP servers.", name)
+                }
+                true => format!("Secret '{}' can be sent to MCP servers: {}.", name, value),
+                false => format!("Secret '{}' not found.", name),
+            },
+            ("share", false, _) => {
+                match secrets::set_shared_chats(&state.pool, name, value).await {
+                    Ok(true) if value.is_empty() => {
+                        format!("Secret '{}' is now only usable by admins.", name)
+                    }
+                    Ok(true) => format!(
+                        "Secret '{}' can be used by everyone in chats: {}.",
+                        name, value
+                    ),
+                    Ok(false) => format!("Secret '{}' not found.", name),
+                    Err(e) => format!("Failed to share secret: {}", e),
+                }
+            }
+            ("delete", false, _) => match secrets::delete(&state.pool, name).await? {
+                true => format!("Deleted secret '{}'.", name),
+                false => format!("Secret '{}' not found.", name),
+            },
+            ("list", ..) => {
+                let items = secrets::list(&state.pool).await?;
+                if items.is_empty() {
+                    "No secrets stored.".to_string()
+                } else {
+                    items
+                        .iter()
+                        .map(|item| {
+                            format!(
+                                "{} (hosts: {}; MCP servers: {}; shared with: {}; updated {})",
+                                item.name,
+                                hosts_or_none(&item.allowed_hosts),
+                                hosts_or_none(&item.mcp_servers),
+                                if item.shared_chats.is_empty() {
+                                    "admins only"
+                                } else {
+                                    &item.shared_chats
+                                },
+                                item.updated_at
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                }
+            }
+            _ => "Usage:\n/secret set NAME VALUE\n/secret hosts NAME HOST,...\n/secret mcp NAME SERVER,...\n/secret share NAME CHAT_ID,...\n/secret delete NAME\n/secret list"
+                .to_string(),
+        }
+    };
+    send_to_origin(bot, msg, reply).await?;
+    Ok(())
+}
+
+fn hosts_or_none(hosts: &str) -> &str {
+    if hosts.is_empty() { "none" } else { hosts }
+}
+
 async fn handle_message(
     bot: Bot,
     msg: Message,
@@ -116,10 +341,19 @@ async fn handle_message(
     let user = msg.from.as_ref();
     let user_id = user.map(|u| u.id.0 as i64).unwrap_or(0);
     let chat_id = msg.chat.id.0;
+    let thread_id = topic_of(&msg).map(|t| t.0.0);
     let user_display_name = user.map(|u| u.full_name()).unwrap_or_default();
     let tg_message_id = Some(msg.id.0 as i64);
     let reply_to_msg_id = msg.reply_to_message().map(|r| r.id.0 as i64);
 
+    // Secret commands are handled before anything is logged or stored
+    if let Some(text) = msg.text()
+        && let Some(args) = command_args(text, "/secret", &state.bot_username)
+    {
+        handle_secret_command(&bot, &msg, &state, user_id, args).await?;
+        return Ok(());
+    }
+
     // Log received message
     let is_voice =
         matches!(&msg.kind, MessageKind::Common(c) if matches!(&c.media_kind, MediaKind::Voice(_)));
@@ -148,10 +382,11 @@ async fn handle_message(
     }
 
     // Log ALL text messages to conversation history (for searchable context)
-    if let Some(text) = msg.text() {
-        if let Ok(row_id) = db::conversation_save(
+    if let Some(text) = msg.text()
+        && let Ok(row_id) = db::conversation_save(
             &state.pool,
             chat_id,
+            thread_id,
             user_id,
             &user_display_name,
             "user",
@@ -161,27 +396,26 @@ async fn handle_message(
             reply_to_msg_id,
         )
         .await
-        {
-            let segment = format!("chat:{}", chat_id);
-            let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-            let _ = state.rag.index_record_sync(
-                "conversation",
+    {
+        state
+            .indexer
+            .enqueue(IndexJob::conversation(
                 row_id,
                 chat_id,
-                &segment,
+                thread_id,
                 text,
                 &user_display_name,
-                &now,
-            );
-        }
+            ))
+            .await;
     }
 
     // Check for commands first
     if let Some(text) = msg.text() {
         let text_trimmed = text.trim();
         if text_trimmed == "/start" || text_trimmed == format!("/start@{}", state.bot_username) {
-            bot.send_message(
-                msg.chat.id,
+            send_to_origin(
+                &bot,
+                &msg,
                 format!(
                     "Hello! I'm {}. Send me a message and I'll do my best to help!",
                     state.bot_name
@@ -196,20 +430,36 @@ async fn handle_message(
                  Commands:\n\
                  /start - Start the bot\n\
                  /help - Show this help\n\
-                 /reset - Clear conversation history\n\n\
+                 /reset - Clear conversation history\n\
+                 /stop - Cancel the answer in progress\n\n\
                  I can remember things using notes and memory. Just ask!\n\
                  In groups, mention me or reply to my messages.",
                 state.bot_name
             );
-            bot.send_message(msg.chat.id, help_text).await?;
+            send_to_origin(&bot, &msg, help_text).await?;
+            return Ok(());
+        }
+        if text_trimmed == "/stop" || text_trimmed == format!("/stop@{}", state.bot_username) {
+            let reply = if !state.turns.is_requester((chat_id, thread_id), user_id)
+                && !config::is_admin(&state.pool, user_id).await?
+            {
+                "Nothing of yours to stop here."
+            } else if state.turns.stop((chat_id, thread_id)) {
+                tracing::info!(chat_id, user_id, "Turn stopped");
+                "Stopped."
+            } else {
+                "Nothing to stop."
+            };
+            send_to_origin(&bot, &msg, reply).await?;
             return Ok(());
         }
         if text_trimmed == "/reset" || text_trimmed == format!("/reset@{}", state.bot_username) {
-            let deleted = db::conversation_clear(&state.pool, msg.chat.id.0)
+            let deleted = db::conversation_clear(&state.pool, chat_id, thread_id)
                 .await
                 .unwrap_or(0);
-            bot.send_message(
-                msg.chat.id,
+            send_to_origin(
+                &bot,
+                &msg,
                 format!(
                     "Conversation history cleared ({} messages removed).",
                     deleted
@@ -251,6 +501,7 @@ async fn handle_message(
         if let Ok(row_id) = db::conversation_save(
             &state.pool,
             chat_id,
+            thread_id,
             user_id,
             &user_display_name,
             "user",
@@ -261,38 +512,147 @@ async fn handle_message(
         )
         .await
         {
-            let segment = format!("chat:{}", chat_id);
-            let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-            let _ = state.rag.index_record_sync(
-                "conversation",
-                row_id,
-                chat_id,
-                &segment,
-                &content_text,
-                &user_display_name,
-                &now,
+            state
+                .indexer
+                .enqueue(IndexJob::conversation(
+                    row_id,
+                    chat_id,
+                    thread_id,
+                    &content_text,
+                    &user_display_name,
+                ))
+                .await;
+        }
+    }
+
+    // Turns in a chat (or forum topic) run one at a time; messages that
+    // arrive while one is running are answered together by the next
+    let queued = QueuedMessage {
+        msg,
+        user_id,
+        content: user_content,
+    };
+    let key = (chat_id, thread_id);
+    state.turns.push(key, user_id, queued, |runner_id| {
+        tokio::spawn(run_turns(bot.clone(), state.clone(), key, runner_id))
+    });
+
+    Ok(())
+}
+
+/// Unregisters a turn runner however it exits, so a panic in a turn does
+/// not leave the conversation waiting for a runner that is gone
+struct RunnerGuard {
+    state: Arc<BotState>,
+    key: (i64, Option<i32>),
+    runner_id: u64,
+}
+
+impl Drop for RunnerGuard {
+    fn drop(&mut self) {
+        if self.state.turns.finish(self.key, self.runner_id) {
+            tracing::warn!(
+                chat_id = self.key.0,
+                "Turn runner exited abnormally; queued messages dropped"
             );
         }
     }
+}
+
+/// Answer the queued messages of a chat or forum topic until none are left.
+/// `/stop` aborts this task, cancelling the running turn and its tool calls.
+async fn run_turns(bot: Bot, state: Arc<BotState>, key: (i64, Option<i32>), runner_id: u64) {
+    let (chat_id, _) = key;
+    let _guard = RunnerGuard {
+        state: state.clone(),
+        key,
+        runner_id,
+    };
+    loop {
+        let batch = state.turns.next_batch(key, runner_id);
+        if batch.is_empty() {
+            break;
+        }
+        if let Err(e) = run_turn(&bot, &state, batch).await {
+            tracing::error!(error = %e, chat_id, "Turn failed");
+        }
+    }
+}
+
+/// One LLM turn answering the last message of `batch`, with the earlier ones
+/// folded into it. All messages of a batch come from one user, whose
+/// permissions the turn runs with.
+async fn run_turn(
+    bot: &Bot,
+    state: &BotState,
+    batch: Vec<QueuedMessage>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(last) = batch.last() else {
+        return Ok(());
+    };
+    let msg = last.msg.clone();
+    let chat_id = msg.chat.id.0;
+    let topic = topic_of(&msg);
+    let thread_id = topic.map(|t| t.0.0);
+    let user_id = last.user_id;
+    let tg_message_id = Some(msg.id.0 as i64);
+    let answered: Vec<(i64, i64)> = batch
+        .iter()
+        .map(|m| (m.user_id, m.msg.id.0 as i64))
+        .collect();
+    if batch.len() > 1 {
+        tracing::info!(
+            chat_id,
+            messages = batch.len(),
+            "Answering queued messages together"
+        );
+    }
+    let user_content = coalesce(batch);
+
+    // Show typing (and optionally a status message) until the answer is sent
+    let show_status = config::get_or_default(&state.pool, "progress_status", "true")
+        .await?
+        .trim()
+        != "false";
+    let progress = TurnProgress::start(bot.clone(), msg.chat.id, topic, msg.id, show_status);
+
+    let is_admin = config::is_admin(&state.pool, user_id).await?;
 
     // Build messages for LLM
-    let messages =
-        build_llm_messages(&state, chat_id, user_id, tg_message_id, user_content).await?;
+    let messages = build_llm_messages(
+        state,
+        chat_id,
+        thread_id,
+        user_id,
+        is_admin,
+        &answered,
+        user_content,
+    )
+    .await?;
 
     // Call LLM
-    match state
+    let tool_ctx = ToolContext {
+        pool: &state.pool,
+        bot,
+        rag: &state.rag,
+        indexer: &state.indexer,
+        mcp: &state.mcp,
+        confirm: &state.confirm,
+        chat_id,
+        thread_id: topic,
+        user_id,
+        is_admin,
+    };
+    let result = state
         .llm
-        .chat(
-            &state.pool,
-            &bot,
-            &state.rag,
-            &state.mcp,
-            messages,
-            chat_id,
-            user_id,
-        )
-        .await
-    {
+        .chat(&state.tools, &tool_ctx, messages, &|p| {
+            tracing::debug!(round = p.round, tools = ?p.tools, "Tool round");
+            progress.tools(&p.tools);
+        })
+        .await;
+    progress.finish().await;
+
+    match result {
         Ok(response) => {
             if response.is_empty() {
                 return Ok(());
@@ -302,6 +662,7 @@ async fn handle_message(
             if let Ok(row_id) = db::conversation_save(
                 &state.pool,
                 chat_id,
+                thread_id,
                 0,
                 &state.bot_name,
                 "assistant",
@@ -312,26 +673,26 @@ async fn handle_message(
             )
             .await
             {
-                let segment = format!("chat:{}", chat_id);
-                let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-                let _ = state.rag.index_record_sync(
-                    "conversation",
-                    row_id,
-                    chat_id,
-                    &segment,
-                    &response,
-                    &state.bot_name,
-                    &now,
-                );
+                state
+                    .indexer
+                    .enqueue(IndexJob::conversation(
+                        row_id,
+                        chat_id,
+                        thread_id,
+                        &response,
+                        &state.bot_name,
+                    ))
+                    .await;
             }
 
             // Send response, splitting if needed
-            send_split_message(&bot, msg.chat.id, &response, msg.id).await?;
+            send_split_message(bot, msg.chat.id, topic, &response, msg.id).await?;
         }
         Err(e) => {
             tracing::error!(error = %e, chat_id, user_id, "LLM error");
-            bot.send_message(
-                msg.chat.id,
+            send_to_origin(
+                bot,
+                &msg,
                 "Sorry, I encountered an error processing your message. Please try again.",
             )
             .await?;
@@ -341,6 +702,35 @@ async fn handle_message(
     Ok(())
 }
 
+/// Merge one user's queued messages into one user message
+fn coalesce(mut batch: Vec<QueuedMessage>) -> MessageContent {
+    if batch.len() == 1
+        && let Some(only) = batch.pop()
+    {
+        return only.content;
+    }
+    let mut parts = Vec::new();
+    for queued in batch {
+        match queued.content {
+            MessageContent::Text(text) => parts.push(ContentPart::Text { text }),
+            MessageContent::Parts(content) => parts.extend(content),
+        }
+    }
+
+    if parts.iter().all(|p| matches!(p, ContentPart::Text { .. })) {
+        let texts: Vec<String> = parts
+            .into_iter()
+            .filter_map(|p| match p {
+                ContentPart::Text { text } => Some(text),
+                _ => None,
+            })
+            .collect();
+        MessageContent::Text(texts.join("\n\n"))
+    } else {
+        MessageContent::Parts(parts)
+    }
+}
+
 fn should_respond(msg: &Message, state: &BotState) -> bool {
     // Always respond in private/DM chats
     if msg.chat.is_private() {
@@ -348,12 +738,11 @@ fn should_respond(msg: &Message, state: &BotState) -> bool {
     }
 
     // Reply to bot's own message (check by bot user ID, not just any bot)
-    if let Some(reply) = msg.reply_to_message() {
-        if let Some(from) = reply.from.as_ref() {
-            if from.id.0 as i64 == state.bot_user_id {
-                return true;
-            }
-        }
+    if let Some(reply) = msg.reply_to_message()
+        && let Some(from) = reply.from.as_ref()
+        && from.id.0 as i64 == state.bot_user_id
+    {
+        return true;
     }
 
     // Voice messages in groups only trigger if replying to the bot (handled above)
@@ -386,17 +775,14 @@ fn should_respond(msg: &Message, state: &BotState) -> bool {
     if let Some(entities) = entities {
         let entity_text = msg.text().or(msg.caption());
         for entity in entities {
-            if entity.kind == teloxide::types::MessageEntityKind::Mention {
-                if let Some(text) = entity_text {
-                    if let Some(mention) = utf16_substr(text, entity.offset, entity.length) {
-                        if mention
-                            .trim_start_matches('@')
-                            .eq_ignore_ascii_case(&state.bot_username)
-                        {
-                            return true;
-                        }
-                    }
-                }
+            if entity.kind == teloxide::types::MessageEntityKind::Mention
+                && let Some(text) = entity_text
+                && let Some(mention) = utf16_substr(text, entity.offset, entity.length)
+                && mention
+                    .trim_start_matches('@')
+                    .eq_ignore_ascii_case(&state.bot_username)
+            {
+                return true;
             }
         }
     }
@@ -579,8 +965,10 @@ async fn transcribe_voice(bot: &Bot, pool: &SqlitePool, voice: &Voice) -> Result
 async fn build_llm_messages(
     state: &BotState,
     chat_id: i64,
+    thread_id: Option<i32>,
     user_id: i64,
-    current_message_id: Option<i64>,
+    is_admin: bool,
+    current_messages: &[(i64, i64)],
     current_content: MessageContent,
 ) -> Result<Vec<ChatMessage>> {
     let mut messages = Vec::new();
@@ -610,6 +998,25 @@ async fn build_llm_messages(
         format!("{} (@{}, id: {})", user_display, user_tg_username, user_id)
     };
 
+    // Inside a forum topic, history and the topic segment cover only that topic
+    let topic_segment = thread_id.map(|t| format!("topic:{}:{}", chat_id, t));
+    let (topic_line, segments) = match &topic_segment {
+        Some(segment) => (
+            format!(
+                "- Forum topic: {} (the history below is from this topic only)\n",
+                thread_id.unwrap_or_default()
+            ),
+            format!(
+                "global, bot, chat:{}, {}, person:{}",
+                chat_id, segment, user_id
+            ),
+        ),
+        None => (
+            String::new(),
+            format!("global, bot, chat:{}, person:{}", chat_id, user_id),
+        ),
+    };
+
     // Build system prompt
     let mut system_text = format!(
         "{}\n\n\
@@ -617,8 +1024,9 @@ async fn build_llm_messages(
          - Current time: {}\n\
          - Bot name: {}\n\
          - Chat: {} (id: {})\n\
+         {}\
          - Current user: {}\n\
-         - Available memory segments for this context: global, bot, chat:{}, person:{}\n\
+         - Available memory segments for this context: {}\n\
          - Voice: Messages prefixed with [voice message, transcribed] were spoken by the user (not typed). \
          You can reply with voice using the send_voice tool. When a user speaks to you, consider replying with voice too for a natural conversation.",
         state.system_prompt,
@@ -626,9 +1034,9 @@ async fn build_llm_messages(
         state.bot_name,
         chat_name,
         chat_id,
+        topic_line,
         user_identity,
-        chat_id,
-        user_id
+        segments
     );
     system_text.push_str(
         "\n\nImportant MCP rules for `crud_mcp_server`:\n\
@@ -649,11 +1057,38 @@ async fn build_llm_messages(
          Workflow: list tools first to see what's available, then call specific methods with the correct arguments.",
     );
 
+    // Name the secrets this turn may use, so the model uses placeholders
+    // instead of asking for keys
+    if let Ok(items) = secrets::list_usable(&state.pool, chat_id, is_admin).await
+        && !items.is_empty()
+    {
+        let names: Vec<String> = items
+            .iter()
+            .map(|item| {
+                format!(
+                    "{} (HTTP hosts: {}; MCP servers: {})",
+                    item.name,
+                    hosts_or_none(&item.allowed_hosts),
+                    hosts_or_none(&item.mcp_servers)
+                )
+            })
+            .collect();
+        system_text.push_str(&format!(
+            "\n\nStored secrets: {}. Put `{{{{secret:NAME}}}}` in HTTP header values for those hosts, or in arguments of those MCP servers' tools, and it is replaced with the value when the tool runs; you never see the value. Secrets are refused in URLs, query parameters and request bodies. Don't ask users to paste credentials into chat.",
+            names.join(", ")
+        ));
+    }
+
     // Inject important/pinned memory for this chat
     let chat_segment = format!("chat:{}", chat_id);
     if let Ok(Some(important)) = db::get_important_memory(&state.pool, &chat_segment).await {
         system_text.push_str(&format!("\n\nPinned memory for this chat:\n{}", important));
     }
+    if let Some(segment) = &topic_segment
+        && let Ok(Some(important)) = db::get_important_memory(&state.pool, segment).await
+    {
+        system_text.push_str(&format!("\n\nPinned memory for this topic:\n{}", important));
+    }
     if let Ok(Some(important)) = db::get_important_memory(&state.pool, "global").await {
         system_text.push_str(&format!("\n\nGlobal pinned memory:\n{}", important));
     }
@@ -678,11 +1113,16 @@ async fn build_llm_messages(
     });
 
     // Load recent conversation history (for LLM context window)
-    let history = db::conversation_load(&state.pool, chat_id, DEFAULT_HISTORY_LIMIT).await?;
+    let history =
+        db::conversation_load(&state.pool, chat_id, thread_id, DEFAULT_HISTORY_LIMIT).await?;
     for row in &history {
-        // The current incoming message is already persisted before this call.
-        // Skip it in history to avoid duplicating the same user turn in prompt context.
-        if row.role == "user" && row.user_id == user_id && row.message_id == current_message_id {
+        // The messages being answered are already persisted before this call.
+        // Skip them in history to avoid duplicating the same user turn in prompt context.
+        if row.role == "user"
+            && current_messages
+                .iter()
+                .any(|&(user, id)| row.user_id == user && row.message_id == Some(id))
+        {
             continue;
         }
 
@@ -733,30 +1173,33 @@ async fn build_llm_messages(
 async fn send_split_message(
     bot: &Bot,
     chat_id: ChatId,
+    thread_id: Option<ThreadId>,
     text: &str,
     reply_to: teloxide::types::MessageId,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     use teloxide::types::ReplyParameters;
 
-    let chunks = if text.len() <= MAX_TELEGRAM_MSG_LEN {
-        vec![text.to_string()]
-    } else {
-        split_message(text, MAX_TELEGRAM_MSG_LEN)
-    };
-
+    let chunks = crate::markdown::to_telegram_html(text, MAX_TELEGRAM_MSG_LEN);
     let reply_params = ReplyParameters::new(reply_to).allow_sending_without_reply();
 
     for (i, chunk) in chunks.iter().enumerate() {
         // Only reply to the original message for the first chunk
-        let mut request = bot.send_message(chat_id, chunk);
+        let mut request = bot.send_message(chat_id, &chunk.html);
+        if let Some(thread_id) = thread_id {
+            request = request.message_thread_id(thread_id);
+        }
         if i == 0 {
             request = request.reply_parameters(reply_params.clone());
         }
-        // Try MarkdownV2 first, fall back to plain text
-        let md_result = request.parse_mode(ParseMode::MarkdownV2).await;
-
-        if md_result.is_err() {
-            let mut request = bot.send_message(chat_id, chunk);
+        // Send as HTML, falling back to the chunk's plain text
+        let html_result = request.parse_mode(ParseMode::Html).await;
+
+        if let Err(e) = html_result {
+            tracing::warn!(error = %e, "HTML message rejected, sending as plain text");
+            let mut request = bot.send_message(chat_id, &chunk.text);
+            if let Some(thread_id) = thread_id {
+                request = request.message_thread_id(thread_id);
+            }
             if i == 0 {
                 request = request.reply_parameters(reply_params.clone());
             }
@@ -767,35 +1210,6 @@ async fn send_split_message(
     Ok(())
 }
 
-fn split_message(text: &str, max_len: usize) -> Vec<String> {
-    let mut chunks = Vec::new();
-    let mut remaining = text;
-
-    while !remaining.is_empty() {
-        if remaining.len() <= max_len {
-            chunks.push(remaining.to_string());
-            break;
-        }
-
-        // Find the largest char boundary <= max_len
-        let mut boundary = max_len;
-        while boundary > 0 && !remaining.is_char_boundary(boundary) {
-            boundary -= 1;
-        }
-
-        // Try to split at a newline or space within the boundary
-        let split_at = remaining[..boundary]
-            .rfind('\n')
-            .unwrap_or_else(|| remaining[..boundary].rfind(' ').unwrap_or(boundary));
-
-        let (chunk, rest) = remaining.split_at(split_at);
-        chunks.push(chunk.to_string());
-        remaining = rest.trim_start_matches('\n');
-    }
-
-    chunks
-}
-
 /// Extract a substring using UTF-16 offset and length (Telegram's encoding).
 fn utf16_substr(text: &str, utf16_offset: usize, utf16_len: usize) -> Option<String> {
     let utf16_units: Vec<u16> = text.encode_utf16().collect();
@@ -35,11 +35,20 @@ pub async fn get_required(pool: &SqlitePool, key: &str) -> Result<String> {
 /// Get the Telegram bot token from config or env var
 pub async fn get_telegram_token(pool: &SqlitePool) -> Result<String> {
     // Try env var first
-    if let Ok(token) = std::env::var("TELEGRAM_BOT_TOKEN") {
-        if !token.is_empty() {
-            return Ok(token);
-        }
+    if let Ok(token) = std::env::var("TELEGRAM_BOT_TOKEN")
+        && !token.is_empty()
+    {
+        return Ok(token);
     }
     // Fall back to config
     get_required(pool, "tg_bot_token").await
 }
+
+/// Whether the user is listed in the comma-separated `admin_user_ids` config key
+pub async fn is_admin(pool: &SqlitePool, user_id: i64) -> Result<bool> {
+    let admins = get_or_default(pool, "admin_user_ids", "").await?;
+    Ok(admins
+        .split(',')
+        .filter_map(|id| id.trim().parse::<i64>().ok())
+        .any(|id| id == user_id))
+}
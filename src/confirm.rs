@@ -0,0 +1,176 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, MessageId, ThreadId};
+use tokio::sync::oneshot;
+
+const CALLBACK_PREFIX: &str = "confirm:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Approved,
+    Denied,
+    TimedOut,
+}
+
+/// Returned (as an error) by a tool call the user did not approve; aborts the
+/// whole LLM turn instead of being handed back to the model
+#[derive(Debug)]
+pub struct ConfirmationDeclined {
+    pub tool_name: String,
+    pub decision: Decision,
+}
+
+impl std::fmt::Display for ConfirmationDeclined {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.decision {
+            Decision::TimedOut => write!(
+                f,
+                "Cancelled: no approval for '{}' arrived in time, so nothing was changed.",
+                self.tool_name
+            ),
+            _ => write!(
+                f,
+                "Cancelled: '{}' was not approved, so nothing was changed.",
+                self.tool_name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfirmationDeclined {}
+
+struct PendingConfirmation {
+    user_id: i64,
+    reply: oneshot::Sender<bool>,
+}
+
+/// Approval prompts waiting for an inline-keyboard answer
+pub struct ConfirmationGate {
+    pending: Mutex<HashMap<u64, PendingConfirmation>>,
+    next_id: AtomicU64,
+}
+
+impl ConfirmationGate {
+    pub fn new() -> Self {
+        Self {
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Post an Approve/Deny prompt and wait until the user answers or the timeout passes
+    pub async fn request(
+        &self,
+        bot: &Bot,
+        chat_id: i64,
+        thread_id: Option<ThreadId>,
+        user_id: i64,
+        prompt: &str,
+        timeout: Duration,
+    ) -> Result<Decision> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.lock()
+            .insert(id, PendingConfirmation { user_id, reply: tx });
+        let mut guard = PendingGuard {
+            gate: self,
+            id,
+            prompt: None,
+        };
+
+        let keyboard = InlineKeyboardMarkup::new([[
+            InlineKeyboardButton::callback("✅ Approve", format!("{}{}:yes", CALLBACK_PREFIX, id)),
+            InlineKeyboardButton::callback("❌ Deny", format!("{}{}:no", CALLBACK_PREFIX, id)),
+        ]]);
+        let mut request = bot
+            .send_message(ChatId(chat_id), prompt)
+            .reply_markup(keyboard);
+        if let Some(thread_id) = thread_id {
+            request = request.message_thread_id(thread_id);
+        }
+        let sent = request.await?;
+        guard.prompt = Some((bot.clone(), sent.chat.id, sent.id, prompt.to_string()));
+
+        let decision = match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(true)) => Decision::Approved,
+            Ok(Ok(false)) => Decision::Denied,
+            Ok(Err(_)) | Err(_) => Decision::TimedOut,
+        };
+        guard.prompt = None;
+        drop(guard);
+
+        tracing::info!(chat_id, user_id, ?decision, "Tool confirmation resolved");
+
+        // Replace the keyboard with the outcome
+        let outcome = match decision {
+            Decision::Approved => "✅ Approved",
+            Decision::Denied => "❌ Denied",
+            Decision::TimedOut => "⌛ Timed out",
+        };
+        if let Err(e) = bot
+            .edit_message_text(sent.chat.id, sent.id, format!("{}\n\n{}", prompt, outcome))
+            .await
+        {
+            tracing::warn!(error = %e, "Failed to update confirmation prompt");
+        }
+
+        Ok(decision)
+    }
+
+    /// Apply an inline-keyboard answer. Returns the notice to show the pressing
+    /// user, or None if the callback does not belong to the gate.
+    pub fn resolve(&self, data: &str, user_id: i64) -> Option<&'static str> {
+        let (id, answer) = data.strip_prefix(CALLBACK_PREFIX)?.split_once(':')?;
+        let id: u64 = id.parse().ok()?;
+        let approved = answer == "yes";
+
+        let mut pending = self.lock();
+        match pending.get(&id) {
+            None => Some("This request is no longer pending"),
+            Some(p) if p.user_id != user_id => {
+                Some("Only the user who made the request can answer")
+            }
+            Some(_) => {
+                let p = pending.remove(&id)?;
+                let _ = p.reply.send(approved);
+                Some(if approved { "Approved" } else { "Denied" })
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, PendingConfirmation>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Removes a pending confirmation however `request` ends. When the wait is
+/// abandoned (the turn was stopped or panicked), the prompt still on screen
+/// is marked as cancelled so its buttons no longer look live.
+struct PendingGuard<'a> {
+    gate: &'a ConfirmationGate,
+    id: u64,
+    /// Posted prompt, until the wait ends normally
+    prompt: Option<(Bot, ChatId, MessageId, String)>,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.gate.lock().remove(&self.id);
+        if let Some((bot, chat_id, message_id, prompt)) = self.prompt.take()
+            && let Ok(runtime) = tokio::runtime::Handle::try_current()
+        {
+            runtime.spawn(async move {
+                if let Err(e) = bot
+                    .edit_message_text(chat_id, message_id, format!("{}\n\n🚫 Cancelled", prompt))
+                    .await
+                {
+                    tracing::warn!(error = %e, "Failed to mark confirmation prompt cancelled");
+                }
+            });
+        }
+    }
+}
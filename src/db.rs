@@ -3,7 +3,10 @@ use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
 use sqlx::{Column, Row, SqlitePool};
 use std::str::FromStr;
 
-use crate::types::{ConversationRow, McpServerRow, MemoryRow, NoteRow};
+use crate::types::{
+    ConversationRow, ExpertRow, HttpCacheEntry, McpServerRow, MemoryRow, NoteRow, OutboundMessage,
+    SandboxAuditEntry, SecretInfo,
+};
 
 pub async fn create_pool(db_path: &str) -> Result<SqlitePool> {
     let options = SqliteConnectOptions::from_str(db_path)?
@@ -160,6 +163,109 @@ async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             updated_at TEXT NOT NULL DEFAULT (datetime('now'))
         )",
         ),
+        (
+            18,
+            "CREATE TABLE IF NOT EXISTS experts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            description TEXT NOT NULL DEFAULT '',
+            model TEXT NOT NULL,
+            system_prompt TEXT NOT NULL DEFAULT '',
+            max_tokens INTEGER NOT NULL DEFAULT 1024,
+            kind TEXT NOT NULL DEFAULT 'generic',
+            allowed_chats TEXT NOT NULL DEFAULT '',
+            enabled INTEGER NOT NULL DEFAULT 1,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )",
+        ),
+        (
+            19,
+            "INSERT OR IGNORE INTO experts (name, description, model, max_tokens, kind) VALUES
+            ('deep', 'Generic expert for deep reasoning', 'anthropic/claude-opus-4.6', 2048, 'generic'),
+            ('fast', 'Generic expert for fast practical reasoning', 'openai/gpt-5.2-pro', 2048, 'generic'),
+            ('search', 'Internet search expert (Perplexity-powered) for current web-aware answers', 'perplexity/sonar-pro', 2048, 'internet_search')",
+        ),
+        (
+            20,
+            "CREATE TABLE IF NOT EXISTS sandbox_audit (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            chat_id INTEGER NOT NULL,
+            user_id INTEGER NOT NULL,
+            tool TEXT NOT NULL,
+            input TEXT NOT NULL,
+            exit_code INTEGER,
+            limit_exceeded TEXT,
+            error TEXT,
+            network TEXT NOT NULL,
+            duration_ms INTEGER NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )",
+        ),
+        (
+            21,
+            "CREATE TABLE IF NOT EXISTS secrets (
+            name TEXT PRIMARY KEY,
+            value TEXT NOT NULL,
+            updated_by INTEGER,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        )",
+        ),
+        (
+            22,
+            "CREATE TABLE IF NOT EXISTS http_cache (
+            key TEXT PRIMARY KEY,
+            etag TEXT,
+            last_modified TEXT,
+            content_type TEXT NOT NULL,
+            body BLOB NOT NULL,
+            fetched_at INTEGER NOT NULL
+        )",
+        ),
+        (
+            23,
+            "CREATE TABLE IF NOT EXISTS outbound_messages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            origin_chat_id INTEGER NOT NULL,
+            origin_user_id INTEGER NOT NULL,
+            target_chat_id INTEGER NOT NULL,
+            text TEXT NOT NULL,
+            parse_mode TEXT,
+            reply_to_message_id INTEGER,
+            silent INTEGER NOT NULL DEFAULT 0,
+            send_at TEXT,
+            status TEXT NOT NULL,
+            message_id INTEGER,
+            error TEXT,
+            created_at TEXT NOT NULL DEFAULT (datetime('now')),
+            sent_at TEXT
+        )",
+        ),
+        (
+            24,
+            "ALTER TABLE conversation_history ADD COLUMN thread_id INTEGER",
+        ),
+        (
+            25,
+            "ALTER TABLE outbound_messages ADD COLUMN thread_id INTEGER",
+        ),
+        (
+            26,
+            "ALTER TABLE secrets ADD COLUMN allowed_hosts TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            27,
+            "ALTER TABLE secrets ADD COLUMN shared_chats TEXT NOT NULL DEFAULT ''",
+        ),
+        (
+            28,
+            "ALTER TABLE http_cache ADD COLUMN final_url TEXT",
+        ),
+        (
+            29,
+            "ALTER TABLE secrets ADD COLUMN mcp_servers TEXT NOT NULL DEFAULT ''",
+        ),
     ];
 
     for (version, sql) in migrations {
@@ -257,11 +363,60 @@ pub async fn name_map_get_full(
     Ok(row)
 }
 
+/// Whether the bot has seen this chat, or this user (whose private chat has
+/// the same ID)
+pub async fn name_map_known(pool: &SqlitePool, entity_id: i64) -> Result<bool> {
+    let row: Option<(i64,)> = sqlx::query_as(
+        "SELECT entity_id FROM name_map WHERE entity_id = ? AND entity_type IN ('chat', 'user')",
+    )
+    .bind(entity_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.is_some())
+}
+
+/// Chats and users whose name or @username matches `query`: exact
+/// (case-insensitive) matches when there are any, otherwise substring ones.
+/// Rows are (entity_type, entity_id, display_name, username).
+pub async fn name_map_find(
+    pool: &SqlitePool,
+    query: &str,
+) -> Result<Vec<(String, i64, String, String)>> {
+    let query = query.trim().trim_start_matches('@');
+    let exact: Vec<(String, i64, String, String)> = sqlx::query_as(
+        "SELECT entity_type, entity_id, display_name, username FROM name_map
+         WHERE entity_type IN ('chat', 'user')
+           AND (LOWER(display_name) = LOWER(?) OR (username != '' AND LOWER(username) = LOWER(?)))
+         LIMIT 10",
+    )
+    .bind(query)
+    .bind(query)
+    .fetch_all(pool)
+    .await?;
+    if !exact.is_empty() {
+        return Ok(exact);
+    }
+
+    let pattern = format!("%{}%", query);
+    let rows: Vec<(String, i64, String, String)> = sqlx::query_as(
+        "SELECT entity_type, entity_id, display_name, username FROM name_map
+         WHERE entity_type IN ('chat', 'user') AND (display_name LIKE ? OR username LIKE ?)
+         LIMIT 10",
+    )
+    .bind(&pattern)
+    .bind(&pattern)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
 // --- Conversation History ---
 
+#[allow(clippy::too_many_arguments)]
 pub async fn conversation_save(
     pool: &SqlitePool,
     chat_id: i64,
+    thread_id: Option<i32>,
     user_id: i64,
     user_name: &str,
     role: &str,
@@ -271,10 +426,11 @@ pub async fn conversation_save(
     reply_to_id: Option<i64>,
 ) -> Result<i64> {
     let result = sqlx::query(
-        "INSERT INTO conversation_history (chat_id, user_id, user_name, role, content, tool_call_id, message_id, reply_to_id)
-         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        "INSERT INTO conversation_history (chat_id, thread_id, user_id, user_name, role, content, tool_call_id, message_id, reply_to_id)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
     )
     .bind(chat_id)
+    .bind(thread_id)
     .bind(user_id)
     .bind(user_name)
     .bind(role)
@@ -300,19 +456,23 @@ pub async fn conversation_save(
     Ok(row_id)
 }
 
+/// Recent history of a chat, or of one forum topic (`thread_id` None is the
+/// chat itself, or the General topic of a forum)
 pub async fn conversation_load(
     pool: &SqlitePool,
     chat_id: i64,
+    thread_id: Option<i32>,
     limit: i64,
 ) -> Result<Vec<ConversationRow>> {
     let rows = sqlx::query(
         "SELECT id, chat_id, user_id, user_name, role, content, tool_call_id, message_id, reply_to_id, created_at
          FROM conversation_history
-         WHERE chat_id = ?
+         WHERE chat_id = ? AND thread_id IS ?
          ORDER BY id DESC
          LIMIT ?",
     )
     .bind(chat_id)
+    .bind(thread_id)
     .bind(limit)
     .fetch_all(pool)
     .await?;
@@ -337,15 +497,22 @@ pub async fn conversation_load(
     Ok(result)
 }
 
-pub async fn conversation_clear(pool: &SqlitePool, chat_id: i64) -> Result<u64> {
-    let result = sqlx::query("DELETE FROM conversation_history WHERE chat_id = ?")
-        .bind(chat_id)
-        .execute(pool)
-        .await?;
+pub async fn conversation_clear(
+    pool: &SqlitePool,
+    chat_id: i64,
+    thread_id: Option<i32>,
+) -> Result<u64> {
+    let result =
+        sqlx::query("DELETE FROM conversation_history WHERE chat_id = ? AND thread_id IS ?")
+            .bind(chat_id)
+            .bind(thread_id)
+            .execute(pool)
+            .await?;
     Ok(result.rows_affected())
 }
 
 /// Search conversation history with various filters
+#[allow(clippy::too_many_arguments)]
 pub async fn conversation_search(
     pool: &SqlitePool,
     chat_id: i64,
@@ -415,6 +582,7 @@ pub async fn conversation_search(
 }
 
 /// Search conversation history across ALL chats (no chat_id filter)
+#[allow(clippy::too_many_arguments)]
 pub async fn conversation_search_global(
     pool: &SqlitePool,
     keyword: Option<&str>,
@@ -528,6 +696,25 @@ pub async fn note_read(pool: &SqlitePool, note_id: i64) -> Result<Option<NoteRow
     }))
 }
 
+/// Insert a note and return its id
+pub async fn note_create(
+    pool: &SqlitePool,
+    segment: &str,
+    title: &str,
+    content: &str,
+    tags: &str,
+) -> Result<i64> {
+    let result =
+        sqlx::query("INSERT INTO notes (segment, title, content, tags) VALUES (?, ?, ?, ?)")
+            .bind(segment)
+            .bind(title)
+            .bind(content)
+            .bind(tags)
+            .execute(pool)
+            .await?;
+    Ok(result.last_insert_rowid())
+}
+
 pub async fn note_search(
     pool: &SqlitePool,
     query: &str,
@@ -587,55 +774,53 @@ fn build_memory_filter_sql(
     let mut sql = String::new();
     let mut binds: Vec<String> = Vec::new();
 
-    if let Some(list) = segments {
-        if !list.is_empty() {
-            let placeholders = std::iter::repeat("?")
-                .take(list.len())
-                .map(|_| "?")
-                .collect::<Vec<_>>()
-                .join(",");
-            sql.push_str(" AND segment IN (");
-            sql.push_str(&placeholders);
-            sql.push(')');
-            for segment in list {
-                binds.push(segment.clone());
-            }
+    if let Some(list) = segments
+        && !list.is_empty()
+    {
+        let placeholders = std::iter::repeat_n("?", list.len())
+            .collect::<Vec<_>>()
+            .join(",");
+        sql.push_str(" AND segment IN (");
+        sql.push_str(&placeholders);
+        sql.push(')');
+        for segment in list {
+            binds.push(segment.clone());
         }
     }
 
-    if let Some(like) = segment_like {
-        if !like.is_empty() {
-            sql.push_str(" AND segment LIKE ?");
-            binds.push(format!("%{}%", like));
-        }
+    if let Some(like) = segment_like
+        && !like.is_empty()
+    {
+        sql.push_str(" AND segment LIKE ?");
+        binds.push(format!("%{}%", like));
     }
 
-    if let Some(key) = key {
-        if !key.is_empty() {
-            sql.push_str(" AND key = ?");
-            binds.push(key.to_string());
-        }
+    if let Some(key) = key
+        && !key.is_empty()
+    {
+        sql.push_str(" AND key = ?");
+        binds.push(key.to_string());
     }
 
-    if let Some(prefix) = key_prefix {
-        if !prefix.is_empty() {
-            sql.push_str(" AND key LIKE ?");
-            binds.push(format!("{}%", prefix));
-        }
+    if let Some(prefix) = key_prefix
+        && !prefix.is_empty()
+    {
+        sql.push_str(" AND key LIKE ?");
+        binds.push(format!("{}%", prefix));
     }
 
-    if let Some(substr) = key_contains {
-        if !substr.is_empty() {
-            sql.push_str(" AND key LIKE ?");
-            binds.push(format!("%{}%", substr));
-        }
+    if let Some(substr) = key_contains
+        && !substr.is_empty()
+    {
+        sql.push_str(" AND key LIKE ?");
+        binds.push(format!("%{}%", substr));
     }
 
-    if let Some(substr) = value_contains {
-        if !substr.is_empty() {
-            sql.push_str(" AND value LIKE ?");
-            binds.push(format!("%{}%", substr));
-        }
+    if let Some(substr) = value_contains
+        && !substr.is_empty()
+    {
+        sql.push_str(" AND value LIKE ?");
+        binds.push(format!("%{}%", substr));
     }
 
     (sql, binds)
@@ -667,6 +852,7 @@ pub async fn memory_get(pool: &SqlitePool, segment: &str, key: &str) -> Result<O
     Ok(row.map(|r| r.0))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn memory_list_filtered(
     pool: &SqlitePool,
     segments: Option<&[String]>,
@@ -804,6 +990,294 @@ pub async fn tool_call_log(
     Ok(())
 }
 
+/// Record one sandboxed run
+pub async fn sandbox_audit_insert(pool: &SqlitePool, entry: &SandboxAuditEntry) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO sandbox_audit
+            (chat_id, user_id, tool, input, exit_code, limit_exceeded, error, network, duration_ms)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(entry.chat_id)
+    .bind(entry.user_id)
+    .bind(&entry.tool)
+    .bind(&entry.input)
+    .bind(entry.exit_code)
+    .bind(&entry.limit_exceeded)
+    .bind(&entry.error)
+    .bind(&entry.network)
+    .bind(entry.duration_ms)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// --- Secrets ---
+
+/// Store an encrypted secret, replacing any with the same name
+pub async fn secret_set(
+    pool: &SqlitePool,
+    name: &str,
+    value: &str,
+    updated_by: Option<i64>,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO secrets (name, value, updated_by) VALUES (?, ?, ?)
+         ON CONFLICT(name) DO UPDATE SET
+            value = excluded.value, updated_by = excluded.updated_by, updated_at = datetime('now')",
+    )
+    .bind(name)
+    .bind(value)
+    .bind(updated_by)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn secret_delete(pool: &SqlitePool, name: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM secrets WHERE name = ?")
+        .bind(name)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Replace the comma-separated hosts a secret may be sent to
+pub async fn secret_set_hosts(pool: &SqlitePool, name: &str, allowed_hosts: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE secrets SET allowed_hosts = ?, updated_at = datetime('now') WHERE name = ?",
+    )
+    .bind(allowed_hosts)
+    .bind(name)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Replace the comma-separated MCP servers a secret may be sent to
+pub async fn secret_set_mcp_servers(
+    pool: &SqlitePool,
+    name: &str,
+    mcp_servers: &str,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE secrets SET mcp_servers = ?, updated_at = datetime('now') WHERE name = ?",
+    )
+    .bind(mcp_servers)
+    .bind(name)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Replace the comma-separated chats a secret is shared with
+pub async fn secret_set_shared_chats(
+    pool: &SqlitePool,
+    name: &str,
+    shared_chats: &str,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE secrets SET shared_chats = ?, updated_at = datetime('now') WHERE name = ?",
+    )
+    .bind(shared_chats)
+    .bind(name)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Stored secrets without their values
+pub async fn secret_list(pool: &SqlitePool) -> Result<Vec<SecretInfo>> {
+    let rows = sqlx::query(
+        "SELECT name, allowed_hosts, mcp_servers, shared_chats, updated_at FROM secrets ORDER BY name",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| SecretInfo {
+            name: r.get("name"),
+            allowed_hosts: r.get("allowed_hosts"),
+            mcp_servers: r.get("mcp_servers"),
+            shared_chats: r.get("shared_chats"),
+            updated_at: r.get("updated_at"),
+        })
+        .collect())
+}
+
+/// Names, encrypted values, allowed hosts, MCP servers and shared chats of
+/// all secrets
+pub async fn secret_all(
+    pool: &SqlitePool,
+) -> Result<Vec<(String, String, String, String, String)>> {
+    let rows: Vec<(String, String, String, String, String)> =
+        sqlx::query_as("SELECT name, value, allowed_hosts, mcp_servers, shared_chats FROM secrets")
+            .fetch_all(pool)
+            .await?;
+    Ok(rows)
+}
+
+// --- Outbound Messages ---
+
+/// Record a `send_message` call with its status (`sent`, `failed` or
+/// `scheduled`) and return its id
+pub async fn outbound_insert(
+    pool: &SqlitePool,
+    message: &OutboundMessage,
+    status: &str,
+    message_id: Option<i32>,
+    error: Option<&str>,
+) -> Result<i64> {
+    let result = sqlx::query(
+        "INSERT INTO outbound_messages
+            (origin_chat_id, origin_user_id, target_chat_id, thread_id, text, parse_mode,
+             reply_to_message_id, silent, send_at, status, message_id, error, sent_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, CASE WHEN ? = 'sent' THEN datetime('now') END)",
+    )
+    .bind(message.origin_chat_id)
+    .bind(message.origin_user_id)
+    .bind(message.target_chat_id)
+    .bind(message.thread_id)
+    .bind(&message.text)
+    .bind(&message.parse_mode)
+    .bind(message.reply_to_message_id)
+    .bind(message.silent)
+    .bind(&message.send_at)
+    .bind(status)
+    .bind(message_id)
+    .bind(error)
+    .bind(status)
+    .execute(pool)
+    .await?;
+    Ok(result.last_insert_rowid())
+}
+
+/// Scheduled messages whose time has come, oldest first
+pub async fn outbound_due(pool: &SqlitePool, limit: i64) -> Result<Vec<OutboundMessage>> {
+    let rows = sqlx::query(
+        "SELECT id, origin_chat_id, origin_user_id, target_chat_id, thread_id, text, parse_mode,
+                reply_to_message_id, silent, send_at
+         FROM outbound_messages
+         WHERE status = 'scheduled' AND send_at <= datetime('now')
+         ORDER BY send_at, id LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| OutboundMessage {
+            id: r.get("id"),
+            origin_chat_id: r.get("origin_chat_id"),
+            origin_user_id: r.get("origin_user_id"),
+            target_chat_id: r.get("target_chat_id"),
+            thread_id: r.get("thread_id"),
+            text: r.get("text"),
+            parse_mode: r.get("parse_mode"),
+            reply_to_message_id: r.get("reply_to_message_id"),
+            silent: r.get("silent"),
+            send_at: r.get("send_at"),
+        })
+        .collect())
+}
+
+/// Mark a due message as being sent, so later polls skip it even if its
+/// outcome can't be recorded. Returns false when it was no longer scheduled.
+pub async fn outbound_claim(pool: &SqlitePool, id: i64) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE outbound_messages SET status = 'sending' WHERE id = ? AND status = 'scheduled'",
+    )
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Record the outcome of a scheduled message
+pub async fn outbound_finish(
+    pool: &SqlitePool,
+    id: i64,
+    message_id: Option<i32>,
+    error: Option<&str>,
+) -> Result<()> {
+    sqlx::query(
+        "UPDATE outbound_messages
+         SET status = CASE WHEN ? IS NULL THEN 'sent' ELSE 'failed' END,
+             message_id = ?, error = ?, sent_at = datetime('now')
+         WHERE id = ?",
+    )
+    .bind(error)
+    .bind(message_id)
+    .bind(error)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// --- HTTP Cache ---
+
+pub async fn http_cache_get(pool: &SqlitePool, key: &str) -> Result<Option<HttpCacheEntry>> {
+    let row = sqlx::query(
+        "SELECT etag, last_modified, content_type, body, final_url, fetched_at
+         FROM http_cache WHERE key = ?",
+    )
+    .bind(key)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| HttpCacheEntry {
+        etag: r.get("etag"),
+        last_modified: r.get("last_modified"),
+        content_type: r.get("content_type"),
+        body: r.get("body"),
+        final_url: r.get("final_url"),
+        fetched_at: r.get("fetched_at"),
+    }))
+}
+
+/// Store a response and drop entries not refreshed for `max_age_secs`
+pub async fn http_cache_put(
+    pool: &SqlitePool,
+    key: &str,
+    entry: &HttpCacheEntry,
+    max_age_secs: i64,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO http_cache (key, etag, last_modified, content_type, body, final_url, fetched_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(key) DO UPDATE SET
+            etag = excluded.etag, last_modified = excluded.last_modified,
+            content_type = excluded.content_type, body = excluded.body,
+            final_url = excluded.final_url, fetched_at = excluded.fetched_at",
+    )
+    .bind(key)
+    .bind(&entry.etag)
+    .bind(&entry.last_modified)
+    .bind(&entry.content_type)
+    .bind(&entry.body)
+    .bind(&entry.final_url)
+    .bind(entry.fetched_at)
+    .execute(pool)
+    .await?;
+    sqlx::query("DELETE FROM http_cache WHERE fetched_at < ?")
+        .bind(entry.fetched_at - max_age_secs)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Mark a cached response as revalidated at `fetched_at`
+pub async fn http_cache_touch(pool: &SqlitePool, key: &str, fetched_at: i64) -> Result<()> {
+    sqlx::query("UPDATE http_cache SET fetched_at = ? WHERE key = ?")
+        .bind(fetched_at)
+        .bind(key)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
 // --- Trigger Keywords ---
 
 pub async fn trigger_keywords_list(pool: &SqlitePool) -> Result<Vec<String>> {
@@ -900,6 +1374,7 @@ pub async fn mcp_server_get(pool: &SqlitePool, name: &str) -> Result<Option<McpS
     }))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn mcp_server_create(
     pool: &SqlitePool,
     name: &str,
@@ -940,6 +1415,7 @@ pub async fn mcp_server_create(
     Ok(result.last_insert_rowid())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn mcp_server_update(
     pool: &SqlitePool,
     current_name: &str,
@@ -986,6 +1462,115 @@ pub async fn mcp_server_delete(pool: &SqlitePool, name: &str) -> Result<bool> {
     Ok(result.rows_affected() > 0)
 }
 
+// --- Experts ---
+
+const EXPERT_COLUMNS: &str = "id, name, description, model, system_prompt, max_tokens, kind, allowed_chats, enabled, created_at, updated_at";
+
+fn expert_from_row(row: &sqlx::sqlite::SqliteRow) -> ExpertRow {
+    ExpertRow {
+        id: row.get("id"),
+        name: row.get("name"),
+        description: row.get("description"),
+        model: row.get("model"),
+        system_prompt: row.get("system_prompt"),
+        max_tokens: row.get("max_tokens"),
+        kind: row.get("kind"),
+        allowed_chats: row.get("allowed_chats"),
+        enabled: row.get::<i64, _>("enabled") != 0,
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+pub async fn expert_list(pool: &SqlitePool, include_disabled: bool) -> Result<Vec<ExpertRow>> {
+    let filter = if include_disabled {
+        ""
+    } else {
+        "WHERE enabled = 1"
+    };
+    let sql = format!(
+        "SELECT {} FROM experts {} ORDER BY id ASC",
+        EXPERT_COLUMNS, filter
+    );
+    let rows = sqlx::query(&sql).fetch_all(pool).await?;
+    Ok(rows.iter().map(expert_from_row).collect())
+}
+
+pub async fn expert_get(pool: &SqlitePool, name: &str) -> Result<Option<ExpertRow>> {
+    let sql = format!("SELECT {} FROM experts WHERE name = ?", EXPERT_COLUMNS);
+    let row = sqlx::query(&sql).bind(name).fetch_optional(pool).await?;
+    Ok(row.as_ref().map(expert_from_row))
+}
+
+pub async fn expert_create(pool: &SqlitePool, expert: &ExpertRow) -> Result<i64> {
+    if expert.name.trim().is_empty() {
+        anyhow::bail!("Expert name cannot be empty");
+    }
+    if expert.model.trim().is_empty() {
+        anyhow::bail!("Expert model cannot be empty");
+    }
+    if expert_get(pool, &expert.name).await?.is_some() {
+        anyhow::bail!("Expert '{}' already exists", expert.name);
+    }
+
+    let result = sqlx::query(
+        "INSERT INTO experts (name, description, model, system_prompt, max_tokens, kind, allowed_chats, enabled)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&expert.name)
+    .bind(&expert.description)
+    .bind(&expert.model)
+    .bind(&expert.system_prompt)
+    .bind(expert.max_tokens)
+    .bind(&expert.kind)
+    .bind(&expert.allowed_chats)
+    .bind(if expert.enabled { 1 } else { 0 })
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+pub async fn expert_update(
+    pool: &SqlitePool,
+    current_name: &str,
+    expert: &ExpertRow,
+) -> Result<bool> {
+    if expert.name.trim().is_empty() {
+        anyhow::bail!("Expert name cannot be empty");
+    }
+    if expert.model.trim().is_empty() {
+        anyhow::bail!("Expert model cannot be empty");
+    }
+
+    let result = sqlx::query(
+        "UPDATE experts
+         SET name = ?, description = ?, model = ?, system_prompt = ?, max_tokens = ?, kind = ?, allowed_chats = ?, enabled = ?, updated_at = datetime('now')
+         WHERE name = ?",
+    )
+    .bind(&expert.name)
+    .bind(&expert.description)
+    .bind(&expert.model)
+    .bind(&expert.system_prompt)
+    .bind(expert.max_tokens)
+    .bind(&expert.kind)
+    .bind(&expert.allowed_chats)
+    .bind(if expert.enabled { 1 } else { 0 })
+    .bind(current_name)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn expert_delete(pool: &SqlitePool, name: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM experts WHERE name = ?")
+        .bind(name)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
 // --- Raw SQL (CLI only) ---
 
 pub async fn raw_query(pool: &SqlitePool, sql: &str) -> Result<Vec<Vec<(String, String)>>> {
@@ -1,17 +1,23 @@
 use anyhow::Result;
+use futures::StreamExt;
 use serde_json::Value;
-use sqlx::SqlitePool;
 use std::time::{Duration, Instant};
-use teloxide::Bot;
 
-use crate::mcp::McpManager;
-use crate::rag::RagEngine;
-use crate::tools;
+use crate::config;
+use crate::confirm::ConfirmationDeclined;
+use crate::tools::{ToolContext, ToolRegistry};
 use crate::types::*;
 
 const OPENROUTER_URL: &str = "https://openrouter.ai/api/v1/chat/completions";
 const MAX_TOOL_ROUNDS: usize = 30;
 const MAX_RETRIES: u32 = 3;
+const DEFAULT_TOOL_CONCURRENCY: usize = 4;
+
+/// Reported by `LlmClient::chat` before each round's tool calls run
+pub struct ChatProgress<'a> {
+    pub round: usize,
+    pub tools: Vec<&'a str>,
+}
 
 pub struct LlmClient {
     http: reqwest::Client,
@@ -36,21 +42,18 @@ impl LlmClient {
     /// Send a chat completion request with tool-call loop
     pub async fn chat(
         &self,
-        pool: &SqlitePool,
-        bot: &Bot,
-        rag: &RagEngine,
-        mcp: &McpManager,
+        tools: &ToolRegistry,
+        ctx: &ToolContext<'_>,
         messages: Vec<ChatMessage>,
-        chat_id: i64,
-        user_id: i64,
+        progress: &(dyn Fn(&ChatProgress<'_>) + Sync),
     ) -> Result<String> {
-        let mut tool_defs = tools::definitions();
-        // Auto-discover MCP tools and register them as first-class LLM tools
-        let mcp_defs = tools::mcp_dynamic_definitions(mcp, pool).await;
-        if !mcp_defs.is_empty() {
-            tracing::info!(count = mcp_defs.len(), "Registered dynamic MCP tools");
-            tool_defs.extend(mcp_defs);
-        }
+        let tool_defs = tools.definitions(ctx).await;
+
+        let tool_concurrency = config::get(ctx.pool, "tool_concurrency")
+            .await?
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .unwrap_or(DEFAULT_TOOL_CONCURRENCY)
+            .max(1);
 
         let mut current_messages = messages;
         let mut last_tool_error_signature: Option<String> = None;
@@ -97,54 +100,82 @@ impl LlmClient {
             }
 
             // Check if there are tool calls
-            if let Some(tool_calls) = &assistant_msg.tool_calls {
-                if !tool_calls.is_empty() {
-                    // Add assistant message with tool calls to context
+            if let Some(tool_calls) = &assistant_msg.tool_calls
+                && !tool_calls.is_empty()
+            {
+                // Add assistant message with tool calls to context
+                current_messages.push(ChatMessage {
+                    role: "assistant".to_string(),
+                    content: assistant_msg.content.clone(),
+                    tool_calls: Some(tool_calls.clone()),
+                    tool_call_id: None,
+                    name: None,
+                });
+
+                progress(&ChatProgress {
+                    round,
+                    tools: tool_calls
+                        .iter()
+                        .map(|tc| tc.function.name.as_str())
+                        .collect(),
+                });
+
+                // Execute the round's tool calls concurrently. Results come back
+                // in the original order; calls with side effects take a shared
+                // lock so they still run one at a time, in order.
+                let side_effect_lock = tokio::sync::Mutex::new(());
+                let mut pending = Vec::with_capacity(tool_calls.len());
+                for tc in tool_calls {
+                    let side_effect_lock = &side_effect_lock;
+                    pending.push(async move {
+                        let _guard =
+                            if tools.has_side_effects(&tc.function.name, &tc.function.arguments) {
+                                Some(side_effect_lock.lock().await)
+                            } else {
+                                None
+                            };
+                        tools
+                            .execute(ctx, &tc.function.name, &tc.function.arguments)
+                            .await
+                    });
+                }
+                let results: Vec<Result<String>> = futures::stream::iter(pending)
+                    .buffered(tool_concurrency)
+                    .collect()
+                    .await;
+
+                for (tc, result) in tool_calls.iter().zip(results) {
+                    let result = match result {
+                        Ok(result) => result,
+                        // The user declined a destructive call: end the turn here
+                        Err(e) => match e.downcast::<ConfirmationDeclined>() {
+                            Ok(declined) => return Ok(declined.to_string()),
+                            Err(e) => return Err(e),
+                        },
+                    };
+
+                    // Add tool result message
+                    let args_signature = tc.function.arguments.trim().to_string();
+                    let error = parse_tool_error_message(&result);
+
                     current_messages.push(ChatMessage {
-                        role: "assistant".to_string(),
-                        content: assistant_msg.content.clone(),
-                        tool_calls: Some(tool_calls.clone()),
-                        tool_call_id: None,
-                        name: None,
+                        role: "tool".to_string(),
+                        content: Some(MessageContent::Text(result)),
+                        tool_calls: None,
+                        tool_call_id: Some(tc.id.clone()),
+                        name: Some(tc.function.name.clone()),
                     });
 
-                    // Execute each tool call
-                    for tc in tool_calls {
-                        let result = tools::execute(
-                            pool,
-                            bot,
-                            rag,
-                            mcp,
-                            &tc.function.name,
-                            &tc.function.arguments,
-                            chat_id,
-                            user_id,
-                        )
-                        .await?;
-
-                        // Add tool result message
-                        let args_signature = tc.function.arguments.trim().to_string();
-                        let error = parse_tool_error_message(&result);
+                    if let Some(error_msg) = error {
+                        let signature = format!("{}|{}", tc.function.name, args_signature);
+                        if Some(&signature) == last_tool_error_signature.as_ref() {
+                            repeated_tool_error_count = repeated_tool_error_count.saturating_add(1);
+                        } else {
+                            repeated_tool_error_count = 1;
+                            last_tool_error_signature = Some(signature);
+                        }
 
                         current_messages.push(ChatMessage {
-                            role: "tool".to_string(),
-                            content: Some(MessageContent::Text(result)),
-                            tool_calls: None,
-                            tool_call_id: Some(tc.id.clone()),
-                            name: Some(tc.function.name.clone()),
-                        });
-
-                        if let Some(error_msg) = error {
-                            let signature = format!("{}|{}", tc.function.name, args_signature);
-                            if Some(&signature) == last_tool_error_signature.as_ref() {
-                                repeated_tool_error_count =
-                                    repeated_tool_error_count.saturating_add(1);
-                            } else {
-                                repeated_tool_error_count = 1;
-                                last_tool_error_signature = Some(signature);
-                            }
-
-                            current_messages.push(ChatMessage {
                                 role: "system".to_string(),
                                 content: Some(MessageContent::Text(format!(
                                     "Tool '{}' returned an error: {}. If you already know the missing values from the conversation, retry the tool call immediately with the corrected arguments. Only ask the user if you truly do not know what values to use.",
@@ -155,22 +186,21 @@ impl LlmClient {
                                 name: None,
                             });
 
-                            if repeated_tool_error_count >= 2 {
-                                return Ok(format!(
-                                    "I couldn't apply that MCP change yet: {}. Please provide the missing field(s) and try again.",
-                                    error_msg
-                                ));
-                            }
-                            continue;
+                        if repeated_tool_error_count >= 2 {
+                            return Ok(format!(
+                                "I couldn't apply that MCP change yet: {}. Please provide the missing field(s) and try again.",
+                                error_msg
+                            ));
                         }
-
-                        // Reset repetition tracker on success
-                        repeated_tool_error_count = 0;
-                        last_tool_error_signature = None;
+                        continue;
                     }
 
-                    continue; // Next round
+                    // Reset repetition tracker on success
+                    repeated_tool_error_count = 0;
+                    last_tool_error_signature = None;
                 }
+
+                continue; // Next round
             }
 
             // No tool calls — return the text response
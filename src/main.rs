@@ -1,17 +1,27 @@
 mod backup;
 mod bot;
 mod config;
+mod confirm;
 mod db;
 mod llm;
 mod logging;
+mod markdown;
 mod mcp;
 mod memory;
+mod outbox;
+mod progress;
 mod rag;
+mod rag_eval;
+mod rag_queue;
+mod rag_rerank;
+mod secrets;
 mod tools;
+mod turns;
 mod types;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use std::io::Write;
 
 const DB_PATH: &str = "sqlite:astartebot.db";
 const LOG_DIR: &str = "logs";
@@ -64,6 +74,16 @@ enum Commands {
         #[command(subcommand)]
         action: RagAction,
     },
+    /// Manage the experts offered by the `expert` tool
+    Expert {
+        #[command(subcommand)]
+        action: ExpertAction,
+    },
+    /// Manage encrypted secrets tools reference as {{secret:NAME}}
+    Secret {
+        #[command(subcommand)]
+        action: SecretAction,
+    },
 }
 
 #[derive(Subcommand)]
@@ -96,12 +116,126 @@ enum TriggerAction {
 
 #[derive(Subcommand)]
 enum RagAction {
-    /// Rebuild the RAG index from all existing SQLite data
-    Reindex,
+    /// Index SQLite records added since the last reindex
+    Reindex {
+        /// Rebuild from scratch in a shadow index, swapped in when complete
+        #[arg(long)]
+        full: bool,
+        /// Only these source types (repeatable)
+        #[arg(long, value_parser = ["conversation", "note", "memory"])]
+        source: Vec<String>,
+    },
     /// Show RAG engine statistics
     Stats,
     /// Test embedding quality with diagnostic pairs
     Test,
+    /// Measure retrieval quality on a JSONL file of labelled queries
+    Eval {
+        /// One `{"query": .., "expected": ["note:12", ..]}` object per line
+        file: std::path::PathBuf,
+        /// Number of results scored per query
+        #[arg(long, default_value_t = 10)]
+        k: usize,
+        /// MMR diversity used by the `+mmr` variants
+        #[arg(long, default_value_t = 0.3)]
+        diversity: f32,
+        /// Only run these variants: dense, dense+mmr, rerank, rerank+mmr
+        #[arg(long)]
+        variant: Vec<String>,
+        /// Write the report as JSON, to use as a later baseline
+        #[arg(long)]
+        save: Option<std::path::PathBuf>,
+        /// Fail if any metric is worse than in this saved report
+        #[arg(long)]
+        baseline: Option<std::path::PathBuf>,
+        /// Largest drop against the baseline that still passes
+        #[arg(long, default_value_t = 0.02)]
+        tolerance: f64,
+        /// Fail if mean recall@k of any variant is below this
+        #[arg(long)]
+        min_recall: Option<f64>,
+        /// Fail if MRR of any variant is below this
+        #[arg(long)]
+        min_mrr: Option<f64>,
+        /// Fail if mean nDCG@k of any variant is below this
+        #[arg(long)]
+        min_ndcg: Option<f64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExpertAction {
+    /// Add an expert
+    Add {
+        name: String,
+        /// OpenRouter model ID, e.g. anthropic/claude-opus-4.6
+        model: String,
+        /// What the expert is good at (shown to the LLM)
+        #[arg(long, default_value = "")]
+        description: String,
+        /// System prompt sent before the question
+        #[arg(long, default_value = "")]
+        system_prompt: String,
+        /// Upper bound on the answer's token budget
+        #[arg(long, default_value_t = 2048)]
+        max_tokens: i64,
+        /// Expert type reported to the LLM, e.g. generic or internet_search
+        #[arg(long, default_value = "generic")]
+        kind: String,
+        /// Comma-separated chat IDs the expert may be used in (default: all chats)
+        #[arg(long, default_value = "", allow_hyphen_values = true)]
+        allowed_chats: String,
+    },
+    /// Change fields of an existing expert
+    Update {
+        name: String,
+        #[arg(long)]
+        rename: Option<String>,
+        #[arg(long)]
+        model: Option<String>,
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long)]
+        system_prompt: Option<String>,
+        #[arg(long)]
+        max_tokens: Option<i64>,
+        #[arg(long)]
+        kind: Option<String>,
+        /// Comma-separated chat IDs; pass "" to allow all chats
+        #[arg(long, allow_hyphen_values = true)]
+        allowed_chats: Option<String>,
+        #[arg(long, conflicts_with = "disable")]
+        enable: bool,
+        #[arg(long)]
+        disable: bool,
+    },
+    /// Remove an expert
+    Remove { name: String },
+    /// List all experts
+    List,
+}
+
+#[derive(Subcommand)]
+enum SecretAction {
+    /// Store a secret; the value is read from stdin when omitted
+    Set { name: String, value: Option<String> },
+    /// Set the comma-separated hosts a secret may be sent to in HTTP headers
+    /// (empty for none)
+    Hosts { name: String, hosts: String },
+    /// Set the comma-separated MCP servers a secret may be sent to in tool
+    /// arguments (empty for none)
+    Mcp { name: String, servers: String },
+    /// Set the comma-separated chat ids whose non-admin users may use a
+    /// secret (empty for admins only)
+    Share {
+        name: String,
+        #[arg(allow_hyphen_values = true)]
+        chats: String,
+    },
+    /// Remove a secret
+    Remove { name: String },
+    /// List secret names (values are never shown)
+    List,
 }
 
 #[tokio::main]
@@ -178,18 +312,52 @@ async fn main() -> Result<()> {
         }
         Commands::Rag { action } => {
             let pool = db::create_pool(DB_PATH).await?;
-            let rag_engine = rag::RagEngine::init(&std::path::PathBuf::from("rag_data")).await?;
+            let rag_model = rag::EmbeddingModelConfig::load(&pool).await?;
+            // Only evaluation compares reranked results
+            let reranker = match action {
+                RagAction::Eval { .. } => rag_rerank::Reranker::load(&pool).await?,
+                _ => None,
+            };
+            let rag_engine =
+                rag::RagEngine::init(&std::path::PathBuf::from("rag_data"), rag_model, reranker)
+                    .await?;
+            if rag_engine.model_changed() && !matches!(action, RagAction::Reindex { .. }) {
+                println!(
+                    "Warning: the embedding model changed since the index was built; run `astartebot rag reindex --full`.\n"
+                );
+            }
             match action {
-                RagAction::Reindex => {
-                    let count = rag_engine.reindex_all(&pool).await?;
-                    println!("Reindexed {} records into RAG engine", count);
+                RagAction::Reindex { full, source } => {
+                    let sources: Vec<&str> = if source.is_empty() {
+                        rag::SOURCE_TYPES.to_vec()
+                    } else {
+                        source.iter().map(String::as_str).collect()
+                    };
+                    let full = *full || rag_engine.model_changed();
+                    let count = rag_engine
+                        .sync(&pool, &sources, full, &|p| {
+                            print!("\r  {:<12} {}/{}", p.source, p.done, p.total);
+                            if p.done == p.total {
+                                println!();
+                            }
+                            let _ = std::io::stdout().flush();
+                        })
+                        .await?;
+                    println!("Indexed {} records into RAG engine", count);
                 }
                 RagAction::Stats => {
-                    let (vector_count, metadata_count, next_id) = rag_engine.stats();
+                    let stats = rag_engine.stats()?;
                     println!("RAG Engine Statistics:");
-                    println!("  Vectors in index:  {}", vector_count);
-                    println!("  Metadata entries:  {}", metadata_count);
-                    println!("  Next vector ID:    {}", next_id);
+                    println!("  Vectors in index:  {}", stats.vectors);
+                    println!("  Metadata entries:  {}", stats.metadata_entries);
+                    println!("  Next vector ID:    {}", stats.next_vector_id);
+                    println!("  Index generation:  {}", stats.generation);
+                    if let Some(building) = stats.building_generation {
+                        println!(
+                            "  Interrupted rebuild: generation {} (resumed by `rag reindex`)",
+                            building
+                        );
+                    }
                 }
                 RagAction::Test => {
                     println!("=== RAG Embedding Diagnostic ===\n");
@@ -230,6 +398,65 @@ async fn main() -> Result<()> {
                         v.len()
                     );
                 }
+                RagAction::Eval {
+                    file,
+                    k,
+                    diversity,
+                    variant,
+                    save,
+                    baseline,
+                    tolerance,
+                    min_recall,
+                    min_mrr,
+                    min_ndcg,
+                } => {
+                    let cases = rag_eval::load_cases(file)?;
+                    let mut variants = rag_eval::variants(&rag_engine, *diversity);
+                    if !variant.is_empty() {
+                        for name in variant {
+                            if !variants.iter().any(|v| v.name == name) {
+                                anyhow::bail!(
+                                    "Unknown or unavailable variant '{}' (rerank variants need rag_rerank_model_id)",
+                                    name
+                                );
+                            }
+                        }
+                        variants.retain(|v| variant.iter().any(|name| name == v.name));
+                    }
+
+                    let report = rag_eval::run(&rag_engine, &cases, *k, &variants)?;
+                    report.print();
+                    if let Some(path) = save {
+                        report.save(path)?;
+                        println!("\nReport saved to {}", path.display());
+                    }
+
+                    let baseline = baseline
+                        .as_deref()
+                        .map(rag_eval::EvalReport::load)
+                        .transpose()?;
+                    if let Some(baseline) = &baseline
+                        && baseline.model != report.model
+                    {
+                        println!(
+                            "\nNote: baseline used a different model ({})",
+                            baseline.model
+                        );
+                    }
+                    let thresholds = rag_eval::Thresholds {
+                        min_recall: *min_recall,
+                        min_mrr: *min_mrr,
+                        min_ndcg: *min_ndcg,
+                        tolerance: *tolerance,
+                    };
+                    let failures = report.regressions(&thresholds, baseline.as_ref());
+                    if !failures.is_empty() {
+                        for failure in &failures {
+                            println!("FAIL: {}", failure);
+                        }
+                        anyhow::bail!("RAG evaluation failed {} check(s)", failures.len());
+                    }
+                }
             }
         }
         Commands::Trigger { action } => {
@@ -264,6 +491,191 @@ async fn main() -> Result<()> {
                 }
             }
         }
+        Commands::Expert { action } => {
+            let pool = db::create_pool(DB_PATH).await?;
+            match action {
+                ExpertAction::Add {
+                    name,
+                    model,
+                    description,
+                    system_prompt,
+                    max_tokens,
+                    kind,
+                    allowed_chats,
+                } => {
+                    let expert = types::ExpertRow {
+                        name: name.clone(),
+                        description: description.clone(),
+                        model: model.clone(),
+                        system_prompt: system_prompt.clone(),
+                        max_tokens: *max_tokens,
+                        kind: kind.clone(),
+                        allowed_chats: allowed_chats.clone(),
+                        enabled: true,
+                        ..Default::default()
+                    };
+                    db::expert_create(&pool, &expert).await?;
+                    println!("Added expert: {} ({})", name, model);
+                }
+                ExpertAction::Update {
+                    name,
+                    rename,
+                    model,
+                    description,
+                    system_prompt,
+                    max_tokens,
+                    kind,
+                    allowed_chats,
+                    enable,
+                    disable,
+                } => {
+                    let Some(mut expert) = db::expert_get(&pool, name).await? else {
+                        println!("Expert '{}' not found", name);
+                        return Ok(());
+                    };
+                    if let Some(v) = rename {
+                        expert.name = v.clone();
+                    }
+                    if let Some(v) = model {
+                        expert.model = v.clone();
+                    }
+                    if let Some(v) = description {
+                        expert.description = v.clone();
+                    }
+                    if let Some(v) = system_prompt {
+                        expert.system_prompt = v.clone();
+                    }
+                    if let Some(v) = max_tokens {
+                        expert.max_tokens = *v;
+                    }
+                    if let Some(v) = kind {
+                        expert.kind = v.clone();
+                    }
+                    if let Some(v) = allowed_chats {
+                        expert.allowed_chats = v.clone();
+                    }
+                    if *enable {
+                        expert.enabled = true;
+                    }
+                    if *disable {
+                        expert.enabled = false;
+                    }
+                    db::expert_update(&pool, name, &expert).await?;
+                    println!("Updated expert: {}", expert.name);
+                }
+                ExpertAction::Remove { name } => {
+                    if db::expert_delete(&pool, name).await? {
+                        println!("Removed expert: {}", name);
+                    } else {
+                        println!("Expert '{}' not found", name);
+                    }
+                }
+                ExpertAction::List => {
+                    let experts = db::expert_list(&pool, true).await?;
+                    if experts.is_empty() {
+                        println!("No experts configured. The expert tool is hidden from the LLM.");
+                    } else {
+                        println!("Experts ({}):", experts.len());
+                        for e in &experts {
+                            let chats = if e.allowed_chats.trim().is_empty() {
+                                "all chats"
+                            } else {
+                                e.allowed_chats.as_str()
+                            };
+                            println!(
+                                "  {}{} — {} [{}], max_tokens={}, {}",
+                                e.name,
+                                if e.enabled { "" } else { " (disabled)" },
+                                e.model,
+                                e.kind,
+                                e.max_tokens,
+                                chats
+                            );
+                            if !e.description.is_empty() {
+                                println!("      {}", e.description);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Commands::Secret { action } => {
+            let pool = db::create_pool(DB_PATH).await?;
+            match action {
+                SecretAction::Set { name, value } => {
+                    let value = match value {
+                        Some(v) => v.clone(),
+                        None => {
+                            eprint!("Value for '{}': ", name);
+                            let mut line = String::new();
+                            std::io::stdin().read_line(&mut line)?;
+                            line.trim_end_matches(['\r', '\n']).to_string()
+                        }
+                    };
+                    secrets::set(&pool, name, &value, None).await?;
+                    println!(
+                        "Stored secret '{}'; reference it as {{{{secret:{}}}}}",
+                        name, name
+                    );
+                }
+                SecretAction::Hosts { name, hosts } => {
+                    if secrets::set_hosts(&pool, name, hosts).await? {
+                        println!("Secret '{}' may be sent to: {}", name, hosts);
+                    } else {
+                        println!("Secret '{}' not found", name);
+                    }
+                }
+                SecretAction::Mcp { name, servers } => {
+                    if secrets::set_mcp_servers(&pool, name, servers).await? {
+                        println!("Secret '{}' may be sent to MCP servers: {}", name, servers);
+                    } else {
+                        println!("Secret '{}' not found", name);
+                    }
+                }
+                SecretAction::Share { name, chats } => {
+                    if secrets::set_shared_chats(&pool, name, chats).await? {
+                        println!("Secret '{}' shared with chats: {}", name, chats);
+                    } else {
+                        println!("Secret '{}' not found", name);
+                    }
+                }
+                SecretAction::Remove { name } => {
+                    if secrets::delete(&pool, name).await? {
+                        println!("Removed secret: {}", name);
+                    } else {
+                        println!("Secret '{}' not found", name);
+                    }
+                }
+                SecretAction::List => {
+                    let items = secrets::list(&pool).await?;
+                    if items.is_empty() {
+                        println!("No secrets stored.");
+                    } else {
+                        for item in items {
+                            let hosts = if item.allowed_hosts.is_empty() {
+                                "none"
+                            } else {
+                                item.allowed_hosts.as_str()
+                            };
+                            let servers = if item.mcp_servers.is_empty() {
+                                "none"
+                            } else {
+                                item.mcp_servers.as_str()
+                            };
+                            let shared = if item.shared_chats.is_empty() {
+                                "admins only"
+                            } else {
+                                item.shared_chats.as_str()
+                            };
+                            println!(
+                                "  {} (hosts: {}; MCP servers: {}; shared with: {}; updated {})",
+                                item.name, hosts, servers, shared, item.updated_at
+                            );
+                        }
+                    }
+                }
+            }
+        }
     }
 
     Ok(())
@@ -0,0 +1,621 @@
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+/// One Telegram message: the HTML to send and its plain text, used when
+/// Telegram rejects the HTML
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub html: String,
+    pub text: String,
+}
+
+/// Convert Markdown as LLMs write it into Telegram HTML messages of at most
+/// `max_len` visible characters (UTF-16 units, as Telegram counts them).
+///
+/// Messages are split between top-level blocks where possible; a block that
+/// is too long on its own is split at a newline or space, closing the open
+/// tags at the end of one message and reopening them in the next.
+pub fn to_telegram_html(markdown: &str, max_len: usize) -> Vec<Chunk> {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut renderer = Renderer::new(max_len.max(16));
+    for event in Parser::new_ext(markdown, options) {
+        renderer.event(event);
+    }
+    renderer.end_piece();
+    pack(renderer.pieces, max_len.max(16))
+}
+
+/// Join pieces into as few messages as fit, separated by blank lines
+fn pack(pieces: Vec<Piece>, max_len: usize) -> Vec<Chunk> {
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut current_len = 0;
+    for piece in pieces {
+        if piece.text.trim().is_empty() {
+            continue;
+        }
+        match chunks.last_mut() {
+            Some(chunk) if current_len + 2 + piece.len <= max_len => {
+                chunk.html.push_str("\n\n");
+                chunk.html.push_str(&piece.html);
+                chunk.text.push_str("\n\n");
+                chunk.text.push_str(&piece.text);
+                current_len += 2 + piece.len;
+            }
+            _ => {
+                current_len = piece.len;
+                chunks.push(Chunk {
+                    html: piece.html,
+                    text: piece.text,
+                });
+            }
+        }
+    }
+    chunks
+}
+
+/// A self-contained run of HTML: every tag it opens is closed
+struct Piece {
+    html: String,
+    text: String,
+    len: usize,
+}
+
+/// An open HTML tag, reopened when a block is split across messages
+struct OpenTag {
+    open: String,
+    close: &'static str,
+}
+
+struct List {
+    next: Option<u64>,
+}
+
+struct Table {
+    rows: Vec<Vec<String>>,
+    cell: Option<String>,
+}
+
+struct Renderer {
+    max_len: usize,
+    pieces: Vec<Piece>,
+    html: String,
+    text: String,
+    len: usize,
+    open: Vec<OpenTag>,
+    /// Newlines owed before the next visible text
+    pending_breaks: usize,
+    /// Nesting of block elements; a piece ends when it drops back to 0
+    depth: usize,
+    lists: Vec<List>,
+    quotes: usize,
+    table: Option<Table>,
+    /// Where each open link or image started, and its URL (empty when not linked)
+    links: Vec<(usize, String)>,
+    /// Visible units written so far, across pieces
+    written: usize,
+}
+
+impl Renderer {
+    fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            pieces: Vec::new(),
+            html: String::new(),
+            text: String::new(),
+            len: 0,
+            open: Vec::new(),
+            pending_breaks: 0,
+            depth: 0,
+            lists: Vec::new(),
+            quotes: 0,
+            table: None,
+            links: Vec::new(),
+            written: 0,
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        if let Some(table) = self.table.as_mut() {
+            match event {
+                Event::Text(t) | Event::Code(t) | Event::InlineHtml(t) | Event::Html(t) => {
+                    if let Some(cell) = table.cell.as_mut() {
+                        cell.push_str(&t);
+                    }
+                }
+                Event::Start(Tag::TableRow) | Event::Start(Tag::TableHead) => {
+                    table.rows.push(Vec::new());
+                }
+                Event::Start(Tag::TableCell) => table.cell = Some(String::new()),
+                Event::End(TagEnd::TableCell) => {
+                    let cell = table.cell.take().unwrap_or_default();
+                    if let Some(row) = table.rows.last_mut() {
+                        row.push(cell.trim().to_string());
+                    }
+                }
+                Event::End(TagEnd::Table) => {
+                    let rows = self.table.take().map(|t| t.rows).unwrap_or_default();
+                    self.write_table(&rows);
+                    self.end_block();
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(t) => self.write(&t),
+            Event::Code(t) => {
+                self.open_tag("<code>".to_string(), "</code>");
+                self.write(&t);
+                self.close_tag();
+            }
+            Event::Html(t) | Event::InlineHtml(t) => {
+                let tag = t.trim().to_ascii_lowercase();
+                if matches!(tag.as_str(), "<br>" | "<br/>" | "<br />") {
+                    self.write("\n");
+                } else {
+                    self.write(&t);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => self.write("\n"),
+            Event::Rule => {
+                self.depth += 1;
+                self.write("──────────");
+                self.end_block();
+            }
+            Event::TaskListMarker(done) => self.write(if done { "☑ " } else { "☐ " }),
+            Event::FootnoteReference(name) => self.write(&format!("[{}]", name)),
+            Event::InlineMath(t) | Event::DisplayMath(t) => self.write(&t),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::HtmlBlock => self.depth += 1,
+            Tag::Heading { .. } => {
+                self.depth += 1;
+                self.open_tag("<b>".to_string(), "</b>");
+            }
+            Tag::BlockQuote(_) => {
+                self.depth += 1;
+                // Telegram does not nest blockquotes
+                if self.quotes == 0 {
+                    self.open_tag("<blockquote>".to_string(), "</blockquote>");
+                }
+                self.quotes += 1;
+            }
+            Tag::CodeBlock(kind) => {
+                self.depth += 1;
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        info.split_whitespace().next().unwrap_or("").to_string()
+                    }
+                    CodeBlockKind::Indented => String::new(),
+                };
+                if language.is_empty() {
+                    self.open_tag("<pre>".to_string(), "</pre>");
+                } else {
+                    self.open_tag(
+                        format!("<pre><code class=\"language-{}\">", escape(&language)),
+                        "</code></pre>",
+                    );
+                }
+            }
+            Tag::List(start) => {
+                self.depth += 1;
+                if !self.lists.is_empty() {
+                    self.pending_breaks = self.pending_breaks.max(1);
+                }
+                self.lists.push(List { next: start });
+            }
+            Tag::Item => {
+                self.depth += 1;
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                let marker = match self.lists.last_mut() {
+                    Some(List { next: Some(n) }) => {
+                        *n += 1;
+                        format!("{}{}. ", indent, *n - 1)
+                    }
+                    _ => format!("{}• ", indent),
+                };
+                self.write(&marker);
+            }
+            Tag::Table(_) => {
+                self.depth += 1;
+                self.table = Some(Table {
+                    rows: Vec::new(),
+                    cell: None,
+                });
+            }
+            Tag::Emphasis => self.open_tag("<i>".to_string(), "</i>"),
+            Tag::Strong => self.open_tag("<b>".to_string(), "</b>"),
+            Tag::Strikethrough => self.open_tag("<s>".to_string(), "</s>"),
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                let url = dest_url.to_string();
+                if is_linkable(&url) {
+                    self.open_tag(format!("<a href=\"{}\">", escape(&url)), "</a>");
+                    self.links.push((self.written, url));
+                } else {
+                    self.links.push((self.written, String::new()));
+                }
+            }
+            _ => self.depth += 1,
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::HtmlBlock => self.end_block(),
+            TagEnd::Heading(_) => {
+                self.close_tag();
+                self.end_block();
+            }
+            TagEnd::BlockQuote(_) => {
+                self.quotes -= 1;
+                if self.quotes == 0 {
+                    self.close_tag();
+                }
+                self.end_block();
+            }
+            TagEnd::CodeBlock => {
+                // Fenced code ends with a newline that Telegram would show
+                if self.html.ends_with('\n') {
+                    self.html.pop();
+                    self.text.pop();
+                    self.len -= 1;
+                }
+                self.close_tag();
+                self.end_block();
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+                self.end_block();
+            }
+            TagEnd::Item => self.end_block(),
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => self.close_tag(),
+            TagEnd::Link | TagEnd::Image => {
+                if let Some((start, url)) = self.links.pop()
+                    && !url.is_empty()
+                {
+                    if self.written == start {
+                        self.write(&url);
+                    }
+                    self.close_tag();
+                }
+            }
+            _ => self.end_block(),
+        }
+    }
+
+    /// Close a block element; the piece ends with the last top-level block
+    fn end_block(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth == 0 {
+            self.end_piece();
+        } else {
+            // List items sit on consecutive lines, other blocks get a blank line
+            let breaks = if self.lists.is_empty() { 2 } else { 1 };
+            self.pending_breaks = self.pending_breaks.max(breaks);
+        }
+    }
+
+    fn open_tag(&mut self, open: String, close: &'static str) {
+        self.flush_breaks();
+        self.html.push_str(&open);
+        self.open.push(OpenTag { open, close });
+    }
+
+    fn close_tag(&mut self) {
+        if let Some(tag) = self.open.pop() {
+            self.html.push_str(tag.close);
+        }
+    }
+
+    fn flush_breaks(&mut self) {
+        if self.len > 0 {
+            for _ in 0..self.pending_breaks {
+                self.push_text("\n");
+            }
+        }
+        self.pending_breaks = 0;
+    }
+
+    /// Write visible text, starting a new piece whenever it would overflow
+    fn write(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.flush_breaks();
+        let mut rest = text;
+        while !rest.is_empty() {
+            let budget = self.max_len.saturating_sub(self.len);
+            let fit = prefix_fitting(rest, budget);
+            if fit == rest.len() {
+                self.push_text(rest);
+                return;
+            }
+            // Prefer a line break, then a space, inside what fits
+            let head = &rest[..fit];
+            match head.rfind('\n').or_else(|| head.rfind(' ')) {
+                Some(at) if at > 0 => {
+                    self.push_text(&rest[..at]);
+                    rest = &rest[at + 1..];
+                }
+                _ if self.len > 0 => {}
+                _ => {
+                    let at = if fit == 0 {
+                        rest.chars().next().map_or(rest.len(), char::len_utf8)
+                    } else {
+                        fit
+                    };
+                    self.push_text(&rest[..at]);
+                    rest = &rest[at..];
+                }
+            }
+            if !rest.is_empty() {
+                self.split_piece();
+            }
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        let units = text.encode_utf16().count();
+        self.html.push_str(&escape(text));
+        self.text.push_str(text);
+        self.len += units;
+        self.written += units;
+    }
+
+    /// End the piece mid-block, carrying the open tags over to the next one
+    fn split_piece(&mut self) {
+        for tag in self.open.iter().rev() {
+            self.html.push_str(tag.close);
+        }
+        self.push_piece();
+        for tag in &self.open {
+            self.html.push_str(&tag.open);
+        }
+    }
+
+    fn end_piece(&mut self) {
+        for tag in self.open.drain(..).rev() {
+            self.html.push_str(tag.close);
+        }
+        self.push_piece();
+        self.pending_breaks = 0;
+    }
+
+    fn push_piece(&mut self) {
+        let html = std::mem::take(&mut self.html);
+        let text = std::mem::take(&mut self.text);
+        if !text.is_empty() {
+            self.pieces.push(Piece {
+                html,
+                text,
+                len: self.len,
+            });
+        }
+        self.len = 0;
+    }
+
+    /// Tables become preformatted text with aligned columns
+    fn write_table(&mut self, rows: &[Vec<String>]) {
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut widths = vec![0; columns];
+        for row in rows {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+
+        let mut lines = Vec::new();
+        for (r, row) in rows.iter().enumerate() {
+            let cells: Vec<String> = (0..columns)
+                .map(|i| {
+                    let cell = row.get(i).map(String::as_str).unwrap_or("");
+                    let pad = widths[i] - cell.chars().count();
+                    format!("{}{}", cell, " ".repeat(pad))
+                })
+                .collect();
+            lines.push(cells.join(" | ").trim_end().to_string());
+            if r == 0 && rows.len() > 1 {
+                let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+                lines.push(rule.join("-+-"));
+            }
+        }
+
+        self.open_tag("<pre>".to_string(), "</pre>");
+        self.write(&lines.join("\n"));
+        self.close_tag();
+    }
+}
+
+/// Byte length of the longest prefix of `text` within `units` UTF-16 units
+fn prefix_fitting(text: &str, units: usize) -> usize {
+    let mut used = 0;
+    for (i, c) in text.char_indices() {
+        used += c.len_utf16();
+        if used > units {
+            return i;
+        }
+    }
+    text.len()
+}
+
+/// Telegram only accepts links with these schemes
+fn is_linkable(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    ["http://", "https://", "tg://", "mailto:"]
+        .iter()
+        .any(|scheme| lower.starts_with(scheme))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: usize = 4096;
+
+    fn html(markdown: &str) -> String {
+        let chunks = to_telegram_html(markdown, MAX);
+        assert_eq!(chunks.len(), 1, "{:?}", chunks);
+        chunks[0].html.clone()
+    }
+
+    /// Tags of `html` in order, as (closing, name)
+    fn tags(html: &str) -> Vec<(bool, String)> {
+        let mut tags = Vec::new();
+        let mut rest = html;
+        while let Some(start) = rest.find('<') {
+            let end = start + rest[start..].find('>').expect("unterminated tag");
+            let tag = &rest[start + 1..end];
+            let closing = tag.starts_with('/');
+            let name = tag
+                .trim_start_matches('/')
+                .split_whitespace()
+                .next()
+                .unwrap_or("")
+                .to_string();
+            tags.push((closing, name));
+            rest = &rest[end + 1..];
+        }
+        tags
+    }
+
+    /// Every chunk fits and closes, in order, each tag it opens
+    fn assert_well_formed(chunks: &[Chunk]) {
+        for chunk in chunks {
+            assert!(chunk.text.encode_utf16().count() <= MAX);
+            let mut stack = Vec::new();
+            for (closing, name) in tags(&chunk.html) {
+                if closing {
+                    assert_eq!(stack.pop(), Some(name), "{}", chunk.html);
+                } else {
+                    stack.push(name);
+                }
+            }
+            assert!(stack.is_empty(), "unclosed {:?} in {}", stack, chunk.html);
+        }
+    }
+
+    #[test]
+    fn converts_inline_formatting() {
+        assert_eq!(
+            html("**bold** and _italic_"),
+            "<b>bold</b> and <i>italic</i>"
+        );
+        assert_eq!(
+            html("~~gone~~ `x < y`"),
+            "<s>gone</s> <code>x &lt; y</code>"
+        );
+        assert_eq!(html("# Title"), "<b>Title</b>");
+    }
+
+    #[test]
+    fn nests_entities() {
+        assert_eq!(
+            html("**bold _both_ [link **strong**](https://example.com)**"),
+            "<b>bold <i>both</i> <a href=\"https://example.com\">link <b>strong</b></a></b>"
+        );
+        assert_eq!(
+            html("> quoted **bold**\n>\n> > inner"),
+            "<blockquote>quoted <b>bold</b>\n\ninner</blockquote>"
+        );
+    }
+
+    #[test]
+    fn escapes_text_and_attributes() {
+        assert_eq!(html("a < b & c > d"), "a &lt; b &amp; c &gt; d");
+        assert_eq!(
+            html("[q](https://example.com/?a=1&b=\"2\")"),
+            "<a href=\"https://example.com/?a=1&amp;b=&quot;2&quot;\">q</a>"
+        );
+    }
+
+    #[test]
+    fn drops_unsupported_link_schemes() {
+        assert_eq!(html("[click](javascript:alert(1))"), "click");
+        assert_eq!(
+            html("<https://example.com>"),
+            "<a href=\"https://example.com\">https://example.com</a>"
+        );
+    }
+
+    #[test]
+    fn keeps_code_block_language() {
+        assert_eq!(
+            html("```rust\nfn main() {}\n```"),
+            "<pre><code class=\"language-rust\">fn main() {}</code></pre>"
+        );
+    }
+
+    #[test]
+    fn splits_between_blocks() {
+        let paragraph = "word ".repeat(500);
+        let markdown = format!("{}\n\n{}\n\n{}", paragraph, paragraph, paragraph);
+        let chunks = to_telegram_html(&markdown, MAX);
+        // Two paragraphs do not fit together, so none is cut in half
+        assert_eq!(chunks.len(), 3);
+        assert_well_formed(&chunks);
+        for chunk in &chunks {
+            assert_eq!(chunk.text.trim(), paragraph.trim());
+        }
+    }
+
+    #[test]
+    fn splits_inside_pre() {
+        let code: String = (0..600).map(|i| format!("line {:04}\n", i)).collect();
+        let chunks = to_telegram_html(&format!("```\n{}```", code), MAX);
+        assert!(chunks.len() > 1);
+        assert_well_formed(&chunks);
+        for chunk in &chunks {
+            assert!(chunk.html.starts_with("<pre>"), "{}", chunk.html);
+            assert!(chunk.html.ends_with("</pre>"), "{}", chunk.html);
+        }
+        // Lines are split at newlines, never in the middle
+        let lines: usize = chunks.iter().map(|c| c.text.lines().count()).sum();
+        assert_eq!(lines, 600);
+    }
+
+    #[test]
+    fn splits_inside_link() {
+        let label = "click ".repeat(1000);
+        let chunks = to_telegram_html(&format!("**[{}](https://example.com)**", label), MAX);
+        assert!(chunks.len() > 1);
+        assert_well_formed(&chunks);
+        for chunk in &chunks {
+            assert!(
+                chunk
+                    .html
+                    .starts_with("<b><a href=\"https://example.com\">"),
+                "{}",
+                chunk.html
+            );
+            assert!(chunk.html.ends_with("</a></b>"), "{}", chunk.html);
+        }
+    }
+
+    #[test]
+    fn splits_unbroken_text_by_utf16_units() {
+        // Each emoji is two UTF-16 units
+        let chunks = to_telegram_html(&"😀".repeat(3000), MAX);
+        assert_eq!(chunks.len(), 2);
+        assert_well_formed(&chunks);
+        assert_eq!(chunks[0].text.encode_utf16().count(), MAX);
+    }
+
+    #[test]
+    fn plain_text_matches_html() {
+        let chunks = to_telegram_html("**a** & [b](https://example.com)", MAX);
+        assert_eq!(chunks[0].text, "a & b");
+    }
+}
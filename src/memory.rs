@@ -3,7 +3,8 @@ use sqlx::SqlitePool;
 
 use crate::db;
 
-/// Validate segment format: "chat:{id}", "person:{id}", "global", "bot"
+/// Validate segment format: "chat:{id}", "topic:{chat_id}:{thread_id}",
+/// "person:{id}", "global", "bot"
 pub fn validate_segment(segment: &str) -> Result<()> {
     if segment == "global" || segment == "bot" {
         return Ok(());
@@ -14,6 +15,18 @@ pub fn validate_segment(segment: &str) -> Result<()> {
             .map_err(|_| anyhow::anyhow!("Invalid chat segment ID: {}", id_str))?;
         return Ok(());
     }
+    if let Some(ids) = segment.strip_prefix("topic:") {
+        let valid = ids.split_once(':').is_some_and(|(chat, thread)| {
+            chat.parse::<i64>().is_ok() && thread.parse::<i32>().is_ok()
+        });
+        if !valid {
+            anyhow::bail!(
+                "Invalid topic segment '{}': expected topic:{{chat_id}}:{{thread_id}}",
+                segment
+            );
+        }
+        return Ok(());
+    }
     if let Some(id_str) = segment.strip_prefix("person:") {
         id_str
             .parse::<i64>()
@@ -21,7 +34,7 @@ pub fn validate_segment(segment: &str) -> Result<()> {
         return Ok(());
     }
     anyhow::bail!(
-        "Invalid segment '{}'. Must be 'global', 'bot', 'chat:{{id}}', or 'person:{{id}}'",
+        "Invalid segment '{}'. Must be 'global', 'bot', 'chat:{{id}}', 'topic:{{chat_id}}:{{thread_id}}', or 'person:{{id}}'",
         segment
     )
 }
@@ -0,0 +1,114 @@
+use anyhow::Result;
+use sqlx::SqlitePool;
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::{MessageId, ParseMode, ReplyParameters, ThreadId};
+use tokio::task::JoinHandle;
+
+use crate::db;
+use crate::markdown;
+use crate::types::OutboundMessage;
+
+const MAX_TELEGRAM_MSG_LEN: usize = 4096;
+
+/// How often scheduled messages are checked
+const POLL_INTERVAL: Duration = Duration::from_secs(20);
+const BATCH_SIZE: i64 = 20;
+
+/// Send a `send_message` message. Markdown is converted to Telegram HTML and
+/// may take several messages; the first one's ID is returned. When Telegram
+/// rejects the formatting the text is sent again as plain text; the flag says
+/// whether that happened.
+pub async fn deliver(bot: &Bot, message: &OutboundMessage) -> Result<(MessageId, bool)> {
+    let (chunks, parse_mode) = match message.parse_mode.as_deref() {
+        Some("html") => (
+            vec![(message.text.clone(), message.text.clone())],
+            Some(ParseMode::Html),
+        ),
+        Some("markdown") => (
+            markdown::to_telegram_html(&message.text, MAX_TELEGRAM_MSG_LEN)
+                .into_iter()
+                .map(|chunk| (chunk.html, chunk.text))
+                .collect(),
+            Some(ParseMode::Html),
+        ),
+        _ => (vec![(message.text.clone(), message.text.clone())], None),
+    };
+
+    let mut first = None;
+    let mut fell_back = false;
+    for (i, (formatted, plain)) in chunks.iter().enumerate() {
+        let request = |text: &str| {
+            let mut request = bot.send_message(ChatId(message.target_chat_id), text);
+            if i == 0
+                && let Some(reply_to) = message.reply_to_message_id
+            {
+                request = request.reply_parameters(
+                    ReplyParameters::new(MessageId(reply_to)).allow_sending_without_reply(),
+                );
+            }
+            if let Some(thread_id) = message.thread_id {
+                request = request.message_thread_id(ThreadId(MessageId(thread_id)));
+            }
+            if message.silent {
+                request = request.disable_notification(true);
+            }
+            request
+        };
+
+        let sent = match parse_mode {
+            None => request(plain).await?,
+            Some(parse_mode) => match request(formatted).parse_mode(parse_mode).await {
+                Ok(sent) => sent,
+                Err(e) if e.to_string().contains("can't parse entities") => {
+                    tracing::warn!(error = %e, "Formatted message rejected, sending as plain text");
+                    fell_back = true;
+                    request(plain).await?
+                }
+                Err(e) => return Err(e.into()),
+            },
+        };
+        first.get_or_insert(sent.id);
+    }
+    first
+        .map(|id| (id, fell_back))
+        .ok_or_else(|| anyhow::anyhow!("Nothing to send"))
+}
+
+/// Spawn a background task that sends scheduled messages when they are due.
+///
+/// Returns the join handle so the caller can abort it on shutdown if needed.
+pub fn start_scheduled_sender(bot: Bot, pool: SqlitePool) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            if let Err(e) = send_due(&bot, &pool).await {
+                tracing::error!(error = %e, "Failed to send scheduled messages");
+            }
+        }
+    })
+}
+
+async fn send_due(bot: &Bot, pool: &SqlitePool) -> Result<()> {
+    for message in db::outbound_due(pool, BATCH_SIZE).await? {
+        if !db::outbound_claim(pool, message.id).await? {
+            continue;
+        }
+        let (message_id, error) = match deliver(bot, &message).await {
+            Ok((id, _)) => (Some(id.0), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        // Telegram has the message already; failing here would send it again
+        if let Err(e) = db::outbound_finish(pool, message.id, message_id, error.as_deref()).await {
+            tracing::error!(id = message.id, error = %e, "Failed to record scheduled message");
+        }
+        tracing::info!(
+            id = message.id,
+            origin_chat_id = message.origin_chat_id,
+            target_chat_id = message.target_chat_id,
+            error = ?error,
+            "Scheduled message sent"
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::{ChatAction, MessageId, ReplyParameters, ThreadId};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Telegram shows a chat action for about 5 seconds
+const ACTION_INTERVAL: Duration = Duration::from_secs(4);
+/// Minimum time between edits of the status message
+const EDIT_INTERVAL: Duration = Duration::from_secs(2);
+
+struct Status {
+    action: ChatAction,
+    text: Option<String>,
+}
+
+/// Shows that a turn is being worked on: a chat action repeated until the
+/// turn ends and, when enabled, a status message naming the tools being
+/// called, deleted once the turn is over
+pub struct TurnProgress {
+    status: watch::Sender<Status>,
+    task: JoinHandle<()>,
+}
+
+impl TurnProgress {
+    pub fn start(
+        bot: Bot,
+        chat_id: ChatId,
+        thread_id: Option<ThreadId>,
+        reply_to: MessageId,
+        show_status: bool,
+    ) -> Self {
+        let (status, mut rx) = watch::channel(Status {
+            action: ChatAction::Typing,
+            text: None,
+        });
+
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(ACTION_INTERVAL);
+            let mut status_message: Option<MessageId> = None;
+            let mut shown: Option<String> = None;
+            let mut last_edit: Option<Instant> = None;
+
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        let action = rx.borrow().action;
+                        let mut request = bot.send_chat_action(chat_id, action);
+                        if let Some(thread_id) = thread_id {
+                            request = request.message_thread_id(thread_id);
+                        }
+                        if let Err(e) = request.await {
+                            tracing::debug!(error = %e, "Failed to send chat action");
+                        }
+                    }
+                    changed = rx.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                    }
+                }
+
+                let text = rx.borrow().text.clone();
+                let throttled = last_edit.is_some_and(|t| t.elapsed() < EDIT_INTERVAL);
+                if !show_status || text.is_none() || text == shown || throttled {
+                    continue;
+                }
+                let text = text.unwrap_or_default();
+                let result = match status_message {
+                    Some(id) => bot.edit_message_text(chat_id, id, &text).await.map(|_| ()),
+                    None => {
+                        let mut request = bot
+                            .send_message(chat_id, &text)
+                            .reply_parameters(
+                                ReplyParameters::new(reply_to).allow_sending_without_reply(),
+                            )
+                            .disable_notification(true);
+                        if let Some(thread_id) = thread_id {
+                            request = request.message_thread_id(thread_id);
+                        }
+                        request.await.map(|sent| status_message = Some(sent.id))
+                    }
+                };
+                if let Err(e) = result {
+                    tracing::debug!(error = %e, "Failed to update status message");
+                }
+                shown = Some(text);
+                last_edit = Some(Instant::now());
+            }
+
+            if let Some(id) = status_message
+                && let Err(e) = bot.delete_message(chat_id, id).await
+            {
+                tracing::debug!(error = %e, "Failed to delete status message");
+            }
+        });
+
+        Self { status, task }
+    }
+
+    /// Report the tools the model is about to call
+    pub fn tools(&self, names: &[&str]) {
+        let action = if names.contains(&"send_voice") {
+            ChatAction::UploadVoice
+        } else {
+            ChatAction::Typing
+        };
+        let mut labels: Vec<String> = Vec::new();
+        for name in names {
+            let label = tool_label(name);
+            if !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+        self.status.send_replace(Status {
+            action,
+            text: Some(labels.join(" ")),
+        });
+    }
+
+    /// Stop the chat action and delete the status message
+    pub async fn finish(self) {
+        drop(self.status);
+        if let Err(e) = self.task.await {
+            tracing::warn!(error = %e, "Progress task failed");
+        }
+    }
+}
+
+fn tool_label(name: &str) -> String {
+    let label = match name {
+        "search_history" | "browse_history" | "search_all_chats" => "Searching history",
+        "rag_search" => "Searching past conversations and notes",
+        "search_notes" | "read_note" => "Reading notes",
+        "unified_memory" | "set_important_memory" | "clear_important_memory" => "Checking memory",
+        "generic_http_request" => "Fetching a web page",
+        "run_python" => "Running Python",
+        "run_shell" => "Running a shell command",
+        "run_node" => "Running Node.js",
+        "run_sql" => "Querying the database",
+        "maigret_osint" => "Looking up usernames",
+        "send_voice" => "Recording a voice message",
+        "send_message" => "Sending a message",
+        "crud_file" => "Working with files",
+        "expert" => "Asking an expert",
+        "crud_mcp_server" | "mcp_list_tools" => "Checking MCP servers",
+        _ => return format!("Calling {}…", name),
+    };
+    format!("{}…", label)
+}
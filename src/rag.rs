@@ -1,31 +1,144 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::task::spawn_blocking;
 
-const EMBEDDING_DIM: usize = 384;
-const MAX_TOKENS: usize = 128; // paraphrase-multilingual-MiniLM-L12-v2 limit
-const SEP_TOKEN_ID: u32 = 102;
+use crate::config;
+use crate::rag_rerank::Reranker;
+
+const DEFAULT_MODEL_ID: &str = "sentence-transformers/paraphrase-multilingual-MiniLM-L12-v2";
+const DEFAULT_EMBEDDING_DIM: usize = 384;
+const DEFAULT_MAX_TOKENS: usize = 128; // paraphrase-multilingual-MiniLM-L12-v2 limit
+const SPECIAL_TOKENS: usize = 2; // [CLS] and [SEP] around every chunk
+const FINGERPRINT_KEY: &str = "__model_fingerprint__";
+const ACTIVE_GENERATION_KEY: &[u8] = b"__active_generation__";
+const BUILDING_GENERATION_KEY: &[u8] = b"__building_generation__";
+/// Key families stored per index generation
+const GENERATION_FAMILIES: [&str; 5] = ["vec:", "raw:", "dedup:", "watermark:", FINGERPRINT_KEY];
+
+/// Source record types kept in the index
+pub const SOURCE_TYPES: [&str; 3] = ["conversation", "note", "memory"];
+const CHUNK_OVERLAP_TOKENS: usize = 32;
+const MAX_CHUNKS_PER_RECORD: usize = 64;
+const EMBED_BATCH_SIZE: usize = 16;
+const REINDEX_BATCH_SIZE: usize = 64;
+const PENDING_PREFIX: &str = "pending:";
+const MIN_RERANK_CANDIDATES: usize = 30;
+
+/// How token embeddings are reduced to one vector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pooling {
+    /// Attention-masked mean of all tokens (sentence-transformers, e5, gte)
+    Mean,
+    /// First token (bge)
+    Cls,
+}
+
+/// Embedding model settings, read from the `rag_*` config keys
+#[derive(Debug, Clone)]
+pub struct EmbeddingModelConfig {
+    /// HuggingFace repo with `onnx/model.onnx` and `tokenizer.json`
+    pub model_id: String,
+    /// Local directory with `model.onnx` (or `onnx/model.onnx`) and
+    /// `tokenizer.json`; used instead of downloading `model_id`
+    pub model_path: Option<PathBuf>,
+    pub pooling: Pooling,
+    pub dim: usize,
+    pub max_tokens: usize,
+    /// Prepended to search queries, e.g. `query: ` for e5
+    pub query_prefix: String,
+    /// Prepended to indexed text, e.g. `passage: ` for e5
+    pub passage_prefix: String,
+}
+
+impl Default for EmbeddingModelConfig {
+    fn default() -> Self {
+        Self {
+            model_id: DEFAULT_MODEL_ID.to_string(),
+            model_path: None,
+            pooling: Pooling::Mean,
+            dim: DEFAULT_EMBEDDING_DIM,
+            max_tokens: DEFAULT_MAX_TOKENS,
+            query_prefix: String::new(),
+            passage_prefix: String::new(),
+        }
+    }
+}
+
+impl EmbeddingModelConfig {
+    pub async fn load(pool: &SqlitePool) -> Result<Self> {
+        let defaults = Self::default();
+        let model_path = config::get_or_default(pool, "rag_model_path", "").await?;
+        let pooling = match config::get_or_default(pool, "rag_pooling", "mean")
+            .await?
+            .trim()
+            .to_lowercase()
+            .as_str()
+        {
+            "mean" => Pooling::Mean,
+            "cls" => Pooling::Cls,
+            other => anyhow::bail!("Invalid rag_pooling '{}': expected mean or cls", other),
+        };
+
+        Ok(Self {
+            model_id: config::get_or_default(pool, "rag_model_id", DEFAULT_MODEL_ID).await?,
+            model_path: (!model_path.trim().is_empty()).then(|| PathBuf::from(model_path.trim())),
+            pooling,
+            dim: config::get(pool, "rag_dim")
+                .await?
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.dim),
+            max_tokens: config::get(pool, "rag_max_tokens")
+                .await?
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|&n: &usize| n > SPECIAL_TOKENS + CHUNK_OVERLAP_TOKENS)
+                .unwrap_or(defaults.max_tokens),
+            query_prefix: config::get_or_default(pool, "rag_query_prefix", "").await?,
+            passage_prefix: config::get_or_default(pool, "rag_passage_prefix", "").await?,
+        })
+    }
+
+    /// Identifies everything that changes stored vectors; vectors built under
+    /// a different fingerprint are not comparable
+    fn fingerprint(&self) -> String {
+        let source = match &self.model_path {
+            Some(path) => format!("path:{}", path.display()),
+            None => format!("hf:{}", self.model_id),
+        };
+        format!(
+            "{}|pooling={:?}|dim={}|max_tokens={}|passage_prefix={:?}",
+            source, self.pooling, self.dim, self.max_tokens, self.passage_prefix
+        )
+    }
+
+    fn chunk_tokens(&self) -> usize {
+        self.max_tokens - SPECIAL_TOKENS
+    }
+}
 
 /// Simple brute-force vector index (replaces FAISS FlatIndex with InnerProduct).
 /// For L2-normalized vectors, inner product == cosine similarity.
 struct FlatIndex {
-    vectors: Vec<f32>, // flat storage: vectors.len() == n * EMBEDDING_DIM
+    vectors: Vec<f32>, // flat storage: vectors.len() == n * dim
+    dim: usize,
     count: usize,
 }
 
 impl FlatIndex {
-    fn new() -> Self {
+    fn new(dim: usize) -> Self {
         Self {
             vectors: Vec::new(),
+            dim,
             count: 0,
         }
     }
 
     fn add(&mut self, vec: &[f32]) {
-        debug_assert_eq!(vec.len(), EMBEDDING_DIM);
+        debug_assert_eq!(vec.len(), self.dim);
         self.vectors.extend_from_slice(vec);
         self.count += 1;
     }
@@ -34,6 +147,10 @@ impl FlatIndex {
         self.count
     }
 
+    fn vector(&self, position: usize) -> &[f32] {
+        &self.vectors[position * self.dim..(position + 1) * self.dim]
+    }
+
     /// Search for top-k nearest neighbors by inner product (descending).
     /// Returns (positions, scores) sorted by score descending.
     fn search(&self, query: &[f32], k: usize) -> (Vec<usize>, Vec<f32>) {
@@ -44,8 +161,8 @@ impl FlatIndex {
         // Compute inner products with all vectors
         let mut scores: Vec<(usize, f32)> = (0..self.count)
             .map(|i| {
-                let start = i * EMBEDDING_DIM;
-                let end = start + EMBEDDING_DIM;
+                let start = i * self.dim;
+                let end = start + self.dim;
                 let dot: f32 = query
                     .iter()
                     .zip(&self.vectors[start..end])
@@ -78,49 +195,219 @@ pub struct RagMetadata {
     pub content_preview: String,
     pub user_name: String,
     pub created_at: String,
+    /// Position of this chunk within the record (0 for single-chunk records)
+    #[serde(default)]
+    pub chunk_index: usize,
+    #[serde(default = "default_chunk_count")]
+    pub chunk_count: usize,
+    /// Byte range of the chunk within the indexed text
+    #[serde(default)]
+    pub chunk_start: usize,
+    #[serde(default)]
+    pub chunk_end: usize,
+    /// Full text of the chunk; empty for records indexed before it was kept
+    #[serde(default)]
+    pub content: String,
 }
 
-pub struct RagResult {
-    pub score: f32,
-    pub metadata: RagMetadata,
+fn default_chunk_count() -> usize {
+    1
 }
 
-pub struct RagEngine {
-    model: candle_onnx::onnx::ModelProto,
-    tokenizer: tokenizers::Tokenizer,
-    index: Mutex<FlatIndex>,
-    position_to_id: Mutex<Vec<i64>>,
-    meta_db: rocksdb::DB,
-    next_vector_id: Mutex<i64>,
+impl RagMetadata {
+    /// Text of the chunk, or its preview for records indexed without it
+    pub fn text(&self) -> &str {
+        if self.content.is_empty() {
+            &self.content_preview
+        } else {
+            &self.content
+        }
+    }
 }
 
-// Safety: candle_onnx::onnx::ModelProto is read-only after init, tokenizers::Tokenizer::encode takes &self,
-// rocksdb::DB is internally thread-safe. All mutable state is behind Mutex.
-unsafe impl Send for RagEngine {}
-unsafe impl Sync for RagEngine {}
+/// Segment conversation messages are indexed under: the forum topic they
+/// were posted in, else their chat
+fn conversation_segment(chat_id: i64, thread_id: Option<i32>) -> String {
+    match thread_id {
+        Some(thread_id) => format!("topic:{}:{}", chat_id, thread_id),
+        None => format!("chat:{}", chat_id),
+    }
+}
 
-impl RagEngine {
-    pub async fn init(data_dir: &Path) -> Result<Self> {
-        let data_dir = data_dir.to_path_buf();
-        std::fs::create_dir_all(&data_dir).context("Failed to create RAG data directory")?;
+/// Records a search on behalf of one chat and user may return
+pub struct RagVisibility {
+    chat_id: i64,
+    /// Conversations are only visible in the chat or topic they happened in
+    conversation_segment: String,
+    segments: Vec<String>,
+}
 
-        // Download model files via hf-hub (cached after first download)
-        let (model_path, tokenizer_path) = {
-            spawn_blocking(move || -> Result<(PathBuf, PathBuf)> {
-                let api = hf_hub::api::sync::Api::new()
-                    .context("Failed to create HuggingFace API client")?;
-                let repo = api.model(
-                    "sentence-transformers/paraphrase-multilingual-MiniLM-L12-v2".to_string(),
-                );
-                let model_path = repo
-                    .get("onnx/model.onnx")
-                    .context("Failed to download model.onnx")?;
-                let tokenizer_path = repo
-                    .get("tokenizer.json")
-                    .context("Failed to download tokenizer.json")?;
-                Ok((model_path, tokenizer_path))
-            })
-            .await??
+impl RagVisibility {
+    /// Conversations of the current chat or forum topic, plus notes and
+    /// memory in the segments available there: global, bot, chat:{chat_id},
+    /// topic:{chat_id}:{thread_id} inside a forum topic, person:{user_id}
+    pub fn for_caller(chat_id: i64, thread_id: Option<i32>, user_id: i64) -> Self {
+        let mut segments = vec![
+            "global".to_string(),
+            "bot".to_string(),
+            format!("chat:{}", chat_id),
+            format!("person:{}", user_id),
+        ];
+        if let Some(thread_id) = thread_id {
+            segments.push(format!("topic:{}:{}", chat_id, thread_id));
+        }
+        Self {
+            chat_id,
+            conversation_segment: conversation_segment(chat_id, thread_id),
+            segments,
+        }
+    }
+
+    /// Whether notes and memory in `segment` are visible here
+    pub fn allows_segment(&self, segment: &str) -> bool {
+        self.segments.iter().any(|s| s == segment)
+    }
+
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    fn allows(&self, metadata: &RagMetadata) -> bool {
+        if metadata.source_type == "conversation" {
+            return metadata.chat_id == self.chat_id
+                && metadata.segment == self.conversation_segment;
+        }
+        self.allows_segment(&metadata.segment)
+    }
+}
+
+/// A source record to embed and store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexJob {
+    pub source_type: String,
+    pub source_id: i64,
+    pub chat_id: i64,
+    pub segment: String,
+    pub content: String,
+    pub user_name: String,
+    pub created_at: String,
+}
+
+impl IndexJob {
+    /// A conversation_history row of `chat_id` (and forum topic
+    /// `thread_id`), stamped with the current time
+    pub fn conversation(
+        row_id: i64,
+        chat_id: i64,
+        thread_id: Option<i32>,
+        content: &str,
+        user_name: &str,
+    ) -> Self {
+        Self {
+            source_type: "conversation".to_string(),
+            source_id: row_id,
+            chat_id,
+            segment: conversation_segment(chat_id, thread_id),
+            content: content.to_string(),
+            user_name: user_name.to_string(),
+            created_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        }
+    }
+
+    fn dedup_key(&self, generation: u64) -> String {
+        generation_key(
+            generation,
+            &format!("dedup:{}:{}", self.source_type, self.source_id),
+        )
+    }
+}
+
+/// Key of an entry in one index generation. Generation 0 is the unprefixed
+/// keyspace used before generations existed.
+fn generation_key(generation: u64, key: &str) -> String {
+    if generation == 0 {
+        key.to_string()
+    } else {
+        format!("g{}:{}", generation, key)
+    }
+}
+
+/// Position of the last row of a source type already synced into a generation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Watermark {
+    /// Empty for conversations, whose rows never change
+    updated_at: String,
+    id: i64,
+}
+
+/// Reported by `RagEngine::sync` after each batch
+pub struct SyncProgress<'a> {
+    pub source: &'a str,
+    pub done: usize,
+    pub total: usize,
+}
+
+pub struct RagStats {
+    pub vectors: usize,
+    pub metadata_entries: usize,
+    pub next_vector_id: i64,
+    pub generation: u64,
+    /// Generation of an interrupted full rebuild, resumed by the next one
+    pub building_generation: Option<u64>,
+}
+
+/// An ONNX transformer with its tokenizer, loaded from HuggingFace or a local directory
+pub(crate) struct OnnxModel {
+    model: candle_onnx::onnx::ModelProto,
+    pub(crate) tokenizer: tokenizers::Tokenizer,
+    /// Name of the ONNX output to read
+    output_name: String,
+    uses_token_type_ids: bool,
+    pad_id: u32,
+}
+
+// Safety: candle_onnx::onnx::ModelProto is read-only after init, tokenizers::Tokenizer::encode takes &self.
+unsafe impl Send for OnnxModel {}
+unsafe impl Sync for OnnxModel {}
+
+/// Token ids, attention mask and token type ids of one encoded input
+pub(crate) type EncodedInput = (Vec<u32>, Vec<u32>, Vec<u32>);
+
+impl OnnxModel {
+    /// Load `model.onnx` and `tokenizer.json` from `model_path` if set, else
+    /// download them from the `model_id` HuggingFace repo. Reads the output
+    /// named `preferred_output`, or the first one if there is none.
+    pub(crate) async fn load(
+        model_id: &str,
+        model_path: Option<&Path>,
+        preferred_output: &str,
+    ) -> Result<Self> {
+        let (model_path, tokenizer_path) = match model_path {
+            // Local model files for offline deployments
+            Some(dir) => {
+                let model_path = [dir.join("model.onnx"), dir.join("onnx/model.onnx")]
+                    .into_iter()
+                    .find(|p| p.is_file())
+                    .with_context(|| format!("No model.onnx found in {}", dir.display()))?;
+                (model_path, dir.join("tokenizer.json"))
+            }
+            // Download model files via hf-hub (cached after first download)
+            None => {
+                let model_id = model_id.to_string();
+                spawn_blocking(move || -> Result<(PathBuf, PathBuf)> {
+                    let api = hf_hub::api::sync::Api::new()
+                        .context("Failed to create HuggingFace API client")?;
+                    let repo = api.model(model_id);
+                    let model_path = repo
+                        .get("onnx/model.onnx")
+                        .context("Failed to download model.onnx")?;
+                    let tokenizer_path = repo
+                        .get("tokenizer.json")
+                        .context("Failed to download tokenizer.json")?;
+                    Ok((model_path, tokenizer_path))
+                })
+                .await??
+            }
         };
 
         tracing::info!(?model_path, ?tokenizer_path, "Model files ready");
@@ -134,11 +421,154 @@ impl RagEngine {
         // Load tokenizer
         let tp = tokenizer_path.clone();
         let tokenizer = spawn_blocking(move || {
-            tokenizers::Tokenizer::from_file(tp)
-                .map_err(|e| anyhow::anyhow!("Failed to load tokenizer: {}", e))
+            let mut tokenizer = tokenizers::Tokenizer::from_file(tp)
+                .map_err(|e| anyhow::anyhow!("Failed to load tokenizer: {}", e))?;
+            // Callers chunk or truncate long inputs themselves
+            tokenizer
+                .with_truncation(None)
+                .map_err(|e| anyhow::anyhow!("Failed to configure tokenizer: {}", e))?;
+            Ok::<_, anyhow::Error>(tokenizer)
         })
         .await??;
 
+        let graph = model.graph.as_ref().context("ONNX model has no graph")?;
+        let output_name = graph
+            .output
+            .iter()
+            .map(|o| o.name.clone())
+            .find(|name| name == preferred_output)
+            .or_else(|| graph.output.first().map(|o| o.name.clone()))
+            .context("ONNX model has no outputs")?;
+        let uses_token_type_ids = graph.input.iter().any(|i| i.name == "token_type_ids");
+        let pad_id = tokenizer
+            .get_padding()
+            .map(|p| p.pad_id)
+            .or_else(|| tokenizer.token_to_id("<pad>"))
+            .or_else(|| tokenizer.token_to_id("[PAD]"))
+            .unwrap_or(0);
+
+        Ok(Self {
+            model,
+            tokenizer,
+            output_name,
+            uses_token_type_ids,
+            pad_id,
+        })
+    }
+
+    /// Run one `[batch, seq_len]` forward pass over inputs padded to the
+    /// longest one. Returns the output tensor and the padded attention mask.
+    pub(crate) fn run(
+        &self,
+        encoded: &[EncodedInput],
+    ) -> Result<(candle_core::Tensor, candle_core::Tensor)> {
+        // Pad every sequence to the longest one; padding is masked out
+        let batch = encoded.len();
+        let seq_len = encoded
+            .iter()
+            .map(|(ids, _, _)| ids.len())
+            .max()
+            .unwrap_or(0);
+        let mut ids_flat = Vec::with_capacity(batch * seq_len);
+        let mut mask_flat = Vec::with_capacity(batch * seq_len);
+        let mut types_flat = Vec::with_capacity(batch * seq_len);
+        for (input_ids, attention_mask, token_type_ids) in encoded {
+            let pad = seq_len - input_ids.len();
+            ids_flat.extend(input_ids.iter().map(|&x| x as i64));
+            ids_flat.extend(std::iter::repeat_n(self.pad_id as i64, pad));
+            mask_flat.extend(attention_mask.iter().map(|&x| x as i64));
+            mask_flat.extend(std::iter::repeat_n(0i64, pad));
+            types_flat.extend(token_type_ids.iter().map(|&x| x as i64));
+            types_flat.extend(std::iter::repeat_n(0i64, pad));
+        }
+
+        let device = &candle_core::Device::Cpu;
+
+        // Create tensors with shape [batch, seq_len]
+        let ids_tensor = candle_core::Tensor::from_vec(ids_flat, (batch, seq_len), device)?;
+        let mask_tensor = candle_core::Tensor::from_vec(mask_flat, (batch, seq_len), device)?;
+        let type_tensor = candle_core::Tensor::from_vec(types_flat, (batch, seq_len), device)?;
+
+        // Build input map for ONNX model
+        let mut inputs = std::collections::HashMap::new();
+        inputs.insert("input_ids".to_string(), ids_tensor);
+        inputs.insert("attention_mask".to_string(), mask_tensor.clone());
+        if self.uses_token_type_ids {
+            inputs.insert("token_type_ids".to_string(), type_tensor);
+        }
+
+        // Run inference
+        let mut outputs = candle_onnx::simple_eval(&self.model, inputs)
+            .map_err(|e| anyhow::anyhow!("ONNX eval failed: {}", e))?;
+        let output = outputs
+            .remove(&self.output_name)
+            .ok_or_else(|| anyhow::anyhow!("No output from ONNX model"))?;
+
+        Ok((output, mask_tensor))
+    }
+}
+
+pub struct RagResult {
+    /// Final relevance: the cross-encoder score when reranked, else `similarity`
+    pub score: f32,
+    /// Cosine similarity between query and chunk embeddings
+    pub similarity: f32,
+    pub metadata: RagMetadata,
+}
+
+/// How `RagEngine::search` selects and orders results
+pub struct SearchOptions<'a> {
+    pub limit: usize,
+    pub source_type: Option<&'a str>,
+    /// Restrict results to one chat's view; None searches everything
+    pub visibility: Option<&'a RagVisibility>,
+    /// Rerank candidates with the cross-encoder, if one is configured
+    pub rerank: bool,
+    /// Drop candidates whose cosine similarity is below this
+    pub min_similarity: f32,
+    /// Drop reranked candidates whose cross-encoder score (a probability) is
+    /// below this; unused when results are not reranked
+    pub min_relevance: f32,
+    /// MMR trade-off: 0 ranks purely by relevance, 1 favours results unlike
+    /// those already picked
+    pub diversity: f32,
+}
+
+pub struct RagEngine {
+    embedder: OnnxModel,
+    reranker: Option<Reranker>,
+    model_config: EmbeddingModelConfig,
+    /// Stored vectors come from a different model; they are not loaded and
+    /// nothing new is indexed until a full `sync` rebuilds them
+    model_changed: AtomicBool,
+    /// Index generation that search and live indexing use
+    generation: Mutex<u64>,
+    index: Mutex<FlatIndex>,
+    position_to_id: Mutex<Vec<i64>>,
+    meta_db: rocksdb::DB,
+    next_vector_id: Mutex<i64>,
+}
+
+// Safety: rocksdb::DB is internally thread-safe. All mutable state is behind Mutex.
+unsafe impl Send for RagEngine {}
+unsafe impl Sync for RagEngine {}
+
+impl RagEngine {
+    pub async fn init(
+        data_dir: &Path,
+        model_config: EmbeddingModelConfig,
+        reranker: Option<Reranker>,
+    ) -> Result<Self> {
+        let data_dir = data_dir.to_path_buf();
+        std::fs::create_dir_all(&data_dir).context("Failed to create RAG data directory")?;
+
+        let embedder = OnnxModel::load(
+            &model_config.model_id,
+            model_config.model_path.as_deref(),
+            "last_hidden_state",
+        )
+        .await?;
+
         // Open RocksDB
         let rocks_path = data_dir.join("rocksdb");
         let mut opts = rocksdb::Options::default();
@@ -151,426 +581,1123 @@ impl RagEngine {
             _ => 0,
         };
 
+        let generation = match meta_db.get(ACTIVE_GENERATION_KEY)? {
+            Some(bytes) if bytes.len() == 8 => u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            _ => 0,
+        };
+        let fingerprint_key = generation_key(generation, FINGERPRINT_KEY);
+
+        // Vectors stored before fingerprints existed came from the default model
+        let fingerprint = model_config.fingerprint();
+        let stored_fingerprint = match meta_db.get(fingerprint_key.as_bytes())? {
+            Some(bytes) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+            None if next_id > 0 => Some(EmbeddingModelConfig::default().fingerprint()),
+            None => None,
+        };
+        let model_changed = stored_fingerprint
+            .as_ref()
+            .is_some_and(|stored| *stored != fingerprint);
+        if stored_fingerprint.is_none() {
+            meta_db.put(fingerprint_key.as_bytes(), fingerprint.as_bytes())?;
+        }
+
         let engine = Self {
-            model,
-            tokenizer,
-            index: Mutex::new(FlatIndex::new()),
+            index: Mutex::new(FlatIndex::new(model_config.dim)),
+            embedder,
+            reranker,
+            model_config,
+            model_changed: AtomicBool::new(model_changed),
+            generation: Mutex::new(generation),
             position_to_id: Mutex::new(Vec::new()),
             meta_db,
             next_vector_id: Mutex::new(next_id),
         };
 
+        if model_changed {
+            // Never mix vectors from different models in one index
+            tracing::warn!(
+                stored = stored_fingerprint.as_deref().unwrap_or(""),
+                current = %fingerprint,
+                "Embedding model changed; stored vectors ignored until reindex"
+            );
+            return Ok(engine);
+        }
+
         // Rebuild index from stored vectors
-        let count = engine.rebuild_index_from_rocksdb()?;
-        tracing::info!(count, next_id, "RAG engine initialized");
+        let (index, pos_map) = engine.load_generation(generation)?;
+        let count = index.ntotal();
+        *engine.index.lock().unwrap() = index;
+        *engine.position_to_id.lock().unwrap() = pos_map;
+        tracing::info!(count, next_id, generation, "RAG engine initialized");
 
         Ok(engine)
     }
 
+    /// Whether the configured model differs from the one the stored index was
+    /// built with; a full `sync` clears this
+    pub fn model_changed(&self) -> bool {
+        self.model_changed.load(Ordering::Relaxed)
+    }
+
+    /// Identifies the embedding model and settings behind the index
+    pub fn model_fingerprint(&self) -> String {
+        self.model_config.fingerprint()
+    }
+
+    pub fn has_reranker(&self) -> bool {
+        self.reranker.is_some()
+    }
+
+    /// Embed a search query, with the configured query prefix
+    pub fn embed_query(&self, query: &str) -> Result<Vec<f32>> {
+        self.embed_text(&format!("{}{}", self.model_config.query_prefix, query))
+    }
+
     pub fn embed_text(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed_batch(&[text])?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("No embedding produced"))
+    }
+
+    /// Embed several texts in one `[batch, seq_len]` forward pass
+    pub fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut encoded: Vec<EncodedInput> = Vec::with_capacity(texts.len());
+        for text in texts {
+            let encoding = self
+                .embedder
+                .tokenizer
+                .encode(*text, true)
+                .map_err(|e| anyhow::anyhow!("Tokenizer encode failed: {}", e))?;
+
+            let mut input_ids: Vec<u32> = encoding.get_ids().to_vec();
+            let mut attention_mask: Vec<u32> = encoding.get_attention_mask().to_vec();
+            let mut token_type_ids: Vec<u32> = encoding.get_type_ids().to_vec();
+
+            // Truncate to max_tokens
+            let max_tokens = self.model_config.max_tokens;
+            if input_ids.len() > max_tokens {
+                let sep_id = input_ids[input_ids.len() - 1];
+                input_ids.truncate(max_tokens);
+                attention_mask.truncate(max_tokens);
+                token_type_ids.truncate(max_tokens);
+                // Keep the closing [SEP] token
+                if let Some(last) = input_ids.last_mut() {
+                    *last = sep_id;
+                }
+            }
+            encoded.push((input_ids, attention_mask, token_type_ids));
+        }
+
+        // last_hidden_state [batch, seq_len, dim]
+        let (hidden, mask) = self.embedder.run(&encoded)?;
+
+        let pooled = match self.model_config.pooling {
+            // Mean pooling with attention mask
+            Pooling::Mean => {
+                let mask_f32 = mask.to_dtype(candle_core::DType::F32)?.unsqueeze(2)?; // [batch, seq_len, 1]
+                let masked = hidden.broadcast_mul(&mask_f32)?; // [batch, seq_len, dim]
+                let summed = masked.sum(1)?; // [batch, dim]
+                let mask_sum = mask_f32.sum(1)?; // [batch, 1]
+                summed.broadcast_div(&mask_sum)? // [batch, dim]
+            }
+            // Hidden state of the leading [CLS] token
+            Pooling::Cls => hidden.narrow(1, 0, 1)?.squeeze(1)?, // [batch, dim]
+        };
+
+        let dim = pooled.dim(1)?;
+        if dim != self.model_config.dim {
+            anyhow::bail!(
+                "Model produced {}-dimensional embeddings but rag_dim is {}",
+                dim,
+                self.model_config.dim
+            );
+        }
+
+        // L2 normalize each row
+        let norms = pooled.sqr()?.sum_keepdim(1)?.sqrt()?.maximum(1e-12)?; // [batch, 1]
+        let normalized = pooled.broadcast_div(&norms)?;
+
+        Ok(normalized.to_vec2()?)
+    }
+
+    /// Split text into overlapping windows that fit the model's token limit.
+    /// Returns byte ranges into `text`.
+    fn chunk_spans(&self, text: &str) -> Result<Vec<(usize, usize)>> {
         let encoding = self
+            .embedder
             .tokenizer
-            .encode(text, true)
+            .encode(text, false)
             .map_err(|e| anyhow::anyhow!("Tokenizer encode failed: {}", e))?;
+        let offsets: Vec<(usize, usize)> = encoding
+            .get_offsets()
+            .iter()
+            .copied()
+            .filter(|(start, end)| end > start)
+            .collect();
 
-        let mut input_ids: Vec<u32> = encoding.get_ids().to_vec();
-        let mut attention_mask: Vec<u32> = encoding.get_attention_mask().to_vec();
-        let mut token_type_ids: Vec<u32> = encoding.get_type_ids().to_vec();
-
-        // Truncate to MAX_TOKENS
-        if input_ids.len() > MAX_TOKENS {
-            input_ids.truncate(MAX_TOKENS);
-            attention_mask.truncate(MAX_TOKENS);
-            token_type_ids.truncate(MAX_TOKENS);
-            // Fix last token to [SEP]
-            if let Some(last) = input_ids.last_mut() {
-                *last = SEP_TOKEN_ID;
+        // Leave room for the passage prefix added before embedding
+        let prefix = &self.model_config.passage_prefix;
+        let prefix_tokens = if prefix.is_empty() {
+            0
+        } else {
+            self.embedder
+                .tokenizer
+                .encode(prefix.as_str(), false)
+                .map_err(|e| anyhow::anyhow!("Tokenizer encode failed: {}", e))?
+                .len()
+        };
+        let chunk_tokens = self
+            .model_config
+            .chunk_tokens()
+            .saturating_sub(prefix_tokens)
+            .max(CHUNK_OVERLAP_TOKENS + 1);
+        if offsets.len() <= chunk_tokens {
+            return Ok(vec![(0, text.len())]);
+        }
+
+        let step = chunk_tokens - CHUNK_OVERLAP_TOKENS;
+        let mut spans = Vec::new();
+        let mut first = 0;
+        loop {
+            let last = (first + chunk_tokens).min(offsets.len()) - 1;
+            spans.push((offsets[first].0, offsets[last].1));
+            if last + 1 >= offsets.len() {
+                break;
             }
+            first += step;
         }
+        Ok(spans)
+    }
 
-        let seq_len = input_ids.len();
-        let device = &candle_core::Device::Cpu;
+    /// Chunk, embed and store records in `generation`. Chunks of all records
+    /// are embedded in batches of EMBED_BATCH_SIZE; records are stored in the
+    /// given order.
+    fn index_records_into(&self, jobs: &[IndexJob], generation: u64) -> Result<()> {
+        // Split every record into chunks first so they can share forward passes
+        let mut chunks: Vec<(usize, usize, usize)> = Vec::new(); // (job, start, end)
+        for (job_idx, job) in jobs.iter().enumerate() {
+            // Skip short content
+            if job.content.trim().len() < 10 {
+                continue;
+            }
+            // Conversations are immutable — skip already indexed ones
+            if job.source_type == "conversation"
+                && self
+                    .meta_db
+                    .get(job.dedup_key(generation).as_bytes())?
+                    .is_some()
+            {
+                continue;
+            }
 
-        // Create tensors with shape [1, seq_len]
-        let ids_tensor = candle_core::Tensor::from_vec(
-            input_ids.iter().map(|&x| x as i64).collect::<Vec<i64>>(),
-            (1, seq_len),
-            device,
-        )?;
-        let mask_tensor = candle_core::Tensor::from_vec(
-            attention_mask
-                .iter()
-                .map(|&x| x as i64)
-                .collect::<Vec<i64>>(),
-            (1, seq_len),
-            device,
-        )?;
-        let type_tensor = candle_core::Tensor::from_vec(
-            token_type_ids
-                .iter()
-                .map(|&x| x as i64)
-                .collect::<Vec<i64>>(),
-            (1, seq_len),
-            device,
-        )?;
+            let mut spans = self.chunk_spans(&job.content)?;
+            if spans.len() > MAX_CHUNKS_PER_RECORD {
+                tracing::warn!(
+                    source_type = %job.source_type,
+                    source_id = job.source_id,
+                    chunks = spans.len(),
+                    "Record too long, indexing only the first chunks"
+                );
+                spans.truncate(MAX_CHUNKS_PER_RECORD);
+            }
+            chunks.extend(spans.into_iter().map(|(start, end)| (job_idx, start, end)));
+        }
 
-        // Build input map for ONNX model
-        let mut inputs = std::collections::HashMap::new();
-        inputs.insert("input_ids".to_string(), ids_tensor);
-        inputs.insert("attention_mask".to_string(), mask_tensor);
-        inputs.insert("token_type_ids".to_string(), type_tensor);
+        let texts: Vec<&str> = chunks
+            .iter()
+            .map(|&(job_idx, start, end)| {
+                let content = jobs[job_idx].content.as_str();
+                content.get(start..end).unwrap_or(content)
+            })
+            .collect();
 
-        // Run inference
-        let outputs = candle_onnx::simple_eval(&self.model, inputs)
-            .map_err(|e| anyhow::anyhow!("ONNX eval failed: {}", e))?;
+        let prefix = &self.model_config.passage_prefix;
+        let prefixed: Vec<String> = texts.iter().map(|t| format!("{}{}", prefix, t)).collect();
+        let prefixed: Vec<&str> = prefixed.iter().map(String::as_str).collect();
 
-        // Get last_hidden_state [1, seq_len, 384]
-        let hidden = outputs
-            .values()
-            .next()
-            .ok_or_else(|| anyhow::anyhow!("No output from ONNX model"))?;
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in prefixed.chunks(EMBED_BATCH_SIZE) {
+            embeddings.extend(self.embed_batch(batch)?);
+        }
 
-        // Mean pooling with attention mask
-        let hidden = hidden.squeeze(0)?; // [seq_len, 384]
-        let mask_f32 = candle_core::Tensor::from_vec(
-            attention_mask
+        let mut offset = 0;
+        while offset < chunks.len() {
+            let job_idx = chunks[offset].0;
+            let count = chunks[offset..]
                 .iter()
-                .map(|&x| x as f32)
-                .collect::<Vec<f32>>(),
-            (seq_len, 1),
-            device,
-        )?;
-
-        // Multiply hidden states by mask, then sum and divide
-        let masked = hidden.broadcast_mul(&mask_f32)?; // [seq_len, 384]
-        let summed = masked.sum(0)?; // [384]
-        let mask_sum = mask_f32.sum(0)?.broadcast_as(summed.shape())?; // [384]
-        let pooled = summed.broadcast_div(&mask_sum)?; // [384]
-
-        // L2 normalize
-        let norm = pooled.sqr()?.sum_all()?.sqrt()?.to_scalar::<f32>()?;
-
-        let normalized = if norm > 0.0 {
-            (pooled / norm as f64)?
-        } else {
-            pooled
-        };
+                .take_while(|(j, _, _)| *j == job_idx)
+                .count();
+            self.store_record(
+                generation,
+                &jobs[job_idx],
+                &chunks[offset..offset + count],
+                &texts[offset..offset + count],
+                &embeddings[offset..offset + count],
+            )?;
+            offset += count;
+        }
 
-        let vec: Vec<f32> = normalized.to_vec1()?;
-        Ok(vec)
+        Ok(())
     }
 
-    pub fn index_record_sync(
+    /// Index a batch into the active generation; if it fails, retry record by
+    /// record so one bad record does not lose the rest. Failures are logged,
+    /// not returned.
+    pub fn index_records_logged(&self, jobs: &[IndexJob]) {
+        if self.model_changed() {
+            tracing::warn!(
+                count = jobs.len(),
+                "Embedding model changed; records not indexed"
+            );
+            return;
+        }
+        self.index_batch_logged(jobs, self.active_generation());
+    }
+
+    fn index_batch_logged(&self, jobs: &[IndexJob], generation: u64) {
+        if let Err(e) = self.index_records_into(jobs, generation) {
+            tracing::warn!(error = %e, count = jobs.len(), "Batch indexing failed, retrying one by one");
+            for job in jobs {
+                if let Err(e) = self.index_records_into(std::slice::from_ref(job), generation) {
+                    tracing::warn!(
+                        source_type = %job.source_type,
+                        source_id = job.source_id,
+                        error = %e,
+                        "Failed to index record"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Replace the stored chunks of one record with freshly embedded ones
+    fn store_record(
         &self,
-        source_type: &str,
-        source_id: i64,
-        chat_id: i64,
-        segment: &str,
-        content: &str,
-        user_name: &str,
-        created_at: &str,
+        generation: u64,
+        job: &IndexJob,
+        chunks: &[(usize, usize, usize)],
+        texts: &[&str],
+        embeddings: &[Vec<f32>],
     ) -> Result<()> {
-        // Skip short content
-        if content.trim().len() < 10 {
-            return Ok(());
+        let dedup_key = job.dedup_key(generation);
+
+        // For notes/memory, remove the old chunks
+        self.remove_record(generation, &dedup_key)?;
+
+        let chunk_count = chunks.len();
+        let mut vector_ids = Vec::with_capacity(chunk_count);
+
+        for (chunk_index, ((&(_, chunk_start, chunk_end), chunk), embedding)) in
+            chunks.iter().zip(texts).zip(embeddings).enumerate()
+        {
+            // Allocate vector_id
+            let vector_id = {
+                let mut next = self.next_vector_id.lock().unwrap();
+                let id = *next;
+                *next += 1;
+                // Persist counter
+                self.meta_db
+                    .put(b"__next_vector_id__", (*next).to_le_bytes())?;
+                id
+            };
+
+            // Build content preview (first 200 chars of the chunk)
+            let content_preview: String = chunk.chars().take(200).collect();
+
+            let metadata = RagMetadata {
+                source_type: job.source_type.clone(),
+                source_id: job.source_id,
+                chat_id: job.chat_id,
+                segment: job.segment.clone(),
+                content_preview,
+                content: chunk.to_string(),
+                user_name: job.user_name.clone(),
+                created_at: job.created_at.clone(),
+                chunk_index,
+                chunk_count,
+                chunk_start,
+                chunk_end,
+            };
+
+            // Store metadata in RocksDB
+            let vec_key = generation_key(generation, &format!("vec:{}", vector_id));
+            let meta_json = serde_json::to_vec(&metadata)?;
+            self.meta_db.put(vec_key.as_bytes(), &meta_json)?;
+
+            // Store raw vector bytes
+            let raw_key = generation_key(generation, &format!("raw:{}", vector_id));
+            let raw_bytes: Vec<u8> = embedding.iter().flat_map(|f| f.to_le_bytes()).collect();
+            self.meta_db.put(raw_key.as_bytes(), &raw_bytes)?;
+
+            // Add to in-memory index, unless building a shadow generation
+            if generation == self.active_generation() {
+                let mut index = self.index.lock().unwrap();
+                let mut pos_map = self.position_to_id.lock().unwrap();
+                index.add(embedding);
+                pos_map.push(vector_id);
+            }
+
+            vector_ids.push(vector_id);
         }
 
-        let dedup_key = format!("dedup:{}:{}", source_type, source_id);
+        // Store dedup key listing every chunk's vector_id
+        let dedup_value: Vec<u8> = vector_ids.iter().flat_map(|id| id.to_le_bytes()).collect();
+        self.meta_db.put(dedup_key.as_bytes(), &dedup_value)?;
 
-        // Check for duplicates
+        Ok(())
+    }
+
+    /// Delete a record's chunks, given its dedup key
+    fn remove_record(&self, generation: u64, dedup_key: &str) -> Result<()> {
         if let Some(existing_bytes) = self.meta_db.get(dedup_key.as_bytes())? {
-            if source_type == "conversation" {
-                // Conversations are immutable — skip
-                return Ok(());
-            }
-            // For notes/memory, remove old entry
-            if existing_bytes.len() == 8 {
-                let old_id = i64::from_le_bytes(existing_bytes[..8].try_into().unwrap());
-                let vec_key = format!("vec:{}", old_id);
-                let raw_key = format!("raw:{}", old_id);
+            for old_id in existing_bytes.chunks_exact(8) {
+                let old_id = i64::from_le_bytes(old_id.try_into().unwrap());
+                let vec_key = generation_key(generation, &format!("vec:{}", old_id));
+                let raw_key = generation_key(generation, &format!("raw:{}", old_id));
                 self.meta_db.delete(vec_key.as_bytes())?;
                 self.meta_db.delete(raw_key.as_bytes())?;
-                self.meta_db.delete(dedup_key.as_bytes())?;
                 // Orphan in index: search will skip results with missing metadata
             }
+            self.meta_db.delete(dedup_key.as_bytes())?;
         }
+        Ok(())
+    }
 
-        // Embed the content
-        let embedding = self.embed_text(content)?;
-
-        // Allocate vector_id
-        let vector_id = {
-            let mut next = self.next_vector_id.lock().unwrap();
-            let id = *next;
-            *next += 1;
-            // Persist counter
-            self.meta_db
-                .put(b"__next_vector_id__", (*next).to_le_bytes())?;
-            id
-        };
+    /// Persist a queued record until it has been indexed
+    pub fn pending_put(&self, seq: u64, job: &IndexJob) -> Result<()> {
+        let key = format!("{}{:020}", PENDING_PREFIX, seq);
+        self.meta_db.put(key.as_bytes(), serde_json::to_vec(job)?)?;
+        Ok(())
+    }
 
-        // Build content preview (first 200 chars)
-        let content_preview: String = content.chars().take(200).collect();
+    pub fn pending_delete(&self, seqs: &[u64]) -> Result<()> {
+        let mut batch = rocksdb::WriteBatch::default();
+        for seq in seqs {
+            batch.delete(format!("{}{:020}", PENDING_PREFIX, seq).as_bytes());
+        }
+        self.meta_db.write(batch)?;
+        Ok(())
+    }
 
-        let metadata = RagMetadata {
-            source_type: source_type.to_string(),
-            source_id,
-            chat_id,
-            segment: segment.to_string(),
-            content_preview,
-            user_name: user_name.to_string(),
-            created_at: created_at.to_string(),
-        };
+    /// Queued records left over from a previous run, oldest first
+    pub fn pending_load(&self) -> Result<Vec<(u64, IndexJob)>> {
+        let mut pending = Vec::new();
+        for item in self.meta_db.prefix_iterator(PENDING_PREFIX.as_bytes()) {
+            let (key, value) = item?;
+            let Some(seq) = key.strip_prefix(PENDING_PREFIX.as_bytes()) else {
+                break; // prefix_iterator may go beyond prefix
+            };
+            let Some(seq) = std::str::from_utf8(seq).ok().and_then(|s| s.parse().ok()) else {
+                continue;
+            };
+            match serde_json::from_slice(&value) {
+                Ok(job) => pending.push((seq, job)),
+                Err(e) => tracing::warn!(seq, error = %e, "Dropping unreadable queued record"),
+            }
+        }
+        pending.sort_by_key(|(seq, _)| *seq);
+        Ok(pending)
+    }
 
-        // Store metadata in RocksDB
-        let vec_key = format!("vec:{}", vector_id);
-        let meta_json = serde_json::to_vec(&metadata)?;
-        self.meta_db.put(vec_key.as_bytes(), &meta_json)?;
+    /// Top matches for `query`, one per source record (its best-scoring
+    /// chunk). With a visibility, records outside it are skipped and the index
+    /// is searched deeper until enough visible candidates are found or the
+    /// index is exhausted. Candidates are filtered by `min_similarity`, then
+    /// optionally reranked on their full chunk text and filtered by
+    /// `min_relevance`, and diversified with MMR.
+    pub fn search(&self, query: &str, opts: &SearchOptions<'_>) -> Result<Vec<RagResult>> {
+        if opts.limit == 0 {
+            return Ok(Vec::new());
+        }
 
-        // Store raw vector bytes
-        let raw_key = format!("raw:{}", vector_id);
-        let raw_bytes: Vec<u8> = embedding.iter().flat_map(|f| f.to_le_bytes()).collect();
-        self.meta_db.put(raw_key.as_bytes(), &raw_bytes)?;
+        let rerank = opts.rerank && self.reranker.is_some();
+        let wanted = if rerank || opts.diversity > 0.0 {
+            (opts.limit * 3).max(MIN_RERANK_CANDIDATES)
+        } else {
+            opts.limit
+        };
 
-        // Store dedup key
-        self.meta_db
-            .put(dedup_key.as_bytes(), vector_id.to_le_bytes())?;
+        let query_vec = self.embed_query(query)?;
+        let mut candidates = self.nearest_sources(&query_vec, wanted, opts)?;
+        candidates.retain(|(r, _)| r.similarity >= opts.min_similarity);
 
-        // Add to in-memory index
-        {
-            let mut index = self.index.lock().unwrap();
-            let mut pos_map = self.position_to_id.lock().unwrap();
-            index.add(&embedding);
-            pos_map.push(vector_id);
+        if rerank && let Some(reranker) = &self.reranker {
+            let passages: Vec<&str> = candidates.iter().map(|(r, _)| r.metadata.text()).collect();
+            let scores = reranker.score(query, &passages)?;
+            for ((result, _), score) in candidates.iter_mut().zip(scores) {
+                result.score = score;
+            }
+            candidates.sort_by(|a, b| b.0.score.total_cmp(&a.0.score));
+            candidates.retain(|(r, _)| r.score >= opts.min_relevance);
         }
 
-        Ok(())
+        let results = if opts.diversity > 0.0 {
+            select_mmr(candidates, opts.limit, opts.diversity)
+        } else {
+            candidates.truncate(opts.limit);
+            candidates.into_iter().map(|(r, _)| r).collect()
+        };
+        Ok(results)
     }
 
-    pub fn search(
+    /// Best-scoring chunk of up to `wanted` distinct source records that pass
+    /// the filters, by cosine similarity, with the chunk's vector
+    fn nearest_sources(
         &self,
-        query: &str,
-        limit: usize,
-        source_type_filter: Option<&str>,
-    ) -> Result<Vec<RagResult>> {
+        query_vec: &[f32],
+        wanted: usize,
+        opts: &SearchOptions<'_>,
+    ) -> Result<Vec<(RagResult, Vec<f32>)>> {
+        let generation = self.active_generation();
         let index = self.index.lock().unwrap();
+        let pos_map = self.position_to_id.lock().unwrap();
+        let mut results = Vec::new();
+        let mut seen_sources = HashSet::new();
 
         if index.ntotal() == 0 {
-            return Ok(Vec::new());
+            return Ok(results);
         }
 
-        let query_vec = self.embed_text(query)?;
-
-        // Over-fetch to compensate for filtered/orphaned results
-        let k = (limit * 3).min(index.ntotal()).max(1);
-
-        let (positions, scores) = index.search(&query_vec, k);
+        // Over-fetch to compensate for filtered/orphaned results, growing the
+        // window while too few candidates survive the filters
+        let mut scanned = 0;
+        let mut k = (wanted * 3).min(index.ntotal()).max(1);
 
-        let pos_map = self.position_to_id.lock().unwrap();
-        let mut results = Vec::new();
-
-        for (i, &position) in positions.iter().enumerate() {
-            if position >= pos_map.len() {
-                continue;
-            }
+        loop {
+            let (positions, scores) = index.search(query_vec, k);
 
-            let vector_id = pos_map[position];
-            let vec_key = format!("vec:{}", vector_id);
+            for (i, &position) in positions.iter().enumerate().skip(scanned) {
+                if position >= pos_map.len() {
+                    continue;
+                }
 
-            // Look up metadata in RocksDB
-            let meta_bytes = match self.meta_db.get(vec_key.as_bytes())? {
-                Some(b) => b,
-                None => continue, // Orphaned vector, skip
-            };
+                let vector_id = pos_map[position];
+                let vec_key = generation_key(generation, &format!("vec:{}", vector_id));
+
+                // Look up metadata in RocksDB
+                let meta_bytes = match self.meta_db.get(vec_key.as_bytes())? {
+                    Some(b) => b,
+                    None => continue, // Orphaned vector, skip
+                };
+
+                let metadata: RagMetadata = match serde_json::from_slice(&meta_bytes) {
+                    Ok(m) => m,
+                    Err(_) => continue,
+                };
+
+                // Apply source_type filter
+                if opts
+                    .source_type
+                    .is_some_and(|filter| metadata.source_type != filter)
+                {
+                    continue;
+                }
 
-            let metadata: RagMetadata = match serde_json::from_slice(&meta_bytes) {
-                Ok(m) => m,
-                Err(_) => continue,
-            };
+                // Apply chat/segment visibility
+                if opts.visibility.is_some_and(|v| !v.allows(&metadata)) {
+                    continue;
+                }
 
-            // Apply source_type filter
-            if let Some(filter) = source_type_filter {
-                if metadata.source_type != filter {
+                // Chunks arrive best-first, so keep only the first per source
+                if !seen_sources.insert((metadata.source_type.clone(), metadata.source_id)) {
                     continue;
                 }
-            }
 
-            results.push(RagResult {
-                score: scores[i],
-                metadata,
-            });
+                results.push((
+                    RagResult {
+                        score: scores[i],
+                        similarity: scores[i],
+                        metadata,
+                    },
+                    index.vector(position).to_vec(),
+                ));
+
+                if results.len() >= wanted {
+                    return Ok(results);
+                }
+            }
 
-            if results.len() >= limit {
-                break;
+            if k >= index.ntotal() {
+                return Ok(results);
             }
+            scanned = positions.len();
+            k = (k * 4).min(index.ntotal());
         }
+    }
 
-        Ok(results)
+    fn active_generation(&self) -> u64 {
+        *self.generation.lock().unwrap()
     }
 
-    pub async fn reindex_all(&self, pool: &SqlitePool) -> Result<usize> {
-        tracing::info!("Starting full RAG reindex...");
+    fn building_generation(&self) -> Result<Option<u64>> {
+        Ok(match self.meta_db.get(BUILDING_GENERATION_KEY)? {
+            Some(bytes) if bytes.len() == 8 => {
+                Some(u64::from_le_bytes(bytes[..8].try_into().unwrap()))
+            }
+            _ => None,
+        })
+    }
 
-        // Clear everything
-        {
-            let mut index = self.index.lock().unwrap();
-            *index = FlatIndex::new();
-        }
-        {
-            let mut pos_map = self.position_to_id.lock().unwrap();
-            pos_map.clear();
-        }
-        {
-            let mut next = self.next_vector_id.lock().unwrap();
-            *next = 0;
-            self.meta_db
-                .put(b"__next_vector_id__", 0i64.to_le_bytes())?;
+    /// Bring the index up to date with SQLite for the given source types.
+    ///
+    /// Incremental by default: only rows past each source's stored watermark
+    /// are embedded, and the watermark is checkpointed after every batch so
+    /// an interrupted sync resumes where it stopped. With `full`, or when the
+    /// embedding model changed, the index is rebuilt in a shadow generation
+    /// while search keeps using the current one, then swapped in atomically.
+    /// An interrupted full rebuild is resumed by the next `sync`.
+    pub async fn sync(
+        &self,
+        pool: &SqlitePool,
+        sources: &[&str],
+        full: bool,
+        progress: &dyn Fn(&SyncProgress<'_>),
+    ) -> Result<usize> {
+        let active = self.active_generation();
+        let building = self.building_generation()?;
+
+        if !full && building.is_none() {
+            if self.model_changed() {
+                anyhow::bail!(
+                    "Embedding model changed; run `astartebot rag reindex --full` to rebuild the index"
+                );
+            }
+            let count = self.sync_into(pool, sources, active, progress).await?;
+            self.prune_deleted(pool, sources, active).await?;
+            return Ok(count);
         }
 
-        // Clear all RocksDB keys
+        // Vectors of a changed model cannot be kept, so every source is rebuilt
+        let sources: &[&str] = if self.model_changed() {
+            &SOURCE_TYPES
+        } else {
+            sources
+        };
+
+        // Resume an interrupted rebuild if it used the same model
+        let fingerprint = self.model_config.fingerprint();
+        let target = match building {
+            Some(generation)
+                if self
+                    .meta_db
+                    .get(generation_key(generation, FINGERPRINT_KEY).as_bytes())?
+                    .is_some_and(|stored| stored == fingerprint.as_bytes()) =>
+            {
+                tracing::info!(generation, "Resuming interrupted RAG rebuild");
+                generation
+            }
+            Some(generation) => {
+                self.delete_generation(generation)?;
+                active.max(generation) + 1
+            }
+            None => active + 1,
+        };
+        tracing::info!(from = active, to = target, ?sources, "Rebuilding RAG index");
+
         let mut batch = rocksdb::WriteBatch::default();
-        let iter = self.meta_db.iterator(rocksdb::IteratorMode::Start);
-        for item in iter {
-            let (key, _) = item?;
-            batch.delete(&key);
-        }
+        batch.put(BUILDING_GENERATION_KEY, target.to_le_bytes());
+        batch.put(
+            generation_key(target, FINGERPRINT_KEY).as_bytes(),
+            fingerprint.as_bytes(),
+        );
         self.meta_db.write(batch)?;
 
-        let mut count: usize = 0;
+        // Sources not being rebuilt keep their current vectors
+        let kept: Vec<&str> = SOURCE_TYPES
+            .iter()
+            .copied()
+            .filter(|s| !sources.contains(s))
+            .collect();
+        self.copy_sources(active, target, &kept)?;
 
-        // Index conversation_history
-        let conv_rows: Vec<(i64, i64, i64, String, String, String, String)> = sqlx::query_as(
-            "SELECT id, chat_id, user_id, user_name, role, content, created_at
-             FROM conversation_history
-             WHERE role IN ('user', 'assistant') AND LENGTH(content) >= 10
-             ORDER BY id",
-        )
-        .fetch_all(pool)
-        .await?;
+        let mut count = self.sync_into(pool, sources, target, progress).await?;
+        self.prune_deleted(pool, sources, target).await?;
+        self.swap_generation(target)?;
 
-        tracing::info!(total = conv_rows.len(), "Indexing conversations...");
-        for (id, chat_id, _user_id, user_name, _role, content, created_at) in &conv_rows {
-            let segment = format!("chat:{}", chat_id);
-            if let Err(e) = self.index_record_sync(
-                "conversation",
-                *id,
-                *chat_id,
-                &segment,
-                content,
-                user_name,
-                created_at,
-            ) {
-                tracing::warn!(id, error = %e, "Failed to index conversation");
-            }
-            count += 1;
-            if count % 500 == 0 {
-                tracing::info!(count, "Reindex progress...");
+        // Catch up on rows that arrived while the shadow generation was built
+        count += self
+            .sync_into(pool, &SOURCE_TYPES, target, progress)
+            .await?;
+
+        tracing::info!(count, generation = target, "RAG rebuild complete");
+        Ok(count)
+    }
+
+    /// Embed rows past the watermark of each source into `generation`
+    async fn sync_into(
+        &self,
+        pool: &SqlitePool,
+        sources: &[&str],
+        generation: u64,
+        progress: &dyn Fn(&SyncProgress<'_>),
+    ) -> Result<usize> {
+        let mut count = 0;
+
+        for &source in sources {
+            let watermark_key = generation_key(generation, &format!("watermark:{}", source));
+            let mut watermark: Watermark = match self.meta_db.get(watermark_key.as_bytes())? {
+                Some(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+                None => Watermark::default(),
+            };
+
+            let total = count_rows_after(pool, source, &watermark).await?;
+            let mut done = 0;
+            progress(&SyncProgress {
+                source,
+                done,
+                total,
+            });
+
+            loop {
+                let rows = fetch_rows_after(pool, source, &watermark, REINDEX_BATCH_SIZE).await?;
+                let Some((_, last)) = rows.last() else {
+                    break;
+                };
+                watermark = last.clone();
+
+                let jobs: Vec<IndexJob> = rows.into_iter().map(|(job, _)| job).collect();
+                self.index_batch_logged(&jobs, generation);
+
+                // Checkpoint
+                self.meta_db
+                    .put(watermark_key.as_bytes(), serde_json::to_vec(&watermark)?)?;
+
+                done += jobs.len();
+                progress(&SyncProgress {
+                    source,
+                    done,
+                    total: total.max(done),
+                });
             }
+
+            count += done;
         }
 
-        // Index notes
-        let note_rows: Vec<(i64, String, String, String, String, String)> = sqlx::query_as(
-            "SELECT id, segment, title, content, tags, created_at FROM notes ORDER BY id",
-        )
-        .fetch_all(pool)
-        .await?;
+        Ok(count)
+    }
 
-        tracing::info!(total = note_rows.len(), "Indexing notes...");
-        for (id, segment, title, content, tags, created_at) in &note_rows {
-            let embed_text = format!("{}\n{}\n{}", title, content, tags);
-            if let Err(e) =
-                self.index_record_sync("note", *id, 0, segment, &embed_text, "", created_at)
-            {
-                tracing::warn!(id, error = %e, "Failed to index note");
+    /// Remove records whose SQLite rows no longer exist
+    async fn prune_deleted(
+        &self,
+        pool: &SqlitePool,
+        sources: &[&str],
+        generation: u64,
+    ) -> Result<()> {
+        for &source in sources {
+            let table = match source {
+                "conversation" => "conversation_history",
+                "note" => "notes",
+                "memory" => "memory",
+                _ => continue,
+            };
+            let ids: HashSet<i64> = sqlx::query_scalar(&format!("SELECT id FROM {}", table))
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .collect();
+
+            let prefix = generation_key(generation, &format!("dedup:{}:", source));
+            let mut stale = Vec::new();
+            for item in self.meta_db.prefix_iterator(prefix.as_bytes()) {
+                let (key, _) = item?;
+                let Some(id) = key.strip_prefix(prefix.as_bytes()) else {
+                    break; // prefix_iterator may go beyond prefix
+                };
+                if let Some(id) = std::str::from_utf8(id).ok().and_then(|s| s.parse().ok())
+                    && !ids.contains(&id)
+                {
+                    stale.push(String::from_utf8_lossy(&key).into_owned());
+                }
+            }
+
+            if !stale.is_empty() {
+                tracing::info!(
+                    source,
+                    count = stale.len(),
+                    "Removing deleted records from RAG index"
+                );
             }
-            count += 1;
-            if count % 500 == 0 {
-                tracing::info!(count, "Reindex progress...");
+            for dedup_key in &stale {
+                self.remove_record(generation, dedup_key)?;
             }
         }
+        Ok(())
+    }
 
-        // Index memory (exclude __important__)
-        let mem_rows: Vec<(i64, String, String, String, String)> = sqlx::query_as(
-            "SELECT id, segment, key, value, created_at FROM memory
-             WHERE key != '__important__' AND LENGTH(value) >= 10
-             ORDER BY id",
-        )
-        .fetch_all(pool)
-        .await?;
+    /// Copy the records and watermarks of `sources` between generations
+    fn copy_sources(&self, from: u64, to: u64, sources: &[&str]) -> Result<()> {
+        for &source in sources {
+            let prefix = generation_key(from, &format!("dedup:{}:", source));
+            for item in self.meta_db.prefix_iterator(prefix.as_bytes()) {
+                let (key, value) = item?;
+                let Some(id) = key.strip_prefix(prefix.as_bytes()) else {
+                    break; // prefix_iterator may go beyond prefix
+                };
+
+                let mut batch = rocksdb::WriteBatch::default();
+                for vector_id in value.chunks_exact(8) {
+                    let vector_id = i64::from_le_bytes(vector_id.try_into().unwrap());
+                    for family in ["vec", "raw"] {
+                        let entry = format!("{}:{}", family, vector_id);
+                        if let Some(bytes) =
+                            self.meta_db.get(generation_key(from, &entry).as_bytes())?
+                        {
+                            batch.put(generation_key(to, &entry).as_bytes(), bytes);
+                        }
+                    }
+                }
+                let dedup = format!("dedup:{}:{}", source, String::from_utf8_lossy(id));
+                batch.put(generation_key(to, &dedup).as_bytes(), &value);
+                self.meta_db.write(batch)?;
+            }
 
-        tracing::info!(total = mem_rows.len(), "Indexing memory...");
-        for (id, segment, key, value, created_at) in &mem_rows {
-            let embed_text = format!("{}: {}", key, value);
-            if let Err(e) =
-                self.index_record_sync("memory", *id, 0, segment, &embed_text, "", created_at)
+            let watermark = format!("watermark:{}", source);
+            if let Some(bytes) = self
+                .meta_db
+                .get(generation_key(from, &watermark).as_bytes())?
             {
-                tracing::warn!(id, error = %e, "Failed to index memory");
-            }
-            count += 1;
-            if count % 500 == 0 {
-                tracing::info!(count, "Reindex progress...");
+                self.meta_db
+                    .put(generation_key(to, &watermark).as_bytes(), bytes)?;
             }
         }
+        Ok(())
+    }
 
-        tracing::info!(count, "RAG reindex complete");
-        Ok(count)
+    /// Make `target` the active generation and drop the previous one
+    fn swap_generation(&self, target: u64) -> Result<()> {
+        let (index, pos_map) = self.load_generation(target)?;
+
+        // One write flips the persisted pointer, so a crash leaves either the
+        // old or the new generation active, never a mix
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.put(ACTIVE_GENERATION_KEY, target.to_le_bytes());
+        batch.delete(BUILDING_GENERATION_KEY);
+        self.meta_db.write(batch)?;
+
+        let previous = {
+            let mut generation = self.generation.lock().unwrap();
+            let mut current_index = self.index.lock().unwrap();
+            let mut current_pos_map = self.position_to_id.lock().unwrap();
+            *current_index = index;
+            *current_pos_map = pos_map;
+            std::mem::replace(&mut *generation, target)
+        };
+        self.model_changed.store(false, Ordering::Relaxed);
+
+        self.delete_generation(previous)?;
+        Ok(())
     }
 
-    fn rebuild_index_from_rocksdb(&self) -> Result<usize> {
+    fn delete_generation(&self, generation: u64) -> Result<()> {
+        let mut batch = rocksdb::WriteBatch::default();
+        for family in GENERATION_FAMILIES {
+            let prefix = generation_key(generation, family);
+            for item in self.meta_db.prefix_iterator(prefix.as_bytes()) {
+                let (key, _) = item?;
+                if !key.starts_with(prefix.as_bytes()) {
+                    break; // prefix_iterator may go beyond prefix
+                }
+                batch.delete(&key);
+            }
+        }
+        self.meta_db.write(batch)?;
+        Ok(())
+    }
+
+    /// Build an in-memory index from the stored vectors of a generation
+    fn load_generation(&self, generation: u64) -> Result<(FlatIndex, Vec<i64>)> {
         // Collect all raw:* keys sorted by vector_id
         let mut entries: Vec<(i64, Vec<f32>)> = Vec::new();
 
-        let iter = self.meta_db.prefix_iterator(b"raw:");
+        let prefix = generation_key(generation, "raw:");
+        let iter = self.meta_db.prefix_iterator(prefix.as_bytes());
         for item in iter {
             let (key, value) = item?;
             let key_str = String::from_utf8_lossy(&key);
-            if !key_str.starts_with("raw:") {
+            let Some(id_str) = key_str.strip_prefix(prefix.as_str()) else {
                 break; // prefix_iterator may go beyond prefix
-            }
-            if let Some(id_str) = key_str.strip_prefix("raw:") {
-                if let Ok(vector_id) = id_str.parse::<i64>() {
-                    if value.len() == EMBEDDING_DIM * 4 {
-                        let vec: Vec<f32> = value
-                            .chunks_exact(4)
-                            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
-                            .collect();
-                        entries.push((vector_id, vec));
-                    }
-                }
+            };
+            if let Ok(vector_id) = id_str.parse::<i64>()
+                && value.len() == self.model_config.dim * 4
+            {
+                let vec: Vec<f32> = value
+                    .chunks_exact(4)
+                    .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+                    .collect();
+                entries.push((vector_id, vec));
             }
         }
 
         entries.sort_by_key(|(id, _)| *id);
 
-        let mut index = self.index.lock().unwrap();
-        let mut pos_map = self.position_to_id.lock().unwrap();
-
+        let mut index = FlatIndex::new(self.model_config.dim);
+        let mut pos_map = Vec::with_capacity(entries.len());
         for (vector_id, vec) in &entries {
             index.add(vec);
             pos_map.push(*vector_id);
         }
 
-        Ok(entries.len())
+        Ok((index, pos_map))
     }
 
-    pub fn stats(&self) -> (usize, usize, i64) {
-        let index = self.index.lock().unwrap();
-        let next_id = *self.next_vector_id.lock().unwrap();
+    pub fn stats(&self) -> Result<RagStats> {
+        let generation = self.active_generation();
+        let vectors = self.index.lock().unwrap().ntotal();
+        let next_vector_id = *self.next_vector_id.lock().unwrap();
 
         // Count metadata entries
-        let mut meta_count: usize = 0;
-        let iter = self.meta_db.prefix_iterator(b"vec:");
-        for item in iter {
+        let mut metadata_entries: usize = 0;
+        let prefix = generation_key(generation, "vec:");
+        for item in self.meta_db.prefix_iterator(prefix.as_bytes()) {
             let (key, _) = match item {
                 Ok(kv) => kv,
                 Err(_) => break,
             };
-            if !key.starts_with(b"vec:") {
+            if !key.starts_with(prefix.as_bytes()) {
                 break;
             }
-            meta_count += 1;
+            metadata_entries += 1;
         }
 
-        (index.ntotal(), meta_count, next_id)
+        Ok(RagStats {
+            vectors,
+            metadata_entries,
+            next_vector_id,
+            generation,
+            building_generation: self.building_generation()?,
+        })
+    }
+}
+
+/// Number of rows of a source type past the watermark
+async fn count_rows_after(pool: &SqlitePool, source: &str, after: &Watermark) -> Result<usize> {
+    let count: i64 = match source {
+        "conversation" => {
+            sqlx::query_scalar(
+                "SELECT COUNT(*) FROM conversation_history
+                 WHERE role IN ('user', 'assistant') AND LENGTH(content) >= 10 AND id > ?",
+            )
+            .bind(after.id)
+            .fetch_one(pool)
+            .await?
+        }
+        "note" => {
+            sqlx::query_scalar(
+                "SELECT COUNT(*) FROM notes
+                 WHERE updated_at > ? OR (updated_at = ? AND id > ?)",
+            )
+            .bind(&after.updated_at)
+            .bind(&after.updated_at)
+            .bind(after.id)
+            .fetch_one(pool)
+            .await?
+        }
+        "memory" => {
+            sqlx::query_scalar(
+                "SELECT COUNT(*) FROM memory
+                 WHERE key != '__important__' AND LENGTH(value) >= 10
+                   AND (updated_at > ? OR (updated_at = ? AND id > ?))",
+            )
+            .bind(&after.updated_at)
+            .bind(&after.updated_at)
+            .bind(after.id)
+            .fetch_one(pool)
+            .await?
+        }
+        other => anyhow::bail!("Unknown RAG source type '{}'", other),
+    };
+    Ok(count as usize)
+}
+
+/// Next rows of a source type past the watermark, in watermark order, each
+/// with the watermark that marks it as synced
+async fn fetch_rows_after(
+    pool: &SqlitePool,
+    source: &str,
+    after: &Watermark,
+    limit: usize,
+) -> Result<Vec<(IndexJob, Watermark)>> {
+    let limit = limit as i64;
+    let rows = match source {
+        "conversation" => {
+            let rows: Vec<(i64, i64, Option<i32>, String, String, String)> = sqlx::query_as(
+                "SELECT id, chat_id, thread_id, user_name, content, created_at
+                 FROM conversation_history
+                 WHERE role IN ('user', 'assistant') AND LENGTH(content) >= 10 AND id > ?
+                 ORDER BY id
+                 LIMIT ?",
+            )
+            .bind(after.id)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?;
+            rows.into_iter()
+                .map(|(id, chat_id, thread_id, user_name, content, created_at)| {
+                    let job = IndexJob {
+                        source_type: "conversation".to_string(),
+                        source_id: id,
+                        chat_id,
+                        segment: conversation_segment(chat_id, thread_id),
+                        content,
+                        user_name,
+                        created_at,
+                    };
+                    (
+                        job,
+                        Watermark {
+                            updated_at: String::new(),
+                            id,
+                        },
+                    )
+                })
+                .collect()
+        }
+        "note" => {
+            let rows: Vec<(i64, String, String, String, String, String, String)> = sqlx::query_as(
+                "SELECT id, segment, title, content, tags, created_at, updated_at FROM notes
+                     WHERE updated_at > ? OR (updated_at = ? AND id > ?)
+                     ORDER BY updated_at, id
+                     LIMIT ?",
+            )
+            .bind(&after.updated_at)
+            .bind(&after.updated_at)
+            .bind(after.id)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?;
+            rows.into_iter()
+                .map(
+                    |(id, segment, title, content, tags, created_at, updated_at)| {
+                        let job = IndexJob {
+                            source_type: "note".to_string(),
+                            source_id: id,
+                            chat_id: 0,
+                            segment,
+                            content: format!("{}\n{}\n{}", title, content, tags),
+                            user_name: String::new(),
+                            created_at,
+                        };
+                        (job, Watermark { updated_at, id })
+                    },
+                )
+                .collect()
+        }
+        "memory" => {
+            // Exclude __important__
+            let rows: Vec<(i64, String, String, String, String, String)> = sqlx::query_as(
+                "SELECT id, segment, key, value, created_at, updated_at FROM memory
+                 WHERE key != '__important__' AND LENGTH(value) >= 10
+                   AND (updated_at > ? OR (updated_at = ? AND id > ?))
+                 ORDER BY updated_at, id
+                 LIMIT ?",
+            )
+            .bind(&after.updated_at)
+            .bind(&after.updated_at)
+            .bind(after.id)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?;
+            rows.into_iter()
+                .map(|(id, segment, key, value, created_at, updated_at)| {
+                    let job = IndexJob {
+                        source_type: "memory".to_string(),
+                        source_id: id,
+                        chat_id: 0,
+                        segment,
+                        content: format!("{}: {}", key, value),
+                        user_name: String::new(),
+                        created_at,
+                    };
+                    (job, Watermark { updated_at, id })
+                })
+                .collect()
+        }
+        other => anyhow::bail!("Unknown RAG source type '{}'", other),
+    };
+    Ok(rows)
+}
+
+/// Maximal marginal relevance: repeatedly pick the candidate with the best
+/// trade-off between its score and its similarity to results already picked.
+/// Candidates must be sorted by score, best first.
+fn select_mmr(
+    mut candidates: Vec<(RagResult, Vec<f32>)>,
+    limit: usize,
+    diversity: f32,
+) -> Vec<RagResult> {
+    let diversity = diversity.clamp(0.0, 1.0);
+    let mut selected: Vec<(RagResult, Vec<f32>)> = Vec::new();
+
+    while selected.len() < limit && !candidates.is_empty() {
+        let mut best = 0;
+        let mut best_value = f32::NEG_INFINITY;
+        for (i, (candidate, vector)) in candidates.iter().enumerate() {
+            let redundancy = selected
+                .iter()
+                .map(|(_, picked)| dot(vector, picked))
+                .fold(0.0, f32::max);
+            let value = (1.0 - diversity) * candidate.score - diversity * redundancy;
+            if value > best_value {
+                best = i;
+                best_value = value;
+            }
+        }
+        selected.push(candidates.remove(best));
+    }
+
+    selected.into_iter().map(|(r, _)| r).collect()
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation(chat_id: i64, thread_id: Option<i32>) -> RagMetadata {
+        let job = IndexJob::conversation(1, chat_id, thread_id, "hello there", "ann");
+        RagMetadata {
+            source_type: job.source_type,
+            source_id: job.source_id,
+            chat_id: job.chat_id,
+            segment: job.segment,
+            content_preview: job.content.clone(),
+            user_name: job.user_name,
+            created_at: job.created_at,
+            chunk_index: 0,
+            chunk_count: 1,
+            chunk_start: 0,
+            chunk_end: job.content.len(),
+            content: job.content,
+        }
+    }
+
+    #[test]
+    fn conversations_stay_in_their_topic() {
+        let general = RagVisibility::for_caller(-100, None, 7);
+        let topic = RagVisibility::for_caller(-100, Some(5), 7);
+
+        assert!(general.allows(&conversation(-100, None)));
+        assert!(!general.allows(&conversation(-100, Some(5))));
+        assert!(topic.allows(&conversation(-100, Some(5))));
+        assert!(!topic.allows(&conversation(-100, Some(6))));
+        assert!(!topic.allows(&conversation(-100, None)));
+        assert!(!general.allows(&conversation(-200, None)));
+    }
+
+    #[test]
+    fn topic_callers_also_see_chat_wide_segments() {
+        let topic = RagVisibility::for_caller(-100, Some(5), 7);
+        for segment in ["global", "bot", "chat:-100", "person:7", "topic:-100:5"] {
+            assert!(topic.allows_segment(segment), "{}", segment);
+        }
+        assert!(!topic.allows_segment("topic:-100:6"));
+        assert!(!topic.allows_segment("person:8"));
     }
 }
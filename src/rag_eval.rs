@@ -0,0 +1,461 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::rag::{RagEngine, SOURCE_TYPES, SearchOptions};
+
+/// One labelled query, a line of the eval file:
+/// `{"query": "...", "expected": ["note:12", "conversation:345"]}`
+#[derive(Debug, Deserialize)]
+pub struct EvalCase {
+    pub query: String,
+    /// Relevant records as `source_type:source_id`
+    pub expected: Vec<String>,
+    /// Only search this source type
+    #[serde(default)]
+    pub source_type: Option<String>,
+}
+
+/// A search configuration to evaluate
+pub struct EvalVariant {
+    pub name: &'static str,
+    pub rerank: bool,
+    pub diversity: f32,
+}
+
+/// Variants the engine can run: plain dense search and MMR, plus the
+/// reranked versions of both when a cross-encoder is configured
+pub fn variants(engine: &RagEngine, diversity: f32) -> Vec<EvalVariant> {
+    let mut variants = vec![
+        EvalVariant {
+            name: "dense",
+            rerank: false,
+            diversity: 0.0,
+        },
+        EvalVariant {
+            name: "dense+mmr",
+            rerank: false,
+            diversity,
+        },
+    ];
+    if engine.has_reranker() {
+        variants.push(EvalVariant {
+            name: "rerank",
+            rerank: true,
+            diversity: 0.0,
+        });
+        variants.push(EvalVariant {
+            name: "rerank+mmr",
+            rerank: true,
+            diversity,
+        });
+    }
+    variants
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VariantReport {
+    pub name: String,
+    pub recall: f64,
+    pub mrr: f64,
+    pub ndcg: f64,
+    pub mean_ms: f64,
+}
+
+/// Metrics of one eval run, saved as JSON to compare later runs against
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EvalReport {
+    /// Fingerprint of the embedding model the index was built with
+    pub model: String,
+    pub vectors: usize,
+    pub k: usize,
+    pub queries: usize,
+    pub variants: Vec<VariantReport>,
+}
+
+/// Minimum acceptable metrics, applied to every variant
+#[derive(Debug, Default)]
+pub struct Thresholds {
+    pub min_recall: Option<f64>,
+    pub min_mrr: Option<f64>,
+    pub min_ndcg: Option<f64>,
+    /// Largest drop allowed against a baseline report
+    pub tolerance: f64,
+}
+
+/// Read a JSONL file of eval cases. Blank lines and lines starting with `#`
+/// are skipped.
+pub fn load_cases(path: &Path) -> Result<Vec<EvalCase>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut cases = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let case: EvalCase = serde_json::from_str(line)
+            .with_context(|| format!("{}:{}: invalid eval case", path.display(), n + 1))?;
+        if case.expected.is_empty() {
+            anyhow::bail!("{}:{}: 'expected' is empty", path.display(), n + 1);
+        }
+        for expected in &case.expected {
+            parse_source(expected).with_context(|| format!("{}:{}", path.display(), n + 1))?;
+        }
+        cases.push(case);
+    }
+
+    if cases.is_empty() {
+        anyhow::bail!("{} contains no eval cases", path.display());
+    }
+    Ok(cases)
+}
+
+fn parse_source(label: &str) -> Result<(String, i64)> {
+    let (source_type, id) = label
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("'{}' is not source_type:source_id", label))?;
+    if !SOURCE_TYPES.contains(&source_type) {
+        anyhow::bail!("'{}' has unknown source type '{}'", label, source_type);
+    }
+    let id = id
+        .parse()
+        .map_err(|_| anyhow::anyhow!("'{}' has a non-numeric source id", label))?;
+    Ok((source_type.to_string(), id))
+}
+
+/// Run every case through each variant and average recall@k, MRR and nDCG@k
+pub fn run(
+    engine: &RagEngine,
+    cases: &[EvalCase],
+    k: usize,
+    variants: &[EvalVariant],
+) -> Result<EvalReport> {
+    let mut reports = Vec::with_capacity(variants.len());
+
+    for variant in variants {
+        let (mut recall, mut mrr, mut ndcg, mut elapsed_ms) = (0.0, 0.0, 0.0, 0.0);
+
+        for case in cases {
+            let expected: HashSet<(String, i64)> = case
+                .expected
+                .iter()
+                .map(|label| parse_source(label))
+                .collect::<Result<_>>()?;
+
+            let started = Instant::now();
+            let results = engine.search(
+                &case.query,
+                &SearchOptions {
+                    limit: k,
+                    source_type: case.source_type.as_deref(),
+                    visibility: None,
+                    rerank: variant.rerank,
+                    min_similarity: -1.0,
+                    min_relevance: 0.0,
+                    diversity: variant.diversity,
+                },
+            )?;
+            elapsed_ms += started.elapsed().as_secs_f64() * 1000.0;
+
+            let relevant: Vec<bool> = results
+                .iter()
+                .map(|r| expected.contains(&(r.metadata.source_type.clone(), r.metadata.source_id)))
+                .collect();
+
+            let (case_recall, case_rr, case_ndcg) = score_ranking(&relevant, expected.len(), k);
+            recall += case_recall;
+            mrr += case_rr;
+            ndcg += case_ndcg;
+        }
+
+        let n = cases.len() as f64;
+        reports.push(VariantReport {
+            name: variant.name.to_string(),
+            recall: recall / n,
+            mrr: mrr / n,
+            ndcg: ndcg / n,
+            mean_ms: elapsed_ms / n,
+        });
+    }
+
+    Ok(EvalReport {
+        model: engine.model_fingerprint(),
+        vectors: engine.stats()?.vectors,
+        k,
+        queries: cases.len(),
+        variants: reports,
+    })
+}
+
+/// Recall, reciprocal rank and nDCG of one ranking, given which results are
+/// relevant and how many relevant records there are
+fn score_ranking(relevant: &[bool], expected: usize, k: usize) -> (f64, f64, f64) {
+    let hits = relevant.iter().filter(|&&r| r).count();
+    let recall = hits as f64 / expected as f64;
+    let reciprocal_rank = relevant
+        .iter()
+        .position(|&r| r)
+        .map_or(0.0, |rank| 1.0 / (rank + 1) as f64);
+
+    let dcg: f64 = relevant
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| **r)
+        .map(|(rank, _)| discount(rank))
+        .sum();
+    let ideal: f64 = (0..expected.min(k)).map(discount).sum();
+    (recall, reciprocal_rank, dcg / ideal)
+}
+
+fn discount(rank: usize) -> f64 {
+    1.0 / ((rank + 2) as f64).log2()
+}
+
+impl EvalReport {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("Invalid report {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn print(&self) {
+        println!("Model:   {}", self.model);
+        println!("Vectors: {}", self.vectors);
+        println!("Queries: {}\n", self.queries);
+        println!(
+            "{:<12} {:>9} {:>7} {:>8} {:>9}",
+            "variant",
+            format!("recall@{}", self.k),
+            "MRR",
+            format!("nDCG@{}", self.k),
+            "ms/query"
+        );
+        for v in &self.variants {
+            println!(
+                "{:<12} {:>9.3} {:>7.3} {:>8.3} {:>9.1}",
+                v.name, v.recall, v.mrr, v.ndcg, v.mean_ms
+            );
+        }
+    }
+
+    /// Metrics below the thresholds or worse than the baseline by more than
+    /// the tolerance, one message each; empty when the run passes
+    pub fn regressions(
+        &self,
+        thresholds: &Thresholds,
+        baseline: Option<&EvalReport>,
+    ) -> Vec<String> {
+        let mut failures = Vec::new();
+
+        for v in &self.variants {
+            let metrics = [
+                ("recall", v.recall, thresholds.min_recall),
+                ("MRR", v.mrr, thresholds.min_mrr),
+                ("nDCG", v.ndcg, thresholds.min_ndcg),
+            ];
+            for (metric, value, min) in metrics {
+                if let Some(min) = min
+                    && value < min
+                {
+                    failures.push(format!(
+                        "{} {} is {:.3}, below the minimum {:.3}",
+                        v.name, metric, value, min
+                    ));
+                }
+            }
+
+            let Some(base) = baseline.and_then(|b| b.variants.iter().find(|b| b.name == v.name))
+            else {
+                continue;
+            };
+            let compared = [
+                ("recall", v.recall, base.recall),
+                ("MRR", v.mrr, base.mrr),
+                ("nDCG", v.ndcg, base.ndcg),
+            ];
+            for (metric, value, before) in compared {
+                if before - value > thresholds.tolerance {
+                    failures.push(format!(
+                        "{} {} dropped from {:.3} to {:.3}",
+                        v.name, metric, before, value
+                    ));
+                }
+            }
+        }
+
+        if let Some(baseline) = baseline
+            && baseline.k != self.k
+        {
+            failures.push(format!(
+                "baseline was measured at k={}, this run at k={}",
+                baseline.k, self.k
+            ));
+        }
+
+        failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    fn report(k: usize, variants: &[(&str, f64, f64, f64)]) -> EvalReport {
+        EvalReport {
+            model: "test".into(),
+            vectors: 10,
+            k,
+            queries: 3,
+            variants: variants
+                .iter()
+                .map(|&(name, recall, mrr, ndcg)| VariantReport {
+                    name: name.into(),
+                    recall,
+                    mrr,
+                    ndcg,
+                    mean_ms: 1.0,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn discounts_by_log_rank() {
+        assert_close(discount(0), 1.0);
+        assert_close(discount(1), 0.6309);
+        assert_close(discount(2), 0.5);
+    }
+
+    #[test]
+    fn scores_a_hand_computed_ranking() {
+        // Relevant at ranks 2 and 4, three relevant records in total:
+        // DCG = 1/log2(3) + 1/log2(5) = 1.0616
+        // IDCG = 1 + 1/log2(3) + 1/log2(4) = 2.1309
+        let (recall, rr, ndcg) = score_ranking(&[false, true, false, true], 3, 4);
+        assert_close(recall, 2.0 / 3.0);
+        assert_close(rr, 0.5);
+        assert_close(ndcg, 1.0616 / 2.1309);
+    }
+
+    #[test]
+    fn scores_perfect_and_empty_rankings() {
+        let (recall, rr, ndcg) = score_ranking(&[true, true, false], 2, 3);
+        assert_close(recall, 1.0);
+        assert_close(rr, 1.0);
+        assert_close(ndcg, 1.0);
+
+        assert_eq!(score_ranking(&[false, false], 1, 2), (0.0, 0.0, 0.0));
+        assert_eq!(score_ranking(&[], 1, 5), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn ideal_ranking_is_capped_at_k() {
+        // Five relevant records but k=2: two hits are a perfect ranking
+        let (recall, _, ndcg) = score_ranking(&[true, true], 5, 2);
+        assert_close(recall, 0.4);
+        assert_close(ndcg, 1.0);
+    }
+
+    #[test]
+    fn parses_source_labels() {
+        assert_eq!(parse_source("note:12").unwrap(), ("note".into(), 12));
+        assert_eq!(
+            parse_source("conversation:-3").unwrap(),
+            ("conversation".into(), -3)
+        );
+        assert!(parse_source("note").is_err());
+        assert!(parse_source("file:1").is_err());
+        assert!(parse_source("memory:x").is_err());
+    }
+
+    #[test]
+    fn loads_cases_skipping_blanks_and_comments() {
+        let path = std::env::temp_dir().join(format!("rag_eval_{}.jsonl", std::process::id()));
+        std::fs::write(
+            &path,
+            "# labelled queries\n\n{\"query\": \"pasta\", \"expected\": [\"note:1\"]}\n\
+             {\"query\": \"tea\", \"expected\": [\"memory:2\"], \"source_type\": \"memory\"}\n",
+        )
+        .unwrap();
+        let cases = load_cases(&path).unwrap();
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[1].source_type.as_deref(), Some("memory"));
+
+        std::fs::write(&path, "{\"query\": \"q\", \"expected\": []}\n").unwrap();
+        let error = load_cases(&path).unwrap_err().to_string();
+        assert!(error.ends_with(":1: 'expected' is empty"), "{}", error);
+
+        std::fs::write(&path, "{\"query\": \"q\", \"expected\": [\"note:1\"]}\n{\"query\": \"q\", \"expected\": [\"bad\"]}\n").unwrap();
+        assert!(format!("{:#}", load_cases(&path).unwrap_err()).contains(":2:"));
+
+        std::fs::write(&path, "# nothing\n").unwrap();
+        assert!(load_cases(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn flags_metrics_below_thresholds() {
+        let run = report(5, &[("dense", 0.8, 0.6, 0.7)]);
+        let thresholds = Thresholds {
+            min_recall: Some(0.75),
+            min_mrr: Some(0.65),
+            min_ndcg: None,
+            tolerance: 0.0,
+        };
+        assert_eq!(
+            run.regressions(&thresholds, None),
+            vec!["dense MRR is 0.600, below the minimum 0.650"]
+        );
+    }
+
+    #[test]
+    fn flags_drops_against_a_baseline_beyond_the_tolerance() {
+        let baseline = report(5, &[("dense", 0.8, 0.6, 0.7), ("rerank", 0.9, 0.8, 0.8)]);
+        let run = report(
+            5,
+            &[
+                ("dense", 0.78, 0.6, 0.7),
+                ("rerank", 0.9, 0.7, 0.8),
+                ("new", 0.1, 0.1, 0.1),
+            ],
+        );
+        let thresholds = Thresholds {
+            tolerance: 0.05,
+            ..Default::default()
+        };
+        // A 0.02 recall drop is tolerated; variants without a baseline are skipped
+        assert_eq!(
+            run.regressions(&thresholds, Some(&baseline)),
+            vec!["rerank MRR dropped from 0.800 to 0.700"]
+        );
+        assert!(
+            baseline
+                .regressions(&thresholds, Some(&baseline))
+                .is_empty()
+        );
+
+        let other_k = report(10, &[("dense", 0.8, 0.6, 0.7)]);
+        assert_eq!(
+            other_k.regressions(&thresholds, Some(&report(5, &[]))),
+            vec!["baseline was measured at k=5, this run at k=10"]
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc;
+
+use crate::rag::{IndexJob, RagEngine};
+
+const QUEUE_CAPACITY: usize = 1024;
+const WORKER_BATCH_SIZE: usize = 32;
+
+/// Background embedding pipeline. Records are persisted as pending in RocksDB,
+/// then handed to a dedicated blocking worker that indexes them in batches, so
+/// ONNX inference never runs on the async runtime. Records still pending after
+/// a crash are indexed when the queue starts again.
+pub struct IndexQueue {
+    rag: Arc<RagEngine>,
+    tx: mpsc::Sender<(u64, IndexJob)>,
+    next_seq: AtomicU64,
+}
+
+impl IndexQueue {
+    /// Start the worker, replaying records left pending by a previous run first
+    pub fn start(rag: Arc<RagEngine>) -> anyhow::Result<Self> {
+        let backlog = rag.pending_load()?;
+        let next_seq = backlog.last().map(|(seq, _)| seq + 1).unwrap_or(0);
+        if !backlog.is_empty() {
+            tracing::info!(count = backlog.len(), "Resuming queued RAG indexing");
+        }
+
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        let worker_rag = rag.clone();
+        tokio::task::spawn_blocking(move || run_worker(&worker_rag, backlog, rx));
+
+        Ok(Self {
+            rag,
+            tx,
+            next_seq: AtomicU64::new(next_seq),
+        })
+    }
+
+    /// Queue a record for indexing. Waits only when the queue is full.
+    pub async fn enqueue(&self, job: IndexJob) {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = self.rag.pending_put(seq, &job) {
+            tracing::warn!(error = %e, "Failed to persist queued RAG record");
+        }
+        if self.tx.send((seq, job)).await.is_err() {
+            tracing::warn!("RAG indexing worker has stopped; record stays pending");
+        }
+    }
+}
+
+fn run_worker(
+    rag: &RagEngine,
+    backlog: Vec<(u64, IndexJob)>,
+    mut rx: mpsc::Receiver<(u64, IndexJob)>,
+) {
+    for batch in backlog.chunks(WORKER_BATCH_SIZE) {
+        index_batch(rag, batch);
+    }
+
+    // Block for the first record, then drain whatever else is already waiting
+    while let Some(first) = rx.blocking_recv() {
+        let mut batch = vec![first];
+        while batch.len() < WORKER_BATCH_SIZE {
+            match rx.try_recv() {
+                Ok(item) => batch.push(item),
+                Err(_) => break,
+            }
+        }
+        index_batch(rag, &batch);
+    }
+
+    tracing::info!("RAG indexing worker stopped");
+}
+
+fn index_batch(rag: &RagEngine, batch: &[(u64, IndexJob)]) {
+    let jobs: Vec<IndexJob> = batch.iter().map(|(_, job)| job.clone()).collect();
+    rag.index_records_logged(&jobs);
+
+    let seqs: Vec<u64> = batch.iter().map(|(seq, _)| *seq).collect();
+    if let Err(e) = rag.pending_delete(&seqs) {
+        tracing::warn!(error = %e, "Failed to clear indexed RAG records from the queue");
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use sqlx::SqlitePool;
+use std::path::PathBuf;
+use tokenizers::{TruncationParams, TruncationStrategy};
+
+use crate::config;
+use crate::rag::{EncodedInput, OnnxModel};
+
+const MAX_PAIR_TOKENS: usize = 512;
+const RERANK_BATCH_SIZE: usize = 16;
+
+/// Cross-encoder that scores (query, passage) pairs jointly. Slower than
+/// comparing embeddings, so it only reorders the top candidates of a search.
+pub struct Reranker {
+    model: OnnxModel,
+}
+
+impl Reranker {
+    /// Load the model named by `rag_rerank_model_path` or `rag_rerank_model_id`.
+    /// Returns None when neither is set, which disables reranking.
+    pub async fn load(pool: &SqlitePool) -> Result<Option<Self>> {
+        let model_id = config::get_or_default(pool, "rag_rerank_model_id", "").await?;
+        let model_path = config::get_or_default(pool, "rag_rerank_model_path", "").await?;
+        let model_path = (!model_path.trim().is_empty()).then(|| PathBuf::from(model_path.trim()));
+        if model_id.trim().is_empty() && model_path.is_none() {
+            return Ok(None);
+        }
+
+        let mut model = OnnxModel::load(model_id.trim(), model_path.as_deref(), "logits").await?;
+        // Trim the passage, not the query, when a pair is too long
+        model
+            .tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: MAX_PAIR_TOKENS,
+                strategy: TruncationStrategy::OnlySecond,
+                ..Default::default()
+            }))
+            .map_err(|e| anyhow::anyhow!("Failed to configure reranker tokenizer: {}", e))?;
+
+        tracing::info!(model_id = %model_id, ?model_path, "Reranker loaded");
+        Ok(Some(Self { model }))
+    }
+
+    /// Relevance of each passage to the query, between 0 and 1
+    pub fn score(&self, query: &str, passages: &[&str]) -> Result<Vec<f32>> {
+        let mut scores = Vec::with_capacity(passages.len());
+
+        for batch in passages.chunks(RERANK_BATCH_SIZE) {
+            let mut encoded: Vec<EncodedInput> = Vec::with_capacity(batch.len());
+            for passage in batch {
+                let encoding = self
+                    .model
+                    .tokenizer
+                    .encode((query, *passage), true)
+                    .map_err(|e| anyhow::anyhow!("Tokenizer encode failed: {}", e))?;
+                encoded.push((
+                    encoding.get_ids().to_vec(),
+                    encoding.get_attention_mask().to_vec(),
+                    encoding.get_type_ids().to_vec(),
+                ));
+            }
+
+            // logits [batch, 1], or [batch, 2] for (irrelevant, relevant) heads
+            let (logits, _) = self.model.run(&encoded)?;
+            let logits: Vec<Vec<f32>> = logits.to_dtype(candle_core::DType::F32)?.to_vec2()?;
+            scores.extend(logits.iter().map(|row| match row.as_slice() {
+                [logit] => sigmoid(*logit),
+                [.., irrelevant, relevant] => sigmoid(relevant - irrelevant),
+                [] => 0.0,
+            }));
+        }
+
+        Ok(scores)
+    }
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
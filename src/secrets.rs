@@ -0,0 +1,463 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use regex::Regex;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::io::Write;
+use std::sync::OnceLock;
+
+use crate::types::SecretInfo;
+use crate::{config, db};
+
+/// Base64 of the 32-byte vault key; when unset the key lives in `KEY_FILE`
+const KEY_ENV: &str = "ASTARTEBOT_SECRETS_KEY";
+const KEY_FILE: &str = "secrets.key";
+const DEFAULT_SECRET_TOOLS: &str = "generic_http_request,crud_mcp_server,mcp_call,mcp__*";
+/// Shorter values are left alone when redacting; they would match ordinary text
+const MIN_REDACT_LEN: usize = 4;
+
+static CIPHER: OnceLock<LessSafeKey> = OnceLock::new();
+static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+
+fn placeholder_re() -> &'static Regex {
+    PLACEHOLDER.get_or_init(|| Regex::new(r"\{\{secret:([A-Za-z0-9_.-]+)\}\}").unwrap())
+}
+
+/// Secret names are what placeholders can refer to
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+/// Encrypt and store a secret, creating the vault key on first use
+pub async fn set(
+    pool: &SqlitePool,
+    name: &str,
+    value: &str,
+    updated_by: Option<i64>,
+) -> Result<()> {
+    if !valid_name(name) {
+        anyhow::bail!("Secret names use letters, digits, '_', '.' and '-' (max 64 chars)");
+    }
+    if value.is_empty() {
+        anyhow::bail!("Secret value is empty");
+    }
+    let encrypted = encrypt(cipher(true)?, name, value)?;
+    db::secret_set(pool, name, &encrypted, updated_by).await
+}
+
+pub async fn delete(pool: &SqlitePool, name: &str) -> Result<bool> {
+    db::secret_delete(pool, name).await
+}
+
+/// Set the hosts a secret may be sent to in HTTP headers; an empty list
+/// means nowhere. Returns false when there is no such secret.
+pub async fn set_hosts(pool: &SqlitePool, name: &str, hosts: &str) -> Result<bool> {
+    db::secret_set_hosts(pool, name, &host_list(hosts).join(",")).await
+}
+
+/// Set the MCP servers a secret may be sent to in tool arguments; an empty
+/// list means none. Returns false when there is no such secret.
+pub async fn set_mcp_servers(pool: &SqlitePool, name: &str, servers: &str) -> Result<bool> {
+    db::secret_set_mcp_servers(pool, name, &server_list(servers).join(",")).await
+}
+
+/// Set the chats whose non-admin turns may use a secret; an empty list
+/// leaves it to admins. Returns false when there is no such secret.
+pub async fn set_shared_chats(pool: &SqlitePool, name: &str, chats: &str) -> Result<bool> {
+    let mut ids = Vec::new();
+    for chat in chats.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        let id: i64 = chat
+            .parse()
+            .map_err(|_| anyhow::anyhow!("'{}' is not a chat id", chat))?;
+        ids.push(id.to_string());
+    }
+    db::secret_set_shared_chats(pool, name, &ids.join(",")).await
+}
+
+/// Stored secrets without their values
+pub async fn list(pool: &SqlitePool) -> Result<Vec<SecretInfo>> {
+    db::secret_list(pool).await
+}
+
+/// Secrets a turn in `chat_id` may use, without their values
+pub async fn list_usable(
+    pool: &SqlitePool,
+    chat_id: i64,
+    is_admin: bool,
+) -> Result<Vec<SecretInfo>> {
+    let mut items = db::secret_list(pool).await?;
+    items.retain(|item| usable(&item.shared_chats, chat_id, is_admin));
+    Ok(items)
+}
+
+/// Admins may use every secret; other users only those shared with the chat
+fn usable(shared_chats: &str, chat_id: i64, is_admin: bool) -> bool {
+    is_admin
+        || shared_chats
+            .split(',')
+            .any(|id| id.trim().parse() == Ok(chat_id))
+}
+
+/// Comma-separated hosts, lowercased and without a trailing dot
+pub fn host_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|h| h.trim().trim_end_matches('.').to_lowercase())
+        .filter(|h| !h.is_empty())
+        .collect()
+}
+
+/// Comma-separated MCP server names
+fn server_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether `text` contains a `{{secret:NAME}}` placeholder
+pub fn has_placeholder(text: &str) -> bool {
+    placeholder_re().is_match(text)
+}
+
+/// Whether any string in `value` contains a `{{secret:NAME}}` placeholder
+pub fn contains_placeholder(value: &Value) -> bool {
+    match value {
+        Value::String(s) => has_placeholder(s),
+        Value::Array(items) => items.iter().any(contains_placeholder),
+        Value::Object(map) => map
+            .iter()
+            .any(|(k, v)| has_placeholder(k) || contains_placeholder(v)),
+        _ => false,
+    }
+}
+
+/// Whether placeholders are filled in for `tool`, per the comma-separated
+/// `secret_tools` key; an entry ending in `*` matches a name prefix
+pub async fn allowed_for(pool: &SqlitePool, tool: &str) -> Result<bool> {
+    let tools = config::get_or_default(pool, "secret_tools", DEFAULT_SECRET_TOOLS).await?;
+    Ok(tools
+        .split(',')
+        .map(str::trim)
+        .any(|entry| match entry.strip_suffix('*') {
+            Some(prefix) => !prefix.is_empty() && tool.starts_with(prefix),
+            None => entry == tool,
+        }))
+}
+
+struct Secret {
+    name: String,
+    value: String,
+    /// Hosts (and their subdomains) the value may be sent to in HTTP headers
+    allowed_hosts: Vec<String>,
+    /// MCP servers the value may be sent to in tool arguments
+    mcp_servers: Vec<String>,
+    /// Comma-separated chats whose non-admin turns may use the secret
+    shared_chats: String,
+}
+
+/// Decrypted secrets, used to fill in placeholders and redact values
+pub struct Secrets {
+    /// Longest value first so redaction never leaves a tail
+    values: Vec<Secret>,
+}
+
+impl Secrets {
+    /// All secrets; use this for redaction, and `load_usable` to fill in
+    /// placeholders
+    pub async fn load(pool: &SqlitePool) -> Result<Self> {
+        let rows = db::secret_all(pool).await?;
+        if rows.is_empty() {
+            return Ok(Self { values: Vec::new() });
+        }
+
+        let cipher = cipher(false)?;
+        let mut values = Vec::with_capacity(rows.len());
+        for (name, encrypted, allowed_hosts, mcp_servers, shared_chats) in rows {
+            match decrypt(cipher, &name, &encrypted) {
+                Ok(value) => values.push(Secret {
+                    name,
+                    value,
+                    allowed_hosts: host_list(&allowed_hosts),
+                    mcp_servers: server_list(&mcp_servers),
+                    shared_chats,
+                }),
+                Err(e) => tracing::error!(secret = %name, error = %e, "Failed to decrypt secret"),
+            }
+        }
+        values.sort_by_key(|secret| std::cmp::Reverse(secret.value.len()));
+        Ok(Self { values })
+    }
+
+    /// Only the secrets a turn in `chat_id` may use; the rest are unknown
+    pub async fn load_usable(pool: &SqlitePool, chat_id: i64, is_admin: bool) -> Result<Self> {
+        let mut secrets = Self::load(pool).await?;
+        secrets
+            .values
+            .retain(|secret| usable(&secret.shared_chats, chat_id, is_admin));
+        Ok(secrets)
+    }
+
+    /// Fill in the placeholders of `text` with secrets `allowed` accepts.
+    /// Otherwise gives the first name left out and whether it exists.
+    fn fill_text(
+        &self,
+        text: &str,
+        allowed: &dyn Fn(&Secret) -> bool,
+    ) -> std::result::Result<String, (String, bool)> {
+        let mut refused = None;
+        let filled = placeholder_re().replace_all(text, |caps: &regex::Captures| {
+            match self.values.iter().find(|secret| secret.name == caps[1]) {
+                Some(secret) if allowed(secret) => secret.value.clone(),
+                found => {
+                    refused.get_or_insert_with(|| (caps[1].to_string(), found.is_some()));
+                    String::new()
+                }
+            }
+        });
+        match refused {
+            Some(refused) => Err(refused),
+            None => Ok(filled.into_owned()),
+        }
+    }
+
+    /// `fill_text` for every string of `value`
+    fn fill_value(
+        &self,
+        value: &mut Value,
+        allowed: &dyn Fn(&Secret) -> bool,
+    ) -> std::result::Result<(), (String, bool)> {
+        match value {
+            Value::String(s) => *s = self.fill_text(s, allowed)?,
+            Value::Array(items) => {
+                for item in items {
+                    self.fill_value(item, allowed)?;
+                }
+            }
+            Value::Object(map) => {
+                for item in map.values_mut() {
+                    self.fill_value(item, allowed)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Fill in the placeholders of an HTTP header value bound for `host`;
+    /// `host_allowed` tells whether a secret's allowed hosts cover it. Fails
+    /// for unknown secrets and for secrets not allowed to go there.
+    pub fn substitute_header(
+        &self,
+        value: &str,
+        host: &str,
+        host_allowed: impl Fn(&[String]) -> bool,
+    ) -> Result<String> {
+        match self.fill_text(value, &|secret| host_allowed(&secret.allowed_hosts)) {
+            Ok(filled) => Ok(filled),
+            Err((name, false)) => anyhow::bail!("Unknown secret '{}'", name),
+            Err((name, true)) => anyhow::bail!(
+                "Secret '{}' may not be sent to {}; an admin sets its hosts with /secret hosts",
+                name,
+                host
+            ),
+        }
+    }
+
+    /// Fill in the placeholders of arguments bound for MCP server `server`.
+    /// Fails for unknown secrets and for secrets not allowed to go there.
+    pub fn substitute_mcp(&self, value: &mut Value, server: &str) -> Result<()> {
+        let allowed = |secret: &Secret| secret.mcp_servers.iter().any(|s| s == server);
+        match self.fill_value(value, &allowed) {
+            Ok(()) => Ok(()),
+            Err((name, false)) => anyhow::bail!("Unknown secret '{}'", name),
+            Err((name, true)) => anyhow::bail!(
+                "Secret '{}' may not be sent to MCP server '{}'; an admin sets its servers with /secret mcp",
+                name,
+                server
+            ),
+        }
+    }
+
+    /// Replace placeholders in every string of `value` with secret values.
+    /// Fails naming the first unknown secret.
+    pub fn substitute(&self, value: &mut Value) -> Result<()> {
+        self.fill_value(value, &|_| true)
+            .map_err(|(name, _)| anyhow::anyhow!("Unknown secret '{}'", name))
+    }
+
+    /// Replace every secret value in `text`, raw or JSON-escaped, with its
+    /// placeholder
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for Secret { name, value, .. } in &self.values {
+            if value.len() < MIN_REDACT_LEN {
+                continue;
+            }
+            let placeholder = format!("{{{{secret:{}}}}}", name);
+            text = text.replace(value.as_str(), &placeholder);
+            let escaped = serde_json::to_string(value).unwrap_or_default();
+            let escaped = &escaped[1..escaped.len() - 1];
+            if escaped != value {
+                text = text.replace(escaped, &placeholder);
+            }
+        }
+        text
+    }
+}
+
+/// The vault key from the environment or the key file. With `create`, a
+/// missing key file is generated (readable by the owner only).
+fn cipher(create: bool) -> Result<&'static LessSafeKey> {
+    if let Some(cipher) = CIPHER.get() {
+        return Ok(cipher);
+    }
+
+    let encoded = match std::env::var(KEY_ENV) {
+        Ok(key) if !key.trim().is_empty() => key,
+        _ => match std::fs::read_to_string(KEY_FILE) {
+            Ok(key) => key,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && create => {
+                let mut key = [0u8; 32];
+                SystemRandom::new()
+                    .fill(&mut key)
+                    .map_err(|_| anyhow::anyhow!("Failed to generate the vault key"))?;
+                let encoded = BASE64.encode(key);
+                write_key_file(&encoded)?;
+                tracing::info!(file = KEY_FILE, "Created secrets vault key");
+                encoded
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "Failed to read the vault key from {} or ${}",
+                        KEY_FILE, KEY_ENV
+                    )
+                });
+            }
+        },
+    };
+
+    let key = BASE64
+        .decode(encoded.trim())
+        .context("The vault key is not valid base64")?;
+    let key = UnboundKey::new(&AES_256_GCM, &key)
+        .map_err(|_| anyhow::anyhow!("The vault key must be 32 bytes"))?;
+    let _ = CIPHER.set(LessSafeKey::new(key));
+    Ok(CIPHER.get().expect("cipher was just set"))
+}
+
+fn write_key_file(encoded: &str) -> Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(KEY_FILE)
+        .with_context(|| format!("Failed to create {}", KEY_FILE))?;
+    writeln!(file, "{}", encoded)?;
+    Ok(())
+}
+
+/// base64(nonce || ciphertext || tag); the name is bound as associated data
+/// so a value can't be moved to another name
+fn encrypt(cipher: &LessSafeKey, name: &str, value: &str) -> Result<String> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| anyhow::anyhow!("Failed to generate a nonce"))?;
+
+    let mut sealed = value.as_bytes().to_vec();
+    cipher
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(name.as_bytes()),
+            &mut sealed,
+        )
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt secret"))?;
+
+    let mut out = nonce.to_vec();
+    out.extend(sealed);
+    Ok(BASE64.encode(out))
+}
+
+fn decrypt(cipher: &LessSafeKey, name: &str, encrypted: &str) -> Result<String> {
+    let data = BASE64.decode(encrypted)?;
+    if data.len() < NONCE_LEN {
+        anyhow::bail!("Encrypted value is truncated");
+    }
+    let (nonce, sealed) = data.split_at(NONCE_LEN);
+    let nonce =
+        Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow::anyhow!("Invalid nonce"))?;
+
+    let mut sealed = sealed.to_vec();
+    let plain = cipher
+        .open_in_place(nonce, Aad::from(name.as_bytes()), &mut sealed)
+        .map_err(|_| anyhow::anyhow!("Wrong vault key or corrupted value"))?;
+    Ok(String::from_utf8(plain.to_vec())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn vault() -> Secrets {
+        Secrets {
+            values: vec![Secret {
+                name: "gh".into(),
+                value: "ghp_token".into(),
+                allowed_hosts: host_list("api.github.com"),
+                mcp_servers: server_list("github, tracker"),
+                shared_chats: String::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn fills_mcp_arguments_only_for_listed_servers() {
+        let mut args = json!({"auth": "Bearer {{secret:gh}}", "list": ["{{secret:gh}}"]});
+        vault().substitute_mcp(&mut args, "tracker").unwrap();
+        assert_eq!(
+            args,
+            json!({"auth": "Bearer ghp_token", "list": ["ghp_token"]})
+        );
+
+        let mut args = json!({"auth": "{{secret:gh}}"});
+        let error = vault().substitute_mcp(&mut args, "other").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("may not be sent to MCP server 'other'")
+        );
+
+        let mut args = json!({"auth": "{{secret:missing}}"});
+        let error = vault().substitute_mcp(&mut args, "github").unwrap_err();
+        assert_eq!(error.to_string(), "Unknown secret 'missing'");
+    }
+
+    #[test]
+    fn fills_headers_only_for_allowed_hosts() {
+        let allowed = |host: &str| {
+            vault().substitute_header("token {{secret:gh}}", host, |hosts| {
+                hosts.iter().any(|h| h == host)
+            })
+        };
+        assert_eq!(allowed("api.github.com").unwrap(), "token ghp_token");
+        assert!(allowed("evil.example").is_err());
+    }
+
+    #[test]
+    fn redacts_values() {
+        assert_eq!(vault().redact("sent ghp_token"), "sent {{secret:gh}}");
+    }
+}
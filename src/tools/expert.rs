@@ -0,0 +1,227 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+
+use super::schema::parse_args;
+use super::{Tool, ToolContext};
+use crate::config;
+use crate::db;
+use crate::types::{ChatMessage, ChatRequest, ChatResponse, ExpertRow, MessageContent};
+
+pub struct Expert;
+
+#[async_trait]
+impl Tool for Expert {
+    fn name(&self) -> &str {
+        "expert"
+    }
+
+    fn description(&self) -> &str {
+        "Ask a specialist model for deeper analysis on difficult questions. This is a higher-cost feature, so use it only when standard reasoning is likely insufficient.\n\
+         Select one of the configured experts by name with `expert`; the available experts are listed in its description.\n\
+         Provide a clear `question` with enough context for best results."
+    }
+
+    fn parameters(&self) -> Value {
+        expert_schema(json!({"type": "string"}), DEFAULT_MAX_TOKENS)
+    }
+
+    async fn parameters_for(&self, ctx: &ToolContext<'_>) -> Option<Value> {
+        let experts = match available_experts(ctx.pool, ctx.chat_id).await {
+            Ok(experts) => experts,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to load expert roster");
+                return None;
+            }
+        };
+        if experts.is_empty() {
+            return None;
+        }
+
+        let names: Vec<&str> = experts.iter().map(|e| e.name.as_str()).collect();
+        let roster: Vec<String> = experts
+            .iter()
+            .map(|e| format!("- {} ({}): {}", e.name, e.kind, e.description))
+            .collect();
+        let max_tokens = experts
+            .iter()
+            .map(|e| e.max_tokens)
+            .max()
+            .unwrap_or(DEFAULT_MAX_TOKENS);
+
+        Some(expert_schema(
+            json!({
+                "type": "string",
+                "enum": names,
+                "description": format!("Expert to ask:\n{}", roster.join("\n"))
+            }),
+            max_tokens,
+        ))
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_expert(ctx.pool, ctx.chat_id, parse_args(args)?).await
+    }
+}
+
+const OPENROUTER_CHAT_COMPLETIONS_URL: &str = "https://openrouter.ai/api/v1/chat/completions";
+const MIN_MAX_TOKENS: i64 = 128;
+const DEFAULT_MAX_TOKENS: i64 = 1024;
+
+fn expert_schema(expert: Value, max_tokens: i64) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "expert": expert,
+            "question": {
+                "type": "string",
+                "description": "The exact question to ask the selected expert. Keep context concise but complete."
+            },
+            "max_tokens": {
+                "type": "integer",
+                "minimum": MIN_MAX_TOKENS,
+                "maximum": max_tokens.max(MIN_MAX_TOKENS),
+                "description": format!(
+                    "Optional token budget for the expert answer. Default: {}, capped by the expert's own limit.",
+                    DEFAULT_MAX_TOKENS
+                )
+            }
+        },
+        "required": ["expert", "question"]
+    })
+}
+
+/// Enabled experts that may be used in this chat
+async fn available_experts(pool: &SqlitePool, chat_id: i64) -> Result<Vec<ExpertRow>> {
+    Ok(db::expert_list(pool, false)
+        .await?
+        .into_iter()
+        .filter(|e| e.allows_chat(chat_id))
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct ExpertArgs {
+    expert: String,
+    question: String,
+    max_tokens: Option<i64>,
+}
+
+async fn execute_expert(pool: &SqlitePool, chat_id: i64, args: ExpertArgs) -> Result<String> {
+    let question = args.question.as_str();
+
+    if question.trim().is_empty() {
+        return Ok(json!({"error": "'question' cannot be empty"}).to_string());
+    }
+
+    let expert = match db::expert_get(pool, &args.expert).await? {
+        Some(e) if e.enabled && e.allows_chat(chat_id) => e,
+        _ => {
+            return Ok(json!({
+                "error": format!("Expert '{}' is not available in this chat", args.expert)
+            })
+            .to_string());
+        }
+    };
+
+    let cap = expert.max_tokens.max(MIN_MAX_TOKENS);
+    let max_tokens = args
+        .max_tokens
+        .unwrap_or(DEFAULT_MAX_TOKENS)
+        .clamp(MIN_MAX_TOKENS, cap);
+
+    let api_key = match config::get(pool, "llm_token").await? {
+        Some(key) if !key.is_empty() => key,
+        _ => {
+            return Ok(
+                json!({"error": "OpenRouter API key not configured. Set it with: astartebot config set llm_token sk-..."}).to_string(),
+            );
+        }
+    };
+
+    let mut messages = Vec::new();
+    if !expert.system_prompt.trim().is_empty() {
+        messages.push(ChatMessage {
+            role: "system".to_string(),
+            content: Some(MessageContent::Text(expert.system_prompt.clone())),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        });
+    }
+    messages.push(ChatMessage {
+        role: "user".to_string(),
+        content: Some(MessageContent::Text(question.to_string())),
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+    });
+
+    let request = ChatRequest {
+        model: expert.model.clone(),
+        messages,
+        tools: None,
+        max_tokens: Some(max_tokens as u32),
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(120))
+        .build()?;
+
+    let response = match client
+        .post(OPENROUTER_CHAT_COMPLETIONS_URL)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Content-Type", "application/json")
+        .json(&request)
+        .send()
+        .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            return Ok(
+                json!({"error": format!("Failed to contact OpenRouter: {}", e)}).to_string(),
+            );
+        }
+    };
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Ok(json!({"error": format!("OpenRouter HTTP {}: {}", status, body)}).to_string());
+    }
+
+    let body = response.text().await?;
+    let parsed = match serde_json::from_str::<ChatResponse>(&body) {
+        Ok(v) => v,
+        Err(e) => {
+            return Ok(json!({
+                "error": format!("Failed to parse OpenRouter response: {}", e),
+                "raw": body
+            })
+            .to_string());
+        }
+    };
+
+    if let Some(err) = parsed.error {
+        return Ok(json!({"error": format!("OpenRouter error: {}", err.message)}).to_string());
+    }
+
+    let answer = parsed
+        .choices
+        .and_then(|c| c.into_iter().next())
+        .and_then(|choice| choice.message.content)
+        .and_then(|content| content.as_text().map(std::string::ToString::to_string));
+
+    match answer {
+        Some(text) => Ok(json!({
+            "expert": expert.name,
+            "expert_type": expert.kind,
+            "model": expert.model,
+            "answer": text,
+        })
+        .to_string()),
+        None => Ok(json!({"error": "Expert response had no text content"}).to_string()),
+    }
+}
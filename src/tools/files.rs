@@ -0,0 +1,1392 @@
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+use teloxide::prelude::*;
+use teloxide::types::InputFile;
+
+use super::schema::parse_args;
+use super::workspace::MAX_DOCUMENT_BYTES;
+use super::{Tool, ToolContext, action_of};
+use crate::config;
+
+pub struct CrudFile;
+
+#[async_trait]
+impl Tool for CrudFile {
+    fn name(&self) -> &str {
+        "crud_file"
+    }
+
+    fn description(&self) -> &str {
+        "Create, read, list, update, delete, search, move, copy and send files under a configured safe root directory.\n\
+         The root directory is `files/` relative to the program by default, or the value of config key `files_root` when set.\n\
+         SECURITY: All file paths are validated to prevent path traversal. Use relative paths only and never include `..`.\n\
+\n\
+         NAMESPACES: the root is split into `chat/<chat_id>/` (this chat's files), `person/<user_id>/` (the asking user's files, \
+         available in every chat they use) and `shared/` (visible everywhere). A path that doesn't start with one of these \
+         is in this chat's namespace, so `report.md` means `chat/<chat_id>/report.md`. Other chats' and users' namespaces \
+         are off limits; admins can set `all_namespaces` to browse everything. Each namespace has a disk quota.\n\
+\n\
+         ACTIONS:\n\
+         - create: create a new file. Requires `path` and `content`. Optional `overwrite`, and `encoding`='base64' to write binary content.\n\
+         - read: read file contents. Requires `path`. Optional `offset` (0-based, default 0) and `line_count` (default 200) for chunked reads. \
+           Binary files (or `encoding`='base64') are returned whole as base64, up to 512 KB.\n\
+         - list: list files/folders with size and modification time. Optional `path` (directory) and `recursive`.\n\
+         - stat: size, modification time and MIME type of a file or directory. Requires `path`.\n\
+         - search: find lines matching the regex `pattern` in text files. Optional `path` (directory to search, default this chat's namespace) and `max_results` (default 100).\n\
+         - move / copy: move (or rename) a file or directory, or copy a file, to `destination`. Optional `overwrite`. Missing parent directories are created.\n\
+         - mkdir: create a directory and its parents. Requires `path`.\n\
+         - send: upload a file to the current chat as a Telegram document (max 50 MB). Requires `path`, optional `caption`. \
+           Use this when the user asks you to send them a file.\n\
+         - update: edit file contents. Requires `path` and `content` for replace/insert modes.\n\
+           - Default behavior (no offset/count/mode): replace entire file.\n\
+           - Partial replace requires `offset`; `line_count` defaults to 1 when omitted.\n\
+           - `mode`: replace (default), insert, delete, append.\n\
+         - delete: delete a file. Requires `path`."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "enum": ["create", "read", "list", "update", "delete", "stat", "search", "move", "copy", "mkdir", "send"],
+                    "description": "The action to perform."
+                },
+                "path": {
+                    "type": "string",
+                    "description": "Relative file path, optionally starting with a namespace (`chat/<id>/`, `person/<id>/`, `shared/`); otherwise it is in this chat's namespace. Must not be absolute and must not contain '..'."
+                },
+                "content": {
+                    "type": "string",
+                    "description": "File content for create/update/insert/append/replace actions."
+                },
+                "overwrite": {
+                    "type": "boolean",
+                    "description": "For create, move and copy: allow replacing an existing file when true. Default: false."
+                },
+                "encoding": {
+                    "type": "string",
+                    "enum": ["text", "base64"],
+                    "description": "For create: 'base64' decodes `content` before writing. For read: 'base64' returns the raw bytes as base64. Default: 'text'."
+                },
+                "destination": {
+                    "type": "string",
+                    "description": "For move and copy: the target path inside the files root."
+                },
+                "pattern": {
+                    "type": "string",
+                    "description": "For search: regex matched against each line. Example: \"(?i)invoice\\s+\\d+\"."
+                },
+                "max_results": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "For search: maximum matching lines returned. Default: 100. Max: 500."
+                },
+                "caption": {
+                    "type": "string",
+                    "description": "For send: optional caption shown under the document."
+                },
+                "offset": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "Zero-based line offset for read/update/delete/insert operations."
+                },
+                "line_count": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "Number of lines for read/update/delete when working with a line range. For read, this is the page size."
+                },
+                "recursive": {
+                    "type": "boolean",
+                    "description": "For list: include nested subdirectories recursively."
+                },
+                "mode": {
+                    "type": "string",
+                    "enum": ["replace", "insert", "delete", "append"],
+                    "description": "Update mode. `replace` is default when no mode is provided."
+                },
+                "all_namespaces": {
+                    "type": "boolean",
+                    "description": "Admin only. Allow paths in any chat's or person's namespace, and treat an empty path as the whole root. Default: false."
+                }
+            },
+            "required": ["action"]
+        })
+    }
+
+    fn has_side_effects(&self, args: &Value) -> bool {
+        !matches!(
+            action_of(args).as_str(),
+            "read" | "list" | "stat" | "search"
+        )
+    }
+
+    fn is_destructive(&self, _ctx: &ToolContext<'_>, args: &Value) -> bool {
+        match action_of(args).as_str() {
+            "delete" => true,
+            "create" | "move" | "copy" => args["overwrite"].as_bool().unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_crud_file(ctx, parse_args(args)?).await
+    }
+}
+
+const FILES_TOOL_DEFAULT_ROOT: &str = "files";
+const FILES_TOOL_ROOT_KEY: &str = "files_root";
+const FILES_TOOL_LIST_LIMIT: usize = 500;
+const FILES_TOOL_DEFAULT_READ_LIMIT: usize = 200;
+const NAMESPACE_CHAT: &str = "chat";
+const NAMESPACE_PERSON: &str = "person";
+const NAMESPACE_SHARED: &str = "shared";
+/// Default quotas in MB, per namespace kind
+const DEFAULT_CHAT_QUOTA_MB: &str = "100";
+const DEFAULT_PERSON_QUOTA_MB: &str = "100";
+const DEFAULT_SHARED_QUOTA_MB: &str = "500";
+/// Largest file returned as base64 by read
+const FILES_TOOL_MAX_BINARY_READ: u64 = 512 * 1024;
+const FILES_TOOL_DEFAULT_SEARCH_RESULTS: usize = 100;
+const FILES_TOOL_MAX_SEARCH_RESULTS: usize = 500;
+/// Files looked at by one search, and the largest one searched
+const FILES_TOOL_SEARCH_FILE_LIMIT: usize = 2000;
+const FILES_TOOL_SEARCH_MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+/// Matching lines are cut to this many characters
+const FILES_TOOL_SEARCH_LINE_CHARS: usize = 300;
+
+/// Which namespaces of the files root a caller may use: `chat/<id>/` of the
+/// current chat, `person/<id>/` of the caller and `shared/`, like the memory
+/// segments. An admin browsing with `all_namespaces` may use all of them.
+pub(super) struct FileScope {
+    chat_id: i64,
+    user_id: i64,
+    all: bool,
+}
+
+impl FileScope {
+    pub(super) fn for_caller(ctx: &ToolContext<'_>) -> Self {
+        Self {
+            chat_id: ctx.chat_id,
+            user_id: ctx.user_id,
+            all: false,
+        }
+    }
+
+    fn chat_dir(&self) -> PathBuf {
+        PathBuf::from(NAMESPACE_CHAT).join(self.chat_id.to_string())
+    }
+
+    /// A sanitized path, relative to the root, checked against the caller's
+    /// namespaces. Paths outside any namespace belong to the current chat.
+    fn qualify(&self, relative: PathBuf) -> Result<PathBuf> {
+        let mut parts = relative.iter().map(|p| p.to_string_lossy());
+        let Some(first) = parts.next() else {
+            return Ok(if self.all { relative } else { self.chat_dir() });
+        };
+
+        let own = match first.as_ref() {
+            NAMESPACE_SHARED => return Ok(relative),
+            NAMESPACE_CHAT => self.chat_id,
+            NAMESPACE_PERSON => self.user_id,
+            _ => return Ok(self.chat_dir().join(relative)),
+        };
+        let kind = first.to_string();
+        match parts.next() {
+            None if self.all => Ok(relative),
+            None => anyhow::bail!(
+                "Give the namespace ID: this chat can use chat/{}/, person/{}/ and shared/",
+                self.chat_id,
+                self.user_id
+            ),
+            Some(id) => {
+                let id: i64 = id
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("'{}/{}' is not a namespace", kind, id))?;
+                if id != own && !self.all {
+                    anyhow::bail!(
+                        "'{}/{}' belongs to another {}; this chat can use chat/{}/, person/{}/ and shared/",
+                        kind,
+                        id,
+                        kind,
+                        self.chat_id,
+                        self.user_id
+                    );
+                }
+                Ok(relative)
+            }
+        }
+    }
+}
+
+/// `shared`, or `chat/<id>` / `person/<id>`, holding a path relative to the root
+fn namespace_dir(relative: &Path) -> Option<PathBuf> {
+    let mut parts = relative.iter();
+    let first = parts.next()?;
+    if first == NAMESPACE_SHARED {
+        return Some(PathBuf::from(first));
+    }
+    if first == NAMESPACE_CHAT || first == NAMESPACE_PERSON {
+        return parts.next().map(|id| Path::new(first).join(id));
+    }
+    None
+}
+
+async fn resolve_file_root(pool: &SqlitePool) -> Result<PathBuf> {
+    let configured = config::get(pool, FILES_TOOL_ROOT_KEY).await?;
+    let root = configured.unwrap_or_else(|| FILES_TOOL_DEFAULT_ROOT.to_string());
+    let trimmed = root.trim();
+    let root_path = if trimmed.is_empty() {
+        std::env::current_dir()?.join(FILES_TOOL_DEFAULT_ROOT)
+    } else {
+        let configured_path = Path::new(trimmed);
+        if configured_path.is_absolute() {
+            configured_path.to_path_buf()
+        } else {
+            std::env::current_dir()?.join(configured_path)
+        }
+    };
+
+    tokio::fs::create_dir_all(&root_path).await?;
+    let root = tokio::fs::canonicalize(&root_path).await?;
+    migrate_legacy_files(&root).await?;
+    Ok(root)
+}
+
+/// Files from before namespaces were visible in every chat; move them to
+/// `shared/` so they stay that way
+async fn migrate_legacy_files(root: &Path) -> Result<()> {
+    let mut entries = tokio::fs::read_dir(root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        if [NAMESPACE_CHAT, NAMESPACE_PERSON, NAMESPACE_SHARED]
+            .iter()
+            .any(|ns| name == *ns)
+        {
+            continue;
+        }
+        let shared = root.join(NAMESPACE_SHARED);
+        tokio::fs::create_dir_all(&shared).await?;
+        let target = shared.join(&name);
+        if tokio::fs::try_exists(&target).await? {
+            tracing::warn!(file = %name.to_string_lossy(), "Not moving file into shared/: name taken");
+            continue;
+        }
+        tokio::fs::rename(entry.path(), &target).await?;
+        tracing::info!(file = %name.to_string_lossy(), "Moved file into the shared namespace");
+    }
+    Ok(())
+}
+
+pub(super) fn sanitize_file_path(raw: &str, allow_empty: bool) -> Result<PathBuf> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        if allow_empty {
+            return Ok(PathBuf::new());
+        }
+        anyhow::bail!("Path is required for this action");
+    }
+
+    if raw == "." {
+        return if allow_empty {
+            Ok(PathBuf::new())
+        } else {
+            anyhow::bail!("Path '.' is not allowed; provide a target file path");
+        };
+    }
+
+    let input_path = Path::new(raw);
+    if input_path.is_absolute() {
+        anyhow::bail!("Absolute paths are not allowed");
+    }
+
+    let mut safe = PathBuf::new();
+    for component in input_path.components() {
+        match component {
+            Component::Prefix(_) => anyhow::bail!("Invalid path prefix in '{}'", raw),
+            Component::RootDir => anyhow::bail!("Absolute path component is not allowed"),
+            Component::CurDir => {}
+            Component::ParentDir => anyhow::bail!("Path traversal using '..' is not allowed"),
+            Component::Normal(seg) => safe.push(seg),
+        }
+    }
+
+    if safe.as_os_str().is_empty() {
+        if allow_empty {
+            Ok(safe)
+        } else {
+            anyhow::bail!("Path is required for this action");
+        }
+    } else {
+        Ok(safe)
+    }
+}
+
+pub(super) async fn resolve_file_path(
+    pool: &SqlitePool,
+    scope: &FileScope,
+    raw: &str,
+    allow_empty: bool,
+) -> Result<PathBuf> {
+    let root = resolve_file_root(pool).await?;
+    let relative = scope.qualify(sanitize_file_path(raw, allow_empty)?)?;
+    if let Some(namespace) = namespace_dir(&relative) {
+        tokio::fs::create_dir_all(root.join(namespace)).await?;
+    }
+    let candidate = root.join(relative);
+
+    if let Ok(canonical) = tokio::fs::canonicalize(&candidate).await {
+        if !canonical.starts_with(&root) {
+            anyhow::bail!("Path escapes the configured root directory");
+        }
+        return Ok(canonical);
+    }
+
+    // Candidate may not exist (new file). Validate the nearest existing ancestor
+    // to avoid creating/reading through a symlink outside root.
+    let mut check_path = candidate.clone();
+    while check_path != root {
+        if let Ok(true) = tokio::fs::try_exists(&check_path).await {
+            let canonical_check = tokio::fs::canonicalize(&check_path).await?;
+            if !canonical_check.starts_with(&root) {
+                anyhow::bail!("Path escapes the configured root directory");
+            }
+            break;
+        }
+        match check_path.parent() {
+            Some(parent) => check_path = parent.to_path_buf(),
+            None => break,
+        }
+    }
+
+    Ok(candidate)
+}
+
+fn display_file_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string_lossy().to_string())
+}
+
+#[derive(Deserialize)]
+struct CrudFileArgs {
+    action: String,
+    #[serde(default)]
+    path: String,
+    content: Option<String>,
+    #[serde(default)]
+    overwrite: bool,
+    offset: Option<usize>,
+    line_count: Option<usize>,
+    mode: Option<String>,
+    #[serde(default)]
+    recursive: bool,
+    encoding: Option<String>,
+    destination: Option<String>,
+    pattern: Option<String>,
+    max_results: Option<usize>,
+    caption: Option<String>,
+    #[serde(default)]
+    all_namespaces: bool,
+}
+
+async fn execute_crud_file(ctx: &ToolContext<'_>, args: CrudFileArgs) -> Result<String> {
+    let pool = ctx.pool;
+    let action = args.action.to_lowercase();
+    let base64 = match args.encoding.as_deref().unwrap_or("text") {
+        "text" => false,
+        "base64" => true,
+        other => {
+            return Ok(json!({
+                "success": false,
+                "error": format!("Invalid encoding '{}'. Expected text or base64", other)
+            })
+            .to_string());
+        }
+    };
+
+    if args.all_namespaces && !ctx.is_admin {
+        return Ok(json!({
+            "success": false,
+            "error": "'all_namespaces' is restricted to bot admins"
+        })
+        .to_string());
+    }
+    let mut scope = FileScope::for_caller(ctx);
+    scope.all = args.all_namespaces;
+    let scope = &scope;
+
+    let root = resolve_file_root(pool).await?;
+
+    match action.as_str() {
+        "create" => {
+            let path = args.path.as_str();
+            let content = args
+                .content
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("Missing 'content' for create action"))?;
+            let overwrite = args.overwrite;
+
+            if path.trim().is_empty() {
+                return Ok(json!({
+                    "success": false,
+                    "error": "Missing 'path' for create action"
+                })
+                .to_string());
+            }
+
+            let file_path = resolve_file_path(pool, scope, path, false).await?;
+            if !overwrite && tokio::fs::try_exists(&file_path).await? {
+                return Ok(json!({
+                    "success": false,
+                    "error": "File already exists. Set overwrite=true to replace it.",
+                    "path": display_file_path(&root, &file_path),
+                })
+                .to_string());
+            }
+
+            if let Some(parent) = file_path.parent()
+                && !tokio::fs::try_exists(parent).await? {
+                    return Ok(json!({
+                        "error": format!("Parent directory '{}' does not exist", display_file_path(&root, parent)),
+                        "path": display_file_path(&root, &file_path),
+                    })
+                    .to_string());
+                }
+
+            let bytes = if base64 {
+                match BASE64.decode(content.trim()) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        return Ok(json!({
+                            "success": false,
+                            "error": format!("content is not valid base64: {}", e),
+                        })
+                        .to_string());
+                    }
+                }
+            } else {
+                content.as_bytes().to_vec()
+            };
+            if let Some(error) = check_quota(pool, &root, &file_path, bytes.len() as u64).await? {
+                return Ok(error.to_string());
+            }
+            tokio::fs::write(&file_path, bytes).await?;
+            let metadata = tokio::fs::metadata(&file_path).await?;
+            Ok(json!({
+                "success": true,
+                "action": "create",
+                "path": display_file_path(&root, &file_path),
+                "bytes": metadata.len(),
+                "overwrite": overwrite,
+            })
+            .to_string())
+        }
+        "read" => {
+            let path = args.path.as_str();
+            if path.trim().is_empty() {
+                return Ok(
+                    json!({"success": false, "error": "Missing 'path' for read action"})
+                        .to_string(),
+                );
+            }
+
+            let file_path = resolve_file_path(pool, scope, path, false).await?;
+            if !tokio::fs::try_exists(&file_path).await? {
+                return Ok(json!({
+                    "success": false,
+                    "message": format!("File '{}' not found", display_file_path(&root, &file_path))
+                })
+                .to_string());
+            }
+
+            let metadata = tokio::fs::metadata(&file_path).await?;
+            if metadata.is_dir() {
+                return Ok(json!({
+                    "success": false,
+                    "error": format!("'{}' is a directory", display_file_path(&root, &file_path))
+                })
+                .to_string());
+            }
+
+            if base64 {
+                return read_base64(&root, &file_path, metadata.len()).await;
+            }
+            let content = match tokio::fs::read_to_string(&file_path).await {
+                Ok(content) => content,
+                // Not UTF-8: return the bytes instead
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    return read_base64(&root, &file_path, metadata.len()).await;
+                }
+                Err(e) => {
+                    return Ok(json!({
+                        "success": false,
+                        "error": format!(
+                            "Failed to read '{}': {}",
+                            display_file_path(&root, &file_path),
+                            e
+                        )
+                    })
+                    .to_string());
+                }
+            };
+
+            let lines: Vec<&str> = content.split('\n').collect();
+            let offset = args.offset.unwrap_or(0);
+            let line_count = args.line_count;
+
+            if let Some(count) = line_count
+                && count == 0 {
+                    return Ok(json!({
+                        "success": false,
+                        "path": display_file_path(&root, &file_path),
+                        "error": "line_count must be a positive integer",
+                        "line_count": count
+                    })
+                    .to_string());
+                }
+
+            if offset > lines.len() {
+                return Ok(json!({
+                    "success": false,
+                    "path": display_file_path(&root, &file_path),
+                    "error": "Offset is beyond end of file",
+                    "offset": offset,
+                    "total_lines": lines.len()
+                })
+                .to_string());
+            }
+
+            if offset == lines.len() {
+                return Ok(json!({
+                    "success": true,
+                    "action": "read",
+                    "path": display_file_path(&root, &file_path),
+                    "size_bytes": metadata.len(),
+                    "total_lines": lines.len(),
+                    "offset": offset,
+                    "line_count": 0,
+                    "content": "",
+                    "has_more": false
+                })
+                .to_string());
+            }
+
+            let take = line_count
+                .unwrap_or(FILES_TOOL_DEFAULT_READ_LIMIT)
+                .min(lines.len() - offset);
+            let end = offset + take;
+            let selected = lines[offset..end].join("\n");
+
+            Ok(json!({
+                "success": true,
+                "action": "read",
+                "path": display_file_path(&root, &file_path),
+                "size_bytes": metadata.len(),
+                "offset": offset,
+                "line_count": take,
+                "total_lines": lines.len(),
+                "content": selected,
+                "has_more": end < lines.len()
+            })
+            .to_string())
+        }
+        "update" => {
+            let path = args.path.as_str();
+            if path.trim().is_empty() {
+                return Ok(
+                    json!({"success": false, "error": "Missing 'path' for update action"})
+                        .to_string(),
+                );
+            }
+
+            let file_path = resolve_file_path(pool, scope, path, false).await?;
+            if !tokio::fs::try_exists(&file_path).await? {
+                return Ok(json!({
+                    "success": false,
+                    "message": format!("File '{}' not found", display_file_path(&root, &file_path))
+                })
+                .to_string());
+            }
+
+            let metadata = tokio::fs::metadata(&file_path).await?;
+            if metadata.is_dir() {
+                return Ok(json!({
+                    "success": false,
+                    "error": format!("'{}' is a directory", display_file_path(&root, &file_path))
+                })
+                .to_string());
+            }
+
+            let mode = args.mode.as_deref().unwrap_or("replace");
+            let offset = args.offset;
+            let line_count = args.line_count;
+
+            if let Some(count) = line_count
+                && count == 0 {
+                    return Ok(json!({
+                        "success": false,
+                        "error": "line_count must be a positive integer",
+                        "path": display_file_path(&root, &file_path)
+                    })
+                    .to_string());
+                }
+
+            let valid_mode = matches!(mode, "replace" | "insert" | "delete" | "append");
+            if !valid_mode {
+                return Ok(json!({
+                    "success": false,
+                    "error": "Invalid update mode. Expected one of: replace, insert, delete, append",
+                    "path": display_file_path(&root, &file_path),
+                    "mode": mode
+                }).to_string());
+            }
+
+            // At most the new content on top of what is there, or exactly it
+            // for a full replace
+            if mode != "delete" {
+                let added = args.content.as_deref().map_or(0, str::len) as u64;
+                let full_replace = mode == "replace" && offset.is_none() && line_count.is_none();
+                let new_size = if full_replace {
+                    added
+                } else {
+                    metadata.len() + added + 1
+                };
+                if let Some(error) = check_quota(pool, &root, &file_path, new_size).await? {
+                    return Ok(error.to_string());
+                }
+            }
+
+            let existing = tokio::fs::read_to_string(&file_path).await?;
+            let mut lines: Vec<String> =
+                existing.split('\n').map(|line| line.to_string()).collect();
+            let total_lines = lines.len();
+
+            match mode {
+                "replace" => {
+                    if offset.is_none() && line_count.is_none() {
+                        let content = args
+                            .content
+                            .as_deref()
+                            .ok_or_else(|| anyhow::anyhow!("Missing 'content' for full replace"))?;
+                        tokio::fs::write(&file_path, content).await?;
+                    } else {
+                        let offset = offset.unwrap_or(0);
+                        let line_count = line_count.unwrap_or(1);
+
+                        if offset > lines.len() {
+                            return Ok(json!({
+                                "success": false,
+                                "error": "Offset is beyond end of file",
+                                "path": display_file_path(&root, &file_path),
+                                "offset": offset,
+                                "total_lines": total_lines
+                            })
+                            .to_string());
+                        }
+
+                        let content = args
+                            .content
+                            .as_deref()
+                            .ok_or_else(|| anyhow::anyhow!("Missing 'content' for replace mode"))?;
+                        let replacement: Vec<String> =
+                            content.split('\n').map(|line| line.to_string()).collect();
+                        let end = std::cmp::min(lines.len(), offset + line_count);
+                        lines.splice(offset..end, replacement);
+                        tokio::fs::write(&file_path, lines.join("\n")).await?;
+                    }
+                }
+                "insert" => {
+                    let content = args
+                        .content
+                        .as_deref()
+                        .ok_or_else(|| anyhow::anyhow!("Missing 'content' for insert mode"))?;
+                    let insert_pos = offset.unwrap_or(lines.len());
+                    if insert_pos > lines.len() {
+                        return Ok(json!({
+                            "success": false,
+                            "error": "Offset is beyond end of file",
+                            "path": display_file_path(&root, &file_path),
+                            "offset": insert_pos,
+                            "total_lines": total_lines
+                        })
+                        .to_string());
+                    }
+
+                    let insertion: Vec<String> =
+                        content.split('\n').map(|line| line.to_string()).collect();
+                    lines.splice(insert_pos..insert_pos, insertion);
+                    tokio::fs::write(&file_path, lines.join("\n")).await?;
+                }
+                "append" => {
+                    let content = args
+                        .content
+                        .as_deref()
+                        .ok_or_else(|| anyhow::anyhow!("Missing 'content' for append mode"))?;
+                    if !lines.is_empty() {
+                        lines.extend(content.split('\n').map(|line| line.to_string()));
+                    } else {
+                        lines = content.split('\n').map(|line| line.to_string()).collect();
+                    }
+                    tokio::fs::write(&file_path, lines.join("\n")).await?;
+                }
+                "delete" => {
+                    let line_count = line_count.unwrap_or(1);
+                    let offset = offset.unwrap_or(0);
+                    if offset >= lines.len() {
+                        return Ok(json!({
+                            "success": false,
+                            "error": "Offset is beyond end of file",
+                            "path": display_file_path(&root, &file_path),
+                            "offset": offset,
+                            "total_lines": total_lines
+                        })
+                        .to_string());
+                    }
+
+                    let end = std::cmp::min(lines.len(), offset + line_count);
+                    lines.splice(offset..end, std::iter::empty());
+                    tokio::fs::write(&file_path, lines.join("\n")).await?;
+                }
+                _ => {}
+            }
+
+            let metadata = tokio::fs::metadata(&file_path).await?;
+            Ok(json!({
+                "success": true,
+                "action": "update",
+                "path": display_file_path(&root, &file_path),
+                "bytes": metadata.len(),
+            })
+            .to_string())
+        }
+        "delete" => {
+            let path = args.path.as_str();
+            if path.trim().is_empty() {
+                return Ok(
+                    json!({"success": false, "error": "Missing 'path' for delete action"})
+                        .to_string(),
+                );
+            }
+
+            let file_path = resolve_file_path(pool, scope, path, false).await?;
+            if !tokio::fs::try_exists(&file_path).await? {
+                return Ok(json!({
+                    "success": false,
+                    "message": format!("File '{}' not found", display_file_path(&root, &file_path))
+                })
+                .to_string());
+            }
+
+            let metadata = tokio::fs::metadata(&file_path).await?;
+            if metadata.is_dir() {
+                return Ok(json!({
+                    "success": false,
+                    "error": format!("'{}' is a directory. Use list only for directories.", display_file_path(&root, &file_path))
+                }).to_string());
+            }
+
+            tokio::fs::remove_file(&file_path).await?;
+            Ok(json!({
+                "success": true,
+                "action": "delete",
+                "path": display_file_path(&root, &file_path),
+            })
+            .to_string())
+        }
+        "list" => {
+            let path = args.path.as_str();
+            let recursive = args.recursive;
+            let list_root = resolve_file_path(pool, scope, path, true).await?;
+
+            let metadata = match tokio::fs::metadata(&list_root).await {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    return Ok(json!({
+                        "success": false,
+                        "error": format!(
+                            "Path '{}' not found or not accessible: {}",
+                            display_file_path(&root, &list_root),
+                            e
+                        )
+                    })
+                    .to_string());
+                }
+            };
+            if !metadata.is_dir() {
+                return Ok(json!({
+                    "success": false,
+                    "error": format!("'{}' is not a directory", display_file_path(&root, &list_root))
+                }).to_string());
+            }
+
+            let mut queue = vec![list_root.clone()];
+            let mut entries = Vec::new();
+            let mut truncated = false;
+
+            while let Some(dir_path) = queue.pop() {
+                let mut dir_entries = match tokio::fs::read_dir(&dir_path).await {
+                    Ok(d) => d,
+                    Err(e) => {
+                        return Ok(json!({
+                            "success": false,
+                            "error": format!(
+                                "Failed to read directory '{}': {}",
+                                display_file_path(&root, &dir_path),
+                                e
+                            )
+                        })
+                        .to_string());
+                    }
+                };
+
+                while let Some(entry) = dir_entries.next_entry().await? {
+                    if entries.len() >= FILES_TOOL_LIST_LIMIT {
+                        truncated = true;
+                        break;
+                    }
+
+                    let entry_path = entry.path();
+                    let entry_name = entry.file_name().to_string_lossy().to_string();
+                    let file_type = entry.file_type().await?;
+
+                    let metadata = tokio::fs::metadata(&entry_path).await;
+                    let is_symlink = file_type.is_symlink();
+                    let modified = metadata
+                        .as_ref()
+                        .ok()
+                        .and_then(|meta| meta.modified().ok())
+                        .map(format_time);
+                    let (kind, size_bytes, is_dir) = match metadata {
+                        Ok(meta) => {
+                            let kind = if meta.is_dir() {
+                                "dir"
+                            } else if is_symlink {
+                                "symlink"
+                            } else {
+                                "file"
+                            };
+                            (kind, meta.len(), meta.is_dir())
+                        }
+                        Err(_) => ("unknown", 0, false),
+                    };
+
+                    entries.push(json!({
+                        "name": entry_name,
+                        "path": display_file_path(&root, &entry_path),
+                        "kind": kind,
+                        "size_bytes": size_bytes,
+                        "modified": modified,
+                    }));
+
+                    if recursive && is_dir && !is_symlink {
+                        queue.push(entry_path);
+                    }
+                }
+
+                if truncated {
+                    break;
+                }
+            }
+
+            Ok(json!({
+                "success": true,
+                "action": "list",
+                "path": display_file_path(&root, &list_root),
+                "count": entries.len(),
+                "truncated": truncated,
+                "limit": FILES_TOOL_LIST_LIMIT,
+                "entries": entries,
+            })
+            .to_string())
+        }
+        "stat" => stat_file(pool, scope, &root, &args.path).await,
+        "search" => {
+            let Some(pattern) = args.pattern.as_deref() else {
+                return Ok(
+                    json!({"success": false, "error": "Missing 'pattern' for search action"})
+                        .to_string(),
+                );
+            };
+            let max_results = args
+                .max_results
+                .map(|v| v.clamp(1, FILES_TOOL_MAX_SEARCH_RESULTS))
+                .unwrap_or(FILES_TOOL_DEFAULT_SEARCH_RESULTS);
+            search_files(pool, scope, &root, &args.path, pattern, max_results).await
+        }
+        "move" | "copy" => {
+            let Some(destination) = args.destination.as_deref() else {
+                return Ok(json!({
+                    "success": false,
+                    "error": format!("Missing 'destination' for {} action", action)
+                })
+                .to_string());
+            };
+            move_or_copy(
+                pool,
+                scope,
+                &root,
+                &args.path,
+                destination,
+                args.overwrite,
+                action == "copy",
+            )
+            .await
+        }
+        "mkdir" => {
+            let dir_path = resolve_file_path(pool, scope, &args.path, false).await?;
+            let created = match tokio::fs::metadata(&dir_path).await {
+                Ok(meta) if meta.is_dir() => false,
+                Ok(_) => {
+                    return Ok(json!({
+                        "success": false,
+                        "error": format!("'{}' exists and is a file", display_file_path(&root, &dir_path))
+                    })
+                    .to_string());
+                }
+                Err(_) => {
+                    tokio::fs::create_dir_all(&dir_path).await?;
+                    true
+                }
+            };
+            Ok(json!({
+                "success": true,
+                "action": "mkdir",
+                "path": display_file_path(&root, &dir_path),
+                "created": created,
+            })
+            .to_string())
+        }
+        "send" => send_file(ctx, scope, &root, &args.path, args.caption.as_deref()).await,
+        _ => Ok(json!({
+            "error": "Invalid action. Expected one of: create, read, list, update, delete, stat, search, move, copy, mkdir, send"
+        })
+        .to_string()),
+    }
+}
+
+/// Return a whole file as base64, if it is small enough
+async fn read_base64(root: &Path, file_path: &Path, size: u64) -> Result<String> {
+    if size > FILES_TOOL_MAX_BINARY_READ {
+        return Ok(json!({
+            "success": false,
+            "path": display_file_path(root, file_path),
+            "error": format!(
+                "File is {} bytes; base64 reads are limited to {} bytes. Use action 'send' to deliver it to the chat.",
+                size, FILES_TOOL_MAX_BINARY_READ
+            ),
+            "size_bytes": size,
+            "mime": mime_type(file_path),
+        })
+        .to_string());
+    }
+
+    let bytes = tokio::fs::read(file_path).await?;
+    Ok(json!({
+        "success": true,
+        "action": "read",
+        "path": display_file_path(root, file_path),
+        "size_bytes": bytes.len(),
+        "mime": mime_type(file_path),
+        "encoding": "base64",
+        "content": BASE64.encode(&bytes),
+    })
+    .to_string())
+}
+
+async fn stat_file(
+    pool: &SqlitePool,
+    scope: &FileScope,
+    root: &Path,
+    path: &str,
+) -> Result<String> {
+    let file_path = resolve_file_path(pool, scope, path, false).await?;
+    let metadata = match tokio::fs::metadata(&file_path).await {
+        Ok(metadata) => metadata,
+        Err(_) => {
+            return Ok(json!({
+                "success": false,
+                "message": format!("File '{}' not found", display_file_path(root, &file_path))
+            })
+            .to_string());
+        }
+    };
+
+    let mut result = json!({
+        "success": true,
+        "action": "stat",
+        "path": display_file_path(root, &file_path),
+        "kind": if metadata.is_dir() { "dir" } else { "file" },
+        "size_bytes": metadata.len(),
+        "modified": metadata.modified().ok().map(format_time),
+        "readonly": metadata.permissions().readonly(),
+    });
+    if let Ok(created) = metadata.created() {
+        result["created"] = json!(format_time(created));
+    }
+    if metadata.is_file() {
+        result["mime"] = json!(mime_type(&file_path));
+    }
+    Ok(result.to_string())
+}
+
+/// Grep text files under `path` for lines matching `pattern`. Symlinks,
+/// large files and files that are not UTF-8 are skipped.
+async fn search_files(
+    pool: &SqlitePool,
+    scope: &FileScope,
+    root: &Path,
+    path: &str,
+    pattern: &str,
+    max_results: usize,
+) -> Result<String> {
+    let re = match regex::Regex::new(pattern) {
+        Ok(re) => re,
+        Err(e) => {
+            return Ok(json!({
+                "success": false,
+                "error": format!("Invalid regex '{}': {}", pattern, e)
+            })
+            .to_string());
+        }
+    };
+    let search_root = resolve_file_path(pool, scope, path, true).await?;
+    if !tokio::fs::metadata(&search_root)
+        .await
+        .is_ok_and(|m| m.is_dir())
+    {
+        return Ok(json!({
+            "success": false,
+            "error": format!("'{}' is not a directory", display_file_path(root, &search_root))
+        })
+        .to_string());
+    }
+
+    let mut queue = vec![search_root.clone()];
+    let mut matches = Vec::new();
+    let mut files_searched = 0;
+    let mut truncated = false;
+
+    'walk: while let Some(dir_path) = queue.pop() {
+        let Ok(mut dir_entries) = tokio::fs::read_dir(&dir_path).await else {
+            continue;
+        };
+        while let Some(entry) = dir_entries.next_entry().await? {
+            let Ok(meta) = tokio::fs::symlink_metadata(entry.path()).await else {
+                continue;
+            };
+            if meta.is_dir() {
+                queue.push(entry.path());
+                continue;
+            }
+            if !meta.is_file() || meta.len() > FILES_TOOL_SEARCH_MAX_FILE_BYTES {
+                continue;
+            }
+            if files_searched >= FILES_TOOL_SEARCH_FILE_LIMIT {
+                truncated = true;
+                break 'walk;
+            }
+            files_searched += 1;
+
+            let Ok(content) = tokio::fs::read_to_string(entry.path()).await else {
+                continue;
+            };
+            for (offset, line) in content.split('\n').enumerate() {
+                if !re.is_match(line) {
+                    continue;
+                }
+                if matches.len() >= max_results {
+                    truncated = true;
+                    break 'walk;
+                }
+                matches.push(json!({
+                    "path": display_file_path(root, &entry.path()),
+                    "offset": offset,
+                    "line": line.chars().take(FILES_TOOL_SEARCH_LINE_CHARS).collect::<String>(),
+                }));
+            }
+        }
+    }
+
+    Ok(json!({
+        "success": true,
+        "action": "search",
+        "path": display_file_path(root, &search_root),
+        "pattern": pattern,
+        "files_searched": files_searched,
+        "count": matches.len(),
+        "truncated": truncated,
+        "matches": matches,
+    })
+    .to_string())
+}
+
+async fn move_or_copy(
+    pool: &SqlitePool,
+    scope: &FileScope,
+    root: &Path,
+    path: &str,
+    destination: &str,
+    overwrite: bool,
+    copy: bool,
+) -> Result<String> {
+    let action = if copy { "copy" } else { "move" };
+    let source = resolve_file_path(pool, scope, path, false).await?;
+    let target = resolve_file_path(pool, scope, destination, false).await?;
+
+    let Ok(metadata) = tokio::fs::metadata(&source).await else {
+        return Ok(json!({
+            "success": false,
+            "message": format!("File '{}' not found", display_file_path(root, &source))
+        })
+        .to_string());
+    };
+    if copy && metadata.is_dir() {
+        return Ok(json!({
+            "success": false,
+            "error": format!("'{}' is a directory; only files can be copied", display_file_path(root, &source))
+        })
+        .to_string());
+    }
+    if target.starts_with(&source) {
+        return Ok(json!({
+            "success": false,
+            "error": "Destination is the source or inside it",
+        })
+        .to_string());
+    }
+    if let Ok(existing) = tokio::fs::metadata(&target).await
+        && (existing.is_dir() || !overwrite)
+    {
+        return Ok(json!({
+            "success": false,
+            "error": if existing.is_dir() {
+                "Destination is an existing directory; give the full target path"
+            } else {
+                "Destination already exists. Set overwrite=true to replace it."
+            },
+            "destination": display_file_path(root, &target),
+        })
+        .to_string());
+    }
+
+    // Moving within a namespace doesn't change its usage
+    let same_namespace = !copy
+        && namespace_dir(source.strip_prefix(root).unwrap_or(&source))
+            == namespace_dir(target.strip_prefix(root).unwrap_or(&target));
+    if !same_namespace {
+        let size = disk_usage(&source).await;
+        if let Some(error) = check_quota(pool, root, &target, size).await? {
+            return Ok(error.to_string());
+        }
+    }
+
+    if let Some(parent) = target.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let bytes = if copy {
+        tokio::fs::copy(&source, &target).await?
+    } else {
+        tokio::fs::rename(&source, &target).await?;
+        metadata.len()
+    };
+
+    Ok(json!({
+        "success": true,
+        "action": action,
+        "path": display_file_path(root, &source),
+        "destination": display_file_path(root, &target),
+        "bytes": bytes,
+    })
+    .to_string())
+}
+
+/// Upload a file from the root to the current chat as a document
+async fn send_file(
+    ctx: &ToolContext<'_>,
+    scope: &FileScope,
+    root: &Path,
+    path: &str,
+    caption: Option<&str>,
+) -> Result<String> {
+    let file_path = resolve_file_path(ctx.pool, scope, path, false).await?;
+    let metadata = match tokio::fs::metadata(&file_path).await {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => {
+            return Ok(json!({
+                "success": false,
+                "message": format!("File '{}' not found", display_file_path(root, &file_path))
+            })
+            .to_string());
+        }
+    };
+    if metadata.len() > MAX_DOCUMENT_BYTES {
+        return Ok(json!({
+            "success": false,
+            "error": format!(
+                "File is {} bytes; Telegram accepts at most {}",
+                metadata.len(),
+                MAX_DOCUMENT_BYTES
+            ),
+        })
+        .to_string());
+    }
+
+    let file_name = file_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "file".to_string());
+    let input = InputFile::memory(tokio::fs::read(&file_path).await?).file_name(file_name);
+    let mut request = ctx.bot.send_document(ChatId(ctx.chat_id), input);
+    if let Some(thread_id) = ctx.thread_id {
+        request = request.message_thread_id(thread_id);
+    }
+    if let Some(caption) = caption.filter(|c| !c.trim().is_empty()) {
+        request = request.caption(caption);
+    }
+    if let Err(e) = request.await {
+        return Ok(json!({
+            "success": false,
+            "error": format!("Failed to send the file: {}", e),
+        })
+        .to_string());
+    }
+
+    tracing::info!(
+        chat_id = ctx.chat_id,
+        file = %display_file_path(root, &file_path),
+        size = metadata.len(),
+        "File sent"
+    );
+    Ok(json!({
+        "success": true,
+        "action": "send",
+        "path": display_file_path(root, &file_path),
+        "size_bytes": metadata.len(),
+        "mime": mime_type(&file_path),
+    })
+    .to_string())
+}
+
+fn mime_type(path: &Path) -> String {
+    mime_guess::from_path(path)
+        .first_or_octet_stream()
+        .essence_str()
+        .to_string()
+}
+
+fn format_time(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// Copy a file from outside the root (such as a sandbox workspace) to
+/// `destination`, within the caller's namespaces and quota. Existing files
+/// are not replaced.
+pub(super) async fn import_file(
+    ctx: &ToolContext<'_>,
+    source: &Path,
+    destination: &str,
+) -> Result<u64> {
+    let root = resolve_file_root(ctx.pool).await?;
+    let target =
+        resolve_file_path(ctx.pool, &FileScope::for_caller(ctx), destination, false).await?;
+    if tokio::fs::try_exists(&target).await? {
+        anyhow::bail!(
+            "'{}' already exists in the files root; choose another path",
+            destination
+        );
+    }
+    let size = tokio::fs::metadata(source).await?.len();
+    if let Some(error) = check_quota(ctx.pool, &root, &target, size).await? {
+        anyhow::bail!("{}", error["error"].as_str().unwrap_or("Quota exceeded"));
+    }
+    if let Some(parent) = target.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    Ok(tokio::fs::copy(source, &target).await?)
+}
+
+/// The error to return when writing `new_size` bytes to `target` would take
+/// its namespace over quota (`files_quota_<kind>_mb`, 0 for none)
+async fn check_quota(
+    pool: &SqlitePool,
+    root: &Path,
+    target: &Path,
+    new_size: u64,
+) -> Result<Option<Value>> {
+    let Some(namespace) = namespace_dir(target.strip_prefix(root).unwrap_or(target)) else {
+        return Ok(None);
+    };
+    let kind = namespace
+        .iter()
+        .next()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let default = match kind.as_ref() {
+        NAMESPACE_CHAT => DEFAULT_CHAT_QUOTA_MB,
+        NAMESPACE_PERSON => DEFAULT_PERSON_QUOTA_MB,
+        _ => DEFAULT_SHARED_QUOTA_MB,
+    };
+    let quota_mb: u64 = config::get_or_default(pool, &format!("files_quota_{}_mb", kind), default)
+        .await?
+        .trim()
+        .parse()
+        .unwrap_or(0);
+    if quota_mb == 0 {
+        return Ok(None);
+    }
+
+    let quota = quota_mb * 1024 * 1024;
+    let used = disk_usage(&root.join(&namespace)).await;
+    let replaced = match tokio::fs::metadata(target).await {
+        Ok(meta) if meta.is_file() => meta.len(),
+        _ => 0,
+    };
+    if used.saturating_sub(replaced) + new_size <= quota {
+        return Ok(None);
+    }
+    Ok(Some(json!({
+        "success": false,
+        "error": format!(
+            "Namespace '{}' is limited to {} MB; it uses {} bytes and this write needs {} more. Delete files first.",
+            namespace.to_string_lossy(),
+            quota_mb,
+            used,
+            new_size.saturating_sub(replaced)
+        ),
+        "quota_bytes": quota,
+        "used_bytes": used,
+    })))
+}
+
+/// Total size of the files under `path` (or of `path` itself), not
+/// following symlinks
+async fn disk_usage(path: &Path) -> u64 {
+    let mut total = 0;
+    let mut pending = vec![path.to_path_buf()];
+    while let Some(path) = pending.pop() {
+        let Ok(meta) = tokio::fs::symlink_metadata(&path).await else {
+            continue;
+        };
+        if !meta.is_dir() {
+            total += meta.len();
+            continue;
+        }
+        let Ok(mut entries) = tokio::fs::read_dir(&path).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            pending.push(entry.path());
+        }
+    }
+    total
+}
@@ -0,0 +1,323 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+
+use super::schema::parse_args;
+use super::{Tool, ToolContext};
+use crate::db;
+
+pub struct SearchHistory;
+
+#[async_trait]
+impl Tool for SearchHistory {
+    fn name(&self) -> &str {
+        "search_history"
+    }
+
+    fn description(&self) -> &str {
+        "Search through the conversation history of the current chat. The last 50 messages are already visible in your context, but this tool lets you search OLDER messages beyond that window. Use this when a user asks about something said days/weeks ago, or when you need to find a specific past discussion. You can filter by keyword, sender name, sender ID, and/or date range. All filters are combined with AND logic."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "keyword": {
+                    "type": "string",
+                    "description": "Optional. Search for messages containing this word or phrase (case-insensitive substring match). Example: 'project deadline', 'restaurant recommendation'."
+                },
+                "sender_name": {
+                    "type": "string",
+                    "description": "Optional. Filter by sender's display name (partial match). Example: 'John', 'Alice'. Useful when the user says 'what did John say about...'."
+                },
+                "sender_id": {
+                    "type": "integer",
+                    "description": "Optional. Filter by exact Telegram user ID. Use this if you know the user's ID from context."
+                },
+                "date_from": {
+                    "type": "string",
+                    "description": "Optional. Only include messages from this date/time onward. Format: 'YYYY-MM-DD' or 'YYYY-MM-DD HH:MM:SS'. Example: '2024-01-15'."
+                },
+                "date_to": {
+                    "type": "string",
+                    "description": "Optional. Only include messages up to this date/time. Format: 'YYYY-MM-DD' or 'YYYY-MM-DD HH:MM:SS'. Example: '2024-02-01'."
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": "Maximum number of messages to return. Default: 20. Max: 100."
+                },
+                "offset": {
+                    "type": "integer",
+                    "description": "Skip this many results (for pagination). Default: 0. Use with limit to page through results."
+                }
+            },
+            "required": []
+        })
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_search_history(ctx.pool, parse_args(args)?, ctx.chat_id).await
+    }
+}
+
+pub struct BrowseHistory;
+
+#[async_trait]
+impl Tool for BrowseHistory {
+    fn name(&self) -> &str {
+        "browse_history"
+    }
+
+    fn description(&self) -> &str {
+        "Browse older conversation messages by loading a specific page of history. Unlike search_history which filters by criteria, this simply loads N messages starting from a given offset. Use this when you want to 'scroll back' through the conversation chronologically — e.g., when a user says 'what were we talking about yesterday?' or 'go back further'."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "offset": {
+                    "type": "integer",
+                    "description": "How many messages to skip from the most recent. 0 = most recent messages (already in your context). Use 50 to see messages just before your context window, 100 for even older, etc."
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": "How many messages to load. Default: 20. Max: 50."
+                }
+            },
+            "required": ["offset"]
+        })
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_browse_history(ctx.pool, parse_args(args)?, ctx.chat_id).await
+    }
+}
+
+pub struct SearchAllChats;
+
+#[async_trait]
+impl Tool for SearchAllChats {
+    fn name(&self) -> &str {
+        "search_all_chats"
+    }
+
+    fn description(&self) -> &str {
+        "Search conversation history across ALL chats and groups at once. Unlike search_history (which only searches the current chat), this tool searches every chat the bot has ever participated in. Use this when:\n\
+         - A user asks 'did anyone in any group mention...' or 'where was X discussed?'\n\
+         - You need to find a conversation but don't know which chat it happened in\n\
+         - You want to find all messages from a specific person across all groups\n\n\
+         At least one filter is required to prevent unbounded searches. All filters are combined with AND logic. Works with any language (multilingual). Each result includes chat_id so you can tell which chat/group it came from."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "keyword": {
+                    "type": "string",
+                    "description": "Optional. Search for messages containing this word or phrase (case-insensitive substring match). Works with any language including CJK, Cyrillic, Arabic, etc."
+                },
+                "sender_name": {
+                    "type": "string",
+                    "description": "Optional. Filter by sender's display name (partial match). Example: 'John', 'Alice'."
+                },
+                "sender_id": {
+                    "type": "integer",
+                    "description": "Optional. Filter by exact Telegram user ID."
+                },
+                "date_from": {
+                    "type": "string",
+                    "description": "Optional. Only include messages from this date/time onward. Format: 'YYYY-MM-DD' or 'YYYY-MM-DD HH:MM:SS'."
+                },
+                "date_to": {
+                    "type": "string",
+                    "description": "Optional. Only include messages up to this date/time. Format: 'YYYY-MM-DD' or 'YYYY-MM-DD HH:MM:SS'."
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": "Maximum number of messages to return. Default: 20. Max: 100."
+                },
+                "offset": {
+                    "type": "integer",
+                    "description": "Skip this many results (for pagination). Default: 0."
+                }
+            },
+            "required": []
+        })
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_search_all_chats(ctx.pool, parse_args(args)?).await
+    }
+}
+
+/// Filters shared by `search_history` and `search_all_chats`
+#[derive(Deserialize)]
+struct SearchHistoryArgs {
+    keyword: Option<String>,
+    sender_name: Option<String>,
+    sender_id: Option<i64>,
+    date_from: Option<String>,
+    date_to: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+async fn execute_search_history(
+    pool: &SqlitePool,
+    args: SearchHistoryArgs,
+    chat_id: i64,
+) -> Result<String> {
+    let keyword = args.keyword.as_deref();
+    let sender_name = args.sender_name.as_deref();
+    let sender_id = args.sender_id;
+    let date_from = args.date_from.as_deref();
+    let date_to = args.date_to.as_deref();
+    let limit = args.limit.unwrap_or(20).min(100);
+    let offset = args.offset.unwrap_or(0);
+
+    let rows = db::conversation_search(
+        pool,
+        chat_id,
+        keyword,
+        sender_name,
+        sender_id,
+        date_from,
+        date_to,
+        limit,
+        offset,
+    )
+    .await?;
+
+    let messages: Vec<_> = rows
+        .iter()
+        .map(|r| {
+            json!({
+                "id": r.id,
+                "message_id": r.message_id,
+                "reply_to_id": r.reply_to_id,
+                "user_name": r.user_name,
+                "user_id": r.user_id,
+                "role": r.role,
+                "content": r.content,
+                "created_at": r.created_at,
+            })
+        })
+        .collect();
+
+    let count = messages.len();
+    Ok(json!({
+        "messages": messages,
+        "count": count,
+        "has_more": count as i64 == limit,
+        "offset": offset,
+    })
+    .to_string())
+}
+
+#[derive(Deserialize)]
+struct BrowseHistoryArgs {
+    offset: i64,
+    limit: Option<i64>,
+}
+
+async fn execute_browse_history(
+    pool: &SqlitePool,
+    args: BrowseHistoryArgs,
+    chat_id: i64,
+) -> Result<String> {
+    let offset = args.offset;
+    let limit = args.limit.unwrap_or(20).min(50);
+
+    let rows =
+        db::conversation_search(pool, chat_id, None, None, None, None, None, limit, offset).await?;
+
+    let messages: Vec<_> = rows
+        .iter()
+        .map(|r| {
+            json!({
+                "id": r.id,
+                "message_id": r.message_id,
+                "reply_to_id": r.reply_to_id,
+                "user_name": r.user_name,
+                "user_id": r.user_id,
+                "role": r.role,
+                "content": r.content,
+                "created_at": r.created_at,
+            })
+        })
+        .collect();
+
+    let count = messages.len();
+    Ok(json!({
+        "messages": messages,
+        "count": count,
+        "offset": offset,
+        "next_offset": offset + count as i64,
+        "has_more": count as i64 == limit,
+    })
+    .to_string())
+}
+
+async fn execute_search_all_chats(pool: &SqlitePool, args: SearchHistoryArgs) -> Result<String> {
+    let keyword = args.keyword.as_deref();
+    let sender_name = args.sender_name.as_deref();
+    let sender_id = args.sender_id;
+    let date_from = args.date_from.as_deref();
+    let date_to = args.date_to.as_deref();
+    let limit = args.limit.unwrap_or(20).min(100);
+    let offset = args.offset.unwrap_or(0);
+
+    // Require at least one filter
+    if keyword.is_none()
+        && sender_name.is_none()
+        && sender_id.is_none()
+        && date_from.is_none()
+        && date_to.is_none()
+    {
+        return Ok(json!({
+            "error": "At least one filter (keyword, sender_name, sender_id, date_from, date_to) is required to search across all chats."
+        }).to_string());
+    }
+
+    let rows = db::conversation_search_global(
+        pool,
+        keyword,
+        sender_name,
+        sender_id,
+        date_from,
+        date_to,
+        limit,
+        offset,
+    )
+    .await?;
+
+    let messages: Vec<_> = rows
+        .iter()
+        .map(|r| {
+            json!({
+                "id": r.id,
+                "chat_id": r.chat_id,
+                "message_id": r.message_id,
+                "reply_to_id": r.reply_to_id,
+                "user_name": r.user_name,
+                "user_id": r.user_id,
+                "role": r.role,
+                "content": r.content,
+                "created_at": r.created_at,
+            })
+        })
+        .collect();
+
+    let count = messages.len();
+    Ok(json!({
+        "messages": messages,
+        "count": count,
+        "has_more": count as i64 == limit,
+        "offset": offset,
+    })
+    .to_string())
+}
@@ -0,0 +1,783 @@
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use ring::digest;
+
+use super::http_policy::{self, HttpPolicy};
+use super::readability;
+use super::sandbox::{host_allowed, truncate_text_for_output};
+use super::schema::parse_args;
+use super::{Tool, ToolContext};
+use crate::rag::{IndexJob, RagVisibility};
+use crate::secrets::{self, Secrets};
+use crate::types::HttpCacheEntry;
+use crate::{config, db, memory};
+
+pub struct HttpRequest;
+
+#[async_trait]
+impl Tool for HttpRequest {
+    fn name(&self) -> &str {
+        "generic_http_request"
+    }
+
+    fn description(&self) -> &str {
+        "Make an HTTP request to a public URL. This is your general-purpose web tool — use it to:\n\
+         - Fetch web pages (by default only the main content, without menus and cookie banners, converted to clean text; \
+         the result also has the page title, description, og tags and canonical URL under 'page')\n\
+         - Save an article as a note with save_to_note, so it can be found later with search_notes and rag_search\n\
+         - Call REST APIs (weather, GitHub, YouTube, etc.)\n\
+         - POST data to endpoints\n\
+         - Check if a URL is reachable\n\n\
+         IMPORTANT — API Key Security:\n\
+         Credentials for some APIs are configured by the admin and added to requests for their domains automatically; don't ask for those. \
+         Stored secrets can be put in header values as {{secret:NAME}}, for the hosts each secret is allowed for; the value is filled in when the request runs. \
+         Secrets are refused in the URL, query_params and the body. \
+         Otherwise, NEVER hardcode API keys in the URL or headers. Instead, first use unified_memory with action='get' to retrieve stored keys \
+         (e.g., unified_memory action='get' segment='person:USER_ID' key='openweather_api_key'), then use the returned value. \
+         If a user wants to give you an API key, ask an admin to store it with /secret set in a private chat instead of pasting it here.\n\n\
+         Private, loopback and link-local addresses (including hosts that resolve to them) are blocked, and the admin may restrict which domains are reachable.\n\n\
+         Retries: Automatically retries up to 3 times on server errors (5xx) and timeouts with exponential backoff.\n\
+         Timeout: 10 seconds default.\n\
+         Caching: GET responses are cached for a few minutes and then revalidated with ETag/Last-Modified; set use_cache=false for live data.\n\n\
+         Examples:\n\
+         - Web page: {\"url\": \"https://en.wikipedia.org/wiki/Rust\"}\n\
+         - Article with its links, saved for later: {\"url\": \"https://blog.rust-lang.org/\", \"include_links\": true, \"save_to_note\": true, \"note_tags\": \"rust,blog\"}\n\
+         - Weather API: {\"url\": \"https://wttr.in/London\", \"headers\": {\"User-Agent\": \"curl/7.0\"}}\n\
+         - REST API: {\"url\": \"https://api.github.com/repos/rust-lang/rust\", \"headers\": {\"Accept\": \"application/json\"}}\n\
+         - POST: {\"url\": \"https://httpbin.org/post\", \"method\": \"POST\", \"headers\": {\"Content-Type\": \"application/json\"}, \"body\": \"{\\\"hello\\\":\\\"world\\\"}\"}\n\
+         - With query params: {\"url\": \"https://api.example.com/search\", \"query_params\": {\"q\": \"rust lang\", \"limit\": \"10\"}}"
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "url": {
+                    "type": "string",
+                    "description": "The base URL. Must start with http:// or https://. Query params can be in the URL or in query_params (which is safer for special characters)."
+                },
+                "method": {
+                    "type": "string",
+                    "enum": ["GET", "POST", "PUT", "DELETE", "PATCH", "HEAD"],
+                    "description": "HTTP method. Default: 'GET'."
+                },
+                "headers": {
+                    "type": "object",
+                    "description": "Optional HTTP headers as key-value pairs. Example: {\"Authorization\": \"Bearer sk-...\", \"Accept\": \"application/json\"}. For APIs, set Content-Type and Accept headers appropriately.",
+                    "additionalProperties": { "type": "string" }
+                },
+                "query_params": {
+                    "type": "object",
+                    "description": "Optional query parameters as key-value pairs. These are URL-encoded and appended to the URL automatically. Safer than putting them in the URL manually. Example: {\"q\": \"search term\", \"page\": \"1\"}.",
+                    "additionalProperties": { "type": "string" }
+                },
+                "body": {
+                    "type": "string",
+                    "description": "Optional request body (for POST/PUT/PATCH). Send as a string — for JSON, stringify it first. Example: \"{\\\"name\\\": \\\"value\\\"}\"."
+                },
+                "response_type": {
+                    "type": "string",
+                    "enum": ["auto", "json", "text", "html"],
+                    "description": "How to process the response. 'auto' (DEFAULT): detect from Content-Type — JSON is parsed to object, HTML is converted to plain text. 'json': force parse as JSON. 'text': return raw text as-is. 'html': force HTML-to-text conversion."
+                },
+                "strip_html": {
+                    "type": "boolean",
+                    "description": "For HTML responses: if true (DEFAULT), convert to clean readable text (lynx-style). If false, return raw HTML. Only relevant when response is HTML."
+                },
+                "extract_main": {
+                    "type": "boolean",
+                    "description": "For HTML responses with strip_html: return only the main content (article text), dropping navigation, sidebars, footers and cookie banners. Default: true. Set false to get the text of the whole page."
+                },
+                "include_links": {
+                    "type": "boolean",
+                    "description": "For HTML responses: also return the links found in the content as [{text, url}] with absolute URLs (max 50). Default: false."
+                },
+                "use_cache": {
+                    "type": "boolean",
+                    "description": "Serve GET requests from the response cache when fresh, and revalidate stale entries. Default: true. Requests with Authorization or Cookie headers are never cached."
+                },
+                "save_to_note": {
+                    "type": "boolean",
+                    "description": "Store the cleaned, untruncated response as a note (titled after the page) and index it for rag_search. Default: false."
+                },
+                "note_segment": {
+                    "type": "string",
+                    "description": "Segment for the saved note: one available in this chat, e.g. 'global', 'chat:ID' of this chat or 'person:ID' of the current user. Default: the current chat."
+                },
+                "note_tags": {
+                    "type": "string",
+                    "description": "Comma-separated tags for the saved note. Example: \"rust,article\"."
+                },
+                "max_length": {
+                    "type": "integer",
+                    "description": "Maximum response body characters to return. Default: 8000. Max: 30000. Truncates at word boundary with a note."
+                },
+                "timeout_secs": {
+                    "type": "integer",
+                    "description": "Request timeout in seconds. Default: 10. Max: 60."
+                }
+            },
+            "required": ["url"]
+        })
+    }
+
+    fn has_side_effects(&self, args: &Value) -> bool {
+        args["save_to_note"].as_bool().unwrap_or(false)
+    }
+
+    fn fills_secrets(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        let policy = Arc::new(HttpPolicy::load(ctx.pool).await?);
+        execute_http_request(ctx, &policy, parse_args(args)?).await
+    }
+}
+
+const DEFAULT_MAX_LENGTH: usize = 8000;
+const ABSOLUTE_MAX_LENGTH: usize = 30000;
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+const MAX_TIMEOUT_SECS: u64 = 60;
+const HTTP_MAX_RETRIES: u32 = 3;
+const MAX_BODY_BYTES: usize = 2_097_152;
+const DEFAULT_CACHE_TTL_SECS: &str = "600";
+/// Stale entries are kept this long for conditional requests
+const CACHE_RETENTION_SECS: i64 = 7 * 24 * 3600;
+const MAX_NOTE_CHARS: usize = 100_000;
+
+#[derive(Deserialize)]
+struct HttpRequestArgs {
+    url: String,
+    method: Option<String>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default)]
+    query_params: HashMap<String, String>,
+    body: Option<String>,
+    strip_html: Option<bool>,
+    extract_main: Option<bool>,
+    #[serde(default)]
+    include_links: bool,
+    use_cache: Option<bool>,
+    response_type: Option<String>,
+    max_length: Option<usize>,
+    timeout_secs: Option<u64>,
+    #[serde(default)]
+    save_to_note: bool,
+    note_segment: Option<String>,
+    #[serde(default)]
+    note_tags: String,
+}
+
+/// A response body, from the network or the cache
+struct Fetched {
+    status: u16,
+    status_text: String,
+    content_type: String,
+    headers: Value,
+    bytes: Vec<u8>,
+    /// URL after redirects
+    final_url: reqwest::Url,
+    /// "hit", "revalidated" or "miss"; None when the request is not cacheable
+    cache: Option<&'static str>,
+}
+
+async fn execute_http_request(
+    ctx: &ToolContext<'_>,
+    policy: &Arc<HttpPolicy>,
+    args: HttpRequestArgs,
+) -> Result<String> {
+    let url = args.url.as_str();
+    let method = args.method.as_deref().unwrap_or("GET").to_uppercase();
+    let strip_html = args.strip_html.unwrap_or(true);
+    let extract_main = args.extract_main.unwrap_or(true);
+    let response_type = args.response_type.as_deref().unwrap_or("auto");
+    let max_length = args
+        .max_length
+        .map(|v| v.min(ABSOLUTE_MAX_LENGTH))
+        .unwrap_or(DEFAULT_MAX_LENGTH);
+    let timeout_secs = args
+        .timeout_secs
+        .map(|v| v.min(MAX_TIMEOUT_SECS))
+        .unwrap_or(DEFAULT_TIMEOUT_SECS);
+
+    // Validate URL
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Ok(json!({"error": "URL must start with http:// or https://"}).to_string());
+    }
+    let parsed = match reqwest::Url::parse(url) {
+        Ok(u) => u,
+        Err(e) => return Ok(json!({"error": format!("Invalid URL: {}", e)}).to_string()),
+    };
+    if let Err(blocked) = policy.check_url(&parsed) {
+        return Ok(json!({"error": blocked.0, "reason": "blocked", "url": url}).to_string());
+    }
+    let note_segment = args
+        .note_segment
+        .clone()
+        .unwrap_or_else(|| format!("chat:{}", ctx.chat_id));
+    if args.save_to_note {
+        if let Err(e) = memory::validate_segment(&note_segment) {
+            return Ok(json!({"error": e.to_string()}).to_string());
+        }
+        // Only admins may file pages into other chats' or people's segments
+        let visibility =
+            RagVisibility::for_caller(ctx.chat_id, ctx.thread_id.map(|t| t.0.0), ctx.user_id);
+        if !ctx.is_admin && !visibility.allows_segment(&note_segment) {
+            return Ok(json!({
+                "error": format!(
+                    "Notes can only be saved to segments available in this chat: {}",
+                    visibility.segments().join(", ")
+                )
+            })
+            .to_string());
+        }
+    }
+    let injected = policy.headers_for(&parsed);
+
+    // Secrets only go into headers, and only to the hosts each one allows
+    let secrets_elsewhere = secrets::has_placeholder(url)
+        || args.body.as_deref().is_some_and(secrets::has_placeholder)
+        || args.headers.keys().any(|k| secrets::has_placeholder(k))
+        || args
+            .query_params
+            .iter()
+            .any(|(k, v)| secrets::has_placeholder(k) || secrets::has_placeholder(v));
+    if secrets_elsewhere {
+        return Ok(json!({
+            "error": "Secrets can only be used in header values, not in the URL, query_params, header names or the body"
+        })
+        .to_string());
+    }
+    let uses_secrets = args.headers.values().any(|v| secrets::has_placeholder(v));
+    let mut headers = args.headers.clone();
+    let host = parsed.host_str().unwrap_or_default().to_lowercase();
+    let host = host.trim_end_matches('.');
+    if uses_secrets {
+        let vault = Secrets::load_usable(ctx.pool, ctx.chat_id, ctx.is_admin).await?;
+        for value in headers.values_mut() {
+            match vault.substitute_header(value, host, |hosts| host_allowed(host, hosts)) {
+                Ok(filled) => *value = filled,
+                Err(e) => return Ok(json!({"error": e.to_string()}).to_string()),
+            }
+        }
+    }
+
+    // Build client. Redirects may not carry configured or secret headers
+    // away from the host they were meant for.
+    let header_domain = if uses_secrets {
+        Some(host.to_string())
+    } else {
+        injected.map(|(domain, _)| domain.to_string())
+    };
+    let client = policy.client(std::time::Duration::from_secs(timeout_secs), header_domain)?;
+
+    // Build the request
+    let mut request_builder = match method.as_str() {
+        "GET" => client.get(url),
+        "POST" => client.post(url),
+        "PUT" => client.put(url),
+        "DELETE" => client.delete(url),
+        "PATCH" => client.patch(url),
+        "HEAD" => client.head(url),
+        _ => {
+            return Ok(
+                json!({"error": format!("Unsupported HTTP method: {}", method)}).to_string(),
+            );
+        }
+    };
+
+    // Default headers
+    request_builder = request_builder
+        .header("User-Agent", "Mozilla/5.0 (compatible; AstarteBot/0.1)")
+        .header("Accept-Language", "en-US,en;q=0.9");
+
+    // Custom headers
+    for (key, val) in &headers {
+        request_builder = request_builder.header(key.as_str(), val.as_str());
+    }
+
+    // Configured headers for this domain override the model's
+    if let Some((_, headers)) = injected {
+        for (key, val) in headers {
+            request_builder = request_builder.header(key.as_str(), val.as_str());
+        }
+    }
+
+    // Query params
+    if !args.query_params.is_empty() {
+        request_builder = request_builder.query(&args.query_params);
+    }
+
+    // Body
+    if let Some(body) = args.body {
+        request_builder = request_builder.body(body);
+    }
+
+    // Only plain GETs without credentials are cached
+    let cache_ttl: i64 =
+        config::get_or_default(ctx.pool, "http_cache_ttl_secs", DEFAULT_CACHE_TTL_SECS)
+            .await?
+            .trim()
+            .parse()
+            .unwrap_or(0);
+    let has_credentials = args
+        .headers
+        .keys()
+        .any(|k| k.eq_ignore_ascii_case("authorization") || k.eq_ignore_ascii_case("cookie"));
+    let cache_key = if args.use_cache.unwrap_or(true)
+        && cache_ttl > 0
+        && method == "GET"
+        && injected.is_none()
+        && !uses_secrets
+        && !has_credentials
+    {
+        request_builder
+            .try_clone()
+            .and_then(|b| b.build().ok())
+            .map(|request| cache_key(request.url()))
+    } else {
+        None
+    };
+    let cached = match &cache_key {
+        Some(key) => db::http_cache_get(ctx.pool, key).await?,
+        None => None,
+    };
+    let now = chrono::Utc::now().timestamp();
+
+    let fetched = match cached {
+        Some(entry) if now - entry.fetched_at < cache_ttl => {
+            Fetched::from_cache(entry, &parsed, "hit")
+        }
+        cached => {
+            // Revalidate a stale entry instead of downloading it again
+            if let Some(entry) = &cached {
+                if let Some(etag) = &entry.etag {
+                    request_builder = request_builder.header("If-None-Match", etag.as_str());
+                }
+                if let Some(last_modified) = &entry.last_modified {
+                    request_builder =
+                        request_builder.header("If-Modified-Since", last_modified.as_str());
+                }
+            }
+
+            let response =
+                match send_with_retries(&client, request_builder, url, &method, timeout_secs).await
+                {
+                    Ok(r) => r,
+                    Err(error) => return Ok(error.to_string()),
+                };
+
+            match (&cache_key, cached) {
+                (Some(key), Some(entry))
+                    if response.status() == reqwest::StatusCode::NOT_MODIFIED =>
+                {
+                    db::http_cache_touch(ctx.pool, key, now).await?;
+                    Fetched::from_cache(entry, &parsed, "revalidated")
+                }
+                _ => {
+                    let mut fetched = match Fetched::read(response, url, &method).await {
+                        Ok(f) => f,
+                        Err(error) => return Ok(error.to_string()),
+                    };
+                    if let Some(key) = &cache_key {
+                        fetched.cache = Some("miss");
+                        if fetched.status == 200 && !fetched.no_store() {
+                            let entry = HttpCacheEntry {
+                                etag: fetched.header("etag"),
+                                last_modified: fetched.header("last-modified"),
+                                content_type: fetched.content_type.clone(),
+                                body: fetched.bytes.clone(),
+                                final_url: Some(fetched.final_url.to_string()),
+                                fetched_at: now,
+                            };
+                            db::http_cache_put(ctx.pool, key, &entry, CACHE_RETENTION_SECS).await?;
+                        }
+                    }
+                    fetched
+                }
+            }
+        }
+    };
+    let status = fetched.status;
+    let status_text = fetched.status_text.as_str();
+    let content_type = fetched.content_type.as_str();
+    let resp_headers = &fetched.headers;
+
+    // For HEAD requests, return just metadata
+    if method == "HEAD" {
+        return Ok(json!({
+            "url": url,
+            "method": "HEAD",
+            "status": status,
+            "status_text": status_text,
+            "headers": resp_headers,
+        })
+        .to_string());
+    }
+
+    let raw_body = String::from_utf8_lossy(&fetched.bytes).to_string();
+    let raw_size = raw_body.len();
+
+    // Non-success: return error with body preview
+    if status >= 400 {
+        let body_preview: String = raw_body.chars().take(1000).collect();
+        return Ok(json!({
+            "error": format!("HTTP {} {}", status, status_text),
+            "url": url,
+            "method": method,
+            "status": status,
+            "status_text": status_text,
+            "content_type": content_type,
+            "headers": resp_headers,
+            "body_preview": body_preview,
+        })
+        .to_string());
+    }
+
+    // Determine how to process the response
+    let is_json =
+        response_type == "json" || (response_type == "auto" && content_type.contains("json"));
+    let is_html = response_type == "html"
+        || (response_type == "auto"
+            && (content_type.contains("html") || content_type.contains("xhtml")));
+    let page = is_html.then(|| readability::extract(&raw_body, &fetched.final_url));
+
+    let content = if is_json {
+        // Parse as JSON and return structured
+        match serde_json::from_str::<Value>(&raw_body) {
+            Ok(parsed) => serde_json::to_string_pretty(&parsed).unwrap_or(raw_body.clone()),
+            Err(_) => raw_body.clone(),
+        }
+    } else if let Some(page) = page.as_ref().filter(|_| strip_html) {
+        // Convert the main content, or the whole page, to clean plain text
+        if extract_main {
+            readability::html_to_text(&page.content_html)
+        } else {
+            readability::html_to_text(&raw_body)
+        }
+    } else {
+        raw_body
+    };
+
+    let note = if args.save_to_note {
+        let title = page
+            .as_ref()
+            .and_then(|p| p.meta.title.clone())
+            .unwrap_or_else(|| url.to_string());
+        let source = page
+            .as_ref()
+            .and_then(|p| p.meta.canonical_url.as_deref())
+            .unwrap_or(url);
+        Some(
+            match save_note(
+                ctx,
+                &note_segment,
+                &title,
+                source,
+                &content,
+                &args.note_tags,
+            )
+            .await
+            {
+                Ok(id) => json!({"id": id, "segment": note_segment, "title": title}),
+                Err(e) => json!({"error": format!("Failed to save note: {}", e)}),
+            },
+        )
+    } else {
+        None
+    };
+
+    // Truncate to max_length (floor to char boundary to avoid panic on multibyte chars)
+    let truncated = content.len() > max_length;
+    let final_content = if truncated {
+        let mut boundary = max_length;
+        while boundary > 0 && !content.is_char_boundary(boundary) {
+            boundary -= 1;
+        }
+        let cut = content[..boundary]
+            .rfind(|c: char| c.is_whitespace())
+            .unwrap_or(boundary);
+        format!(
+            "{}...\n\n[TRUNCATED: {}/{} chars]",
+            &content[..cut],
+            cut,
+            content.len()
+        )
+    } else {
+        content
+    };
+
+    let mut result = json!({
+        "url": url,
+        "method": method,
+        "status": status,
+        "status_text": status_text,
+        "content_type": content_type,
+        "headers": resp_headers,
+        "body": final_content,
+        "raw_size_bytes": raw_size,
+        "returned_size": final_content.len(),
+        "truncated": truncated,
+        "strip_html": strip_html && is_html,
+    });
+    if fetched.final_url != parsed {
+        result["final_url"] = json!(fetched.final_url.as_str());
+    }
+    if let Some(page) = page {
+        result["page"] = json!(page.meta);
+        if strip_html {
+            result["main_content"] = json!(extract_main && page.main_found);
+        }
+        if args.include_links {
+            result["links"] = json!(page.links);
+        }
+    }
+    if let Some(cache) = fetched.cache {
+        result["cache"] = json!(cache);
+    }
+    if let Some(note) = note {
+        result["note"] = note;
+    }
+    Ok(result.to_string())
+}
+
+/// Send the request, retrying on 5xx and timeouts. Errors are the JSON to
+/// return to the model.
+async fn send_with_retries(
+    client: &reqwest::Client,
+    request_builder: reqwest::RequestBuilder,
+    url: &str,
+    method: &str,
+    timeout_secs: u64,
+) -> Result<reqwest::Response, Value> {
+    let mut last_error = None;
+
+    for attempt in 0..HTTP_MAX_RETRIES {
+        if attempt > 0 {
+            let delay = std::time::Duration::from_millis(500 * 2u64.pow(attempt));
+            tokio::time::sleep(delay).await;
+        }
+
+        match request_builder
+            .try_clone()
+            .unwrap_or_else(|| {
+                // If clone fails (body was consumed), rebuild a simple GET
+                client.get(url)
+            })
+            .send()
+            .await
+        {
+            Ok(resp) => {
+                if resp.status().is_server_error() && attempt < HTTP_MAX_RETRIES - 1 {
+                    last_error = Some(format!(
+                        "HTTP {} (attempt {}/{})",
+                        resp.status().as_u16(),
+                        attempt + 1,
+                        HTTP_MAX_RETRIES
+                    ));
+                    continue;
+                }
+                return Ok(resp);
+            }
+            Err(e) => {
+                if let Some(reason) = http_policy::blocked_reason(&e) {
+                    return Err(json!({
+                        "error": reason,
+                        "reason": "blocked",
+                        "url": url,
+                        "method": method,
+                    }));
+                }
+                let mut error_info = json!({
+                    "error": format!("{}", e),
+                    "url": url,
+                    "method": method,
+                    "attempt": attempt + 1,
+                    "max_retries": HTTP_MAX_RETRIES,
+                });
+                if e.is_timeout() {
+                    error_info["reason"] = json!("timeout");
+                    error_info["detail"] =
+                        json!(format!("Request timed out after {}s", timeout_secs));
+                } else if e.is_connect() {
+                    error_info["reason"] = json!("connection_failed");
+                    error_info["detail"] =
+                        json!("Could not connect. DNS resolution failed or host is unreachable.");
+                } else if e.is_redirect() {
+                    error_info["reason"] = json!("too_many_redirects");
+                } else {
+                    error_info["reason"] = json!("request_error");
+                }
+                if let Some(source) = StdError::source(&e) {
+                    error_info["source"] = json!(format!("{}", source));
+                }
+
+                // Only retry on timeout/connect errors
+                if (e.is_timeout() || e.is_connect()) && attempt < HTTP_MAX_RETRIES - 1 {
+                    last_error = Some(format!("{}", e));
+                    continue;
+                }
+                return Err(error_info);
+            }
+        }
+    }
+
+    Err(json!({
+        "error": format!("All {} retries exhausted", HTTP_MAX_RETRIES),
+        "last_error": last_error,
+        "url": url,
+        "method": method,
+    }))
+}
+
+impl Fetched {
+    /// Read status, useful headers and the body of a response
+    async fn read(response: reqwest::Response, url: &str, method: &str) -> Result<Self, Value> {
+        let status = response.status().as_u16();
+        let status_text = response
+            .status()
+            .canonical_reason()
+            .unwrap_or("")
+            .to_string();
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("unknown")
+            .to_string();
+        let final_url = response.url().clone();
+
+        // Collect useful response headers
+        let mut headers = json!({});
+        for key in [
+            "content-type",
+            "content-length",
+            "location",
+            "retry-after",
+            "x-ratelimit-remaining",
+            "x-ratelimit-reset",
+            "etag",
+            "last-modified",
+            "cache-control",
+        ] {
+            if let Some(val) = response.headers().get(key).and_then(|v| v.to_str().ok()) {
+                headers[key] = json!(val);
+            }
+        }
+
+        let bytes = if method == "HEAD" {
+            Vec::new()
+        } else {
+            match response.bytes().await {
+                Ok(b) => b.to_vec(),
+                Err(e) => {
+                    return Err(json!({
+                        "error": format!("Failed to read response body: {}", e),
+                        "url": url,
+                        "status": status,
+                    }));
+                }
+            }
+        };
+
+        if bytes.len() > MAX_BODY_BYTES {
+            return Err(json!({
+                "error": "Response too large (>2MB)",
+                "url": url,
+                "status": status,
+                "size_bytes": bytes.len(),
+            }));
+        }
+
+        Ok(Self {
+            status,
+            status_text,
+            content_type,
+            headers,
+            bytes,
+            final_url,
+            cache: None,
+        })
+    }
+
+    /// A cached response; `url` stands in for the final URL of entries stored
+    /// without one
+    fn from_cache(entry: HttpCacheEntry, url: &reqwest::Url, cache: &'static str) -> Self {
+        let mut headers = json!({"content-type": entry.content_type});
+        if let Some(etag) = &entry.etag {
+            headers["etag"] = json!(etag);
+        }
+        if let Some(last_modified) = &entry.last_modified {
+            headers["last-modified"] = json!(last_modified);
+        }
+        Self {
+            status: 200,
+            status_text: "OK".to_string(),
+            content_type: entry.content_type,
+            headers,
+            bytes: entry.body,
+            final_url: entry
+                .final_url
+                .and_then(|u| reqwest::Url::parse(&u).ok())
+                .unwrap_or_else(|| url.clone()),
+            cache: Some(cache),
+        }
+    }
+
+    fn header(&self, key: &str) -> Option<String> {
+        self.headers[key].as_str().map(str::to_string)
+    }
+
+    fn no_store(&self) -> bool {
+        self.header("cache-control")
+            .is_some_and(|v| v.to_lowercase().contains("no-store"))
+    }
+}
+
+/// Cache key of a request URL; a digest, so API keys in query strings are
+/// not stored in the clear
+fn cache_key(url: &reqwest::Url) -> String {
+    digest::digest(&digest::SHA256, url.as_str().as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Store a fetched page as a note and queue it for RAG indexing
+async fn save_note(
+    ctx: &ToolContext<'_>,
+    segment: &str,
+    title: &str,
+    source: &str,
+    content: &str,
+    tags: &str,
+) -> Result<i64> {
+    let content = format!(
+        "Source: {}\n\n{}",
+        source,
+        truncate_text_for_output(content.trim(), MAX_NOTE_CHARS)
+    );
+    let id = db::note_create(ctx.pool, segment, title, &content, tags).await?;
+    ctx.indexer
+        .enqueue(IndexJob {
+            source_type: "note".to_string(),
+            source_id: id,
+            chat_id: 0,
+            segment: segment.to_string(),
+            content: format!("{}\n{}\n{}", title, content, tags),
+            user_name: String::new(),
+            created_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        })
+        .await;
+    Ok(id)
+}
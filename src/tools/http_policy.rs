@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use reqwest::Url;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use sqlx::SqlitePool;
+
+use super::sandbox::host_allowed;
+use crate::config;
+
+const MAX_REDIRECTS: usize = 10;
+
+/// Why a request was refused by the policy
+#[derive(Debug)]
+pub struct Blocked(pub String);
+
+impl std::fmt::Display for Blocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl StdError for Blocked {}
+
+/// Where `generic_http_request` may connect, and the headers it adds per
+/// domain. Private, loopback and link-local addresses are refused after DNS
+/// resolution, on every redirect, unless `http_allow_private` is set.
+pub struct HttpPolicy {
+    allow_private: bool,
+    allowlist: Vec<String>,
+    denylist: Vec<String>,
+    /// Headers added to requests for a domain and its subdomains
+    domain_headers: Vec<(String, HashMap<String, String>)>,
+}
+
+impl HttpPolicy {
+    pub async fn load(pool: &SqlitePool) -> Result<Self> {
+        let allow_private = config::get_or_default(pool, "http_allow_private", "off").await?;
+        let allowlist = config::get_or_default(pool, "http_domain_allowlist", "").await?;
+        let denylist = config::get_or_default(pool, "http_domain_denylist", "").await?;
+        let headers = config::get_or_default(pool, "http_domain_headers", "").await?;
+
+        let domain_headers: HashMap<String, HashMap<String, String>> = if headers.trim().is_empty()
+        {
+            HashMap::new()
+        } else {
+            serde_json::from_str(&headers).map_err(|e| {
+                anyhow::anyhow!(
+                    "http_domain_headers must be a JSON object of {{domain: {{header: value}}}}: {}",
+                    e
+                )
+            })?
+        };
+
+        Ok(Self {
+            allow_private: matches!(allow_private.trim(), "on" | "true" | "1" | "yes"),
+            allowlist: domain_list(&allowlist),
+            denylist: domain_list(&denylist),
+            domain_headers: domain_headers
+                .into_iter()
+                .map(|(domain, headers)| (normalize_host(&domain), headers))
+                .collect(),
+        })
+    }
+
+    /// Refuse URLs that are not http(s), are on the deny list, are missing
+    /// from a non-empty allow list, or name a private IP address directly
+    pub fn check_url(&self, url: &Url) -> Result<(), Blocked> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(Blocked(format!(
+                "Scheme '{}' is not allowed; use http or https",
+                url.scheme()
+            )));
+        }
+        let Some(host) = url.host_str() else {
+            return Err(Blocked(format!("{} has no host", url)));
+        };
+        let host = normalize_host(host);
+
+        if host_allowed(&host, &self.denylist) {
+            return Err(Blocked(format!("{} is on the domain deny list", host)));
+        }
+        if !self.allowlist.is_empty() && !host_allowed(&host, &self.allowlist) {
+            return Err(Blocked(format!("{} is not on the domain allow list", host)));
+        }
+
+        // IP literals never reach the resolver, so check them here
+        if let Ok(ip) = host.parse::<IpAddr>()
+            && !self.allow_private
+            && !is_public_ip(ip)
+        {
+            return Err(Blocked(format!("{} is a private or reserved address", ip)));
+        }
+        Ok(())
+    }
+
+    /// Configured headers for `url`, with the domain they were configured
+    /// for. The most specific matching domain wins.
+    pub fn headers_for(&self, url: &Url) -> Option<(&str, &HashMap<String, String>)> {
+        let host = normalize_host(url.host_str()?);
+        self.domain_headers
+            .iter()
+            .filter(|(domain, _)| host_allowed(&host, std::slice::from_ref(domain)))
+            .max_by_key(|(domain, _)| domain.len())
+            .map(|(domain, headers)| (domain.as_str(), headers))
+    }
+
+    /// Client that applies the policy on connect and on every redirect. When
+    /// `header_domain` is set, redirects leaving that domain are not followed,
+    /// so configured credentials don't leak to other hosts.
+    pub fn client(
+        self: &Arc<Self>,
+        timeout: Duration,
+        header_domain: Option<String>,
+    ) -> Result<reqwest::Client> {
+        let policy = self.clone();
+        let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error(Blocked(format!(
+                    "Stopped after {} redirects",
+                    MAX_REDIRECTS
+                )));
+            }
+            if let Err(blocked) = policy.check_url(attempt.url()) {
+                return attempt.error(blocked);
+            }
+            let leaves_domain = header_domain.as_ref().is_some_and(|domain| {
+                let host = attempt.url().host_str().map(normalize_host);
+                !host.is_some_and(|host| host_allowed(&host, std::slice::from_ref(domain)))
+            });
+            if leaves_domain {
+                attempt.stop()
+            } else {
+                attempt.follow()
+            }
+        });
+
+        Ok(reqwest::Client::builder()
+            .timeout(timeout)
+            .redirect(redirect)
+            .dns_resolver(Arc::new(GuardedResolver {
+                allow_private: self.allow_private,
+            }))
+            // A proxy would resolve names itself, out of the guard's sight
+            .no_proxy()
+            .build()?)
+    }
+}
+
+/// The policy's reason, if it is what made a request fail
+pub fn blocked_reason(error: &reqwest::Error) -> Option<String> {
+    let mut source: Option<&(dyn StdError + 'static)> = Some(error);
+    while let Some(e) = source {
+        if let Some(blocked) = e.downcast_ref::<Blocked>() {
+            return Some(blocked.0.clone());
+        }
+        source = e.source();
+    }
+    None
+}
+
+/// System resolver that drops private and reserved addresses, failing when
+/// none are left
+struct GuardedResolver {
+    allow_private: bool,
+}
+
+impl Resolve for GuardedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        let allow_private = self.allow_private;
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            let allowed: Vec<SocketAddr> = addrs
+                .into_iter()
+                .filter(|addr| allow_private || is_public_ip(addr.ip()))
+                .collect();
+            if allowed.is_empty() {
+                return Err(Box::new(Blocked(format!(
+                    "{} resolves only to private or reserved addresses",
+                    host
+                ))) as Box<dyn StdError + Send + Sync>);
+            }
+            Ok(Box::new(allowed.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether `ip` is a globally routable unicast address
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_public_v4(v4),
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_v4(v4);
+            }
+            let s = v6.segments();
+            // NAT64 (64:ff9b::/96) and 6to4 (2002::/16) embed an IPv4 address
+            if s[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                return is_public_v4(embedded_v4(s[6], s[7]));
+            }
+            if s[0] == 0x2002 {
+                return is_public_v4(embedded_v4(s[1], s[2]));
+            }
+            // Teredo (2001::/32) tunnels to an (obfuscated) IPv4 client that
+            // may be anywhere, so it is never treated as public
+            if s[0] == 0x2001 && s[1] == 0 {
+                return false;
+            }
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || s[..6] == [0; 6] // IPv4-compatible
+                || (s[0] & 0xfe00) == 0xfc00 // unique local
+                || (s[0] & 0xffc0) == 0xfe80 // link-local
+                || (s[0] & 0xffc0) == 0xfec0 // site-local
+                || (s[0] == 0x2001 && s[1] == 0x0db8)) // documentation
+        }
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        || (a == 100 && (b & 0xc0) == 64) // carrier-grade NAT
+        || (a == 192 && b == 0 && c == 0) // IETF protocol assignments
+        || (a == 198 && (b & 0xfe) == 18) // benchmarking
+        || a >= 240) // reserved
+}
+
+fn embedded_v4(high: u16, low: u16) -> Ipv4Addr {
+    let [a, b] = high.to_be_bytes();
+    let [c, d] = low.to_be_bytes();
+    Ipv4Addr::new(a, b, c, d)
+}
+
+fn domain_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(normalize_host)
+        .filter(|d| !d.is_empty())
+        .collect()
+}
+
+/// Lowercase, without brackets around IPv6 or a trailing dot
+fn normalize_host(host: &str) -> String {
+    host.trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .trim_end_matches('.')
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn public(ip: &str) -> bool {
+        is_public_ip(ip.parse().unwrap())
+    }
+
+    fn policy(allowlist: &str, denylist: &str) -> HttpPolicy {
+        HttpPolicy {
+            allow_private: false,
+            allowlist: domain_list(allowlist),
+            denylist: domain_list(denylist),
+            domain_headers: Vec::new(),
+        }
+    }
+
+    fn check(policy: &HttpPolicy, url: &str) -> Result<(), Blocked> {
+        policy.check_url(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn classifies_ipv4() {
+        assert!(public("8.8.8.8"));
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "255.255.255.255",
+            "240.0.0.1",
+            "198.18.0.1",
+        ] {
+            assert!(!public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn classifies_ipv6() {
+        assert!(public("2606:4700:4700::1111"));
+        for ip in [
+            "::1",
+            "::",
+            "fc00::1",
+            "fe80::1",
+            "fec0::1",
+            "ff02::1",
+            "2001:db8::1",
+        ] {
+            assert!(!public(ip), "{} should not be public", ip);
+        }
+    }
+
+    #[test]
+    fn follows_ipv4_embedded_in_ipv6() {
+        // v4-mapped
+        assert!(!public("::ffff:127.0.0.1"));
+        assert!(!public("::ffff:169.254.169.254"));
+        assert!(public("::ffff:8.8.8.8"));
+        // 6to4
+        assert!(!public("2002:7f00:1::"));
+        assert!(!public("2002:c0a8:101::1"));
+        assert!(public("2002:808:808::1"));
+        // NAT64
+        assert!(!public("64:ff9b::10.0.0.1"));
+        assert!(public("64:ff9b::8.8.8.8"));
+        // IPv4-compatible
+        assert!(!public("::127.0.0.1"));
+    }
+
+    #[test]
+    fn blocks_teredo() {
+        assert!(!public("2001:0:4136:e378:8000:63bf:3fff:fdd2"));
+        assert!(!public("2001::1"));
+    }
+
+    #[test]
+    fn check_url_blocks_private_literals() {
+        let policy = policy("", "");
+        assert!(check(&policy, "https://example.com/").is_ok());
+        assert!(check(&policy, "http://127.0.0.1:8080/").is_err());
+        assert!(check(&policy, "http://[::ffff:7f00:1]/").is_err());
+        assert!(check(&policy, "http://[2002:7f00:1::]/").is_err());
+        assert!(check(&policy, "ftp://example.com/").is_err());
+    }
+
+    #[test]
+    fn domain_lists_ignore_case_and_trailing_dots() {
+        let denied = policy("", "Localhost., internal.example");
+        assert!(check(&denied, "http://localhost./").is_err());
+        assert!(check(&denied, "http://LOCALHOST/").is_err());
+        assert!(check(&denied, "http://api.internal.example./").is_err());
+        assert!(check(&denied, "http://notinternal.example/").is_ok());
+
+        let allowed = policy("example.com", "");
+        assert!(check(&allowed, "https://www.example.com./x").is_ok());
+        assert!(check(&allowed, "https://example.com.evil.net/").is_err());
+        assert!(check(&allowed, "https://badexample.com/").is_err());
+    }
+
+    #[test]
+    fn headers_for_picks_the_most_specific_domain() {
+        let mut policy = policy("", "");
+        policy.domain_headers = vec![
+            (
+                "example.com".to_string(),
+                HashMap::from([("a".into(), "1".into())]),
+            ),
+            (
+                "api.example.com".to_string(),
+                HashMap::from([("b".into(), "2".into())]),
+            ),
+        ];
+        let url = Url::parse("https://v1.api.example.com./").unwrap();
+        assert_eq!(
+            policy.headers_for(&url).map(|(d, _)| d),
+            Some("api.example.com")
+        );
+        let url = Url::parse("https://example.org/").unwrap();
+        assert!(policy.headers_for(&url).is_none());
+    }
+
+    #[tokio::test]
+    async fn resolver_refuses_localhost() {
+        let resolver = GuardedResolver {
+            allow_private: false,
+        };
+        let error = match resolver.resolve(Name::from_str("localhost").unwrap()).await {
+            Ok(_) => panic!("localhost must be refused"),
+            Err(e) => e,
+        };
+        assert!(error.downcast_ref::<Blocked>().is_some(), "{}", error);
+        // With a trailing dot the name either resolves to loopback and is
+        // blocked, or doesn't resolve at all; it never gets through
+        let trailing_dot = resolver
+            .resolve(Name::from_str("localhost.").unwrap())
+            .await;
+        assert!(trailing_dot.is_err());
+    }
+}
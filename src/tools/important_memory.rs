@@ -0,0 +1,129 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+
+use super::schema::parse_args;
+use super::{Tool, ToolContext};
+use crate::db;
+use crate::memory;
+
+pub struct SetImportantMemory;
+
+#[async_trait]
+impl Tool for SetImportantMemory {
+    fn name(&self) -> &str {
+        "set_important_memory"
+    }
+
+    fn description(&self) -> &str {
+        "Set a pinned 'important' memory for a segment. This is a special single memory entry that gets automatically included in EVERY system prompt for the relevant scope — the bot always sees it without needing to look it up. Use this for critical information that should always be top-of-mind:\n\
+         - For 'chat:{chat_id}': e.g., 'This is a work group. Be professional. The project deadline is March 15.'\n\
+         - For 'global': e.g., 'Always respond in English unless asked otherwise.'\n\
+         - For 'bot': e.g., 'I prefer to be called Astarte. I should be cheerful and use casual language.'\n\
+         There is only ONE important memory per segment (setting a new one overwrites the previous). Max 500 characters."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "segment": {
+                    "type": "string",
+                    "description": "The segment to pin this memory to. Example: 'chat:-1001234567890', 'global', 'bot'. Person segments are also supported: 'person:123456789', and forum topic segments: 'topic:-1001234567890:42'."
+                },
+                "content": {
+                    "type": "string",
+                    "description": "The important information to always remember. Max 500 characters. Be concise — this is injected into every prompt."
+                }
+            },
+            "required": ["segment", "content"]
+        })
+    }
+
+    fn has_side_effects(&self, _args: &Value) -> bool {
+        true
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_set_important_memory(ctx.pool, parse_args(args)?).await
+    }
+}
+
+pub struct ClearImportantMemory;
+
+#[async_trait]
+impl Tool for ClearImportantMemory {
+    fn name(&self) -> &str {
+        "clear_important_memory"
+    }
+
+    fn description(&self) -> &str {
+        "Remove the pinned 'important' memory from a segment. After clearing, the information will no longer be automatically included in prompts for that scope."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "segment": {
+                    "type": "string",
+                    "description": "The segment to clear the pinned memory from. Example: 'chat:-1001234567890', 'global', 'bot'."
+                }
+            },
+            "required": ["segment"]
+        })
+    }
+
+    fn has_side_effects(&self, _args: &Value) -> bool {
+        true
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_clear_important_memory(ctx.pool, parse_args(args)?).await
+    }
+}
+
+#[derive(Deserialize)]
+struct SetImportantMemoryArgs {
+    segment: String,
+    content: String,
+}
+
+async fn execute_set_important_memory(
+    pool: &SqlitePool,
+    args: SetImportantMemoryArgs,
+) -> Result<String> {
+    let segment = args.segment.as_str();
+    let content = args.content.as_str();
+
+    memory::validate_segment(segment)?;
+    db::set_important_memory(pool, segment, content).await?;
+    Ok(json!({
+        "success": true,
+        "message": format!("Pinned important memory for segment '{}'. This will now be included in every prompt.", segment),
+        "segment": segment,
+        "content": content,
+    }).to_string())
+}
+
+#[derive(Deserialize)]
+struct ClearImportantMemoryArgs {
+    segment: String,
+}
+
+async fn execute_clear_important_memory(
+    pool: &SqlitePool,
+    args: ClearImportantMemoryArgs,
+) -> Result<String> {
+    let segment = args.segment.as_str();
+
+    memory::validate_segment(segment)?;
+    let cleared = db::clear_important_memory(pool, segment).await?;
+    if cleared {
+        Ok(json!({"success": true, "message": format!("Cleared pinned memory for segment '{}'", segment)}).to_string())
+    } else {
+        Ok(json!({"success": false, "message": format!("No pinned memory found for segment '{}'", segment)}).to_string())
+    }
+}
@@ -0,0 +1,346 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::sandbox::{NetworkMode, SandboxRunner, truncate_text_for_output};
+use super::schema::parse_args;
+use super::workspace::Workspace;
+use super::{Tool, ToolContext};
+
+pub struct MaigretOsint;
+
+#[async_trait]
+impl Tool for MaigretOsint {
+    fn name(&self) -> &str {
+        "maigret_osint"
+    }
+
+    fn description(&self) -> &str {
+        "Run an OSINT (Open Source Intelligence) username search across 500+ websites using Maigret. \
+         Finds matching profiles on social networks, forums, coding platforms, and other sites.\n\
+         Use ONLY when a user explicitly asks for an OSINT search, username lookup, or online profile search.\n\n\
+         Depth levels control speed vs coverage:\n\
+         - 'light' (default): Top 50 popular sites, ~30 seconds\n\
+         - 'medium': Top 200 sites, ~2 minutes\n\
+         - 'full': All 500+ sites, ~5 minutes\n\n\
+         You can also specify individual sites to check instead of using depth.\n\
+         Prerequisite: Maigret must be installed on the host (sudo pip3 install maigret).\n\n\
+         Examples:\n\
+         - Quick search: {\"query\": \"johndoe\"}\n\
+         - Deep scan: {\"query\": \"johndoe\", \"depth\": \"full\", \"timeout_secs\": 300}\n\
+         - Specific sites: {\"query\": \"johndoe\", \"sites\": \"github,twitter,reddit\"}"
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "Username/handle to search. Only alphanumeric, dots, underscores, hyphens. Max 50 chars."
+                },
+                "depth": {
+                    "type": "string",
+                    "enum": ["light", "medium", "full"],
+                    "description": "Search depth. 'light' = top 50 sites (~30s), 'medium' = top 200 (~2min), 'full' = all 500+ (~5min). Default: 'light'."
+                },
+                "timeout_secs": {
+                    "type": "integer",
+                    "description": "Max scan time in seconds. Default: 120. Max: 300."
+                },
+                "sites": {
+                    "type": "string",
+                    "description": "Optional comma-separated specific sites to check (e.g., 'github,twitter,reddit'). Overrides depth."
+                }
+            },
+            "required": ["query"]
+        })
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_maigret_osint(ctx, parse_args(args)?).await
+    }
+}
+
+const MAIGRET_DEFAULT_TIMEOUT: u64 = 120;
+const MAIGRET_MAX_TIMEOUT: u64 = 300;
+
+#[derive(Deserialize)]
+struct MaigretArgs {
+    query: String,
+    depth: Option<String>,
+    timeout_secs: Option<u64>,
+    #[serde(default)]
+    sites: String,
+}
+
+async fn execute_maigret_osint(ctx: &ToolContext<'_>, args: MaigretArgs) -> Result<String> {
+    let query = args.query.as_str();
+    let depth = args.depth.as_deref().unwrap_or("light");
+    let timeout_secs = args
+        .timeout_secs
+        .map(|v| v.min(MAIGRET_MAX_TIMEOUT))
+        .unwrap_or(MAIGRET_DEFAULT_TIMEOUT);
+    let sites = args.sites.as_str();
+
+    // Validate username
+    let username_re = regex::Regex::new(r"^[a-zA-Z0-9._-]+$").unwrap();
+    if query.is_empty() || query.len() > 50 || !username_re.is_match(query) {
+        return Ok(json!({
+            "error": "Invalid username. Only letters, digits, dots, underscores, hyphens. 1-50 chars."
+        }).to_string());
+    }
+
+    let workspace = Workspace::open(ctx.pool, ctx.chat_id, false, false).await?;
+
+    // Write a small Python wrapper that runs maigret and writes clean JSON
+    let params = json!({
+        "query": query,
+        "depth": depth,
+        "timeout": timeout_secs,
+    });
+    tokio::fs::write(
+        workspace.dir.join("__params__.json"),
+        serde_json::to_string(&params)?,
+    )
+    .await?;
+
+    // Build depth flags
+    let mut maigret_flags: Vec<String> = Vec::new();
+    if !sites.is_empty() {
+        for site in sites.split(',') {
+            let t = site.trim();
+            if !t.is_empty() {
+                maigret_flags.extend(["--site".into(), t.to_string()]);
+            }
+        }
+    } else {
+        match depth {
+            "medium" => maigret_flags.extend(["--top-sites".into(), "200".into()]),
+            "full" => {}
+            _ => maigret_flags.extend(["--top-sites".into(), "50".into()]),
+        }
+    }
+
+    // Write Python wrapper script
+    let script = r#"
+import subprocess, json, sys, os
+
+with open('/workspace/__params__.json') as f:
+    p = json.load(f)
+
+query = p['query']
+depth = p['depth']
+timeout = p['timeout']
+
+# Redirect maigret cache writes to writable dirs
+os.environ['XDG_DATA_HOME'] = '/workspace/.data'
+os.environ['XDG_CONFIG_HOME'] = '/workspace/.config'
+os.environ['XDG_CACHE_HOME'] = '/workspace/.cache'
+for d in ['/workspace/.data', '/workspace/.config', '/workspace/.cache']:
+    os.makedirs(d, exist_ok=True)
+
+# Read extra flags (depth/site flags)
+flags = []
+flag_file = '/workspace/__flags__.json'
+if os.path.exists(flag_file):
+    with open(flag_file) as f:
+        flags = json.load(f)
+
+# Copy maigret's bundled database to writable workspace so it can read+update it
+import shutil, importlib
+try:
+    maigret_pkg = importlib.import_module('maigret')
+    pkg_dir = os.path.dirname(maigret_pkg.__file__)
+    src_db = os.path.join(pkg_dir, 'resources', 'data.json')
+    dst_db = '/workspace/data.json'
+    if os.path.exists(src_db):
+        shutil.copy2(src_db, dst_db)
+except Exception:
+    dst_db = None
+
+# --json takes a format type ('simple' or 'ndjson'), output goes to stdout
+cmd = ['python3', '-m', 'maigret', query, '--json', 'simple', '--no-color', '--no-progressbar'] + flags
+if dst_db and os.path.exists(dst_db):
+    cmd += ['--db', dst_db]
+
+try:
+    proc = subprocess.run(cmd, capture_output=True, text=True, timeout=timeout, cwd='/workspace')
+except subprocess.TimeoutExpired:
+    print(json.dumps({"error": "Scan timed out", "query": query, "timeout_secs": timeout}))
+    sys.exit(0)
+except Exception as e:
+    msg = str(e)
+    if 'No module' in msg or 'not found' in msg:
+        print(json.dumps({"error": "Maigret not installed. Run: sudo pip3 install maigret"}))
+    else:
+        print(json.dumps({"error": msg}))
+    sys.exit(0)
+
+# Parse JSON from stdout
+data = None
+if proc.stdout:
+    raw = proc.stdout.strip()
+    # Try direct parse
+    try:
+        data = json.loads(raw)
+    except:
+        pass
+    # Try finding JSON object/array in mixed output (maigret may print text before JSON)
+    if not data:
+        for start_char, end_char in [('{', '}'), ('[', ']')]:
+            start = raw.rfind('\n' + start_char)
+            if start == -1:
+                start = raw.find(start_char)
+            else:
+                start += 1  # skip newline
+            end = raw.rfind(end_char)
+            if start != -1 and end != -1 and end >= start:
+                try:
+                    data = json.loads(raw[start:end+1])
+                    break
+                except:
+                    pass
+
+if not data:
+    print(json.dumps({
+        "error": "No parseable output from maigret",
+        "exit_code": proc.returncode,
+        "stderr": (proc.stderr or "")[:3000],
+        "stdout": (proc.stdout or "")[:3000],
+    }))
+    sys.exit(0)
+
+# Extract claimed profiles from maigret output (handles multiple formats)
+profiles = []
+checked = 0
+
+def get_status(info):
+    s = info.get('status', 'Unknown')
+    if isinstance(s, dict):
+        return s.get('status', str(s))
+    return str(s)
+
+def scan_sites(d):
+    global checked
+    for name, info in d.items():
+        if not isinstance(info, dict):
+            continue
+        if 'url_user' not in info and 'status' not in info:
+            continue
+        checked += 1
+        st = get_status(info)
+        if st.lower() in ('claimed', 'taken', 'found', 'detected'):
+            profiles.append({
+                "site": name,
+                "url": info.get('url_user', info.get('url', '')),
+                "status": st,
+            })
+
+if isinstance(data, list):
+    for item in data:
+        if isinstance(item, dict):
+            if 'sites' in item and isinstance(item['sites'], dict):
+                scan_sites(item['sites'])
+            elif 'url_user' in item:
+                checked += 1
+                st = get_status(item)
+                if st.lower() in ('claimed', 'taken', 'found', 'detected'):
+                    profiles.append({
+                        "site": item.get('site_name', item.get('name', '?')),
+                        "url": item.get('url_user', ''),
+                        "status": st,
+                    })
+elif isinstance(data, dict):
+    target = data.get(query, data)
+    if isinstance(target, dict):
+        if 'sites' in target and isinstance(target['sites'], dict):
+            scan_sites(target['sites'])
+        else:
+            scan_sites(target)
+
+profiles.sort(key=lambda x: x['site'].lower())
+
+print(json.dumps({
+    "query": query,
+    "total_found": len(profiles),
+    "total_checked": checked,
+    "scan_depth": depth,
+    "profiles": profiles,
+}, indent=2, ensure_ascii=False))
+"#;
+    tokio::fs::write(workspace.dir.join("__script__.py"), script).await?;
+    tokio::fs::write(
+        workspace.dir.join("__flags__.json"),
+        serde_json::to_string(&maigret_flags)?,
+    )
+    .await?;
+
+    // Maigret needs the network, and ~/.local for pip --user installs
+    let home = std::env::var("HOME").unwrap_or_else(|_| "/root".to_string());
+    let runner = SandboxRunner::new(ctx.pool, &workspace)
+        .await?
+        .network(NetworkMode::Full)
+        .ro_bind(format!("{}/.local", home))
+        .env("HOME", home);
+
+    let total_timeout = timeout_secs + 30;
+    tracing::info!(query, depth, timeout_secs, "Executing Maigret OSINT scan");
+
+    let output = match runner
+        .run(
+            ctx,
+            "maigret_osint",
+            query,
+            &["python3", "/workspace/__script__.py"],
+            total_timeout,
+        )
+        .await
+    {
+        Ok(o) => o,
+        Err(e) => {
+            workspace.cleanup().await;
+            return Ok(json!({
+                "error": format!("Failed to launch sandbox: {}", e),
+                "hint": "Ensure 'bwrap' (bubblewrap) is installed: sudo apt install bubblewrap",
+            })
+            .to_string());
+        }
+    };
+    workspace.cleanup().await;
+
+    // Timeout
+    if output.timed_out() {
+        return Ok(json!({
+            "error": "Maigret scan timed out",
+            "timeout_secs": total_timeout,
+            "query": query,
+        })
+        .to_string());
+    }
+
+    // Parse stdout JSON (Python script outputs clean JSON)
+    let stdout_trimmed = output.stdout.trim();
+    if !stdout_trimmed.is_empty() {
+        // Try direct parse
+        if let Ok(result) = serde_json::from_str::<Value>(stdout_trimmed) {
+            return Ok(result.to_string());
+        }
+        // Try extracting JSON from mixed output (maigret may print progress)
+        if let Some(start) = stdout_trimmed.rfind("\n{")
+            && let Ok(result) = serde_json::from_str::<Value>(&stdout_trimmed[start + 1..])
+        {
+            return Ok(result.to_string());
+        }
+    }
+
+    Ok(json!({
+        "error": "Failed to parse maigret output",
+        "exit_code": output.exit_code,
+        "stdout": truncate_text_for_output(&output.stdout, 5000),
+        "stderr": truncate_text_for_output(&output.stderr, 3000),
+        "query": query,
+    })
+    .to_string())
+}
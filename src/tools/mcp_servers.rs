@@ -0,0 +1,590 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+
+use super::schema::{ArgError, parse_args};
+use super::{Tool, ToolContext, action_of};
+use crate::db;
+use crate::secrets::{self, Secrets};
+
+pub struct CrudMcpServer;
+
+#[async_trait]
+impl Tool for CrudMcpServer {
+    fn name(&self) -> &str {
+        "crud_mcp_server"
+    }
+
+    fn description(&self) -> &str {
+        "Manage MCP server registrations.\n\n\
+         ACTIONS:\n\
+         - list: no extra fields needed.\n\
+         - read: requires `name`.\n\
+         - delete: requires `name`.\n\
+         - update: requires `name` + at least one field to change.\n\
+         - create: requires `name` + connection info (see below).\n\n\
+         CREATE RULES (IMPORTANT — follow these strictly):\n\
+         1. For a LOCAL COMMAND server (stdio): set `command` (e.g. 'npx -y @modelcontextprotocol/server-sqlite'). Transport is auto-detected as stdio.\n\
+         2. For an HTTP/SSE server: set `endpoint` to a full URL (e.g. 'http://localhost:3000/mcp'). Transport is auto-detected as http.\n\
+         3. For a TCP server: set `transport` to 'tcp' and `endpoint` to 'tcp://host:port' or 'host:port'.\n\
+         4. You may optionally set `transport` explicitly, but if you do, you MUST also provide the matching field (`command` for stdio, `endpoint` for tcp/http/sse/streamable_http).\n\
+         5. NEVER call create with only `name` and `transport` — you MUST include `command` or `endpoint`.\n\
+         6. If the user has not told you the command or endpoint, ASK THEM FIRST — do not guess."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "enum": ["create", "read", "list", "update", "delete"],
+                    "description": "The CRUD action to perform."
+                },
+                "name": {
+                    "type": "string",
+                    "minLength": 1,
+                    "description": "Server name. Required for create/read/update/delete."
+                },
+                "new_name": {
+                    "type": "string",
+                    "minLength": 1,
+                    "description": "New name when updating (rename)."
+                },
+                "description": {
+                    "type": "string",
+                    "minLength": 1,
+                    "description": "Description for the server."
+                },
+                "transport": {
+                    "type": "string",
+                    "enum": ["stdio", "tcp", "http", "sse", "streamable_http"],
+                    "description": "Optional transport mode. If omitted, inferred from command/endpoint. IMPORTANT: if you set transport to tcp/http/sse/streamable_http you MUST also provide endpoint. If you set transport to stdio you MUST provide command."
+                },
+                "command": {
+                    "type": "string",
+                    "minLength": 1,
+                    "description": "Shell command to start the server process. Required for stdio transport. Example: 'npx -y @modelcontextprotocol/server-sqlite'."
+                },
+                "endpoint": {
+                    "type": "string",
+                    "minLength": 1,
+                    "description": "Server URL. Required for http/sse/streamable_http transport. Examples: 'http://localhost:3000/mcp', 'tcp://127.0.0.1:5000', 'host:port'."
+                },
+                "args": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Startup arguments for command mode (stdio)."
+                },
+                "environment": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                    "description": "Environment variables for the server process. Use {{secret:NAME}} for credentials."
+                }
+            },
+            "required": ["action"]
+        })
+    }
+
+    fn has_side_effects(&self, args: &Value) -> bool {
+        !matches!(action_of(args).as_str(), "read" | "list")
+    }
+
+    fn is_destructive(&self, _ctx: &ToolContext<'_>, args: &Value) -> bool {
+        action_of(args) == "delete"
+    }
+
+    fn fills_secrets(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        let args: CrudMcpServerArgs = parse_args(args)?;
+        if let Some(error) = check_secret_placeholders(ctx, &args).await? {
+            return Ok(error);
+        }
+        execute_crud_mcp_server(ctx.pool, args, ctx.user_id).await
+    }
+}
+
+/// Secrets are only allowed in environment values, and are stored there as
+/// placeholders so the database never holds their values; they are filled
+/// in when the server process is started. Returns the error for the model
+/// when placeholders appear elsewhere or name secrets the caller can't use.
+async fn check_secret_placeholders(
+    ctx: &ToolContext<'_>,
+    args: &CrudMcpServerArgs,
+) -> Result<Option<String>> {
+    let fields = [
+        &args.name,
+        &args.new_name,
+        &args.description,
+        &args.transport,
+        &args.command,
+        &args.endpoint,
+    ];
+    let elsewhere = fields
+        .iter()
+        .any(|field| field.as_deref().is_some_and(secrets::has_placeholder))
+        || args
+            .args
+            .iter()
+            .flatten()
+            .any(|a| secrets::has_placeholder(a))
+        || args
+            .environment
+            .iter()
+            .flatten()
+            .any(|(key, _)| secrets::has_placeholder(key));
+    if elsewhere {
+        return Ok(Some(
+            json!({"error": "Secrets can only be used in environment values"}).to_string(),
+        ));
+    }
+
+    let mut values = json!(
+        args.environment
+            .iter()
+            .flatten()
+            .map(|(_, v)| v)
+            .collect::<Vec<_>>()
+    );
+    if secrets::contains_placeholder(&values) {
+        let vault = Secrets::load_usable(ctx.pool, ctx.chat_id, ctx.is_admin).await?;
+        if let Err(e) = vault.substitute(&mut values) {
+            return Ok(Some(json!({"error": e.to_string()}).to_string()));
+        }
+    }
+    Ok(None)
+}
+
+fn parse_json_or_default<T: serde::Serialize>(value: Option<&T>, fallback: &str) -> Result<String> {
+    Ok(match value {
+        Some(v) => serde_json::to_string(v)?,
+        None => fallback.to_string(),
+    })
+}
+
+fn parse_json_fallback(raw: &str) -> Value {
+    serde_json::from_str(raw).unwrap_or_else(|_| json!(raw))
+}
+
+fn normalize_transport(transport: &str) -> Result<String> {
+    let transport = transport.trim().to_lowercase();
+    let transport = transport.as_str();
+    match transport {
+        "stdio" | "tcp" | "http" | "sse" | "streamable_http" | "streamable-http" => {
+            Ok(match transport {
+                "streamable-http" => "streamable_http".to_string(),
+                other => other.to_string(),
+            })
+        }
+        _ => anyhow::bail!(
+            "Invalid transport '{}'. Allowed values: stdio, tcp, http, sse, streamable_http",
+            transport
+        ),
+    }
+}
+
+fn validate_mcp_runtime(transport: &str, command: &str, endpoint: &str) -> Result<()> {
+    match transport {
+        "stdio" if command.trim().is_empty() => {
+            anyhow::bail!(
+                "Transport 'stdio' requires a `command` field (e.g. 'npx -y @modelcontextprotocol/server-sqlite'). Ask the user what command to run."
+            );
+        }
+        "tcp" if command.trim().is_empty() => {
+            if endpoint.trim().is_empty() {
+                anyhow::bail!(
+                    "Transport 'tcp' requires a `command` or `endpoint` field (e.g. 'tcp://127.0.0.1:3001'). Ask the user for the connection details."
+                );
+            }
+            validate_tcp_endpoint(endpoint)?;
+        }
+        "http" | "sse" | "streamable_http" => {
+            if endpoint.trim().is_empty() {
+                anyhow::bail!(
+                    "Transport '{}' requires an `endpoint` URL (e.g. 'http://localhost:3000/mcp'). Ask the user for the server URL.",
+                    transport
+                );
+            }
+            if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+                anyhow::bail!("'endpoint' must start with http:// or https://");
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn infer_mcp_transport(
+    explicit_transport: Option<&str>,
+    command: &str,
+    endpoint: &str,
+) -> Result<String> {
+    if let Some(transport_raw) = explicit_transport {
+        let transport = transport_raw.trim();
+        if !transport.is_empty() {
+            return normalize_transport(transport);
+        }
+    }
+
+    let command = command.trim();
+    let endpoint = endpoint.trim();
+
+    if !endpoint.is_empty() {
+        if endpoint.starts_with("http://") || endpoint.starts_with("https://") {
+            return Ok("http".to_string());
+        }
+        if validate_tcp_endpoint(endpoint).is_ok() {
+            return Ok("tcp".to_string());
+        }
+        anyhow::bail!(
+            "Invalid endpoint '{}': expected `tcp://host:port`, `host:port`, `http://host`, or `https://host`.",
+            endpoint
+        );
+    }
+
+    if !command.is_empty() {
+        return Ok("stdio".to_string());
+    }
+
+    anyhow::bail!(
+        "Cannot create MCP server without knowing how to connect. Provide `command` (for stdio) or `endpoint` (for http/tcp). Ask the user which type of server they want to add and the connection details."
+    );
+}
+
+fn validate_tcp_endpoint(endpoint: &str) -> Result<()> {
+    let trimmed = endpoint.trim();
+    if trimmed.starts_with("tcp://") {
+        return Ok(());
+    }
+
+    // Accept host:port shorthand
+    let has_colon = trimmed.contains(':');
+    let has_slash = trimmed.contains('/');
+    let has_spaces = trimmed.contains(char::is_whitespace);
+
+    if !has_colon || has_slash || has_spaces {
+        anyhow::bail!("'endpoint' for transport 'tcp' must be 'tcp://host:port' or 'host:port'");
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct CrudMcpServerArgs {
+    action: String,
+    name: Option<String>,
+    new_name: Option<String>,
+    description: Option<String>,
+    transport: Option<String>,
+    command: Option<String>,
+    endpoint: Option<String>,
+    args: Option<Vec<String>>,
+    environment: Option<BTreeMap<String, String>>,
+}
+
+async fn execute_crud_mcp_server(
+    pool: &SqlitePool,
+    args: CrudMcpServerArgs,
+    actor_id: i64,
+) -> Result<String> {
+    let action = args.action.to_lowercase();
+    let args = &args;
+
+    match action.as_str() {
+        "list" => execute_crud_mcp_server_list(pool, args).await,
+        "create" => execute_crud_mcp_server_create(pool, args, actor_id).await,
+        "read" => execute_crud_mcp_server_read(pool, args).await,
+        "update" => execute_crud_mcp_server_update(pool, args, actor_id).await,
+        "delete" => execute_crud_mcp_server_delete(pool, args).await,
+        _ => Ok(json!({
+            "error": "Invalid action. Expected one of: create, read, list, update, delete",
+        })
+        .to_string()),
+    }
+}
+
+fn required_name(args: &CrudMcpServerArgs) -> Result<&str, ArgError> {
+    args.name
+        .as_deref()
+        .ok_or_else(|| ArgError::new("name", format!("required for action '{}'", args.action)))
+}
+
+async fn execute_crud_mcp_server_list(
+    pool: &SqlitePool,
+    _args: &CrudMcpServerArgs,
+) -> Result<String> {
+    let servers = db::mcp_server_list(pool, false).await?;
+    let items: Vec<_> = servers
+        .iter()
+        .map(|server| {
+            json!({
+                "id": server.id,
+                "name": server.name,
+                "description": server.description,
+                "transport": server.transport,
+                "endpoint": server.endpoint,
+                "command": server.command,
+                "args": parse_json_fallback(&server.args),
+                "environment": parse_json_fallback(&server.environment),
+                "created_by": server.created_by,
+                "updated_by": server.updated_by,
+                "created_at": server.created_at,
+                "updated_at": server.updated_at,
+            })
+        })
+        .collect();
+
+    let count = items.len();
+    Ok(json!({
+        "count": count,
+        "servers": items,
+    })
+    .to_string())
+}
+
+async fn execute_crud_mcp_server_create(
+    pool: &SqlitePool,
+    args: &CrudMcpServerArgs,
+    actor_id: i64,
+) -> Result<String> {
+    let name = match args.name.as_deref() {
+        Some(n) if !n.trim().is_empty() => n,
+        _ => return Ok(json!({
+            "error": "Missing 'name'. You must provide a name for the MCP server.",
+            "correct_call_example": {"action": "create", "name": "my_server", "endpoint": "http://localhost:9090"}
+        }).to_string()),
+    };
+    let command = args.command.clone().unwrap_or_default();
+    let endpoint = args.endpoint.clone().unwrap_or_default();
+
+    // Check for the common LLM mistake: transport set but no command/endpoint
+    let explicit_transport = args.transport.as_deref();
+    if let Some(t) = explicit_transport {
+        let t = t.trim();
+        match t {
+            "tcp" if endpoint.trim().is_empty() => {
+                return Ok(json!({
+                    "error": "You set transport='tcp' but did not provide 'endpoint'. You MUST include an endpoint when using tcp transport.",
+                    "fix": "Add the 'endpoint' field with the server address. Ask the user for the host and port if you do not know them.",
+                    "correct_call_example": {
+                        "action": "create",
+                        "name": name,
+                        "transport": "tcp",
+                        "endpoint": "tcp://HOST:PORT"
+                    }
+                }).to_string());
+            }
+            "http" | "sse" | "streamable_http" if endpoint.trim().is_empty() => {
+                return Ok(json!({
+                    "error": format!("You set transport='{}' but did not provide 'endpoint'. You MUST include an endpoint URL when using this transport.", t),
+                    "fix": "Add the 'endpoint' field with the server URL. Ask the user for the URL if you do not know it.",
+                    "correct_call_example": {
+                        "action": "create",
+                        "name": name,
+                        "transport": t,
+                        "endpoint": "http://HOST:PORT"
+                    }
+                }).to_string());
+            }
+            "stdio" if command.trim().is_empty() => {
+                return Ok(json!({
+                    "error": "You set transport='stdio' but did not provide 'command'. You MUST include a command when using stdio transport.",
+                    "fix": "Add the 'command' field with the shell command to start the server. Ask the user for the command if you do not know it.",
+                    "correct_call_example": {
+                        "action": "create",
+                        "name": name,
+                        "transport": "stdio",
+                        "command": "npx -y @modelcontextprotocol/server-example"
+                    }
+                }).to_string());
+            }
+            _ => {}
+        }
+    }
+
+    if command.trim().is_empty() && endpoint.trim().is_empty() {
+        return Ok(json!({
+            "error": "You must provide either 'command' (for stdio servers) or 'endpoint' (for http/tcp servers). Ask the user which type of server they want and the connection details.",
+            "examples": [
+                {"action": "create", "name": name, "command": "npx -y @modelcontextprotocol/server-sqlite /path/to/db"},
+                {"action": "create", "name": name, "endpoint": "http://127.0.0.1:9090"},
+                {"action": "create", "name": name, "endpoint": "127.0.0.1:9090"}
+            ]
+        }).to_string());
+    }
+
+    let transport = infer_mcp_transport(explicit_transport, &command, &endpoint)?;
+    let description = args.description.as_deref().unwrap_or("");
+    let args_json = parse_json_or_default(args.args.as_ref(), "[]")?;
+    let env_json = parse_json_or_default(args.environment.as_ref(), "{}")?;
+
+    validate_mcp_runtime(&transport, &command, &endpoint)?;
+
+    let id = db::mcp_server_create(
+        pool,
+        name,
+        description,
+        &transport,
+        &endpoint,
+        &command,
+        &args_json,
+        &env_json,
+        true,
+        Some(actor_id),
+    )
+    .await?;
+
+    Ok(json!({
+        "success": true,
+        "id": id,
+        "name": name,
+        "message": format!("MCP server '{}' added", name),
+        "transport": transport,
+    })
+    .to_string())
+}
+
+async fn execute_crud_mcp_server_read(
+    pool: &SqlitePool,
+    args: &CrudMcpServerArgs,
+) -> Result<String> {
+    let name = required_name(args)?;
+
+    match db::mcp_server_get(pool, name).await? {
+        Some(server) => Ok(json!({
+            "success": true,
+            "server": {
+                "id": server.id,
+                "name": server.name,
+                "description": server.description,
+            "transport": server.transport,
+            "endpoint": server.endpoint,
+            "command": server.command,
+            "args": parse_json_fallback(&server.args),
+            "environment": parse_json_fallback(&server.environment),
+            "created_by": server.created_by,
+            "updated_by": server.updated_by,
+            "created_at": server.created_at,
+            "updated_at": server.updated_at,
+        }
+        })
+        .to_string()),
+        None => Ok(json!({
+            "success": false,
+            "message": format!("MCP server '{}' not found", name),
+        })
+        .to_string()),
+    }
+}
+
+async fn execute_crud_mcp_server_update(
+    pool: &SqlitePool,
+    args: &CrudMcpServerArgs,
+    actor_id: i64,
+) -> Result<String> {
+    let current_name = required_name(args)?;
+
+    let existing = db::mcp_server_get(pool, current_name)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("MCP server '{}' not found", current_name))?;
+
+    let new_name = args.new_name.as_deref().unwrap_or(current_name);
+
+    if new_name != current_name && db::mcp_server_get(pool, new_name).await?.is_some() {
+        anyhow::bail!("MCP server '{}' already exists", new_name);
+    }
+
+    let description = args.description.as_deref().unwrap_or(&existing.description);
+    let command = args
+        .command
+        .clone()
+        .unwrap_or_else(|| existing.command.clone());
+    let endpoint = args
+        .endpoint
+        .clone()
+        .unwrap_or_else(|| existing.endpoint.clone());
+    let transport = if args.transport.is_none() && args.command.is_none() && args.endpoint.is_none()
+    {
+        existing.transport.clone()
+    } else {
+        infer_mcp_transport(args.transport.as_deref(), &command, &endpoint)?
+    };
+    let args_json = parse_json_or_default(args.args.as_ref(), &existing.args)?;
+    let env_json = parse_json_or_default(args.environment.as_ref(), &existing.environment)?;
+
+    if current_name == new_name
+        && description == existing.description
+        && transport == existing.transport
+        && command == existing.command
+        && endpoint == existing.endpoint
+        && args_json == existing.args
+        && env_json == existing.environment
+    {
+        return Ok(json!({
+            "success": false,
+            "message": "No changes were provided for update",
+        })
+        .to_string());
+    }
+
+    validate_mcp_runtime(&transport, &command, &endpoint)?;
+
+    let updated = db::mcp_server_update(
+        pool,
+        current_name,
+        new_name,
+        description,
+        &transport,
+        &endpoint,
+        &command,
+        &args_json,
+        &env_json,
+        existing.enabled,
+        Some(actor_id),
+    )
+    .await?;
+
+    if updated {
+        Ok(json!({
+            "success": true,
+            "name": new_name,
+            "message": format!("MCP server '{}' updated", new_name),
+        })
+        .to_string())
+    } else {
+        Ok(json!({
+            "success": false,
+            "message": format!("Failed to update MCP server '{}'", current_name),
+        })
+        .to_string())
+    }
+}
+
+async fn execute_crud_mcp_server_delete(
+    pool: &SqlitePool,
+    args: &CrudMcpServerArgs,
+) -> Result<String> {
+    let name = required_name(args)?;
+
+    let deleted = db::mcp_server_delete(pool, name).await?;
+    if deleted {
+        Ok(json!({
+            "success": true,
+            "message": format!("MCP server '{}' deleted", name),
+        })
+        .to_string())
+    } else {
+        Ok(json!({
+            "success": false,
+            "message": format!("MCP server '{}' not found", name),
+        })
+        .to_string())
+    }
+}
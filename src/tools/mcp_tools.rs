@@ -0,0 +1,511 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+
+use super::schema::{self, ArgError, parse_args};
+use super::{Tool, ToolContext, substitute_mcp_secrets};
+use crate::db;
+use crate::mcp::McpManager;
+use crate::types::{FunctionDefinition, ToolDefinition};
+
+pub struct McpListTools;
+
+#[async_trait]
+impl Tool for McpListTools {
+    fn name(&self) -> &str {
+        "mcp_list_tools"
+    }
+
+    fn description(&self) -> &str {
+        "Discover tools/methods available on registered MCP servers (tcp/http transports only). \
+         Call this FIRST before using mcp_call, so you know what methods exist and what arguments they accept.\n\
+         Returns tool names, descriptions, and input schemas for each server.\n\
+         If server_name is omitted, queries ALL enabled tcp/http MCP servers at once.\n\
+         Use refresh=true to force re-fetching the tool list from the server (e.g., after server restart or update)."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "server_name": {
+                    "type": "string",
+                    "description": "Optional. Name of a specific MCP server to query (as registered via crud_mcp_server). If omitted, discovers tools from ALL enabled tcp/http servers."
+                },
+                "refresh": {
+                    "type": "boolean",
+                    "description": "Optional. If true, forces re-fetching the tool list from the server instead of using cached results. Default: false."
+                }
+            },
+            "required": []
+        })
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_mcp_list_tools(ctx.pool, ctx.mcp, parse_args(args)?).await
+    }
+}
+
+pub struct McpCall;
+
+#[async_trait]
+impl Tool for McpCall {
+    fn name(&self) -> &str {
+        "mcp_call"
+    }
+
+    fn description(&self) -> &str {
+        "Invoke a specific tool/method on an MCP server. You MUST call mcp_list_tools first to discover available methods and their expected arguments.\n\
+         The server is auto-connected if not already connected.\n\
+         Returns the server's response or an error message.\n\n\
+         CRITICAL: Most methods require arguments. You MUST pass the required fields from the method's inputSchema \
+         in the 'arguments' parameter as a JSON object. For example, if create_category requires 'name', call:\n\
+         mcp_call(server_name=\"myserver\", method=\"create_category\", arguments={\"name\": \"Electronics\"})\n\
+         Calling without arguments when the method requires them will ALWAYS fail."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "server_name": {
+                    "type": "string",
+                    "description": "Name of the MCP server to call (as registered via crud_mcp_server)."
+                },
+                "method": {
+                    "type": "string",
+                    "description": "The tool/method name to invoke (exactly as shown in mcp_list_tools results)."
+                },
+                "arguments": {
+                    "type": "object",
+                    "description": "IMPORTANT: The method's input parameters as a JSON object. Check the inputSchema from mcp_list_tools for required fields. Most methods need this — e.g. {\"name\": \"value\"}. Only omit for methods with no required parameters."
+                }
+            },
+            "required": ["server_name", "method"]
+        })
+    }
+
+    fn fills_secrets(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        let server = args["server_name"].as_str().unwrap_or_default();
+        let args = substitute_mcp_secrets(ctx, server, args.clone()).await?;
+        execute_mcp_call(ctx.pool, ctx.mcp, parse_args(&args)?).await
+    }
+}
+
+const MCP_DYNAMIC_CONNECT_RETRY_SECS: u64 = 300;
+static MCP_DYNAMIC_CONNECT_RETRY_AFTER: OnceLock<Mutex<HashMap<String, Instant>>> = OnceLock::new();
+
+fn mcp_dynamic_retry_map() -> &'static Mutex<HashMap<String, Instant>> {
+    MCP_DYNAMIC_CONNECT_RETRY_AFTER.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn mcp_dynamic_connect_allowed(server_name: &str, now: Instant) -> bool {
+    let map = mcp_dynamic_retry_map()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    match map.get(server_name) {
+        Some(next_allowed_at) => *next_allowed_at <= now,
+        None => true,
+    }
+}
+
+fn mcp_dynamic_mark_connect_failure(server_name: &str, now: Instant) {
+    let mut map = mcp_dynamic_retry_map()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    map.insert(
+        server_name.to_string(),
+        now + Duration::from_secs(MCP_DYNAMIC_CONNECT_RETRY_SECS),
+    );
+}
+
+fn mcp_dynamic_clear_connect_failure(server_name: &str) {
+    let mut map = mcp_dynamic_retry_map()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    map.remove(server_name);
+}
+
+/// Generate dynamic tool definitions from connected MCP servers.
+/// Each MCP tool becomes a first-class LLM tool named `mcp__{server}__{method}`
+/// so the LLM fills in parameters naturally without the mcp_call indirection.
+pub(super) async fn mcp_dynamic_definitions(
+    mcp: &McpManager,
+    pool: &SqlitePool,
+) -> Vec<ToolDefinition> {
+    let mut defs = Vec::new();
+
+    let servers = match db::mcp_server_list(pool, false).await {
+        Ok(s) => s,
+        Err(_) => return defs,
+    };
+
+    for server in &servers {
+        if !matches!(
+            server.transport.as_str(),
+            "tcp" | "http" | "sse" | "streamable_http"
+        ) {
+            continue;
+        }
+
+        let tools = if let Some(cached) = mcp.cached_tools(&server.name).await {
+            cached
+        } else {
+            let now = Instant::now();
+            if !mcp_dynamic_connect_allowed(&server.name, now) {
+                continue;
+            }
+
+            match mcp.ensure_connected(pool, &server.name).await {
+                Ok(()) => {
+                    mcp_dynamic_clear_connect_failure(&server.name);
+                    match mcp.cached_tools(&server.name).await {
+                        Some(t) => t,
+                        None => continue,
+                    }
+                }
+                Err(e) => {
+                    mcp_dynamic_mark_connect_failure(&server.name, now);
+                    tracing::warn!(
+                        server = %server.name,
+                        error = %e,
+                        retry_after_secs = MCP_DYNAMIC_CONNECT_RETRY_SECS,
+                        "MCP auto-connect failed, skipping dynamic tools"
+                    );
+                    continue;
+                }
+            }
+        };
+
+        for mcp_tool in &tools {
+            let prefixed_name = format!("mcp__{}__{}", server.name, mcp_tool.name);
+            let description = format!(
+                "[MCP server: {}] {}",
+                server.name,
+                if mcp_tool.description.is_empty() {
+                    &mcp_tool.name
+                } else {
+                    &mcp_tool.description
+                }
+            );
+
+            // Resolve $ref / $defs in the schema so the LLM sees flat parameters
+            let parameters =
+                if mcp_tool.input_schema.is_null() || mcp_tool.input_schema == json!({}) {
+                    json!({"type": "object", "properties": {}})
+                } else {
+                    resolve_json_schema_refs(&mcp_tool.input_schema)
+                };
+
+            defs.push(ToolDefinition {
+                tool_type: "function".to_string(),
+                function: FunctionDefinition {
+                    name: prefixed_name,
+                    description,
+                    parameters,
+                },
+            });
+        }
+    }
+
+    defs
+}
+
+/// Resolve `$ref` references in a JSON Schema by inlining definitions.
+/// Removes `$defs`, `definitions`, and `$schema` from the top level.
+fn resolve_json_schema_refs(schema: &Value) -> Value {
+    let defs = schema.get("$defs").or(schema.get("definitions")).cloned();
+
+    let mut resolved = inline_refs(schema, &defs);
+
+    if let Some(obj) = resolved.as_object_mut() {
+        obj.remove("$defs");
+        obj.remove("definitions");
+        obj.remove("$schema");
+    }
+
+    resolved
+}
+
+fn inline_refs(value: &Value, defs: &Option<Value>) -> Value {
+    match value {
+        Value::Object(map) => {
+            // If this object is a $ref, replace it with the referenced definition
+            if let Some(ref_val) = map.get("$ref") {
+                if let Some(ref_str) = ref_val.as_str() {
+                    let name = ref_str
+                        .strip_prefix("#/$defs/")
+                        .or(ref_str.strip_prefix("#/definitions/"));
+                    if let Some(name) = name
+                        && let Some(defs_val) = defs
+                        && let Some(def) = defs_val.get(name)
+                    {
+                        return inline_refs(def, defs);
+                    }
+                }
+                return value.clone();
+            }
+
+            // Recursively resolve all values in the object
+            let new_map: serde_json::Map<String, Value> = map
+                .iter()
+                .map(|(k, v)| (k.clone(), inline_refs(v, defs)))
+                .collect();
+            Value::Object(new_map)
+        }
+        Value::Array(arr) => Value::Array(arr.iter().map(|v| inline_refs(v, defs)).collect()),
+        _ => value.clone(),
+    }
+}
+
+#[derive(Deserialize)]
+struct McpListToolsArgs {
+    server_name: Option<String>,
+    #[serde(default)]
+    refresh: bool,
+}
+
+async fn execute_mcp_list_tools(
+    pool: &SqlitePool,
+    mcp: &McpManager,
+    args: McpListToolsArgs,
+) -> Result<String> {
+    let server_name = args.server_name.as_deref();
+    let refresh = args.refresh;
+
+    // Determine which servers to query
+    let servers: Vec<String> = if let Some(name) = server_name {
+        vec![name.to_string()]
+    } else {
+        // All enabled servers with tcp/http transports
+        let all = db::mcp_server_list(pool, false).await?;
+        all.into_iter()
+            .filter(|s| {
+                matches!(
+                    s.transport.as_str(),
+                    "tcp" | "http" | "sse" | "streamable_http"
+                )
+            })
+            .map(|s| s.name)
+            .collect()
+    };
+
+    if servers.is_empty() {
+        return Ok(json!({
+            "servers": [],
+            "message": "No enabled MCP servers with tcp/http transport found. Register one first with crud_mcp_server."
+        }).to_string());
+    }
+
+    let mut results = Vec::new();
+    for name in &servers {
+        match mcp.ensure_connected(pool, name).await {
+            Ok(()) => {
+                let tools = if refresh {
+                    match mcp.discover_tools(name).await {
+                        Ok(t) => t,
+                        Err(e) => {
+                            results.push(json!({
+                                "server": name,
+                                "error": format!("Failed to refresh tools: {}", e),
+                            }));
+                            continue;
+                        }
+                    }
+                } else {
+                    mcp.cached_tools(name).await.unwrap_or_default()
+                };
+
+                let tool_list: Vec<_> = tools
+                    .iter()
+                    .map(|t| {
+                        json!({
+                            "name": t.name,
+                            "description": t.description,
+                            "inputSchema": t.input_schema,
+                        })
+                    })
+                    .collect();
+
+                results.push(json!({
+                    "server": name,
+                    "tools": tool_list,
+                    "count": tool_list.len(),
+                }));
+            }
+            Err(e) => {
+                results.push(json!({
+                    "server": name,
+                    "error": format!("Connection failed: {}", e),
+                }));
+            }
+        }
+    }
+
+    Ok(json!({"servers": results}).to_string())
+}
+
+#[derive(Deserialize)]
+struct McpCallArgs {
+    server_name: String,
+    method: String,
+    arguments: Option<serde_json::Map<String, Value>>,
+    /// Keys the model put next to `server_name`/`method` instead of inside `arguments`
+    #[serde(flatten)]
+    implicit: serde_json::Map<String, Value>,
+}
+
+async fn execute_mcp_call(
+    pool: &SqlitePool,
+    mcp: &McpManager,
+    args: McpCallArgs,
+) -> Result<String> {
+    let server_name = args.server_name.as_str();
+    let method = args.method.as_str();
+    if server_name.is_empty() {
+        return Err(ArgError::new("server_name", "cannot be empty").into());
+    }
+    if method.is_empty() {
+        return Err(ArgError::new("method", "cannot be empty").into());
+    }
+    // Accept arguments as an object or as top-level keys (LLMs sometimes pass
+    // e.g. id="abc" at the top level instead of nesting inside
+    // "arguments": {"id": "abc"})
+    let arguments = match args.arguments {
+        Some(map) => Value::Object(map),
+        None => Value::Object(args.implicit),
+    };
+
+    // Pre-validate against the method's inputSchema so a bad call fails here
+    // with the offending field named, instead of a round-trip to the server
+    if let Some(error) =
+        validate_mcp_arguments(mcp, server_name, method, &arguments, "arguments").await
+    {
+        return Ok(error);
+    }
+
+    match mcp.call_tool(pool, server_name, method, arguments).await {
+        Ok(result) => {
+            if result.get("error").is_some() {
+                // MCP server returned a JSON-RPC error — format as a top-level
+                // "error" string so the LLM retry mechanism in llm.rs detects it
+                // and limits to 2 identical attempts before bailing.
+                let server_err = result["error"].as_str().unwrap_or("unknown error");
+                let code = result["code"].as_i64().unwrap_or(0);
+
+                // Include the tool's inputSchema so the LLM can self-correct
+                let schema_hint = if let Some(tools) = mcp.cached_tools(server_name).await {
+                    tools
+                        .iter()
+                        .find(|t| t.name == method)
+                        .map(|t| format!(" inputSchema: {}", t.input_schema))
+                        .unwrap_or_default()
+                } else {
+                    String::new()
+                };
+
+                Ok(json!({
+                    "error": format!(
+                        "MCP method '{}' on '{}' failed (code {}): {}. \
+                         Pass the required fields in the 'arguments' parameter.{}",
+                        method, server_name, code, server_err, schema_hint
+                    )
+                })
+                .to_string())
+            } else {
+                Ok(json!({"success": true, "result": result}).to_string())
+            }
+        }
+        Err(e) => Ok(json!({"error": e.to_string()}).to_string()),
+    }
+}
+
+/// Server and method of a dynamic tool name `mcp__{server}__{method}`
+pub(super) fn dynamic_tool_parts(tool_name: &str) -> Option<(&str, &str)> {
+    let rest = tool_name.strip_prefix("mcp__").unwrap_or(tool_name);
+    rest.split_once("__")
+        .filter(|(server, method)| !server.is_empty() && !method.is_empty())
+}
+
+/// Execute a dynamically-registered MCP tool (name format: mcp__{server}__{method}).
+/// Arguments are passed directly to the MCP server — no wrapping needed.
+pub(super) async fn execute_mcp_dynamic(
+    pool: &SqlitePool,
+    mcp: &McpManager,
+    tool_name: &str,
+    args: &Value,
+) -> Result<String> {
+    let (server_name, method) = match dynamic_tool_parts(tool_name) {
+        Some(parts) => parts,
+        None => {
+            return Ok(
+                json!({"error": format!("Invalid MCP tool name format: {}", tool_name)})
+                    .to_string(),
+            );
+        }
+    };
+
+    if let Some(error) = validate_mcp_arguments(mcp, server_name, method, args, "").await {
+        return Ok(error);
+    }
+
+    match mcp.call_tool(pool, server_name, method, args.clone()).await {
+        Ok(result) => {
+            if result.get("error").is_some() {
+                let server_err = result["error"].as_str().unwrap_or("unknown error");
+                let code = result["code"].as_i64().unwrap_or(0);
+                Ok(json!({
+                    "error": format!("MCP method '{}' on '{}' failed (code {}): {}", method, server_name, code, server_err)
+                }).to_string())
+            } else {
+                Ok(json!({"success": true, "result": result}).to_string())
+            }
+        }
+        Err(e) => Ok(json!({"error": e.to_string()}).to_string()),
+    }
+}
+
+/// Check MCP call arguments against the method's cached inputSchema. Returns
+/// the error result to hand back to the model when they do not match.
+async fn validate_mcp_arguments(
+    mcp: &McpManager,
+    server_name: &str,
+    method: &str,
+    arguments: &Value,
+    field_prefix: &str,
+) -> Option<String> {
+    let tools = mcp.cached_tools(server_name).await?;
+    let tool_info = tools.iter().find(|t| t.name == method)?;
+    if tool_info.input_schema.is_null() {
+        return None;
+    }
+
+    let err = schema::validate(
+        &resolve_json_schema_refs(&tool_info.input_schema),
+        arguments,
+    )
+    .err()?;
+    let field = match (field_prefix.is_empty(), err.field.is_empty()) {
+        (true, _) => err.field,
+        (false, true) => field_prefix.to_string(),
+        (false, false) => format!("{}.{}", field_prefix, err.field),
+    };
+    let err = ArgError::new(field, err.message);
+    Some(
+        json!({
+            "error": format!("{}. inputSchema: {}", err, tool_info.input_schema),
+            "field": err.field,
+        })
+        .to_string(),
+    )
+}
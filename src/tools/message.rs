@@ -0,0 +1,324 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use teloxide::prelude::*;
+
+use super::schema::parse_args;
+use super::{Tool, ToolContext};
+use crate::types::OutboundMessage;
+use crate::{config, db, outbox};
+
+pub struct SendMessage;
+
+#[async_trait]
+impl Tool for SendMessage {
+    fn name(&self) -> &str {
+        "send_message"
+    }
+
+    fn description(&self) -> &str {
+        "Send a text message to a Telegram chat. Use this when a user asks to forward/send a message to another chat/group where the bot is present.
+         The target is given by `chat_id` or by `chat` (a chat title, user name or @username the bot has seen). \
+         Chats other than the current one must be known to the bot and allowed by the send_message_policy config. \
+         Messages can be formatted (`parse_mode`: html or markdown, sent as plain text if Telegram rejects the markup), \
+         sent as a reply, sent silently, or scheduled with `send_at` (RFC 3339) or `delay_secs`. \
+         Every message is recorded with the chat and user that asked for it.
+
+         Examples:
+         - Send to a chat by ID: {\"chat_id\": -1001234567890, \"text\": \"Hello from bot!\"}
+         - Send by name, formatted: {\"chat\": \"Family\", \"text\": \"<b>Dinner</b> at 8\", \"parse_mode\": \"html\"}
+         - Remind later, silently: {\"chat_id\": 123456789, \"text\": \"Stand-up in 5 minutes\", \"delay_secs\": 3300, \"silent\": true}"
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "chat_id": {
+                    "type": "integer",
+                    "description": "Target Telegram chat ID (can be user, group, or supergroup ID). Default: the current chat."
+                },
+                "chat": {
+                    "type": "string",
+                    "description": "Target chat title, user name or @username, resolved through the names the bot has seen. Used when chat_id is not given."
+                },
+                "text": {
+                    "type": "string",
+                    "description": "The message text to send. Keep it concise for Telegram limits."
+                },
+                "parse_mode": {
+                    "type": "string",
+                    "enum": ["plain", "html", "markdown"],
+                    "description": "Text formatting. html takes Telegram HTML tags; markdown takes ordinary Markdown (bold, italics, code, links, lists, tables), converted for Telegram. Default: plain."
+                },
+                "reply_to_message_id": {
+                    "type": "integer",
+                    "description": "Message ID in the target chat to reply to."
+                },
+                "silent": {
+                    "type": "boolean",
+                    "description": "Deliver without a notification sound. Default: false."
+                },
+                "send_at": {
+                    "type": "string",
+                    "description": "Send later, at this RFC 3339 time (e.g. 2026-05-01T09:00:00+02:00)."
+                },
+                "delay_secs": {
+                    "type": "integer",
+                    "description": "Send later, after this many seconds."
+                }
+            },
+            "required": ["text"]
+        })
+    }
+
+    fn has_side_effects(&self, _args: &Value) -> bool {
+        true
+    }
+
+    async fn prepare_args(
+        &self,
+        ctx: &ToolContext<'_>,
+        mut args: Value,
+    ) -> Result<Result<Value, Value>> {
+        // A chat given by name is resolved once, for confirmation and sending
+        if args["chat_id"].is_null()
+            && let Some(name) = args["chat"].as_str()
+        {
+            match resolve_chat(ctx, name).await? {
+                Ok(id) => args["chat_id"] = json!(id),
+                Err(error) => return Ok(Err(error)),
+            }
+        }
+        Ok(Ok(args))
+    }
+
+    fn is_destructive(&self, ctx: &ToolContext<'_>, args: &Value) -> bool {
+        // Replies in the current chat are expected; posting elsewhere is not
+        args["chat_id"].as_i64().is_some_and(|id| id != ctx.chat_id)
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_send_message(ctx, parse_args(args)?).await
+    }
+}
+
+/// Longest delay accepted for a scheduled message
+const MAX_SCHEDULE_SECS: i64 = 365 * 24 * 3600;
+
+#[derive(Deserialize)]
+struct SendMessageArgs {
+    chat_id: Option<i64>,
+    text: String,
+    parse_mode: Option<String>,
+    reply_to_message_id: Option<i32>,
+    #[serde(default)]
+    silent: bool,
+    send_at: Option<String>,
+    delay_secs: Option<i64>,
+}
+
+async fn execute_send_message(ctx: &ToolContext<'_>, args: SendMessageArgs) -> Result<String> {
+    let text = args.text.as_str();
+
+    if text.trim().is_empty() {
+        return Ok(json!({"error": "Text cannot be empty"}).to_string());
+    }
+    if text.chars().count() > 4096 {
+        return Ok(json!(
+            {"error": "Text is too long for Telegram messages. Maximum is 4096 characters."}
+        )
+        .to_string());
+    }
+
+    let parse_mode = match args.parse_mode.as_deref().unwrap_or("plain") {
+        "plain" | "" => None,
+        mode @ ("html" | "markdown") => Some(mode.to_string()),
+        other => {
+            return Ok(json!({
+                "error": format!("Unknown parse_mode '{}'. Use plain, html or markdown.", other)
+            })
+            .to_string());
+        }
+    };
+
+    // `chat` was resolved to `chat_id` by prepare_args
+    let chat_id = args.chat_id.unwrap_or(ctx.chat_id);
+
+    if let Err(reason) = check_target(ctx, chat_id).await? {
+        return Ok(json!({"error": reason, "chat_id": chat_id}).to_string());
+    }
+
+    let send_at = match schedule_time(args.send_at.as_deref(), args.delay_secs) {
+        Ok(t) => t,
+        Err(e) => return Ok(json!({"error": e}).to_string()),
+    };
+
+    let mut message = OutboundMessage {
+        id: 0,
+        origin_chat_id: ctx.chat_id,
+        origin_user_id: ctx.user_id,
+        target_chat_id: chat_id,
+        // Messages to the current chat stay in the current forum topic
+        thread_id: ctx
+            .thread_id
+            .filter(|_| chat_id == ctx.chat_id)
+            .map(|t| t.0.0),
+        text: args.text.clone(),
+        parse_mode,
+        reply_to_message_id: args.reply_to_message_id,
+        silent: args.silent,
+        send_at: send_at.map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string()),
+    };
+
+    if let Some(send_at) = send_at {
+        message.id = db::outbound_insert(ctx.pool, &message, "scheduled", None, None).await?;
+        tracing::info!(
+            id = message.id,
+            origin_chat_id = ctx.chat_id,
+            target_chat_id = chat_id,
+            send_at = %send_at,
+            "Message scheduled"
+        );
+        return Ok(json!({
+            "success": true,
+            "message": "Message scheduled",
+            "chat_id": chat_id,
+            "send_at": send_at.to_rfc3339(),
+            "audit_id": message.id,
+        })
+        .to_string());
+    }
+
+    match outbox::deliver(ctx.bot, &message).await {
+        Ok((message_id, plain_fallback)) => {
+            let audit_id =
+                db::outbound_insert(ctx.pool, &message, "sent", Some(message_id.0), None).await?;
+            let mut result = json!({
+                "success": true,
+                "message": "Message sent",
+                "chat_id": chat_id,
+                "message_id": message_id.0,
+                "audit_id": audit_id,
+            });
+            if plain_fallback {
+                result["warning"] = json!("Telegram rejected the formatting; sent as plain text");
+            }
+            Ok(result.to_string())
+        }
+        Err(e) => {
+            let error = format!("Failed to send message via Telegram: {}", e);
+            let audit_id =
+                db::outbound_insert(ctx.pool, &message, "failed", None, Some(&error)).await?;
+            Ok(json!({
+                "error": error,
+                "chat_id": chat_id,
+                "audit_id": audit_id,
+            })
+            .to_string())
+        }
+    }
+}
+
+/// Look up a chat by title, name or @username. The inner error is the JSON
+/// returned to the model when there is no single match.
+async fn resolve_chat(ctx: &ToolContext<'_>, name: &str) -> Result<Result<i64, Value>> {
+    let matches = db::name_map_find(ctx.pool, name).await?;
+    Ok(match matches.as_slice() {
+        [] => Err(json!({"error": format!("No known chat or user matches '{}'", name)})),
+        [(_, id, _, _)] => Ok(*id),
+        _ => Err(json!({
+            "error": format!("'{}' matches several chats; pass chat_id instead", name),
+            "candidates": matches
+                .iter()
+                .map(|(kind, id, display_name, username)| json!({
+                    "type": kind,
+                    "chat_id": id,
+                    "name": display_name,
+                    "username": username,
+                }))
+                .collect::<Vec<_>>(),
+        })),
+    })
+}
+
+/// Check the `send_message_policy` for a target other than the current chat.
+/// The inner error is the reason the message is refused.
+async fn check_target(ctx: &ToolContext<'_>, chat_id: i64) -> Result<Result<(), String>> {
+    if chat_id == ctx.chat_id {
+        return Ok(Ok(()));
+    }
+    if !db::name_map_known(ctx.pool, chat_id).await? {
+        return Ok(Err(
+            "The bot has not seen this chat; it can only post to chats and users it knows"
+                .to_string(),
+        ));
+    }
+    if ctx.is_admin {
+        return Ok(Ok(()));
+    }
+
+    let policy = config::get_or_default(ctx.pool, "send_message_policy", "known").await?;
+    match policy.trim() {
+        "known" => Ok(Ok(())),
+        "member" => {
+            if chat_id == ctx.user_id {
+                return Ok(Ok(()));
+            }
+            let present = ctx
+                .bot
+                .get_chat_member(ChatId(chat_id), UserId(ctx.user_id as u64))
+                .await
+                .map(|member| member.is_present())
+                .unwrap_or(false);
+            Ok(if present {
+                Ok(())
+            } else {
+                Err("You can only send messages to chats you are a member of".to_string())
+            })
+        }
+        "allowlist" => {
+            let allowed =
+                config::get_or_default(ctx.pool, "send_message_allowed_chats", "").await?;
+            let listed = allowed
+                .split(',')
+                .filter_map(|s| s.trim().parse::<i64>().ok())
+                .any(|id| id == chat_id);
+            Ok(if listed {
+                Ok(())
+            } else {
+                Err("This chat is not in send_message_allowed_chats".to_string())
+            })
+        }
+        other => Ok(Err(format!(
+            "Unknown send_message_policy '{}'; expected known, member or allowlist",
+            other
+        ))),
+    }
+}
+
+/// When a message should be sent, or None to send it now
+fn schedule_time(
+    send_at: Option<&str>,
+    delay_secs: Option<i64>,
+) -> Result<Option<DateTime<Utc>>, String> {
+    let now = Utc::now();
+    let at = match (send_at, delay_secs) {
+        (Some(_), Some(_)) => return Err("Use either send_at or delay_secs, not both".to_string()),
+        (Some(s), None) => DateTime::parse_from_rfc3339(s.trim())
+            .map_err(|e| format!("Invalid send_at '{}': {} (expected RFC 3339)", s, e))?
+            .with_timezone(&Utc),
+        (None, Some(secs)) if secs <= 0 => return Ok(None),
+        (None, Some(secs)) => now + chrono::Duration::seconds(secs.min(MAX_SCHEDULE_SECS + 1)),
+        (None, None) => return Ok(None),
+    };
+    if at <= now {
+        return Err("send_at is in the past".to_string());
+    }
+    if (at - now).num_seconds() > MAX_SCHEDULE_SECS {
+        return Err("Messages can be scheduled at most one year ahead".to_string());
+    }
+    Ok(Some(at))
+}
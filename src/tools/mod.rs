@@ -0,0 +1,463 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use teloxide::Bot;
+use teloxide::types::ThreadId;
+
+use crate::config;
+use crate::confirm::{ConfirmationDeclined, ConfirmationGate, Decision};
+use crate::db;
+use crate::mcp::McpManager;
+use crate::rag::RagEngine;
+use crate::rag_queue::IndexQueue;
+use crate::secrets::{self, Secrets};
+use crate::types::{FunctionDefinition, ToolDefinition};
+use schema::ArgError;
+
+mod expert;
+mod files;
+mod history;
+mod http;
+mod http_policy;
+mod important_memory;
+mod maigret;
+mod mcp_servers;
+mod mcp_tools;
+mod message;
+mod notes;
+mod python;
+mod rag_search;
+mod readability;
+mod runtimes;
+mod sandbox;
+mod schema;
+mod sql;
+mod unified_memory;
+mod voice;
+mod workspace;
+
+const DEFAULT_CONFIRM_TIMEOUT_SECS: u64 = 120;
+const CONFIRM_PROMPT_MAX_ARGS: usize = 1500;
+
+/// Who may invoke a tool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Any user the bot responds to
+    Anyone,
+    /// Only users listed in the `admin_user_ids` config key
+    Admin,
+}
+
+/// Everything a tool needs to run on behalf of one incoming message
+pub struct ToolContext<'a> {
+    pub pool: &'a SqlitePool,
+    pub bot: &'a Bot,
+    pub rag: &'a Arc<RagEngine>,
+    pub indexer: &'a IndexQueue,
+    pub mcp: &'a McpManager,
+    pub confirm: &'a ConfirmationGate,
+    pub chat_id: i64,
+    /// Forum topic the message came from; replies go there too
+    pub thread_id: Option<ThreadId>,
+    pub user_id: i64,
+    pub is_admin: bool,
+}
+
+/// A tool the LLM can call
+#[async_trait]
+pub trait Tool: Send + Sync {
+    /// Name the LLM uses to call the tool
+    fn name(&self) -> &str;
+
+    /// Description shown to the LLM
+    fn description(&self) -> &str;
+
+    /// JSON schema of the arguments object
+    fn parameters(&self) -> Value;
+
+    /// Schema offered in this context, or None to hide the tool. Override when
+    /// the schema depends on stored data or on the chat.
+    async fn parameters_for(&self, _ctx: &ToolContext<'_>) -> Option<Value> {
+        Some(self.parameters())
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Anyone
+    }
+
+    /// Whether this call changes state outside of its own result (sends
+    /// messages, writes files or stored data). Such calls are never run
+    /// concurrently with each other, so their effects keep the order the
+    /// model asked for.
+    fn has_side_effects(&self, _args: &Value) -> bool {
+        false
+    }
+
+    /// Rewrite validated arguments before confirmation and execution, e.g.
+    /// resolve a name to an id so both act on the same target. An inner
+    /// error is returned to the model as the call's result.
+    async fn prepare_args(
+        &self,
+        _ctx: &ToolContext<'_>,
+        args: Value,
+    ) -> Result<Result<Value, Value>> {
+        Ok(Ok(args))
+    }
+
+    /// Whether this call is hard to undo (bulk deletes, overwrites, messages
+    /// to other chats) and needs the requesting user's approval first
+    fn is_destructive(&self, _ctx: &ToolContext<'_>, _args: &Value) -> bool {
+        false
+    }
+
+    /// Whether the tool fills in `{{secret:NAME}}` placeholders itself,
+    /// because only some of its arguments may carry secrets
+    fn fills_secrets(&self) -> bool {
+        false
+    }
+
+    /// Run the tool and return the result as JSON string
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String>;
+}
+
+/// Old tool names the model may still call, mapped to (tool, action)
+const LEGACY_ALIASES: &[(&str, &str, &str)] = &[
+    ("list_mcp_servers", "crud_mcp_server", "list"),
+    ("add_mcp_server", "crud_mcp_server", "create"),
+    ("update_mcp_server", "crud_mcp_server", "update"),
+    ("delete_mcp_server", "crud_mcp_server", "delete"),
+];
+
+/// Set of tools offered to the LLM, in registration order
+pub struct ToolRegistry {
+    tools: Vec<Box<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self { tools: Vec::new() }
+    }
+
+    /// Registry with all built-in tools
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(notes::SearchNotes);
+        registry.register(notes::ReadNote);
+        registry.register(unified_memory::UnifiedMemory);
+        registry.register(expert::Expert);
+        registry.register(mcp_servers::CrudMcpServer);
+        registry.register(history::SearchHistory);
+        registry.register(history::BrowseHistory);
+        registry.register(history::SearchAllChats);
+        registry.register(rag_search::RagSearch);
+        registry.register(important_memory::SetImportantMemory);
+        registry.register(important_memory::ClearImportantMemory);
+        registry.register(http::HttpRequest);
+        registry.register(python::RunPython);
+        registry.register(runtimes::RunShell);
+        registry.register(runtimes::RunNode);
+        registry.register(sql::RunSql);
+        registry.register(maigret::MaigretOsint);
+        registry.register(voice::SendVoice);
+        registry.register(message::SendMessage);
+        registry.register(files::CrudFile);
+        registry.register(mcp_tools::McpListTools);
+        registry.register(mcp_tools::McpCall);
+        registry
+    }
+
+    /// Add a tool, replacing any registered tool with the same name
+    pub fn register(&mut self, tool: impl Tool + 'static) {
+        let tool: Box<dyn Tool> = Box::new(tool);
+        match self.tools.iter().position(|t| t.name() == tool.name()) {
+            Some(idx) => self.tools[idx] = tool,
+            None => self.tools.push(tool),
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&dyn Tool> {
+        self.tools
+            .iter()
+            .find(|t| t.name() == name)
+            .map(|t| t.as_ref())
+    }
+
+    /// Tool definitions available in this context, including tools
+    /// auto-discovered from MCP servers
+    pub async fn definitions(&self, ctx: &ToolContext<'_>) -> Vec<ToolDefinition> {
+        let mut defs = Vec::new();
+        for tool in &self.tools {
+            if !ctx.is_admin && tool.permission() != Permission::Anyone {
+                continue;
+            }
+            let Some(parameters) = tool.parameters_for(ctx).await else {
+                continue;
+            };
+            defs.push(ToolDefinition {
+                tool_type: "function".to_string(),
+                function: FunctionDefinition {
+                    name: tool.name().to_string(),
+                    description: tool.description().to_string(),
+                    parameters,
+                },
+            });
+        }
+
+        // Auto-discover MCP tools and register them as first-class LLM tools
+        let mcp_defs = mcp_tools::mcp_dynamic_definitions(ctx.mcp, ctx.pool).await;
+        if !mcp_defs.is_empty() {
+            tracing::info!(count = mcp_defs.len(), "Registered dynamic MCP tools");
+            defs.extend(mcp_defs);
+        }
+
+        defs
+    }
+
+    /// Whether a call has side effects and must be serialized with other such calls
+    pub fn has_side_effects(&self, tool_name: &str, arguments: &str) -> bool {
+        let Ok(args) = parse_arguments(arguments) else {
+            return false;
+        };
+        let (tool_name, args) = resolve_alias(tool_name, args);
+        self.get(tool_name)
+            .is_some_and(|tool| tool.has_side_effects(&args))
+    }
+
+    /// Execute a tool call and return the result as JSON string
+    pub async fn execute(
+        &self,
+        ctx: &ToolContext<'_>,
+        tool_name: &str,
+        arguments: &str,
+    ) -> Result<String> {
+        let result = self.dispatch(ctx, tool_name, arguments).await;
+
+        let result_str = match &result {
+            Ok(r) => r.clone(),
+            // Argument errors name the offending field so the model can fix the call
+            Err(e) => match e.downcast_ref::<ArgError>() {
+                Some(arg_err) => json!({"error": arg_err.to_string(), "field": arg_err.field}),
+                None => json!({"error": e.to_string()}),
+            }
+            .to_string(),
+        };
+
+        // Secret values never reach the logs or the model
+        let (arguments, result_str) = match Secrets::load(ctx.pool).await {
+            Ok(vault) => (vault.redact(arguments), vault.redact(&result_str)),
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to load secrets for redaction");
+                (arguments.to_string(), result_str)
+            }
+        };
+        let arguments = arguments.as_str();
+
+        // Log the tool call
+        if let Err(e) = db::tool_call_log(
+            ctx.pool,
+            ctx.chat_id,
+            ctx.user_id,
+            tool_name,
+            arguments,
+            &result_str,
+        )
+        .await
+        {
+            tracing::error!(error = %e, "Failed to log tool call");
+        }
+
+        tracing::info!(
+            tool_name,
+            arguments,
+            result = %result_str,
+            chat_id = ctx.chat_id,
+            user_id = ctx.user_id,
+            "Tool call executed"
+        );
+
+        // A declined confirmation aborts the whole turn, not just this call
+        match result {
+            Err(e) if e.is::<ConfirmationDeclined>() => Err(e),
+            _ => Ok(result_str),
+        }
+    }
+
+    async fn dispatch(
+        &self,
+        ctx: &ToolContext<'_>,
+        tool_name: &str,
+        arguments: &str,
+    ) -> Result<String> {
+        let args = parse_arguments(arguments)?;
+        let (resolved_name, args) = resolve_alias(tool_name, args);
+
+        match self.get(resolved_name) {
+            Some(tool) if tool.permission() == Permission::Admin && !ctx.is_admin => Ok(json!({
+                "error": format!("Tool '{}' is restricted to bot admins", tool_name)
+            })
+            .to_string()),
+            Some(tool) => {
+                let Some(parameters) = tool.parameters_for(ctx).await else {
+                    return Ok(json!({
+                        "error": format!("Tool '{}' is not available in this chat", tool_name)
+                    })
+                    .to_string());
+                };
+                schema::validate(&parameters, &args)?;
+                let args = match tool.prepare_args(ctx, args).await? {
+                    Ok(args) => args,
+                    Err(error) => return Ok(error.to_string()),
+                };
+                if tool.is_destructive(ctx, &args) {
+                    confirm_tool_call(ctx, tool.name(), &args).await?;
+                }
+                let args = fill_secrets(ctx, tool, args).await?;
+                tool.execute(ctx, &args).await
+            }
+            // Dynamic MCP tools: mcp__{server}__{method} → direct invocation
+            None if resolved_name.starts_with("mcp__") => {
+                check_secrets_allowed(ctx, resolved_name, &args).await?;
+                let args = match mcp_tools::dynamic_tool_parts(resolved_name) {
+                    Some((server, _)) => substitute_mcp_secrets(ctx, server, args).await?,
+                    // Rejected as malformed without being sent anywhere
+                    None => args,
+                };
+                mcp_tools::execute_mcp_dynamic(ctx.pool, ctx.mcp, resolved_name, &args).await
+            }
+            None => Ok(json!({"error": format!("Unknown tool: {}", tool_name)}).to_string()),
+        }
+    }
+}
+
+/// Fill in `{{secret:NAME}}` placeholders, for tools the `secret_tools` key
+/// allows, unless the tool does that itself. Done last so confirmation
+/// prompts show placeholders, not values.
+async fn fill_secrets(ctx: &ToolContext<'_>, tool: &dyn Tool, args: Value) -> Result<Value> {
+    check_secrets_allowed(ctx, tool.name(), &args).await?;
+    if tool.fills_secrets() {
+        return Ok(args);
+    }
+    substitute_secrets(ctx, args).await
+}
+
+async fn check_secrets_allowed(ctx: &ToolContext<'_>, tool_name: &str, args: &Value) -> Result<()> {
+    if secrets::contains_placeholder(args) && !secrets::allowed_for(ctx.pool, tool_name).await? {
+        anyhow::bail!(
+            "Secrets can't be used with '{}'; the secret_tools config key lists the tools that can",
+            tool_name
+        );
+    }
+    Ok(())
+}
+
+async fn substitute_secrets(ctx: &ToolContext<'_>, mut args: Value) -> Result<Value> {
+    if secrets::contains_placeholder(&args) {
+        Secrets::load_usable(ctx.pool, ctx.chat_id, ctx.is_admin)
+            .await?
+            .substitute(&mut args)?;
+    }
+    Ok(args)
+}
+
+/// Fill in placeholders of arguments bound for MCP server `server`, with
+/// the secrets allowed to go there
+async fn substitute_mcp_secrets(
+    ctx: &ToolContext<'_>,
+    server: &str,
+    mut args: Value,
+) -> Result<Value> {
+    if secrets::contains_placeholder(&args) {
+        Secrets::load_usable(ctx.pool, ctx.chat_id, ctx.is_admin)
+            .await?
+            .substitute_mcp(&mut args, server)?;
+    }
+    Ok(args)
+}
+
+/// Ask the requesting user to approve a destructive call, unless the
+/// `confirm_<tool>` policy key turns confirmation off for this tool
+async fn confirm_tool_call(ctx: &ToolContext<'_>, tool_name: &str, args: &Value) -> Result<()> {
+    let policy = config::get_or_default(ctx.pool, &format!("confirm_{}", tool_name), "on").await?;
+    if matches!(policy.trim(), "off" | "false" | "0" | "no") {
+        return Ok(());
+    }
+
+    let timeout_secs = config::get(ctx.pool, "confirm_timeout_secs")
+        .await?
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_CONFIRM_TIMEOUT_SECS);
+
+    let mut shown_args = serde_json::to_string_pretty(args).unwrap_or_default();
+    if shown_args.chars().count() > CONFIRM_PROMPT_MAX_ARGS {
+        shown_args = shown_args
+            .chars()
+            .take(CONFIRM_PROMPT_MAX_ARGS)
+            .collect::<String>()
+            + "…";
+    }
+    let prompt = format!(
+        "⚠️ Approval needed\n\nThe assistant wants to run '{}' with:\n{}\n\nOnly the user who asked can answer.",
+        tool_name, shown_args
+    );
+
+    let decision = ctx
+        .confirm
+        .request(
+            ctx.bot,
+            ctx.chat_id,
+            ctx.thread_id,
+            ctx.user_id,
+            &prompt,
+            std::time::Duration::from_secs(timeout_secs),
+        )
+        .await?;
+
+    match decision {
+        Decision::Approved => Ok(()),
+        decision => Err(ConfirmationDeclined {
+            tool_name: tool_name.to_string(),
+            decision,
+        }
+        .into()),
+    }
+}
+
+/// Parse the raw arguments string of a tool call. An empty string stands for
+/// no arguments; anything else must be a JSON object.
+fn parse_arguments(arguments: &str) -> Result<Value, ArgError> {
+    if arguments.trim().is_empty() {
+        return Ok(json!({}));
+    }
+    match serde_json::from_str::<Value>(arguments) {
+        Ok(value @ Value::Object(_)) => Ok(value),
+        Ok(_) => Err(ArgError::new("", "arguments must be a JSON object")),
+        Err(e) => Err(ArgError::new(
+            "",
+            format!("arguments are not valid JSON: {}", e),
+        )),
+    }
+}
+
+/// Map a backward-compatible tool name to the tool that now implements it
+fn resolve_alias(tool_name: &str, args: Value) -> (&str, Value) {
+    match LEGACY_ALIASES
+        .iter()
+        .find(|(alias, _, _)| *alias == tool_name)
+    {
+        Some((_, target, action)) => (target, with_action(&args, action)),
+        None => (tool_name, args),
+    }
+}
+
+fn with_action(args: &Value, action: &str) -> Value {
+    let mut enriched = args.clone();
+    enriched["action"] = json!(action);
+    enriched
+}
+
+/// Lowercased `action` argument of CRUD-style tools
+fn action_of(args: &Value) -> String {
+    args["action"].as_str().unwrap_or("").to_lowercase()
+}
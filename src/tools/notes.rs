@@ -0,0 +1,141 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+
+use super::schema::parse_args;
+use super::{Tool, ToolContext};
+use crate::db;
+
+pub struct SearchNotes;
+
+#[async_trait]
+impl Tool for SearchNotes {
+    fn name(&self) -> &str {
+        "search_notes"
+    }
+
+    fn description(&self) -> &str {
+        "Search through all saved notes by keyword or regex pattern. Use this when a user asks 'do you remember...', 'what did I say about...', or when you need to find previously saved information. Returns a list of matching notes with their IDs (use read_note to get full content). Searches across title, content, and tags fields."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "The search term. For keyword search: any word or phrase (e.g., 'birthday', 'project deadline'). For regex: a valid regex pattern (e.g., '\\\\bmeeting\\\\b.*2024')."
+                },
+                "segment": {
+                    "type": "string",
+                    "description": "Optional. Restrict search to a specific segment (e.g., 'global', 'chat:-1001234567890'). If omitted, searches ALL segments."
+                },
+                "use_regex": {
+                    "type": "boolean",
+                    "description": "Set to true to interpret 'query' as a regex pattern instead of a plain keyword. Default: false."
+                }
+            },
+            "required": ["query"]
+        })
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_search_notes(ctx.pool, parse_args(args)?).await
+    }
+}
+
+pub struct ReadNote;
+
+#[async_trait]
+impl Tool for ReadNote {
+    fn name(&self) -> &str {
+        "read_note"
+    }
+
+    fn description(&self) -> &str {
+        "Retrieve the full content of a specific note by its ID. Use this after search_notes returns matching results and you need to see the complete note content (search results only show titles and metadata)."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "note_id": {
+                    "type": "integer",
+                    "description": "The numeric ID of the note to read (obtained from search_notes results)."
+                }
+            },
+            "required": ["note_id"]
+        })
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_read_note(ctx.pool, parse_args(args)?).await
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchNotesArgs {
+    query: String,
+    segment: Option<String>,
+    #[serde(default)]
+    use_regex: bool,
+}
+
+async fn execute_search_notes(pool: &SqlitePool, args: SearchNotesArgs) -> Result<String> {
+    let query = args.query.as_str();
+    let segment = args.segment.as_deref();
+
+    if args.use_regex {
+        let all_notes = db::note_search(pool, "", segment).await?;
+        let re = regex::Regex::new(query)
+            .map_err(|e| anyhow::anyhow!("Invalid regex '{}': {}", query, e))?;
+
+        let matching: Vec<_> = all_notes
+            .into_iter()
+            .filter(|n| re.is_match(&n.title) || re.is_match(&n.content) || re.is_match(&n.tags))
+            .map(|n| {
+                json!({
+                    "id": n.id, "segment": n.segment, "title": n.title,
+                    "tags": n.tags, "created_at": n.created_at,
+                })
+            })
+            .collect();
+
+        Ok(json!({"results": matching, "count": matching.len()}).to_string())
+    } else {
+        let notes = db::note_search(pool, query, segment).await?;
+        let results: Vec<_> = notes
+            .iter()
+            .map(|n| {
+                json!({
+                    "id": n.id, "segment": n.segment, "title": n.title,
+                    "tags": n.tags, "created_at": n.created_at,
+                })
+            })
+            .collect();
+        let count = results.len();
+        Ok(json!({"results": results, "count": count}).to_string())
+    }
+}
+
+#[derive(Deserialize)]
+struct ReadNoteArgs {
+    note_id: i64,
+}
+
+async fn execute_read_note(pool: &SqlitePool, args: ReadNoteArgs) -> Result<String> {
+    let note_id = args.note_id;
+
+    match db::note_read(pool, note_id).await? {
+        Some(note) => Ok(json!({
+            "id": note.id, "segment": note.segment, "title": note.title,
+            "content": note.content, "tags": note.tags,
+            "created_at": note.created_at, "updated_at": note.updated_at,
+        })
+        .to_string()),
+        None => Ok(json!({"error": format!("Note with ID {} not found", note_id)}).to_string()),
+    }
+}
@@ -0,0 +1,56 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::runtimes::{self, PYTHON};
+use super::schema::parse_args;
+use super::{Tool, ToolContext};
+
+pub struct RunPython;
+
+#[async_trait]
+impl Tool for RunPython {
+    fn name(&self) -> &str {
+        "run_python"
+    }
+
+    fn description(&self) -> &str {
+        "Execute Python 3 code in a sandboxed environment (bubblewrap). Use this for:\n\
+         - Calculations, math, data processing\n\
+         - Generating text, parsing data, regex operations\n\
+         - Fetching data from the internet (requests/urllib work), if the network policy allows it\n\
+         - File I/O within the sandbox (read/write files in /workspace)\n\
+         - Any task that benefits from actual code execution\n\n\
+         Security: The code runs in an isolated filesystem — it can only read system libraries (read-only) and write to /workspace. \
+         Network access depends on the sandbox policy: none, full, or only allowlisted hosts through an HTTP proxy. \
+         Memory, CPU time, process count, file size and output are limited; when a limit stops the code, `limit_exceeded` in the result says which. \
+         Execution is killed after the timeout.\n\n\
+         The sandbox has Python 3 standard library available. For pip packages, they must be pre-installed on the host.\n\n\
+         Tips:\n\
+         - Print results to stdout — that's what gets returned to you\n\
+         - Use /workspace/ for any file operations\n\
+         - Set persistent_workspace to keep files between calls in this chat\n\
+         - Images the code saves are sent to the chat automatically; use send_files for other files and save_files to keep them in the files root\n\
+         - Keep code concise and focused\n\
+         - For long-running tasks, increase timeout_secs\n\n\
+         Examples:\n\
+         - Math: {\"code\": \"import math\\nprint(math.factorial(100))\"}\n\
+         - Web fetch: {\"code\": \"import urllib.request\\ndata = urllib.request.urlopen('https://httpbin.org/ip').read()\\nprint(data.decode())\"}\n\
+         - File I/O: {\"code\": \"with open('/workspace/result.txt', 'w') as f:\\n    f.write('hello')\\nprint('saved')\"}\n\
+         - Data: {\"code\": \"import json\\ndata = [{'name': 'a', 'val': 1}, {'name': 'b', 'val': 2}]\\nprint(json.dumps(data, indent=2))\"}"
+    }
+
+    fn parameters(&self) -> Value {
+        runtimes::script_parameters(
+            "Python 3 code to execute. Use print() for output. Use /workspace/ for file operations.",
+        )
+    }
+
+    fn has_side_effects(&self, args: &Value) -> bool {
+        runtimes::script_has_side_effects(args)
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        runtimes::run_script(ctx, &PYTHON, parse_args(args)?).await
+    }
+}
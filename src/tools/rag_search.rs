@@ -0,0 +1,175 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::schema::parse_args;
+use super::{Tool, ToolContext};
+use crate::config;
+use crate::rag::{RagVisibility, SearchOptions};
+
+const DEFAULT_DIVERSITY: f32 = 0.3;
+
+pub struct RagSearch;
+
+#[async_trait]
+impl Tool for RagSearch {
+    fn name(&self) -> &str {
+        "rag_search"
+    }
+
+    fn description(&self) -> &str {
+        "Semantic (meaning-based) search across conversations, notes, and memory visible in this chat: this chat's (or forum topic's) messages plus the global, bot, chat and person (the asking user) segments. Unlike keyword search tools (search_history, search_notes), this finds results by MEANING — so searching 'cooking Italian food' will find messages about 'making pasta with tomato sauce' even though they share no keywords. Use this when:\n\
+         - You don't know the exact keywords to search for\n\
+         - Keyword search (search_history, search_notes) returned nothing useful\n\
+         - You want to find conceptually related content across all data types\n\
+         Results are ranked by score, higher = more relevant: the reranker's relevance (0-1) when reranked, else the semantic similarity."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "A natural language description of what you're looking for. Be descriptive — 'recipes for Italian pasta dishes' works better than just 'pasta'."
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": "Maximum number of results to return. Default: 10. Max: 50."
+                },
+                "source_type": {
+                    "type": "string",
+                    "enum": ["conversation", "note", "memory"],
+                    "description": "Optional. Filter results to only this data type. If omitted, searches all types."
+                },
+                "min_similarity": {
+                    "type": "number",
+                    "minimum": -1,
+                    "maximum": 1,
+                    "description": "Optional. Drop results whose semantic similarity is below this. Default: the rag_min_similarity setting (0)."
+                },
+                "min_relevance": {
+                    "type": "number",
+                    "minimum": 0,
+                    "maximum": 1,
+                    "description": "Optional. When reranked, drop results whose reranker relevance is below this. Default: the rag_min_relevance setting (0)."
+                },
+                "diversity": {
+                    "type": "number",
+                    "minimum": 0,
+                    "maximum": 1,
+                    "description": "Optional. 0 ranks purely by relevance; higher values skip results that repeat ones already returned. Default: the rag_diversity setting (0.3)."
+                },
+                "rerank": {
+                    "type": "boolean",
+                    "description": "Optional. Rescore candidates with the cross-encoder reranker when one is configured. Default: true."
+                },
+                "all_chats": {
+                    "type": "boolean",
+                    "description": "Admin only. Search every chat and segment instead of only those visible here. Default: false."
+                }
+            },
+            "required": ["query"]
+        })
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_rag_search(ctx, parse_args(args)?).await
+    }
+}
+
+#[derive(Deserialize)]
+struct RagSearchArgs {
+    query: String,
+    limit: Option<usize>,
+    source_type: Option<String>,
+    min_similarity: Option<f32>,
+    min_relevance: Option<f32>,
+    diversity: Option<f32>,
+    rerank: Option<bool>,
+    #[serde(default)]
+    all_chats: bool,
+}
+
+async fn execute_rag_search(ctx: &ToolContext<'_>, args: RagSearchArgs) -> Result<String> {
+    let query = args.query.as_str();
+    let limit = args.limit.map(|v| v.min(50)).unwrap_or(10);
+
+    if query.trim().is_empty() {
+        return Ok(json!({"error": "Query cannot be empty"}).to_string());
+    }
+
+    if args.all_chats && !ctx.is_admin {
+        return Ok(json!({"error": "'all_chats' is restricted to bot admins"}).to_string());
+    }
+
+    // Only what this chat and user may see, unless an admin asks for everything
+    let visibility = (!args.all_chats)
+        .then(|| RagVisibility::for_caller(ctx.chat_id, ctx.thread_id.map(|t| t.0.0), ctx.user_id));
+
+    let min_similarity = match args.min_similarity {
+        Some(v) => v,
+        None => config_f32(ctx, "rag_min_similarity", 0.0).await?,
+    };
+    let min_relevance = match args.min_relevance {
+        Some(v) => v,
+        None => config_f32(ctx, "rag_min_relevance", 0.0).await?,
+    };
+    let diversity = match args.diversity {
+        Some(v) => v,
+        None => config_f32(ctx, "rag_diversity", DEFAULT_DIVERSITY).await?,
+    };
+
+    // Embedding and reranking are CPU-bound; keep them off the async workers
+    let rag = ctx.rag.clone();
+    let rerank = args.rerank.unwrap_or(true);
+    let search_query = query.to_string();
+    let source_type = args.source_type.clone();
+    let results = tokio::task::spawn_blocking(move || {
+        let opts = SearchOptions {
+            limit,
+            source_type: source_type.as_deref(),
+            visibility: visibility.as_ref(),
+            rerank,
+            min_similarity,
+            min_relevance,
+            diversity,
+        };
+        rag.search(&search_query, &opts)
+    })
+    .await??;
+
+    let items: Vec<Value> = results
+        .iter()
+        .map(|r| {
+            json!({
+                "score": format!("{:.4}", r.score),
+                "similarity": format!("{:.4}", r.similarity),
+                "source_type": r.metadata.source_type,
+                "source_id": r.metadata.source_id,
+                "chat_id": r.metadata.chat_id,
+                "segment": r.metadata.segment,
+                "content_preview": r.metadata.content_preview,
+                "user_name": r.metadata.user_name,
+                "created_at": r.metadata.created_at,
+                "chunk": format!("{}/{}", r.metadata.chunk_index + 1, r.metadata.chunk_count),
+            })
+        })
+        .collect();
+
+    let count = items.len();
+    Ok(json!({
+        "results": items,
+        "count": count,
+        "query": query,
+    })
+    .to_string())
+}
+
+async fn config_f32(ctx: &ToolContext<'_>, key: &str, default: f32) -> Result<f32> {
+    Ok(config::get(ctx.pool, key)
+        .await?
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default))
+}
@@ -0,0 +1,656 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use html5ever::tendril::StrTendril;
+use html5ever::tokenizer::states::RawKind;
+use html5ever::tokenizer::{
+    BufferQueue, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer, TokenizerOpts,
+};
+use reqwest::Url;
+use serde::Serialize;
+
+const VOID_TAGS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+/// Elements that never hold article text
+const SKIP_TAGS: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "nav", "header", "footer", "aside", "form",
+    "iframe", "svg", "button", "select", "textarea", "dialog", "menu",
+];
+/// Blocks that end an open `<p>`
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "div",
+    "ul",
+    "ol",
+    "dl",
+    "table",
+    "pre",
+    "blockquote",
+    "section",
+    "article",
+    "aside",
+    "header",
+    "footer",
+    "nav",
+    "figure",
+    "form",
+    "hr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+/// class/id fragments of cookie notices, pruned wherever they appear
+const CONSENT_HINTS: &[&str] = &["cookie", "consent", "gdpr"];
+/// class/id fragments of boilerplate such as menus and share buttons, unless
+/// the element also looks like content
+const BOILERPLATE_HINTS: &[&str] = &[
+    "banner",
+    "navbar",
+    "navigation",
+    "menu",
+    "sidebar",
+    "footer",
+    "masthead",
+    "breadcrumb",
+    "share",
+    "social",
+    "subscribe",
+    "newsletter",
+    "promo",
+    "advert",
+    "sponsor",
+    "popup",
+    "modal",
+    "related",
+    "comment",
+];
+/// class/id fragments of the main text
+const CONTENT_HINTS: &[&str] = &["article", "content", "entry", "post", "story", "main"];
+/// Elements whose text counts towards their ancestors' score
+const SCORED_TAGS: &[&str] = &["p", "pre", "blockquote", "td"];
+const MIN_PARAGRAPH_CHARS: usize = 25;
+/// Below this much text the best candidate is not trusted and the whole body is used
+const MIN_CONTENT_CHARS: usize = 250;
+const MAX_LINKS: usize = 50;
+const MAX_LINK_TEXT: usize = 100;
+
+/// Title, description and other metadata from the page head
+#[derive(Debug, Default, Serialize)]
+pub struct PageMeta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canonical_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "type")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Link {
+    pub text: String,
+    pub url: String,
+}
+
+pub struct Extracted {
+    pub meta: PageMeta,
+    /// HTML of the main content, or of the whole body when none stood out
+    pub content_html: String,
+    pub main_found: bool,
+    /// Links inside the content, resolved against the page URL
+    pub links: Vec<Link>,
+}
+
+/// Find the main content of a page the way Readability does: prune
+/// boilerplate, score blocks by the paragraphs they hold and keep the best
+/// one with its related siblings
+pub fn extract(html: &str, base: &Url) -> Extracted {
+    let dom = Dom::parse(html);
+    let meta = dom.meta(base);
+    let (roots, main_found) = match dom.main_content() {
+        Some(roots) => (roots, true),
+        None => (vec![dom.body()], false),
+    };
+
+    let mut content_html = String::new();
+    let mut links = Vec::new();
+    let mut seen = HashSet::new();
+    for &root in &roots {
+        dom.write_html(root, base, &mut content_html);
+        dom.collect_links(root, base, &mut links, &mut seen);
+    }
+
+    Extracted {
+        meta,
+        content_html,
+        main_found,
+        links,
+    }
+}
+
+/// Render HTML as plain text, without runs of blank lines
+pub fn html_to_text(html: &str) -> String {
+    let text = html2text::from_read(html.as_bytes(), 80).unwrap_or_else(|_| html.to_string());
+
+    let mut cleaned = String::with_capacity(text.len());
+    let mut blank_count = 0;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            blank_count += 1;
+            if blank_count <= 2 {
+                cleaned.push('\n');
+            }
+        } else {
+            blank_count = 0;
+            cleaned.push_str(trimmed);
+            cleaned.push('\n');
+        }
+    }
+    cleaned
+}
+
+/// An element, or a text run when `tag` is empty
+struct Node {
+    tag: String,
+    attrs: Vec<(String, String)>,
+    text: String,
+    parent: usize,
+    children: Vec<usize>,
+}
+
+/// A forgiving element tree; children always come after their parent
+struct Dom {
+    nodes: Vec<Node>,
+    /// Inside a pruned element
+    skipped: Vec<bool>,
+    /// Characters of unpruned text below each node
+    text_len: Vec<usize>,
+    /// Of which inside links
+    link_len: Vec<usize>,
+}
+
+impl Dom {
+    fn parse(html: &str) -> Self {
+        let mut builder = TreeBuilder::default();
+        builder.current(); // creates the root
+        let tokenizer = Tokenizer::new(TreeSink(RefCell::new(builder)), TokenizerOpts::default());
+        let queue = BufferQueue::default();
+        queue.push_back(StrTendril::from_slice(html));
+        let _ = tokenizer.feed(&queue);
+        tokenizer.end();
+        let nodes = tokenizer.sink.0.into_inner().nodes;
+
+        let n = nodes.len();
+        let mut skipped = vec![false; n];
+        let mut in_link = vec![false; n];
+        for i in 1..n {
+            let parent = nodes[i].parent;
+            skipped[i] = skipped[parent] || is_boilerplate(&nodes[i]);
+            in_link[i] = in_link[parent] || nodes[i].tag == "a";
+        }
+
+        let mut text_len = vec![0; n];
+        let mut link_len = vec![0; n];
+        for i in (0..n).rev() {
+            if skipped[i] {
+                continue;
+            }
+            if nodes[i].tag.is_empty() {
+                text_len[i] = text_chars(&nodes[i].text);
+                if in_link[i] {
+                    link_len[i] = text_len[i];
+                }
+            }
+            if i > 0 {
+                let parent = nodes[i].parent;
+                text_len[parent] += text_len[i];
+                link_len[parent] += link_len[i];
+            }
+        }
+
+        Self {
+            nodes,
+            skipped,
+            text_len,
+            link_len,
+        }
+    }
+
+    /// The best scoring block and the siblings that belong with it
+    fn main_content(&self) -> Option<Vec<usize>> {
+        let n = self.nodes.len();
+        let mut scores = vec![0.0f64; n];
+        for i in 1..n {
+            let node = &self.nodes[i];
+            if self.skipped[i]
+                || !SCORED_TAGS.contains(&node.tag.as_str())
+                || self.text_len[i] < MIN_PARAGRAPH_CHARS
+            {
+                continue;
+            }
+            let mut text = String::new();
+            self.text_into(i, &mut text);
+            let score =
+                1.0 + text.matches(',').count() as f64 + (self.text_len[i] as f64 / 100.0).min(3.0);
+            let parent = node.parent;
+            scores[parent] += score;
+            if parent > 0 {
+                scores[self.nodes[parent].parent] += score / 2.0;
+            }
+        }
+
+        let adjusted: Vec<f64> = (0..n)
+            .map(|i| {
+                if scores[i] == 0.0 || i == 0 {
+                    return 0.0;
+                }
+                (scores[i] + self.class_weight(i)) * (1.0 - self.link_density(i))
+            })
+            .collect();
+        let (best, best_score) = adjusted
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, s)| (i, *s))?;
+        if best_score <= 0.0 || self.text_len[best] < MIN_CONTENT_CHARS {
+            return None;
+        }
+
+        // Siblings that score well or read like paragraphs of the same text
+        let parent = self.nodes[best].parent;
+        let threshold = (best_score * 0.2).max(10.0);
+        let roots = self.nodes[parent]
+            .children
+            .iter()
+            .copied()
+            .filter(|&i| {
+                i == best
+                    || (!self.skipped[i]
+                        && !self.nodes[i].tag.is_empty()
+                        && (adjusted[i] >= threshold
+                            || (self.nodes[i].tag == "p"
+                                && self.text_len[i] >= 80
+                                && self.link_density(i) < 0.25)))
+            })
+            .collect();
+        Some(roots)
+    }
+
+    fn class_weight(&self, i: usize) -> f64 {
+        let node = &self.nodes[i];
+        let mut weight = if matches!(node.tag.as_str(), "article" | "main") {
+            25.0
+        } else {
+            0.0
+        };
+        let hints = class_and_id(node);
+        if CONTENT_HINTS.iter().any(|h| hints.contains(h)) {
+            weight += 25.0;
+        }
+        weight
+    }
+
+    fn link_density(&self, i: usize) -> f64 {
+        if self.text_len[i] == 0 {
+            return 0.0;
+        }
+        self.link_len[i] as f64 / self.text_len[i] as f64
+    }
+
+    fn body(&self) -> usize {
+        self.nodes
+            .iter()
+            .position(|node| node.tag == "body")
+            .unwrap_or(0)
+    }
+
+    fn text_into(&self, i: usize, out: &mut String) {
+        if self.skipped[i] {
+            return;
+        }
+        let node = &self.nodes[i];
+        if node.tag.is_empty() {
+            out.push_str(&node.text);
+        }
+        for &child in &node.children {
+            self.text_into(child, out);
+        }
+    }
+
+    /// Serialize the unpruned part of a subtree, keeping only the attributes
+    /// the text renderer uses, with links made absolute
+    fn write_html(&self, i: usize, base: &Url, out: &mut String) {
+        if self.skipped[i] {
+            return;
+        }
+        let node = &self.nodes[i];
+        if node.tag.is_empty() {
+            out.push_str(&escape_html(&node.text));
+            return;
+        }
+        if i > 0 {
+            out.push('<');
+            out.push_str(&node.tag);
+            for (name, value) in &node.attrs {
+                let value = match name.as_str() {
+                    "href" | "src" => base
+                        .join(value.trim())
+                        .map(String::from)
+                        .unwrap_or_else(|_| value.clone()),
+                    "alt" | "colspan" | "rowspan" => value.clone(),
+                    _ => continue,
+                };
+                out.push_str(&format!(" {}=\"{}\"", name, escape_html(&value)));
+            }
+            out.push('>');
+        }
+        for &child in &node.children {
+            self.write_html(child, base, out);
+        }
+        if i > 0 && !VOID_TAGS.contains(&node.tag.as_str()) {
+            out.push_str(&format!("</{}>", node.tag));
+        }
+    }
+
+    fn collect_links(
+        &self,
+        i: usize,
+        base: &Url,
+        links: &mut Vec<Link>,
+        seen: &mut HashSet<String>,
+    ) {
+        if self.skipped[i] || links.len() >= MAX_LINKS {
+            return;
+        }
+        let node = &self.nodes[i];
+        if node.tag == "a"
+            && let Some(href) = attr(node, "href")
+            && let Ok(url) = base.join(href.trim())
+            && matches!(url.scheme(), "http" | "https")
+        {
+            let mut url = url;
+            url.set_fragment(None);
+            let mut text = String::new();
+            self.text_into(i, &mut text);
+            let text: String = collapse_whitespace(&text)
+                .chars()
+                .take(MAX_LINK_TEXT)
+                .collect();
+            if !text.is_empty() && url.as_str() != base.as_str() && seen.insert(url.to_string()) {
+                links.push(Link {
+                    text,
+                    url: url.to_string(),
+                });
+            }
+            return;
+        }
+        for &child in &node.children {
+            self.collect_links(child, base, links, seen);
+        }
+    }
+
+    fn meta(&self, base: &Url) -> PageMeta {
+        let mut meta = PageMeta::default();
+        let mut title_tag = None;
+        let mut description = None;
+        let mut author = None;
+
+        for node in &self.nodes {
+            match node.tag.as_str() {
+                "html" => meta.language = attr(node, "lang").map(str::to_string),
+                "title" if title_tag.is_none() => {
+                    let mut text = String::new();
+                    for &child in &node.children {
+                        text.push_str(&self.nodes[child].text);
+                    }
+                    title_tag = non_empty(&text);
+                }
+                "link" => {
+                    let rel = attr(node, "rel").unwrap_or_default().to_lowercase();
+                    if rel.split_whitespace().any(|r| r == "canonical")
+                        && let Some(href) = attr(node, "href")
+                    {
+                        meta.canonical_url = base.join(href.trim()).ok().map(String::from);
+                    }
+                }
+                "meta" => {
+                    let Some(key) = attr(node, "property").or_else(|| attr(node, "name")) else {
+                        continue;
+                    };
+                    let Some(value) = attr(node, "content").and_then(non_empty) else {
+                        continue;
+                    };
+                    match key.to_lowercase().as_str() {
+                        "og:title" => meta.title = Some(value),
+                        "twitter:title" if meta.title.is_none() => meta.title = Some(value),
+                        "og:description" => meta.description = Some(value),
+                        "description" => description = Some(value),
+                        "og:site_name" => meta.site_name = Some(value),
+                        "og:type" => meta.kind = Some(value),
+                        "og:image" => meta.image = base.join(&value).ok().map(String::from),
+                        "article:author" => meta.author = Some(value),
+                        "author" => author = Some(value),
+                        "article:published_time" | "date" => {
+                            meta.published.get_or_insert(value);
+                        }
+                        "og:url" if meta.canonical_url.is_none() => {
+                            meta.canonical_url = base.join(&value).ok().map(String::from);
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        meta.title = meta.title.or(title_tag);
+        meta.description = meta.description.or(description);
+        meta.author = meta.author.or(author);
+        meta
+    }
+}
+
+#[derive(Default)]
+struct TreeBuilder {
+    nodes: Vec<Node>,
+    /// Open elements, innermost last
+    open: Vec<usize>,
+}
+
+impl TreeBuilder {
+    fn current(&mut self) -> usize {
+        if self.nodes.is_empty() {
+            self.nodes.push(Node {
+                tag: "#root".to_string(),
+                attrs: Vec::new(),
+                text: String::new(),
+                parent: 0,
+                children: Vec::new(),
+            });
+            self.open.push(0);
+        }
+        *self.open.last().unwrap_or(&0)
+    }
+
+    fn push(&mut self, tag: String, attrs: Vec<(String, String)>, text: String) -> usize {
+        let parent = self.current();
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            tag,
+            attrs,
+            text,
+            parent,
+            children: Vec::new(),
+        });
+        self.nodes[parent].children.push(index);
+        index
+    }
+
+    fn start(&mut self, tag: String, attrs: Vec<(String, String)>) {
+        // Close elements this one implicitly ends, like a <p> before a <div>
+        loop {
+            let current = self.current();
+            if current == 0 {
+                break;
+            }
+            let open = self.nodes[current].tag.as_str();
+            let closes = match tag.as_str() {
+                "li" => open == "li" || open == "p",
+                "dt" | "dd" => matches!(open, "dt" | "dd" | "p"),
+                "tr" => matches!(open, "tr" | "td" | "th"),
+                "td" | "th" => matches!(open, "td" | "th"),
+                "option" => open == "option",
+                t if BLOCK_TAGS.contains(&t) => open == "p",
+                _ => false,
+            };
+            if !closes {
+                break;
+            }
+            self.open.pop();
+        }
+
+        let is_void = VOID_TAGS.contains(&tag.as_str());
+        let index = self.push(tag, attrs, String::new());
+        if !is_void {
+            self.open.push(index);
+        }
+    }
+
+    fn end(&mut self, tag: &str) {
+        if let Some(pos) = self
+            .open
+            .iter()
+            .rposition(|&i| i > 0 && self.nodes[i].tag == tag)
+        {
+            self.open.truncate(pos);
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let current = self.current();
+        if let Some(&last) = self.nodes[current].children.last()
+            && self.nodes[last].tag.is_empty()
+        {
+            self.nodes[last].text.push_str(text);
+            return;
+        }
+        self.push(String::new(), Vec::new(), text.to_string());
+    }
+}
+
+struct TreeSink(RefCell<TreeBuilder>);
+
+impl TokenSink for TreeSink {
+    type Handle = ();
+
+    fn process_token(&self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
+        let mut builder = self.0.borrow_mut();
+        match token {
+            Token::TagToken(tag) => {
+                let name = tag.name.to_string();
+                match tag.kind {
+                    TagKind::StartTag => {
+                        let attrs = tag
+                            .attrs
+                            .iter()
+                            .map(|a| (a.name.local.to_string(), a.value.to_string()))
+                            .collect();
+                        builder.start(name.clone(), attrs);
+                        if tag.self_closing {
+                            builder.end(&name);
+                            return TokenSinkResult::Continue;
+                        }
+                        // The tokenizer needs to be told which elements hold raw text
+                        match name.as_str() {
+                            "script" => return TokenSinkResult::RawData(RawKind::ScriptData),
+                            "style" | "noscript" | "xmp" | "iframe" | "noembed" | "noframes" => {
+                                return TokenSinkResult::RawData(RawKind::Rawtext);
+                            }
+                            "title" | "textarea" => {
+                                return TokenSinkResult::RawData(RawKind::Rcdata);
+                            }
+                            _ => {}
+                        }
+                    }
+                    TagKind::EndTag => builder.end(&name),
+                }
+            }
+            Token::CharacterTokens(text) => builder.text(&text),
+            _ => {}
+        }
+        TokenSinkResult::Continue
+    }
+}
+
+fn is_boilerplate(node: &Node) -> bool {
+    if node.tag.is_empty() {
+        return false;
+    }
+    if SKIP_TAGS.contains(&node.tag.as_str()) || attr(node, "hidden").is_some() {
+        return true;
+    }
+    if matches!(node.tag.as_str(), "body" | "html" | "article" | "main") {
+        return false;
+    }
+    let hints = class_and_id(node);
+    let role = attr(node, "role").unwrap_or_default();
+    matches!(
+        role,
+        "navigation" | "banner" | "contentinfo" | "complementary" | "dialog" | "alertdialog"
+    ) || CONSENT_HINTS.iter().any(|h| hints.contains(h))
+        || (BOILERPLATE_HINTS.iter().any(|h| hints.contains(h))
+            && !CONTENT_HINTS.iter().any(|h| hints.contains(h)))
+}
+
+fn class_and_id(node: &Node) -> String {
+    format!(
+        "{} {}",
+        attr(node, "class").unwrap_or_default(),
+        attr(node, "id").unwrap_or_default()
+    )
+    .to_lowercase()
+}
+
+fn attr<'a>(node: &'a Node, name: &str) -> Option<&'a str> {
+    node.attrs
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+fn text_chars(text: &str) -> usize {
+    text.split_whitespace().map(|w| w.chars().count() + 1).sum()
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = collapse_whitespace(text);
+    (!text.is_empty()).then_some(text)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
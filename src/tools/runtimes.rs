@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::sandbox::SandboxRunner;
+use super::schema::parse_args;
+use super::workspace::{self, Workspace};
+use super::{Tool, ToolContext};
+
+const SCRIPT_DEFAULT_TIMEOUT: u64 = 30;
+const SCRIPT_MAX_TIMEOUT: u64 = 120;
+
+/// An interpreter that runs a script written to the workspace
+pub struct Runtime {
+    /// Tool name, used in logs and the audit table
+    pub tool: &'static str,
+    pub script_name: &'static str,
+    pub interpreter: &'static str,
+    /// Flag capping the heap, completed with the memory limit in MB. Such
+    /// runtimes reserve far more address space than they use, so they get
+    /// this instead of an address-space limit.
+    pub heap_flag: Option<&'static str>,
+}
+
+pub const PYTHON: Runtime = Runtime {
+    tool: "run_python",
+    script_name: "__script__.py",
+    interpreter: "python3",
+    heap_flag: None,
+};
+
+const SHELL: Runtime = Runtime {
+    tool: "run_shell",
+    script_name: "__script__.sh",
+    interpreter: "bash",
+    heap_flag: None,
+};
+
+const NODE: Runtime = Runtime {
+    tool: "run_node",
+    script_name: "__script__.js",
+    interpreter: "node",
+    heap_flag: Some("--max-old-space-size="),
+};
+
+/// Arguments shared by the script runtimes
+#[derive(Deserialize)]
+pub struct ScriptArgs {
+    code: String,
+    timeout_secs: Option<u64>,
+    #[serde(default)]
+    input_files: HashMap<String, String>,
+    #[serde(default)]
+    persistent_workspace: bool,
+    #[serde(default)]
+    reset_workspace: bool,
+    send_images: Option<bool>,
+    #[serde(default)]
+    send_files: Vec<String>,
+    #[serde(default)]
+    save_files: HashMap<String, String>,
+}
+
+/// Argument schema of a script runtime; `code` describes the code argument
+pub fn script_parameters(code: &str) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "code": {
+                "type": "string",
+                "description": code
+            },
+            "timeout_secs": {
+                "type": "integer",
+                "description": "Maximum execution time in seconds. Default: 30. Max: 120. The process is killed if it exceeds this."
+            },
+            "input_files": {
+                "type": "object",
+                "description": "Optional files to create in /workspace/ before execution. Keys are filenames, values are file contents. Example: {\"data.csv\": \"name,value\\na,1\\nb,2\"}",
+                "additionalProperties": { "type": "string" }
+            },
+            "persistent_workspace": {
+                "type": "boolean",
+                "description": "Use this chat's persistent /workspace, kept between calls, instead of a fresh one. Default: false."
+            },
+            "reset_workspace": {
+                "type": "boolean",
+                "description": "Empty the persistent workspace before running. Default: false."
+            },
+            "send_images": {
+                "type": "boolean",
+                "description": "Send images (png, jpg, gif, webp) the code creates or changes to the chat as photos. Default: true."
+            },
+            "send_files": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "Workspace files to send to the chat after the run, as photos (images) or documents. Example: [\"report.pdf\"]"
+            },
+            "save_files": {
+                "type": "object",
+                "description": "Workspace files to copy into crud_file storage, as {workspace file: destination path}; destinations are in this chat's namespace unless they start with person/<id>/ or shared/. Existing files are not replaced. Example: {\"chart.png\": \"charts/sales.png\"}",
+                "additionalProperties": { "type": "string" }
+            }
+        },
+        "required": ["code"]
+    })
+}
+
+/// Sends files to the chat, writes the files root or a shared workspace
+pub fn script_has_side_effects(args: &Value) -> bool {
+    args["send_images"].as_bool().unwrap_or(true)
+        || args["persistent_workspace"].as_bool().unwrap_or(false)
+        || args["send_files"].as_array().is_some_and(|a| !a.is_empty())
+        || args["save_files"]
+            .as_object()
+            .is_some_and(|o| !o.is_empty())
+}
+
+/// Run a script in the sandbox, then send and save the files it produced
+pub async fn run_script(
+    ctx: &ToolContext<'_>,
+    runtime: &Runtime,
+    args: ScriptArgs,
+) -> Result<String> {
+    let code = args.code.as_str();
+    let timeout_secs = args
+        .timeout_secs
+        .map(|v| v.min(SCRIPT_MAX_TIMEOUT))
+        .unwrap_or(SCRIPT_DEFAULT_TIMEOUT);
+
+    let workspace = Workspace::open(
+        ctx.pool,
+        ctx.chat_id,
+        args.persistent_workspace,
+        args.reset_workspace,
+    )
+    .await?;
+    workspace.write_inputs(&args.input_files).await?;
+
+    // Write the script
+    let script_path = workspace.dir.join(runtime.script_name);
+    tokio::fs::write(&script_path, code).await?;
+    let before = workspace.snapshot().await;
+
+    let mut runner = SandboxRunner::new(ctx.pool, &workspace).await?;
+    let mut command = vec![runtime.interpreter.to_string()];
+    if let Some(flag) = runtime.heap_flag {
+        if runner.policy.limits.memory_mb > 0 {
+            command.push(format!("{}{}", flag, runner.policy.limits.memory_mb));
+        }
+        runner = runner.without_address_space_limit();
+    }
+    command.push(format!("/workspace/{}", runtime.script_name));
+    let command: Vec<&str> = command.iter().map(String::as_str).collect();
+
+    tracing::info!(
+        tool = runtime.tool,
+        timeout_secs,
+        code_len = code.len(),
+        network = runner.policy.network.name(),
+        persistent = workspace.persistent,
+        "Executing script in sandbox"
+    );
+
+    let output = match runner
+        .run(ctx, runtime.tool, code, &command, timeout_secs)
+        .await
+    {
+        Ok(o) => o,
+        Err(e) => {
+            workspace.cleanup().await;
+            return Ok(json!({
+                "error": format!("Failed to launch sandbox: {}", e),
+                "hint": "Ensure 'bwrap' (bubblewrap) is installed: apt install bubblewrap",
+            })
+            .to_string());
+        }
+    };
+    let _ = tokio::fs::remove_file(&script_path).await;
+    let mut result = runner.result_json(&output, timeout_secs);
+
+    // Files this run created or modified
+    let changed = workspace::changed_files(&before, &workspace.snapshot().await);
+    let created_files: Vec<Value> = changed
+        .iter()
+        .map(|(path, size)| json!({"name": path.to_string_lossy(), "size_bytes": size}))
+        .collect();
+
+    // New images go to the chat unless turned off; other files only on request
+    let mut to_send = args.send_files.clone();
+    if args.send_images.unwrap_or(true) {
+        for (path, _) in &changed {
+            let name = path.to_string_lossy().to_string();
+            if workspace::is_image(path) && !to_send.contains(&name) {
+                to_send.push(name);
+            }
+        }
+    }
+    let sent_files = workspace::send_artifacts(ctx, &workspace, &to_send).await;
+    let saved_files = workspace::save_artifacts(ctx, &workspace, &args.save_files).await;
+
+    result["workspace"] = json!(if workspace.persistent {
+        "persistent"
+    } else {
+        "temporary"
+    });
+    result["files_created"] = json!(created_files);
+    result["files_sent"] = json!(sent_files);
+    result["files_saved"] = json!(saved_files);
+    workspace.cleanup().await;
+
+    Ok(result.to_string())
+}
+
+pub struct RunShell;
+
+#[async_trait]
+impl Tool for RunShell {
+    fn name(&self) -> &str {
+        "run_shell"
+    }
+
+    fn description(&self) -> &str {
+        "Execute a bash script in the same sandbox as run_python. Use this for command-line tools \
+         installed on the host (coreutils, grep, sed, awk, jq, curl, ffmpeg, imagemagick and the like), \
+         file conversions and quick text processing.\n\n\
+         The filesystem is read-only except /workspace; network access, memory, CPU time, process count \
+         and output are limited by the same sandbox policy. Workspace options (persistent_workspace, \
+         send_files, save_files, send_images) work as in run_python.\n\n\
+         Examples:\n\
+         - {\"code\": \"ls -la /usr/bin | wc -l\"}\n\
+         - {\"code\": \"jq '.items | length' data.json\", \"input_files\": {\"data.json\": \"{\\\"items\\\": [1, 2]}\"}}"
+    }
+
+    fn parameters(&self) -> Value {
+        script_parameters(
+            "Bash script to execute. Output on stdout is returned. The working directory is /workspace/.",
+        )
+    }
+
+    fn has_side_effects(&self, args: &Value) -> bool {
+        script_has_side_effects(args)
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        run_script(ctx, &SHELL, parse_args(args)?).await
+    }
+}
+
+pub struct RunNode;
+
+#[async_trait]
+impl Tool for RunNode {
+    fn name(&self) -> &str {
+        "run_node"
+    }
+
+    fn description(&self) -> &str {
+        "Execute JavaScript with Node.js in the same sandbox as run_python. Use this when a task is easier \
+         in JavaScript or needs an npm package installed on the host.\n\n\
+         The script runs as a CommonJS file; use console.log() for output. The filesystem is read-only \
+         except /workspace; network access, memory (as the V8 heap limit), CPU time, process count and \
+         output are limited by the same sandbox policy. Workspace options (persistent_workspace, \
+         send_files, save_files, send_images) work as in run_python.\n\n\
+         Examples:\n\
+         - {\"code\": \"console.log([3, 1, 2].sort((a, b) => a - b))\"}\n\
+         - {\"code\": \"const fs = require('fs');\\nfs.writeFileSync('out.json', JSON.stringify({ok: true}));\\nconsole.log('saved')\"}"
+    }
+
+    fn parameters(&self) -> Value {
+        script_parameters(
+            "JavaScript code to execute with Node.js. Use console.log() for output. The working directory is /workspace/.",
+        )
+    }
+
+    fn has_side_effects(&self, args: &Value) -> bool {
+        script_has_side_effects(args)
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        run_script(ctx, &NODE, parse_args(args)?).await
+    }
+}
@@ -0,0 +1,1100 @@
+use std::net::SocketAddr;
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UnixListener, UnixStream};
+
+use super::ToolContext;
+use super::http_policy::is_public_ip;
+use super::workspace::Workspace;
+use crate::config;
+use crate::db;
+use crate::types::SandboxAuditEntry;
+
+/// Read-only system paths mounted into every sandbox
+const RO_MOUNTS: [&str; 9] = [
+    "/usr",
+    "/lib",
+    "/lib64",
+    "/lib32",
+    "/etc/resolv.conf",
+    "/etc/ssl",
+    "/etc/ca-certificates",
+    "/etc/alternatives",
+    "/etc/ld.so.cache",
+];
+
+/// Sandbox path of the per-run support directory (proxy socket, bridge)
+const SUPPORT_MOUNT: &str = "/run/sandbox";
+const PROXY_URL: &str = "http://127.0.0.1:3128";
+const PROXY_MAX_HEAD: usize = 16 * 1024;
+/// How long past its own timeout a sandbox may run before it is killed from outside
+const KILL_GRACE_SECS: u64 = 5;
+/// Characters of stdout and stderr each returned to the model
+const MAX_RESULT_OUTPUT: usize = 15000;
+/// Characters of the code or query kept in the audit log
+const AUDIT_MAX_INPUT: usize = 4000;
+
+/// Relays 127.0.0.1:3128 inside the sandbox to the host-side proxy socket,
+/// since a sandbox without a network namespace cannot reach the host
+const PROXY_BRIDGE: &str = r#"import socket, threading
+
+def pipe(src, dst):
+    try:
+        while True:
+            data = src.recv(65536)
+            if not data:
+                break
+            dst.sendall(data)
+    except OSError:
+        pass
+    finally:
+        try:
+            dst.shutdown(socket.SHUT_WR)
+        except OSError:
+            pass
+
+def serve(client):
+    upstream = socket.socket(socket.AF_UNIX)
+    try:
+        upstream.connect("/run/sandbox/proxy.sock")
+    except OSError:
+        client.close()
+        return
+    threading.Thread(target=pipe, args=(upstream, client), daemon=True).start()
+    pipe(client, upstream)
+
+server = socket.socket()
+server.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+server.bind(("127.0.0.1", 3128))
+server.listen(64)
+open("/tmp/.proxy-ready", "w").close()
+while True:
+    client, _ = server.accept()
+    threading.Thread(target=serve, args=(client,), daemon=True).start()
+"#;
+
+/// Starts the bridge, waits until it listens, then runs the command
+const PROXY_WRAPPER: &str = r#"python3 /run/sandbox/bridge.py &
+i=0
+while [ ! -e /tmp/.proxy-ready ] && [ $i -lt 100 ]; do sleep 0.05; i=$((i+1)); done
+exec "$@""#;
+
+/// Network access of sandboxed code
+pub enum NetworkMode {
+    /// No network namespace shared with the host
+    None,
+    /// Host network shared; only when configured explicitly
+    Full,
+    /// No network namespace, but an HTTP(S) proxy that only connects to
+    /// these hosts and their subdomains
+    Allowlist(Vec<String>),
+}
+
+impl NetworkMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            NetworkMode::None => "none",
+            NetworkMode::Full => "full",
+            NetworkMode::Allowlist(_) => "allowlist",
+        }
+    }
+}
+
+/// Per-run resource limits; 0 disables a limit. These are rlimits set with
+/// `prlimit`, so memory and CPU time are capped per process, not for the
+/// sandbox as a whole as a cgroup would.
+pub struct SandboxLimits {
+    pub memory_mb: u64,
+    pub cpu_secs: u64,
+    pub max_processes: u64,
+    pub file_size_mb: u64,
+    pub max_output_bytes: usize,
+}
+
+/// How code is sandboxed, from the `sandbox_*` config keys
+pub struct SandboxPolicy {
+    pub limits: SandboxLimits,
+    pub network: NetworkMode,
+    pub seccomp: bool,
+}
+
+impl SandboxPolicy {
+    pub async fn load(pool: &SqlitePool) -> Result<Self> {
+        let limits = SandboxLimits {
+            memory_mb: config_u64(pool, "sandbox_memory_mb", 512).await?,
+            cpu_secs: config_u64(pool, "sandbox_cpu_secs", 60).await?,
+            max_processes: config_u64(pool, "sandbox_max_procs", 64).await?,
+            file_size_mb: config_u64(pool, "sandbox_file_size_mb", 64).await?,
+            max_output_bytes: config_u64(pool, "sandbox_max_output_kb", 1024).await? as usize
+                * 1024,
+        };
+
+        // Anything unrecognised falls back to no network
+        let network = match config::get_or_default(pool, "sandbox_network", "none")
+            .await?
+            .trim()
+            .to_lowercase()
+            .as_str()
+        {
+            "full" => NetworkMode::Full,
+            "allowlist" => {
+                let hosts = config::get_or_default(pool, "sandbox_network_allowlist", "").await?;
+                NetworkMode::Allowlist(
+                    hosts
+                        .split(',')
+                        .map(|h| h.trim().trim_start_matches("*.").to_lowercase())
+                        .filter(|h| !h.is_empty())
+                        .collect(),
+                )
+            }
+            _ => NetworkMode::None,
+        };
+
+        let seccomp = config::get_or_default(pool, "sandbox_seccomp", "on").await?;
+        let seccomp = !matches!(seccomp.trim(), "off" | "false" | "0" | "no");
+
+        Ok(Self {
+            limits,
+            network,
+            seccomp,
+        })
+    }
+}
+
+async fn config_u64(pool: &SqlitePool, key: &str, default: u64) -> Result<u64> {
+    Ok(config::get(pool, key)
+        .await?
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default))
+}
+
+pub struct SandboxOutput {
+    pub exit_code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    /// Output went past `max_output_bytes`, so the sandbox was stopped
+    pub output_limited: bool,
+    /// Still running past its timeout and killed from outside
+    pub killed: bool,
+}
+
+impl SandboxOutput {
+    pub fn timed_out(&self) -> bool {
+        self.exit_code == 124 || self.killed // timeout command returns 124
+    }
+
+    /// Which limit stopped the run, if any, and a message saying so
+    fn limit_hit(
+        &self,
+        limits: &SandboxLimits,
+        timeout_secs: u64,
+    ) -> Option<(&'static str, String)> {
+        let failed = self.exit_code != 0;
+        let hit = if self.output_limited {
+            (
+                "output",
+                format!(
+                    "Output went past {} KB, so the program was stopped",
+                    limits.max_output_bytes / 1024
+                ),
+            )
+        } else if self.timed_out() {
+            (
+                "timeout",
+                format!("Killed after the {}s timeout", timeout_secs),
+            )
+        } else if self.exit_code == 128 + libc::SIGXCPU {
+            (
+                "cpu_time",
+                format!("Killed after using {}s of CPU time", limits.cpu_secs),
+            )
+        } else if self.exit_code == 128 + libc::SIGXFSZ
+            || (failed && self.stderr.contains("File too large"))
+        {
+            (
+                "file_size",
+                format!("A file grew past the {} MB limit", limits.file_size_mb),
+            )
+        } else if failed
+            && (self.stderr.contains("MemoryError")
+                || self.stderr.contains("Cannot allocate memory")
+                || self.stderr.contains("JavaScript heap out of memory"))
+        {
+            (
+                "memory",
+                format!("Ran out of memory; the limit is {} MB", limits.memory_mb),
+            )
+        } else if failed
+            && (self.stderr.contains("Resource temporarily unavailable")
+                || self.stderr.contains("can't start new thread"))
+        {
+            (
+                "processes",
+                format!(
+                    "Could not start more processes or threads; the limit is {}",
+                    limits.max_processes
+                ),
+            )
+        } else {
+            return None;
+        };
+        Some(hit)
+    }
+}
+
+/// Runs commands under bubblewrap: system directories read-only, the
+/// workspace writable at /workspace, fresh user/pid/ipc/uts/cgroup
+/// namespaces with no nested user namespaces, resource limits, seccomp and
+/// the network policy applied.
+/// Every run is recorded in the `sandbox_audit` table.
+pub struct SandboxRunner<'a> {
+    pub policy: SandboxPolicy,
+    workspace: &'a Workspace,
+    ro_binds: Vec<String>,
+    env: Vec<(String, String)>,
+    limit_address_space: bool,
+}
+
+impl<'a> SandboxRunner<'a> {
+    /// Runner with the configured policy
+    pub async fn new(pool: &SqlitePool, workspace: &'a Workspace) -> Result<Self> {
+        Ok(Self {
+            policy: SandboxPolicy::load(pool).await?,
+            workspace,
+            ro_binds: Vec::new(),
+            env: Vec::new(),
+            limit_address_space: true,
+        })
+    }
+
+    /// Override the configured network policy
+    pub fn network(mut self, network: NetworkMode) -> Self {
+        self.policy.network = network;
+        self
+    }
+
+    /// Also mount this host path read-only, if it exists
+    pub fn ro_bind(mut self, path: impl Into<String>) -> Self {
+        self.ro_binds.push(path.into());
+        self
+    }
+
+    pub fn env(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.env.push((key.to_string(), value.into()));
+        self
+    }
+
+    /// Skip the address-space limit, for runtimes that reserve far more
+    /// virtual memory than they use and cap their heap themselves
+    pub fn without_address_space_limit(mut self) -> Self {
+        self.limit_address_space = false;
+        self
+    }
+
+    /// Run `command` wrapped in `timeout`, and audit it as a call of `tool`
+    /// on behalf of the context's chat and user with `input` (the code or
+    /// query being run)
+    pub async fn run(
+        &self,
+        ctx: &ToolContext<'_>,
+        tool: &str,
+        input: &str,
+        command: &[&str],
+        timeout_secs: u64,
+    ) -> Result<SandboxOutput> {
+        let started = Instant::now();
+        let result = self.spawn(command, timeout_secs).await;
+
+        let (exit_code, limit, error) = match &result {
+            Ok(output) => (
+                Some(output.exit_code),
+                output
+                    .limit_hit(&self.policy.limits, timeout_secs)
+                    .map(|(limit, _)| limit.to_string()),
+                None,
+            ),
+            Err(e) => (None, None, Some(e.to_string())),
+        };
+        let entry = SandboxAuditEntry {
+            chat_id: ctx.chat_id,
+            user_id: ctx.user_id,
+            tool: tool.to_string(),
+            input: truncate_text_for_output(input, AUDIT_MAX_INPUT),
+            exit_code,
+            limit_exceeded: limit,
+            error,
+            network: self.policy.network.name().to_string(),
+            duration_ms: started.elapsed().as_millis() as i64,
+        };
+        if let Err(e) = db::sandbox_audit_insert(ctx.pool, &entry).await {
+            tracing::error!(error = %e, "Failed to record sandbox audit entry");
+        }
+
+        result
+    }
+
+    /// Common result fields: exit code, truncated output, and which limit
+    /// (if any) stopped the run
+    pub fn result_json(&self, output: &SandboxOutput, timeout_secs: u64) -> Value {
+        json!({
+            "exit_code": output.exit_code,
+            "stdout": truncate_text_for_output(&output.stdout, MAX_RESULT_OUTPUT),
+            "stderr": truncate_text_for_output(&output.stderr, MAX_RESULT_OUTPUT),
+            "timed_out": output.timed_out(),
+            "timeout_secs": timeout_secs,
+            "limit_exceeded": output
+                .limit_hit(&self.policy.limits, timeout_secs)
+                .map(|(limit, message)| json!({"limit": limit, "message": message})),
+            "network": self.policy.network.name(),
+        })
+    }
+
+    async fn spawn(&self, command: &[&str], timeout_secs: u64) -> Result<SandboxOutput> {
+        let policy = &self.policy;
+        let support_dir = self.workspace.sandbox_dir.join("support");
+        tokio::fs::create_dir_all(&support_dir).await?;
+
+        let mut args: Vec<String> = Vec::new();
+
+        // Filesystem
+        for path in RO_MOUNTS
+            .iter()
+            .copied()
+            .chain(self.ro_binds.iter().map(String::as_str))
+        {
+            if tokio::fs::metadata(path).await.is_ok() {
+                args.extend(["--ro-bind".to_string(), path.to_string(), path.to_string()]);
+            }
+        }
+        args.extend(
+            [
+                "--bind",
+                &self.workspace.dir.to_string_lossy(),
+                "/workspace",
+                "--proc",
+                "/proc",
+                "--dev",
+                "/dev",
+                "--tmpfs",
+                "/tmp",
+                "--chdir",
+                "/workspace",
+            ]
+            .map(String::from),
+        );
+        for (key, value) in &self.env {
+            args.extend(["--setenv".to_string(), key.clone(), value.clone()]);
+        }
+
+        // Isolation
+        args.extend(
+            [
+                "--unshare-user",
+                "--disable-userns",
+                "--unshare-pid",
+                "--unshare-ipc",
+                "--unshare-uts",
+                "--unshare-cgroup",
+                "--new-session",
+                "--die-with-parent",
+            ]
+            .map(String::from),
+        );
+
+        // Network
+        let mut proxy = None;
+        match &policy.network {
+            NetworkMode::Full => {}
+            NetworkMode::None => args.push("--unshare-net".to_string()),
+            NetworkMode::Allowlist(hosts) => {
+                let socket_path = support_dir.join("proxy.sock");
+                let listener = UnixListener::bind(&socket_path)?;
+                proxy = Some(tokio::spawn(serve_proxy(listener, Arc::new(hosts.clone()))));
+                tokio::fs::write(support_dir.join("bridge.py"), PROXY_BRIDGE).await?;
+
+                args.extend(
+                    [
+                        "--unshare-net",
+                        "--bind",
+                        &support_dir.to_string_lossy(),
+                        SUPPORT_MOUNT,
+                    ]
+                    .map(String::from),
+                );
+                for var in ["HTTP_PROXY", "HTTPS_PROXY", "http_proxy", "https_proxy"] {
+                    args.extend([
+                        "--setenv".to_string(),
+                        var.to_string(),
+                        PROXY_URL.to_string(),
+                    ]);
+                }
+            }
+        }
+
+        // Seccomp: bwrap reads the compiled filter from an inherited fd
+        let seccomp_file = match seccomp_program() {
+            Some(program) if policy.seccomp => {
+                let path = self.workspace.sandbox_dir.join("seccomp.bpf");
+                tokio::fs::write(&path, program).await?;
+                Some(std::fs::File::open(&path)?)
+            }
+            _ => None,
+        };
+        let seccomp_fd = seccomp_file.as_ref().map(|f| f.as_raw_fd());
+        if let Some(fd) = seccomp_fd {
+            args.extend(["--seccomp".to_string(), fd.to_string()]);
+        }
+
+        // Command: [proxy bridge] + prlimit + timeout + program. Limits are set
+        // inside the user namespace so the process count only covers the sandbox.
+        if matches!(policy.network, NetworkMode::Allowlist(_)) {
+            args.extend(["sh", "-c", PROXY_WRAPPER, "sh"].map(String::from));
+        }
+        args.extend(prlimit_args(&policy.limits, self.limit_address_space));
+        args.extend(["timeout".to_string(), timeout_secs.to_string()]);
+        args.extend(command.iter().map(|s| s.to_string()));
+
+        let mut cmd = tokio::process::Command::new("bwrap");
+        cmd.args(&args)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(fd) = seccomp_fd {
+            // SAFETY: only calls fcntl, which is async-signal-safe
+            unsafe {
+                cmd.pre_exec(move || {
+                    if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        let result = supervise(cmd, policy.limits.max_output_bytes, timeout_secs).await;
+        if let Some(proxy) = proxy {
+            proxy.abort();
+        }
+        result
+    }
+}
+
+/// `prlimit` prefix applying the limits to the sandboxed command
+fn prlimit_args(limits: &SandboxLimits, limit_address_space: bool) -> Vec<String> {
+    let mut args = vec!["prlimit".to_string()];
+    if limits.memory_mb > 0 && limit_address_space {
+        args.push(format!("--as={}", limits.memory_mb * 1024 * 1024));
+    }
+    if limits.cpu_secs > 0 {
+        // SIGXCPU at the soft limit, SIGKILL a second later
+        args.push(format!("--cpu={}:{}", limits.cpu_secs, limits.cpu_secs + 1));
+    }
+    if limits.max_processes > 0 {
+        args.push(format!("--nproc={}", limits.max_processes));
+    }
+    if limits.file_size_mb > 0 {
+        args.push(format!("--fsize={}", limits.file_size_mb * 1024 * 1024));
+    }
+    args.push("--".to_string());
+    args
+}
+
+/// Cut text to at most `max_len` bytes, at a word boundary, noting the cut
+pub fn truncate_text_for_output(text: &str, max_len: usize) -> String {
+    if text.len() <= max_len {
+        return text.to_string();
+    }
+
+    let mut boundary = max_len;
+    while boundary > 0 && !text.is_char_boundary(boundary) {
+        boundary -= 1;
+    }
+
+    let cut = text[..boundary]
+        .rfind(|c: char| c.is_whitespace())
+        .unwrap_or(boundary);
+
+    format!(
+        "{}...\n[TRUNCATED: {}/{} chars]",
+        &text[..cut],
+        cut,
+        text.len()
+    )
+}
+
+/// Spawn the sandbox and collect its output, stopping it when the output
+/// grows past `max_output` (unless 0) or it outlives its timeout
+async fn supervise(
+    mut cmd: tokio::process::Command,
+    max_output: usize,
+    timeout_secs: u64,
+) -> Result<SandboxOutput> {
+    let mut child = cmd.spawn()?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+
+    let deadline = tokio::time::sleep(Duration::from_secs(timeout_secs + KILL_GRACE_SECS));
+    tokio::pin!(deadline);
+
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    let (mut out_buf, mut err_buf) = ([0u8; 8192], [0u8; 8192]);
+    let (mut out_open, mut err_open) = (true, true);
+    let mut output_limited = false;
+    let mut killed = false;
+
+    while out_open || err_open {
+        tokio::select! {
+            n = stdout.read(&mut out_buf), if out_open => match n {
+                Ok(0) | Err(_) => out_open = false,
+                Ok(n) => out.extend_from_slice(&out_buf[..n]),
+            },
+            n = stderr.read(&mut err_buf), if err_open => match n {
+                Ok(0) | Err(_) => err_open = false,
+                Ok(n) => err.extend_from_slice(&err_buf[..n]),
+            },
+            _ = &mut deadline => {
+                killed = true;
+                break;
+            }
+        }
+        if max_output > 0 && out.len() + err.len() > max_output {
+            output_limited = true;
+            break;
+        }
+    }
+
+    let status = if output_limited || killed {
+        let _ = child.start_kill();
+        child.wait().await?
+    } else {
+        tokio::select! {
+            status = child.wait() => status?,
+            _ = &mut deadline => {
+                killed = true;
+                let _ = child.start_kill();
+                child.wait().await?
+            }
+        }
+    };
+
+    if max_output > 0 {
+        out.truncate(max_output);
+        err.truncate(max_output.saturating_sub(out.len()));
+    }
+    Ok(SandboxOutput {
+        exit_code: status.code().unwrap_or(-1),
+        stdout: String::from_utf8_lossy(&out).to_string(),
+        stderr: String::from_utf8_lossy(&err).to_string(),
+        output_limited,
+        killed,
+    })
+}
+
+// --- Allowlist proxy ---
+
+/// Serve HTTP proxy requests from the sandbox bridge until aborted. Handles
+/// `CONNECT host:port` (HTTPS) and absolute-form plain HTTP requests, and
+/// only connects to allowlisted hosts.
+async fn serve_proxy(listener: UnixListener, allowlist: Arc<Vec<String>>) {
+    // Dropped with this task, which aborts open connections
+    let mut connections = tokio::task::JoinSet::new();
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let allowlist = allowlist.clone();
+                connections.spawn(async move {
+                    if let Err(e) = proxy_connection(stream, &allowlist).await {
+                        tracing::debug!(error = %e, "Sandbox proxy connection failed");
+                    }
+                });
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "Sandbox proxy stopped accepting");
+                return;
+            }
+        }
+    }
+}
+
+async fn proxy_connection(mut client: UnixStream, allowlist: &[String]) -> Result<()> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 4096];
+    let head_end = loop {
+        let n = client.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..n]);
+        if let Some(pos) = head.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if head.len() > PROXY_MAX_HEAD {
+            anyhow::bail!("request head too large");
+        }
+    };
+
+    let head_text = String::from_utf8_lossy(&head[..head_end]).to_string();
+    let (request_line, rest) = head_text.split_once("\r\n").unwrap_or((&head_text, ""));
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let version = parts.next().unwrap_or("HTTP/1.1");
+
+    // For plain HTTP, the request to forward in origin form
+    let (host, port, forward) = if method.eq_ignore_ascii_case("CONNECT") {
+        let (host, port) = split_host_port(target, 443);
+        (host, port, None)
+    } else if let Some(url) = target.strip_prefix("http://") {
+        let (authority, path) = match url.find('/') {
+            Some(i) => (&url[..i], &url[i..]),
+            None => (url, "/"),
+        };
+        let (host, port) = split_host_port(authority, 80);
+        let mut request = format!("{} {} {}\r\n{}", method, path, version, rest).into_bytes();
+        request.extend_from_slice(&head[head_end..]);
+        (host, port, Some(request))
+    } else {
+        client
+            .write_all(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n")
+            .await?;
+        return Ok(());
+    };
+
+    if !host_allowed(&host, allowlist) {
+        tracing::info!(host, "Sandbox network request blocked");
+        client
+            .write_all(
+                b"HTTP/1.1 403 Forbidden\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\nBlocked by the sandbox network policy\n",
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let addrs = match public_addrs(&host, port).await {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            tracing::info!(host, error = %e, "Sandbox network request blocked");
+            client
+                .write_all(
+                    b"HTTP/1.1 403 Forbidden\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\nHost does not resolve to a public address\n",
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+    let mut upstream = match TcpStream::connect(&addrs[..]).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            client
+                .write_all(b"HTTP/1.1 502 Bad Gateway\r\nConnection: close\r\n\r\n")
+                .await?;
+            return Err(e.into());
+        }
+    };
+
+    match forward {
+        Some(request) => upstream.write_all(&request).await?,
+        None => {
+            client
+                .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+                .await?;
+            upstream.write_all(&head[head_end..]).await?;
+        }
+    }
+    tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(())
+}
+
+/// Addresses of an allowlisted host, keeping only public ones so a name
+/// pointing at loopback, link-local or private space cannot reach the host
+/// or its network
+async fn public_addrs(host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await?
+        .filter(|addr| is_public_ip(addr.ip()))
+        .collect();
+    if addrs.is_empty() {
+        anyhow::bail!("{} resolves only to private or reserved addresses", host);
+    }
+    Ok(addrs)
+}
+
+/// Lowercased host and port of `host[:port]` or `[v6]:port`
+fn split_host_port(authority: &str, default_port: u16) -> (String, u16) {
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        match rest.split_once(']') {
+            Some((host, port)) => (host, port.strip_prefix(':')),
+            None => (rest, None),
+        }
+    } else {
+        match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        }
+    };
+    let port = port.and_then(|p| p.parse().ok()).unwrap_or(default_port);
+    (host.trim_end_matches('.').to_lowercase(), port)
+}
+
+/// Whether `host` is an allowlisted host or a subdomain of one
+pub(super) fn host_allowed(host: &str, allowlist: &[String]) -> bool {
+    allowlist.iter().any(|allowed| {
+        host == allowed
+            || host
+                .strip_suffix(allowed.as_str())
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    })
+}
+
+// --- Seccomp ---
+
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_JEQ_K: u16 = 0x15;
+const BPF_JGE_K: u16 = 0x35;
+const BPF_JSET_K: u16 = 0x45;
+const BPF_RET_K: u16 = 0x06;
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+/// Offset of the low word of the first syscall argument in `seccomp_data`
+const SECCOMP_ARG0_LOW: u32 = if cfg!(target_endian = "little") {
+    16
+} else {
+    20
+};
+/// CLONE_NEWNS, CLONE_NEWCGROUP, CLONE_NEWUTS, CLONE_NEWIPC, CLONE_NEWUSER,
+/// CLONE_NEWPID and CLONE_NEWNET
+const CLONE_NAMESPACE_FLAGS: u32 = 0x7e02_0000;
+const SYS_CLONE3: u32 = 435;
+
+/// Syscalls that fail with EPERM in the sandbox: namespace, mount and kernel
+/// administration, tracing other processes, and rarely needed kernel
+/// interfaces with a history of exploits. `clone` is checked separately.
+#[cfg(target_arch = "x86_64")]
+const DENIED_SYSCALLS: &[u32] = &[
+    101, // ptrace
+    155, // pivot_root
+    163, // acct
+    165, // mount
+    166, // umount2
+    167, // swapon
+    168, // swapoff
+    169, // reboot
+    170, // sethostname
+    171, // setdomainname
+    175, // init_module
+    176, // delete_module
+    246, // kexec_load
+    248, // add_key
+    249, // request_key
+    250, // keyctl
+    272, // unshare
+    298, // perf_event_open
+    304, // open_by_handle_at
+    308, // setns
+    310, // process_vm_readv
+    311, // process_vm_writev
+    313, // finit_module
+    320, // kexec_file_load
+    321, // bpf
+    323, // userfaultfd
+    425, // io_uring_setup
+    426, // io_uring_enter
+    427, // io_uring_register
+    428, // open_tree
+    429, // move_mount
+    430, // fsopen
+    431, // fsconfig
+    432, // fsmount
+    433, // fspick
+    442, // mount_setattr
+];
+#[cfg(target_arch = "x86_64")]
+const SYS_CLONE: u32 = 56;
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+/// Set in x32 ABI syscall numbers, which would otherwise dodge the list
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: Option<u32> = Some(0x4000_0000);
+
+#[cfg(target_arch = "aarch64")]
+const DENIED_SYSCALLS: &[u32] = &[
+    39,  // umount2
+    40,  // mount
+    41,  // pivot_root
+    89,  // acct
+    97,  // unshare
+    104, // kexec_load
+    105, // init_module
+    106, // delete_module
+    117, // ptrace
+    142, // reboot
+    161, // sethostname
+    162, // setdomainname
+    217, // add_key
+    218, // request_key
+    219, // keyctl
+    224, // swapon
+    225, // swapoff
+    241, // perf_event_open
+    265, // open_by_handle_at
+    268, // setns
+    270, // process_vm_readv
+    271, // process_vm_writev
+    273, // finit_module
+    280, // bpf
+    282, // userfaultfd
+    294, // kexec_file_load
+    425, // io_uring_setup
+    426, // io_uring_enter
+    427, // io_uring_register
+    428, // open_tree
+    429, // move_mount
+    430, // fsopen
+    431, // fsconfig
+    432, // fsmount
+    433, // fspick
+    442, // mount_setattr
+];
+#[cfg(target_arch = "aarch64")]
+const SYS_CLONE: u32 = 220;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+#[cfg(target_arch = "aarch64")]
+const X32_SYSCALL_BIT: Option<u32> = None;
+
+/// Compiled classic-BPF seccomp filter: kill on a foreign architecture,
+/// EPERM for denied syscalls and for `clone` creating namespaces, ENOSYS for
+/// `clone3` (its flags are behind a pointer the filter cannot read, and libc
+/// falls back to `clone`), allow the rest
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn seccomp_program() -> Option<Vec<u8>> {
+    let mut checks: Vec<(u16, u32)> = X32_SYSCALL_BIT
+        .map(|bit| (BPF_JGE_K, bit))
+        .into_iter()
+        .collect();
+    checks.extend(DENIED_SYSCALLS.iter().map(|nr| (BPF_JEQ_K, *nr)));
+    let n = checks.len();
+
+    // (code, jump if true, jump if false, k). Jumps are relative and forward
+    // only; after the checks come, in order: the clone3 and clone tests (4
+    // instructions), then allow, EPERM and ENOSYS.
+    let mut program: Vec<(u16, u8, u8, u32)> = vec![
+        (BPF_LD_W_ABS, 0, 0, 4), // seccomp_data.arch
+        (BPF_JEQ_K, 1, 0, AUDIT_ARCH),
+        (BPF_RET_K, 0, 0, SECCOMP_RET_KILL_PROCESS),
+        (BPF_LD_W_ABS, 0, 0, 0), // seccomp_data.nr
+    ];
+    for (i, (code, k)) in checks.iter().enumerate() {
+        // On a match, jump over the remaining checks, the clone tests and
+        // the allow to EPERM
+        program.push((*code, (n - i + 4) as u8, 0, *k));
+    }
+    program.extend([
+        (BPF_JEQ_K, 5, 0, SYS_CLONE3),
+        (BPF_JEQ_K, 0, 2, SYS_CLONE),
+        (BPF_LD_W_ABS, 0, 0, SECCOMP_ARG0_LOW), // clone flags
+        (BPF_JSET_K, 1, 0, CLONE_NAMESPACE_FLAGS),
+        (BPF_RET_K, 0, 0, SECCOMP_RET_ALLOW),
+        (BPF_RET_K, 0, 0, SECCOMP_RET_ERRNO | libc::EPERM as u32),
+        (BPF_RET_K, 0, 0, SECCOMP_RET_ERRNO | libc::ENOSYS as u32),
+    ]);
+
+    let mut bytes = Vec::with_capacity(program.len() * 8);
+    for (code, jt, jf, k) in program {
+        bytes.extend_from_slice(&code.to_ne_bytes());
+        bytes.push(jt);
+        bytes.push(jf);
+        bytes.extend_from_slice(&k.to_ne_bytes());
+    }
+    Some(bytes)
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn seccomp_program() -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowlist(hosts: &[&str]) -> Vec<String> {
+        hosts.iter().map(|h| h.to_string()).collect()
+    }
+
+    #[test]
+    fn splits_host_and_port() {
+        assert_eq!(
+            split_host_port("example.com", 80),
+            ("example.com".into(), 80)
+        );
+        assert_eq!(
+            split_host_port("Example.COM:8443", 80),
+            ("example.com".into(), 8443)
+        );
+        assert_eq!(
+            split_host_port("localhost.:443", 80),
+            ("localhost".into(), 443)
+        );
+        assert_eq!(split_host_port("[::1]:8080", 80), ("::1".into(), 8080));
+        assert_eq!(split_host_port("[fe80::1]", 443), ("fe80::1".into(), 443));
+        assert_eq!(
+            split_host_port("example.com:junk", 80),
+            ("example.com".into(), 80)
+        );
+    }
+
+    #[test]
+    fn allows_hosts_and_their_subdomains_only() {
+        let list = allowlist(&["pypi.org", "files.pythonhosted.org"]);
+        assert!(host_allowed("pypi.org", &list));
+        assert!(host_allowed("test.pypi.org", &list));
+        assert!(host_allowed("files.pythonhosted.org", &list));
+        assert!(!host_allowed("pythonhosted.org", &list));
+        assert!(!host_allowed("evilpypi.org", &list));
+        assert!(!host_allowed("pypi.org.evil.net", &list));
+        assert!(!host_allowed("anything", &[]));
+    }
+
+    #[tokio::test]
+    async fn proxy_refuses_non_public_addresses() {
+        assert!(public_addrs("127.0.0.1", 80).await.is_err());
+        assert!(public_addrs("169.254.169.254", 80).await.is_err());
+        assert!(public_addrs("10.1.2.3", 443).await.is_err());
+        assert!(public_addrs("::1", 443).await.is_err());
+        assert_eq!(
+            public_addrs("1.1.1.1", 443).await.unwrap(),
+            vec!["1.1.1.1:443".parse().unwrap()]
+        );
+    }
+
+    /// Run `program args` with the sandbox seccomp filter installed
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn run_filtered(program: &str, args: &[&str]) -> std::process::Output {
+        use std::os::unix::process::CommandExt;
+
+        let filter: Vec<libc::sock_filter> = seccomp_program()
+            .unwrap()
+            .chunks_exact(8)
+            .map(|b| libc::sock_filter {
+                code: u16::from_ne_bytes([b[0], b[1]]),
+                jt: b[2],
+                jf: b[3],
+                k: u32::from_ne_bytes([b[4], b[5], b[6], b[7]]),
+            })
+            .collect();
+        let mut cmd = std::process::Command::new(program);
+        cmd.args(args);
+        let (len, addr) = (filter.len() as u16, filter.as_ptr() as usize);
+        // SAFETY: only calls prctl, which is async-signal-safe; `filter`
+        // outlives the spawn
+        unsafe {
+            cmd.pre_exec(move || {
+                let prog = libc::sock_fprog {
+                    len,
+                    filter: addr as *mut libc::sock_filter,
+                };
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+                    || libc::prctl(
+                        libc::PR_SET_SECCOMP,
+                        libc::SECCOMP_MODE_FILTER,
+                        &prog as *const libc::sock_fprog,
+                    ) != 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        cmd.output().unwrap()
+    }
+
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    #[test]
+    fn seccomp_blocks_new_namespaces() {
+        let unshare = run_filtered("unshare", &["-U", "true"]);
+        assert!(!unshare.status.success());
+
+        // clone(CLONE_NEWUSER | SIGCHLD) and clone3 fail; a plain fork works
+        let script = format!(
+            r#"import ctypes, os
+libc = ctypes.CDLL(None, use_errno=True)
+r = libc.syscall({clone}, 0x10000000 | 17, 0, 0, 0, 0)
+if r == 0:
+    os._exit(0)
+print(r, ctypes.get_errno())
+print(libc.syscall({clone3}, 0, 0), ctypes.get_errno())
+pid = os.fork()
+if pid == 0:
+    os._exit(7)
+print(os.waitpid(pid, 0)[1] >> 8)
+"#,
+            clone = SYS_CLONE,
+            clone3 = SYS_CLONE3
+        );
+        let python = run_filtered("python3", &["-c", &script]);
+        assert_eq!(
+            String::from_utf8_lossy(&python.stdout),
+            format!("-1 {}\n-1 {}\n7\n", libc::EPERM, libc::ENOSYS),
+            "{}",
+            String::from_utf8_lossy(&python.stderr)
+        );
+    }
+
+    #[tokio::test]
+    async fn sandbox_cannot_create_user_namespaces() {
+        if std::process::Command::new("bwrap")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            return; // bubblewrap not installed
+        }
+        let pool = SqlitePool::connect_lazy("sqlite::memory:").unwrap();
+        let workspace = Workspace::open(&pool, 0, false, false).await.unwrap();
+        // Without seccomp, --disable-userns alone must stop it
+        for seccomp in [true, false] {
+            let runner = SandboxRunner {
+                policy: SandboxPolicy {
+                    limits: SandboxLimits {
+                        memory_mb: 0,
+                        cpu_secs: 0,
+                        max_processes: 0,
+                        file_size_mb: 0,
+                        max_output_bytes: 0,
+                    },
+                    network: NetworkMode::None,
+                    seccomp,
+                },
+                workspace: &workspace,
+                ro_binds: Vec::new(),
+                env: Vec::new(),
+                limit_address_space: false,
+            };
+            let sanity = runner.spawn(&["true"], 10).await.unwrap();
+            if sanity.exit_code != 0 {
+                return; // no unprivileged user namespaces on this host
+            }
+            let output = runner.spawn(&["unshare", "-U", "true"], 10).await.unwrap();
+            assert_ne!(output.exit_code, 0, "seccomp: {}", seccomp);
+        }
+        workspace.cleanup().await;
+    }
+
+    #[test]
+    fn trailing_dot_does_not_bypass_the_allowlist() {
+        let list = allowlist(&["pypi.org"]);
+        let (host, _) = split_host_port("pypi.org.:443", 443);
+        assert!(host_allowed(&host, &list));
+        let (host, _) = split_host_port("localhost.:80", 80);
+        assert!(!host_allowed(&host, &list));
+    }
+}
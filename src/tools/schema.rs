@@ -0,0 +1,297 @@
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// A tool argument that failed validation or deserialization
+#[derive(Debug)]
+pub struct ArgError {
+    /// Path of the offending field (`entries[0].key`), empty for the whole object
+    pub field: String,
+    pub message: String,
+}
+
+impl ArgError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ArgError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "Invalid arguments: {}", self.message)
+        } else {
+            write!(f, "Invalid argument '{}': {}", self.field, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ArgError {}
+
+/// Deserialize validated tool arguments into a typed struct
+pub fn parse_args<T: DeserializeOwned>(args: &Value) -> Result<T, ArgError> {
+    serde_path_to_error::deserialize(args).map_err(|e| {
+        let path = e.path().to_string();
+        let field = if path == "." { String::new() } else { path };
+        ArgError::new(field, e.into_inner().to_string())
+    })
+}
+
+/// Validate a value against the subset of JSON Schema used by tool definitions:
+/// `type`, `enum`, `properties`, `required`, `additionalProperties`, `items`,
+/// `minimum`/`maximum`, `minLength`/`maxLength` and `minItems`/`maxItems`.
+pub fn validate(schema: &Value, value: &Value) -> Result<(), ArgError> {
+    validate_at(schema, value, "")
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str) -> Result<(), ArgError> {
+    let Some(schema) = schema.as_object() else {
+        return Ok(());
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(|t| t.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|t| type_matches(t, value)) {
+            return Err(ArgError::new(
+                path,
+                format!("expected {}, got {}", types.join(" or "), type_name(value)),
+            ));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(|e| e.as_array())
+        && !allowed.contains(value)
+    {
+        let options: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+        return Err(ArgError::new(
+            path,
+            format!("must be one of {}, got {}", options.join(", "), value),
+        ));
+    }
+
+    match value {
+        Value::Object(map) => {
+            let properties = schema.get("properties").and_then(|p| p.as_object());
+
+            if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+                for name in required.iter().filter_map(|r| r.as_str()) {
+                    if map.get(name).is_none_or(|v| v.is_null()) {
+                        return Err(ArgError::new(
+                            join_path(path, name),
+                            "missing required field",
+                        ));
+                    }
+                }
+            }
+
+            for (name, field_value) in map {
+                let field_path = join_path(path, name);
+                match properties.and_then(|p| p.get(name)) {
+                    // Optional fields sent as explicit null are treated as absent
+                    Some(_) if field_value.is_null() => {}
+                    Some(field_schema) => validate_at(field_schema, field_value, &field_path)?,
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            return Err(ArgError::new(field_path, "unknown field"));
+                        }
+                        Some(extra) if extra.is_object() => {
+                            validate_at(extra, field_value, &field_path)?
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+        Value::Array(items) => {
+            check_bound(schema, "minItems", items.len(), path, |n| {
+                format!("must contain at least {} item(s)", n)
+            })?;
+            check_bound(schema, "maxItems", items.len(), path, |n| {
+                format!("must contain at most {} item(s)", n)
+            })?;
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_at(item_schema, item, &format!("{}[{}]", path, i))?;
+                }
+            }
+        }
+        Value::String(s) => {
+            let len = s.chars().count();
+            check_bound(schema, "minLength", len, path, |n| {
+                format!("must be at least {} character(s) long", n)
+            })?;
+            check_bound(schema, "maxLength", len, path, |n| {
+                format!("must be at most {} character(s) long", n)
+            })?;
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(|m| m.as_f64())
+                && n < min
+            {
+                return Err(ArgError::new(path, format!("must be >= {}", min)));
+            }
+            if let Some(max) = schema.get("maximum").and_then(|m| m.as_f64())
+                && n > max
+            {
+                return Err(ArgError::new(path, format!("must be <= {}", max)));
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn check_bound(
+    schema: &serde_json::Map<String, Value>,
+    keyword: &str,
+    actual: usize,
+    path: &str,
+    message: impl Fn(u64) -> String,
+) -> Result<(), ArgError> {
+    let Some(bound) = schema.get(keyword).and_then(|b| b.as_u64()) else {
+        return Ok(());
+    };
+    let ok = if keyword.starts_with("min") {
+        actual as u64 >= bound
+    } else {
+        actual as u64 <= bound
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(ArgError::new(path, message(bound)))
+    }
+}
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn join_path(parent: &str, field: &str) -> String {
+    if parent.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", parent, field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string", "maxLength": 5},
+                "limit": {"type": "integer", "minimum": 1},
+                "mode": {"type": "string", "enum": ["a", "b"]},
+                "entries": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {"key": {"type": "string"}},
+                        "required": ["key"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["name"],
+            "additionalProperties": false
+        })
+    }
+
+    fn field_of(value: Value) -> String {
+        validate(&schema(), &value).unwrap_err().field
+    }
+
+    #[test]
+    fn accepts_valid_arguments() {
+        let value = json!({"name": "abc", "limit": 3, "mode": "b", "entries": [{"key": "k"}]});
+        assert!(validate(&schema(), &value).is_ok());
+    }
+
+    #[test]
+    fn rejects_wrong_types() {
+        assert_eq!(field_of(json!({"name": 5})), "name");
+        assert_eq!(field_of(json!({"name": "a", "limit": 1.5})), "limit");
+        assert_eq!(field_of(json!({"name": "a", "entries": {}})), "entries");
+        assert_eq!(
+            field_of(json!({"name": "a", "entries": [{"key": 1}]})),
+            "entries[0].key"
+        );
+        assert!(validate(&schema(), &json!("name")).is_err());
+    }
+
+    #[test]
+    fn rejects_extra_keys() {
+        assert_eq!(field_of(json!({"name": "a", "extra": true})), "extra");
+        assert_eq!(
+            field_of(json!({"name": "a", "entries": [{"key": "k", "value": 1}]})),
+            "entries[0].value"
+        );
+    }
+
+    #[test]
+    fn rejects_missing_required_fields() {
+        assert_eq!(field_of(json!({})), "name");
+        assert_eq!(field_of(json!({"name": null})), "name");
+        assert_eq!(
+            field_of(json!({"name": "a", "entries": [{}]})),
+            "entries[0].key"
+        );
+    }
+
+    #[test]
+    fn checks_enums_and_bounds() {
+        assert_eq!(field_of(json!({"name": "a", "mode": "c"})), "mode");
+        assert_eq!(field_of(json!({"name": "a", "limit": 0})), "limit");
+        assert_eq!(field_of(json!({"name": "abcdef"})), "name");
+    }
+
+    #[test]
+    fn treats_null_optional_fields_as_absent() {
+        assert!(validate(&schema(), &json!({"name": "a", "limit": null})).is_ok());
+    }
+
+    #[test]
+    fn parse_args_names_the_failing_field() {
+        #[derive(serde::Deserialize, Debug)]
+        struct Args {
+            limit: u32,
+        }
+        assert_eq!(parse_args::<Args>(&json!({"limit": 2})).unwrap().limit, 2);
+        let err = parse_args::<Args>(&json!({"limit": -1})).unwrap_err();
+        assert_eq!(err.field, "limit");
+    }
+}
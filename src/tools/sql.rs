@@ -0,0 +1,211 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
+use sqlx::{ConnectOptions, Connection, SqlitePool};
+use std::path::Path;
+
+use super::sandbox::{NetworkMode, SandboxRunner};
+use super::schema::parse_args;
+use super::workspace::Workspace;
+use super::{Permission, Tool, ToolContext};
+
+pub struct RunSql;
+
+#[async_trait]
+impl Tool for RunSql {
+    fn name(&self) -> &str {
+        "run_sql"
+    }
+
+    fn description(&self) -> &str {
+        "Run one SQLite statement against a snapshot of the bot's own database (admin only). \
+         The query runs in the sandbox without network access, on a fresh copy of astartebot.db \
+         with API keys, tokens, secrets, per-domain HTTP headers, MCP server environments and cached pages removed, so writes are discarded.\n\n\
+         Useful tables: conversation_history, notes, memory, name_map, config, tool_call_log, sandbox_audit, outbound_messages, experts, mcp_servers. \
+         Use `SELECT name, sql FROM sqlite_master` to see the schema.\n\n\
+         Examples:\n\
+         - {\"query\": \"SELECT chat_id, COUNT(*) FROM conversation_history GROUP BY chat_id ORDER BY 2 DESC\"}\n\
+         - {\"query\": \"SELECT tool, COUNT(*), AVG(duration_ms) FROM sandbox_audit GROUP BY tool\", \"max_rows\": 20}"
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": {
+                    "type": "string",
+                    "description": "A single SQLite statement."
+                },
+                "max_rows": {
+                    "type": "integer",
+                    "description": "Maximum rows returned. Default: 100. Max: 1000."
+                },
+                "timeout_secs": {
+                    "type": "integer",
+                    "description": "Maximum execution time in seconds. Default: 30. Max: 120."
+                }
+            },
+            "required": ["query"]
+        })
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Admin
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_run_sql(ctx, parse_args(args)?).await
+    }
+}
+
+const SQL_DEFAULT_TIMEOUT: u64 = 30;
+const SQL_MAX_TIMEOUT: u64 = 120;
+const SQL_DEFAULT_ROWS: usize = 100;
+const SQL_MAX_ROWS: usize = 1000;
+/// Largest result returned as parsed rows; longer output is returned as truncated text
+const SQL_MAX_RESULT: usize = 15000;
+const SNAPSHOT_NAME: &str = "astartebot.db";
+
+/// Runs the query from __query__.sql and prints columns and rows as JSON
+const QUERY_SCRIPT: &str = r#"
+import json, sqlite3, sys
+
+max_rows = int(sys.argv[1])
+with open('/workspace/__query__.sql') as f:
+    query = f.read()
+
+conn = sqlite3.connect('/workspace/astartebot.db')
+try:
+    cur = conn.execute(query)
+    rows = cur.fetchmany(max_rows + 1)
+except sqlite3.Error as e:
+    print(json.dumps({"error": str(e)}))
+    sys.exit(1)
+
+def cell(v):
+    if isinstance(v, bytes):
+        return f"<{len(v)} bytes>"
+    return v
+
+print(json.dumps({
+    "columns": [d[0] for d in cur.description or []],
+    "rows": [[cell(v) for v in row] for row in rows[:max_rows]],
+    "truncated": len(rows) > max_rows,
+    "changes": conn.total_changes,
+}, ensure_ascii=False))
+"#;
+
+#[derive(Deserialize)]
+struct RunSqlArgs {
+    query: String,
+    max_rows: Option<usize>,
+    timeout_secs: Option<u64>,
+}
+
+async fn execute_run_sql(ctx: &ToolContext<'_>, args: RunSqlArgs) -> Result<String> {
+    let query = args.query.trim();
+    if query.is_empty() {
+        return Ok(json!({"error": "query is empty"}).to_string());
+    }
+    let max_rows = args
+        .max_rows
+        .map(|v| v.clamp(1, SQL_MAX_ROWS))
+        .unwrap_or(SQL_DEFAULT_ROWS);
+    let timeout_secs = args
+        .timeout_secs
+        .map(|v| v.min(SQL_MAX_TIMEOUT))
+        .unwrap_or(SQL_DEFAULT_TIMEOUT);
+
+    let workspace = Workspace::open(ctx.pool, ctx.chat_id, false, false).await?;
+    if let Err(e) = write_snapshot(ctx.pool, &workspace.dir.join(SNAPSHOT_NAME)).await {
+        workspace.cleanup().await;
+        return Ok(json!({"error": format!("Failed to copy the database: {}", e)}).to_string());
+    }
+    tokio::fs::write(workspace.dir.join("__query__.sql"), query).await?;
+    tokio::fs::write(workspace.dir.join("__script__.py"), QUERY_SCRIPT).await?;
+
+    let runner = SandboxRunner::new(ctx.pool, &workspace)
+        .await?
+        .network(NetworkMode::None);
+    let max_rows_arg = max_rows.to_string();
+
+    tracing::info!(
+        query_len = query.len(),
+        max_rows,
+        "Executing SQL in sandbox"
+    );
+
+    let output = match runner
+        .run(
+            ctx,
+            "run_sql",
+            query,
+            &["python3", "/workspace/__script__.py", &max_rows_arg],
+            timeout_secs,
+        )
+        .await
+    {
+        Ok(o) => o,
+        Err(e) => {
+            workspace.cleanup().await;
+            return Ok(json!({
+                "error": format!("Failed to launch sandbox: {}", e),
+                "hint": "Ensure 'bwrap' (bubblewrap) is installed: apt install bubblewrap",
+            })
+            .to_string());
+        }
+    };
+    let mut result = runner.result_json(&output, timeout_secs);
+    workspace.cleanup().await;
+
+    // Return the rows as JSON when they fit, otherwise the truncated text
+    let stdout = output.stdout.trim();
+    if stdout.len() <= SQL_MAX_RESULT
+        && let Ok(rows) = serde_json::from_str::<Value>(stdout)
+        && let Some(object) = result.as_object_mut()
+    {
+        object.remove("stdout");
+        object.insert("result".to_string(), rows);
+    } else if stdout.len() > SQL_MAX_RESULT {
+        result["hint"] = json!("Result too large; lower max_rows or select fewer columns");
+    }
+
+    Ok(result.to_string())
+}
+
+/// Copy the database to `path` and strip secrets from the copy. Deleted rows
+/// are overwritten and the copy is compacted afterwards, so nothing of them
+/// is left in free pages or a journal for the query to read back.
+async fn write_snapshot(pool: &SqlitePool, path: &Path) -> Result<()> {
+    sqlx::query("VACUUM INTO ?")
+        .bind(path.to_string_lossy().to_string())
+        .execute(pool)
+        .await?;
+
+    let mut conn = SqliteConnectOptions::new()
+        .filename(path)
+        .journal_mode(SqliteJournalMode::Delete)
+        .pragma("secure_delete", "ON")
+        .connect()
+        .await?;
+    sqlx::query(
+        "DELETE FROM config WHERE key LIKE '%token' OR key LIKE '%\\_key' ESCAPE '\\'
+            OR key LIKE '%secret%' OR key LIKE '%password%' OR key LIKE '%\\_headers' ESCAPE '\\'",
+    )
+    .execute(&mut conn)
+    .await?;
+    sqlx::query("UPDATE mcp_servers SET environment = '{}'")
+        .execute(&mut conn)
+        .await?;
+    sqlx::query("DELETE FROM secrets")
+        .execute(&mut conn)
+        .await?;
+    sqlx::query("DELETE FROM http_cache")
+        .execute(&mut conn)
+        .await?;
+    sqlx::query("VACUUM").execute(&mut conn).await?;
+    conn.close().await?;
+    Ok(())
+}
@@ -0,0 +1,371 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+
+use super::schema::{ArgError, parse_args};
+use super::{Tool, ToolContext, action_of};
+use crate::db;
+use crate::memory;
+use crate::rag::IndexJob;
+use crate::rag_queue::IndexQueue;
+
+pub struct UnifiedMemory;
+
+#[async_trait]
+impl Tool for UnifiedMemory {
+    fn name(&self) -> &str {
+        "unified_memory"
+    }
+
+    fn description(&self) -> &str {
+        "Unified memory CRUD with fine-grained filters and multi-segment operations.\n\
+         Actions:\n\
+         - set: create/update one or many entries in one call\n\
+         - get: read a single value by exact segment+key\n\
+         - list: list entries with optional filters\n\
+         - delete: remove entries by exact segment+key, or filtered bulk delete (requires confirm: true)\n\
+         Segment formats: 'global', 'bot', 'chat:{chat_id}', 'topic:{chat_id}:{thread_id}' (a forum topic), 'person:{user_id}'."
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "action": {
+                    "type": "string",
+                    "enum": ["set", "get", "list", "delete"]
+                },
+                "segment": {
+                    "type": "string",
+                    "description": "Target segment for exact operations (set/get/delete)."
+                },
+                "segments": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Optional explicit list of segments for list/delete."
+                },
+                "segment_like": {
+                    "type": "string",
+                    "description": "Optional segment filter (substring). Example: 'chat:' to match all chat segments."
+                },
+                "key": {
+                    "type": "string",
+                    "description": "Exact key for set/get/delete."
+                },
+                "key_prefix": {
+                    "type": "string",
+                    "description": "Match keys by prefix (list/delete)."
+                },
+                "key_contains": {
+                    "type": "string",
+                    "description": "Match keys containing this text (list/delete)."
+                },
+                "value_contains": {
+                    "type": "string",
+                    "description": "Match values containing this text (list/delete)."
+                },
+                "entries": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "segment": {
+                                "type": "string"
+                            },
+                            "key": {
+                                "type": "string"
+                            },
+                            "value": {
+                                "type": "string"
+                            }
+                        },
+                        "required": ["segment", "key", "value"]
+                    },
+                    "description": "One or more memory entries for set action."
+                },
+                "value": {
+                    "type": "string",
+                    "description": "Value for set action when using single segment+key."
+                },
+                "limit": {
+                    "type": "integer",
+                    "description": "Maximum results for list. Default: 50, max: 500."
+                },
+                "offset": {
+                    "type": "integer",
+                    "description": "List pagination offset. Default: 0."
+                },
+                "confirm": {
+                    "type": "boolean",
+                    "description": "Set true for bulk delete operations (more than one entry or no exact key)."
+                }
+            },
+            "required": ["action"]
+        })
+    }
+
+    fn has_side_effects(&self, args: &Value) -> bool {
+        !matches!(action_of(args).as_str(), "get" | "list")
+    }
+
+    fn is_destructive(&self, _ctx: &ToolContext<'_>, args: &Value) -> bool {
+        // Deleting one exact segment+key is routine; filtered deletes are not
+        action_of(args) == "delete" && (args["segment"].is_null() || args["key"].is_null())
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_unified_memory(ctx.pool, ctx.indexer, parse_args(args)?).await
+    }
+}
+
+#[derive(Deserialize)]
+struct MemoryEntry {
+    segment: String,
+    key: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct UnifiedMemoryArgs {
+    action: String,
+    segment: Option<String>,
+    segments: Option<Vec<String>>,
+    segment_like: Option<String>,
+    key: Option<String>,
+    key_prefix: Option<String>,
+    key_contains: Option<String>,
+    value_contains: Option<String>,
+    entries: Option<Vec<MemoryEntry>>,
+    value: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+    #[serde(default)]
+    confirm: bool,
+}
+
+async fn execute_unified_memory(
+    pool: &SqlitePool,
+    indexer: &IndexQueue,
+    args: UnifiedMemoryArgs,
+) -> Result<String> {
+    let action = args.action.to_lowercase();
+
+    let segment = args.segment.as_deref();
+    let segments = parse_segment_list(args.segments.as_deref(), segment)?;
+    let segment_like = args.segment_like.as_deref();
+    let key = args.key.as_deref();
+    let key_prefix = args.key_prefix.as_deref();
+    let key_contains = args.key_contains.as_deref();
+    let value_contains = args.value_contains.as_deref();
+    let confirm = args.confirm;
+
+    let limit = args.limit.unwrap_or(50).clamp(1, 500);
+    let offset = args.offset.unwrap_or(0).max(0);
+
+    match action.as_str() {
+        "set" => {
+            let mut written = Vec::new();
+
+            if let Some(entries) = &args.entries {
+                if entries.is_empty() {
+                    return Err(ArgError::new("entries", "cannot be empty").into());
+                }
+
+                for (i, entry) in entries.iter().enumerate() {
+                    let (segment, key, value) = (&entry.segment, &entry.key, &entry.value);
+                    memory::validate_segment(segment).map_err(|e| {
+                        ArgError::new(format!("entries[{}].segment", i), e.to_string())
+                    })?;
+
+                    memory::set(pool, segment, key, value).await?;
+                    index_memory(pool, indexer, segment, key, value).await?;
+                    written.push(json!({"segment": segment, "key": key, "value": value}));
+                }
+            } else {
+                let segment = segment.ok_or_else(|| anyhow::anyhow!("Set requires 'segment'"))?;
+                let key = key.ok_or_else(|| anyhow::anyhow!("Set requires 'key'"))?;
+                let value = args
+                    .value
+                    .as_deref()
+                    .ok_or_else(|| ArgError::new("value", "required for set without 'entries'"))?;
+
+                memory::set(pool, segment, key, value).await?;
+                index_memory(pool, indexer, segment, key, value).await?;
+                written.push(json!({"segment": segment, "key": key, "value": value}));
+            }
+
+            Ok(json!({
+                "action": "set",
+                "written": written.len(),
+                "entries": written,
+            })
+            .to_string())
+        }
+        "get" => {
+            let segment = segment.ok_or_else(|| anyhow::anyhow!("Get requires 'segment'"))?;
+            let key = key.ok_or_else(|| anyhow::anyhow!("Get requires 'key'"))?;
+
+            match memory::get(pool, segment, key).await? {
+                Some(value) => Ok(json!({"segment": segment, "key": key, "value": value}).to_string()),
+                None => Ok(json!({"segment": segment, "key": key, "value": null, "message": "Key not found"}).to_string()),
+            }
+        }
+        "list" => {
+            let entries = db::memory_list_filtered(
+                pool,
+                segments.as_deref(),
+                segment_like,
+                key,
+                key_prefix,
+                key_contains,
+                value_contains,
+                limit,
+                offset,
+            )
+            .await?;
+            let total = db::memory_count_filtered(
+                pool,
+                segments.as_deref(),
+                segment_like,
+                key,
+                key_prefix,
+                key_contains,
+                value_contains,
+            )
+            .await?;
+
+            let items: Vec<_> = entries
+                .iter()
+                .map(|m| {
+                    json!({
+                        "segment": m.segment,
+                        "key": m.key,
+                        "value": m.value,
+                        "updated_at": m.updated_at,
+                    })
+                })
+                .collect();
+            Ok(json!({
+                "action": "list",
+                "limit": limit,
+                "offset": offset,
+                "total": total,
+                "count": items.len(),
+                "entries": items,
+            })
+            .to_string())
+        }
+        "delete" => {
+            if let (Some(segment), Some(key)) = (segment, key) {
+                let deleted = db::memory_delete(pool, segment, key).await?;
+                return Ok(json!({
+                    "action": "delete",
+                    "segment": segment,
+                    "key": key,
+                    "deleted": deleted,
+                })
+                .to_string());
+            }
+
+            let has_filters = segments.is_some()
+                || segment_like.is_some()
+                || key.is_some()
+                || key_prefix.is_some()
+                || key_contains.is_some()
+                || value_contains.is_some();
+            if !has_filters {
+                anyhow::bail!("Delete requires segment+key or at least one filter");
+            }
+
+            if !confirm {
+                anyhow::bail!("Filtered delete requires confirm=true");
+            }
+
+            let deleted = db::memory_delete_filtered(
+                pool,
+                segments.as_deref(),
+                segment_like,
+                key,
+                key_prefix,
+                key_contains,
+                value_contains,
+            )
+            .await?;
+
+            Ok(json!({
+                "action": "delete",
+                "deleted": deleted,
+                "filters": {
+                    "segment": segment,
+                    "segments": segments,
+                    "segment_like": segment_like,
+                    "key_prefix": key_prefix,
+                    "key_contains": key_contains,
+                    "value_contains": value_contains,
+                },
+            })
+            .to_string())
+        }
+        _ => {
+            anyhow::bail!(
+                "Unsupported action '{}'. Use one of: set, get, list, delete",
+                action
+            )
+        }
+    }
+}
+
+async fn index_memory(
+    pool: &SqlitePool,
+    indexer: &IndexQueue,
+    segment: &str,
+    key: &str,
+    value: &str,
+) -> Result<()> {
+    if let Ok(Some((mem_id,))) =
+        sqlx::query_as::<_, (i64,)>("SELECT id FROM memory WHERE segment = ? AND key = ?")
+            .bind(segment)
+            .bind(key)
+            .fetch_optional(pool)
+            .await
+    {
+        indexer
+            .enqueue(IndexJob {
+                source_type: "memory".to_string(),
+                source_id: mem_id,
+                chat_id: 0,
+                segment: segment.to_string(),
+                content: format!("{}: {}", key, value),
+                user_name: String::new(),
+                created_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            })
+            .await;
+    }
+    Ok(())
+}
+
+fn parse_segment_list(
+    segments_arg: Option<&[String]>,
+    single_segment: Option<&str>,
+) -> Result<Option<Vec<String>>, ArgError> {
+    let mut segments = Vec::new();
+
+    if let Some(segment) = single_segment {
+        memory::validate_segment(segment).map_err(|e| ArgError::new("segment", e.to_string()))?;
+        segments.push(segment.to_string());
+    }
+
+    for (i, segment) in segments_arg.unwrap_or_default().iter().enumerate() {
+        memory::validate_segment(segment)
+            .map_err(|e| ArgError::new(format!("segments[{}]", i), e.to_string()))?;
+        segments.push(segment.clone());
+    }
+
+    if segments.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(segments))
+    }
+}
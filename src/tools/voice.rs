@@ -0,0 +1,208 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+use teloxide::prelude::*;
+use teloxide::types::{InputFile, ThreadId};
+
+use super::schema::parse_args;
+use super::{Tool, ToolContext};
+
+pub struct SendVoice;
+
+#[async_trait]
+impl Tool for SendVoice {
+    fn name(&self) -> &str {
+        "send_voice"
+    }
+
+    fn description(&self) -> &str {
+        "Generate speech from text and send it as a Telegram voice message in the current chat.\n\
+         The voice message is sent DIRECTLY to the chat as a playable audio — users hear your voice. \
+         After calling this tool, do NOT repeat the same text in your text reply (avoid duplication).\n\n\
+         When to use:\n\
+         - User explicitly asks you to speak, say something out loud, or send a voice/audio message\n\
+         - You want to add emotional impact (greetings, congratulations, dramatic readings, storytelling)\n\
+         - User sends a voice message and you want to reply in kind\n\n\
+         The 'instructions' parameter controls emotion and delivery — be creative and match the mood:\n\
+         - 'Speak warmly and cheerfully, like greeting a close friend'\n\
+         - 'Whisper mysteriously, slow pace'\n\
+         - 'Sound excited and energetic, fast pace'\n\
+         - 'Calm, soothing bedtime story narrator'\n\
+         - 'Dramatic movie trailer voice'\n\
+         - 'Sarcastic and dry, deadpan delivery'\n\n\
+         Examples:\n\
+         - Simple: {\"text\": \"Hello! How are you today?\"}\n\
+         - Emotional: {\"text\": \"Happy birthday! Wishing you the best!\", \"instructions\": \"Sing-song cheerful voice, celebratory\", \"voice\": \"shimmer\"}\n\
+         - Story: {\"text\": \"Once upon a time...\", \"instructions\": \"Gentle bedtime story narrator, slow and calming\", \"voice\": \"fable\"}"
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "text": {
+                    "type": "string",
+                    "description": "The text to speak and send as a voice message. Keep under 4096 chars."
+                },
+                "voice": {
+                    "type": "string",
+                    "enum": ["coral", "fable", "nova", "sage", "shimmer"],
+                    "description": "Female voice to use. 'nova' (default) — warm and natural, 'shimmer' — soft and bright, 'fable' — expressive British, 'coral' — clear and friendly, 'sage' — calm and composed."
+                },
+                "instructions": {
+                    "type": "string",
+                    "description": "How to speak: emotion, pace, style. Examples: 'cheerful and upbeat', 'slow dramatic whisper', 'professional newsreader'. Be descriptive for best results."
+                }
+            },
+            "required": ["text"]
+        })
+    }
+
+    fn has_side_effects(&self, _args: &Value) -> bool {
+        true
+    }
+
+    async fn execute(&self, ctx: &ToolContext<'_>, args: &Value) -> Result<String> {
+        execute_send_voice(
+            ctx.pool,
+            ctx.bot,
+            parse_args(args)?,
+            ctx.chat_id,
+            ctx.thread_id,
+        )
+        .await
+    }
+}
+
+const OPENAI_TTS_URL: &str = "https://api.openai.com/v1/audio/speech";
+
+#[derive(Deserialize)]
+struct SendVoiceArgs {
+    text: String,
+    voice: Option<String>,
+    instructions: Option<String>,
+}
+
+async fn execute_send_voice(
+    pool: &SqlitePool,
+    bot: &Bot,
+    args: SendVoiceArgs,
+    chat_id: i64,
+    thread_id: Option<ThreadId>,
+) -> Result<String> {
+    let text = args.text.as_str();
+    let voice = args.voice.as_deref().unwrap_or("nova");
+    let instructions = args.instructions.as_deref();
+
+    if text.is_empty() {
+        return Ok(json!({"error": "Text cannot be empty"}).to_string());
+    }
+    if text.len() > 4096 {
+        return Ok(json!({"error": "Text too long, max 4096 characters"}).to_string());
+    }
+
+    // Get OpenAI API key from config
+    let api_key = match crate::config::get(pool, "openai_api_key").await? {
+        Some(key) if !key.is_empty() => key,
+        _ => {
+            return Ok(json!({
+                "error": "OpenAI API key not configured. Set it with: astartebot config set openai_api_key sk-..."
+            }).to_string());
+        }
+    };
+
+    // Use gpt-4o-mini-tts if instructions are provided (supports steerability), otherwise tts-1
+    let model = if instructions.is_some() {
+        "gpt-4o-mini-tts"
+    } else {
+        "tts-1"
+    };
+
+    // Build request
+    let mut body = json!({
+        "model": model,
+        "input": text,
+        "voice": voice,
+        "response_format": "opus",
+    });
+    if let Some(instr) = instructions {
+        body["instructions"] = json!(instr);
+    }
+
+    tracing::info!(
+        voice,
+        model,
+        text_len = text.len(),
+        instructions = instructions.unwrap_or("none"),
+        "Generating TTS voice message"
+    );
+
+    // Call OpenAI TTS API
+    let client = reqwest::Client::new();
+    let response = client
+        .post(OPENAI_TTS_URL)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .header("Content-Type", "application/json")
+        .json(&body)
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(r) => r,
+        Err(e) => {
+            return Ok(json!({
+                "error": format!("Failed to call OpenAI TTS: {}", e),
+            })
+            .to_string());
+        }
+    };
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_body = response.text().await.unwrap_or_default();
+        return Ok(json!({
+            "error": format!("OpenAI TTS API error: HTTP {}", status),
+            "detail": error_body,
+        })
+        .to_string());
+    }
+
+    // Get audio bytes
+    let audio_bytes = match response.bytes().await {
+        Ok(b) => b,
+        Err(e) => {
+            return Ok(json!({
+                "error": format!("Failed to read TTS response: {}", e),
+            })
+            .to_string());
+        }
+    };
+
+    let audio_size = audio_bytes.len();
+
+    // Send as Telegram voice message
+    let input_file = InputFile::memory(audio_bytes.to_vec()).file_name("voice.ogg");
+    let mut request = bot.send_voice(ChatId(chat_id), input_file);
+    if let Some(thread_id) = thread_id {
+        request = request.message_thread_id(thread_id);
+    }
+    match request.await {
+        Ok(_) => {
+            tracing::info!(audio_size, chat_id, "Voice message sent");
+            Ok(json!({
+                "success": true,
+                "message": "Voice message sent to chat",
+                "audio_size_bytes": audio_size,
+                "model": model,
+                "voice": voice,
+            })
+            .to_string())
+        }
+        Err(e) => Ok(json!({
+            "error": format!("Failed to send voice message via Telegram: {}", e),
+        })
+        .to_string()),
+    }
+}
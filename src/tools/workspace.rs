@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use anyhow::Result;
+use serde_json::{Value, json};
+use sqlx::SqlitePool;
+use teloxide::prelude::*;
+use teloxide::types::InputFile;
+use tokio::sync::OwnedMutexGuard;
+
+use super::ToolContext;
+use super::files;
+use crate::config;
+
+const WORKSPACES_DEFAULT_ROOT: &str = "workspaces";
+const WORKSPACES_ROOT_KEY: &str = "sandbox_workspaces_root";
+/// Files looked at when listing what a run changed
+const WORKSPACE_SCAN_LIMIT: usize = 1000;
+/// Files sent to the chat after one run
+const MAX_SENT_FILES: usize = 10;
+/// Telegram upload limits
+const MAX_PHOTO_BYTES: u64 = 10 * 1024 * 1024;
+pub(super) const MAX_DOCUMENT_BYTES: u64 = 50 * 1024 * 1024;
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
+
+static SANDBOX_SEQ: AtomicU64 = AtomicU64::new(0);
+static WORKSPACE_LOCKS: OnceLock<Mutex<HashMap<i64, Arc<tokio::sync::Mutex<()>>>>> =
+    OnceLock::new();
+
+/// Size and modification time of each file, by path relative to the workspace
+pub type Snapshot = HashMap<PathBuf, (u64, SystemTime)>;
+
+/// Directory mounted at /workspace for one sandboxed run: a fresh temporary
+/// one, or the chat's persistent workspace, which is locked for the run so
+/// concurrent calls in one chat don't interleave
+pub struct Workspace {
+    pub dir: PathBuf,
+    /// Per-run temporary directory for sandbox support files
+    pub sandbox_dir: PathBuf,
+    pub persistent: bool,
+    _lock: Option<OwnedMutexGuard<()>>,
+}
+
+impl Workspace {
+    pub async fn open(
+        pool: &SqlitePool,
+        chat_id: i64,
+        persistent: bool,
+        reset: bool,
+    ) -> Result<Self> {
+        // Unique temp directory for this invocation to avoid cross-call races
+        let ts = SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let seq = SANDBOX_SEQ.fetch_add(1, Ordering::Relaxed);
+        let sandbox_dir = std::env::temp_dir().join(format!(
+            "astarte_sandbox_{}_{}_{}",
+            std::process::id(),
+            ts,
+            seq
+        ));
+        tokio::fs::create_dir_all(&sandbox_dir).await?;
+
+        if !persistent {
+            let dir = sandbox_dir.join("workspace");
+            tokio::fs::create_dir_all(&dir).await?;
+            return Ok(Self {
+                dir: tokio::fs::canonicalize(&dir).await?,
+                sandbox_dir,
+                persistent,
+                _lock: None,
+            });
+        }
+
+        let lock = {
+            let mut locks = WORKSPACE_LOCKS
+                .get_or_init(|| Mutex::new(HashMap::new()))
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            locks.entry(chat_id).or_default().clone()
+        };
+        let guard = lock.lock_owned().await;
+
+        let root =
+            config::get_or_default(pool, WORKSPACES_ROOT_KEY, WORKSPACES_DEFAULT_ROOT).await?;
+        let dir = std::env::current_dir()?
+            .join(root.trim())
+            .join(format!("chat_{}", chat_id));
+        if reset && tokio::fs::try_exists(&dir).await? {
+            tokio::fs::remove_dir_all(&dir).await?;
+        }
+        tokio::fs::create_dir_all(&dir).await?;
+
+        Ok(Self {
+            dir: tokio::fs::canonicalize(&dir).await?,
+            sandbox_dir,
+            persistent,
+            _lock: Some(guard),
+        })
+    }
+
+    /// Write caller-supplied files into the workspace root
+    pub async fn write_inputs(&self, input_files: &HashMap<String, String>) -> Result<()> {
+        for (filename, text) in input_files {
+            // Sanitize filename — no path traversal
+            let safe_name: String = filename.replace("..", "").replace(['/', '\\'], "_");
+            tokio::fs::write(self.dir.join(&safe_name), text).await?;
+        }
+        Ok(())
+    }
+
+    /// Regular files in the workspace; symlinks are skipped
+    pub async fn snapshot(&self) -> Snapshot {
+        let mut files = HashMap::new();
+        let mut pending = vec![self.dir.clone()];
+        while let Some(dir) = pending.pop() {
+            let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+                continue;
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                if files.len() >= WORKSPACE_SCAN_LIMIT {
+                    return files;
+                }
+                let Ok(meta) = tokio::fs::symlink_metadata(entry.path()).await else {
+                    continue;
+                };
+                if meta.is_dir() {
+                    pending.push(entry.path());
+                } else if meta.is_file()
+                    && let Ok(relative) = entry.path().strip_prefix(&self.dir)
+                {
+                    let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    files.insert(relative.to_path_buf(), (meta.len(), modified));
+                }
+            }
+        }
+        files
+    }
+
+    /// Host path of a workspace file named by the model. Rejects paths that
+    /// leave the workspace, including through symlinks made by sandboxed code.
+    pub async fn resolve(&self, name: &str) -> Result<PathBuf> {
+        let relative = files::sanitize_file_path(name.trim_start_matches("/workspace/"), false)?;
+        let path = tokio::fs::canonicalize(self.dir.join(relative))
+            .await
+            .map_err(|_| anyhow::anyhow!("'{}' not found in the workspace", name))?;
+        if !path.starts_with(&self.dir) {
+            anyhow::bail!("'{}' points outside the workspace", name);
+        }
+        if !tokio::fs::metadata(&path).await?.is_file() {
+            anyhow::bail!("'{}' is not a file", name);
+        }
+        Ok(path)
+    }
+
+    /// Delete the per-run directories; a persistent workspace is kept
+    pub async fn cleanup(self) {
+        let _ = tokio::fs::remove_dir_all(&self.sandbox_dir).await;
+    }
+}
+
+/// Files new or modified since `before`, sorted by path
+pub fn changed_files(before: &Snapshot, after: &Snapshot) -> Vec<(PathBuf, u64)> {
+    let mut changed: Vec<(PathBuf, u64)> = after
+        .iter()
+        .filter(|(path, state)| before.get(*path) != Some(state))
+        .map(|(path, (size, _))| (path.clone(), *size))
+        .collect();
+    changed.sort();
+    changed
+}
+
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Send workspace files to the chat: images as photos, everything else (and
+/// images too large for a photo) as documents. Returns one result per file.
+pub async fn send_artifacts(
+    ctx: &ToolContext<'_>,
+    workspace: &Workspace,
+    names: &[String],
+) -> Vec<Value> {
+    let mut results = Vec::new();
+    for name in names.iter().take(MAX_SENT_FILES) {
+        let result = match send_artifact(ctx, workspace, name).await {
+            Ok(kind) => json!({"file": name, "sent_as": kind}),
+            Err(e) => json!({"file": name, "error": e.to_string()}),
+        };
+        results.push(result);
+    }
+    if names.len() > MAX_SENT_FILES {
+        results.push(json!({
+            "error": format!("Only the first {} files were sent", MAX_SENT_FILES)
+        }));
+    }
+    results
+}
+
+async fn send_artifact(
+    ctx: &ToolContext<'_>,
+    workspace: &Workspace,
+    name: &str,
+) -> Result<&'static str> {
+    let path = workspace.resolve(name).await?;
+    let size = tokio::fs::metadata(&path).await?.len();
+    if size > MAX_DOCUMENT_BYTES {
+        anyhow::bail!(
+            "File is {} bytes; Telegram accepts at most {}",
+            size,
+            MAX_DOCUMENT_BYTES
+        );
+    }
+
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "file".to_string());
+    let input = InputFile::memory(tokio::fs::read(&path).await?).file_name(file_name);
+    let chat = ChatId(ctx.chat_id);
+
+    let kind = if is_image(&path) && size <= MAX_PHOTO_BYTES {
+        let mut request = ctx.bot.send_photo(chat, input);
+        if let Some(thread_id) = ctx.thread_id {
+            request = request.message_thread_id(thread_id);
+        }
+        request.await?;
+        "photo"
+    } else {
+        let mut request = ctx.bot.send_document(chat, input);
+        if let Some(thread_id) = ctx.thread_id {
+            request = request.message_thread_id(thread_id);
+        }
+        request.await?;
+        "document"
+    };
+    tracing::info!(
+        chat_id = ctx.chat_id,
+        file = name,
+        size,
+        kind,
+        "Sandbox artifact sent"
+    );
+    Ok(kind)
+}
+
+/// Copy workspace files into the `crud_file` root, given as
+/// `{workspace file: destination path}`. Existing files are not replaced.
+pub async fn save_artifacts(
+    ctx: &ToolContext<'_>,
+    workspace: &Workspace,
+    targets: &HashMap<String, String>,
+) -> Vec<Value> {
+    let mut results = Vec::new();
+    for (name, destination) in targets {
+        let result = match save_artifact(ctx, workspace, name, destination).await {
+            Ok(bytes) => json!({"file": name, "saved_to": destination, "bytes": bytes}),
+            Err(e) => json!({"file": name, "error": e.to_string()}),
+        };
+        results.push(result);
+    }
+    results
+}
+
+async fn save_artifact(
+    ctx: &ToolContext<'_>,
+    workspace: &Workspace,
+    name: &str,
+    destination: &str,
+) -> Result<u64> {
+    let source = workspace.resolve(name).await?;
+    files::import_file(ctx, &source, destination).await
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::task::{AbortHandle, JoinHandle};
+
+/// Messages waiting for a conversation's turn runner, and the runner itself
+struct ChatTurns<T> {
+    /// Tells this runner apart from one cancelled by `stop`
+    runner_id: u64,
+    runner: Option<AbortHandle>,
+    /// Queued messages with their senders, oldest first
+    pending: Vec<(i64, T)>,
+    /// Sender of the batch the runner took last
+    current_user: Option<i64>,
+}
+
+/// Runs the LLM turns of each conversation (a chat, or a forum topic) one at
+/// a time. Messages that arrive while a turn is running wait here; the next
+/// turn takes the oldest ones that share a sender, so a turn always runs on
+/// behalf of one user.
+pub struct TurnQueue<K, T> {
+    chats: Mutex<HashMap<K, ChatTurns<T>>>,
+    next_id: AtomicU64,
+}
+
+impl<K: Copy + Eq + Hash, T> TurnQueue<K, T> {
+    pub fn new() -> Self {
+        Self {
+            chats: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Queue a message for the conversation, calling `spawn` to start its runner when
+    /// none is running. The runner takes messages with `next_batch`, passing
+    /// the id `spawn` was given.
+    pub fn push(
+        &self,
+        key: K,
+        user_id: i64,
+        message: T,
+        spawn: impl FnOnce(u64) -> JoinHandle<()>,
+    ) {
+        let mut chats = self.lock();
+        let turns = chats.entry(key).or_insert_with(|| ChatTurns {
+            runner_id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            runner: None,
+            pending: Vec::new(),
+            current_user: None,
+        });
+        turns.pending.push((user_id, message));
+        if turns.runner.is_none() {
+            turns.runner = Some(spawn(turns.runner_id).abort_handle());
+        }
+    }
+
+    /// The oldest queued messages of the conversation, up to the first one
+    /// from a different sender. An empty batch means the runner is done and
+    /// must exit.
+    pub fn next_batch(&self, key: K, runner_id: u64) -> Vec<T> {
+        let mut chats = self.lock();
+        let Some(turns) = chats
+            .get_mut(&key)
+            .filter(|turns| turns.runner_id == runner_id)
+        else {
+            return Vec::new();
+        };
+        if turns.pending.is_empty() {
+            chats.remove(&key);
+            return Vec::new();
+        }
+        let sender = turns.pending[0].0;
+        turns.current_user = Some(sender);
+        let same_sender = turns
+            .pending
+            .iter()
+            .take_while(|(user_id, _)| *user_id == sender)
+            .count();
+        turns
+            .pending
+            .drain(..same_sender)
+            .map(|(_, message)| message)
+            .collect()
+    }
+
+    /// Whether the conversation's running turn is the user's: the runner
+    /// took their messages, or is about to take them first
+    pub fn is_requester(&self, key: K, user_id: i64) -> bool {
+        self.lock().get(&key).is_some_and(|turns| {
+            turns
+                .current_user
+                .or_else(|| turns.pending.first().map(|(sender, _)| *sender))
+                == Some(user_id)
+        })
+    }
+
+    /// Forget the conversation's runner when it exits without `next_batch`
+    /// ending it, e.g. after a panic, so the next message starts a new one.
+    /// Returns whether it was still registered, dropping its queued messages.
+    pub fn finish(&self, key: K, runner_id: u64) -> bool {
+        let mut chats = self.lock();
+        if chats
+            .get(&key)
+            .is_some_and(|turns| turns.runner_id == runner_id)
+        {
+            chats.remove(&key);
+            return true;
+        }
+        false
+    }
+
+    /// Cancel the conversation's running turn, with its tool calls, and drop
+    /// the queued messages. Returns false when nothing was running.
+    pub fn stop(&self, key: K) -> bool {
+        let Some(turns) = self.lock().remove(&key) else {
+            return false;
+        };
+        if let Some(runner) = turns.runner {
+            runner.abort();
+        }
+        true
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<K, ChatTurns<T>>> {
+        self.chats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn push(
+        queue: &TurnQueue<i64, &'static str>,
+        user_id: i64,
+        message: &'static str,
+    ) -> Option<u64> {
+        let spawned = Cell::new(None);
+        queue.push(1, user_id, message, |runner_id| {
+            spawned.set(Some(runner_id));
+            tokio::spawn(async {})
+        });
+        spawned.get()
+    }
+
+    #[tokio::test]
+    async fn batches_one_sender_at_a_time() {
+        let queue = TurnQueue::new();
+        let runner = push(&queue, 10, "a").unwrap();
+        assert_eq!(push(&queue, 10, "b"), None);
+        push(&queue, 20, "c");
+        push(&queue, 10, "d");
+        assert_eq!(queue.next_batch(1, runner), vec!["a", "b"]);
+        assert_eq!(queue.next_batch(1, runner), vec!["c"]);
+        assert_eq!(queue.next_batch(1, runner), vec!["d"]);
+        assert!(queue.next_batch(1, runner).is_empty());
+        assert!(push(&queue, 10, "e").is_some());
+    }
+
+    #[tokio::test]
+    async fn only_the_running_user_is_the_requester() {
+        let queue = TurnQueue::new();
+        let runner = push(&queue, 10, "a").unwrap();
+        push(&queue, 20, "b");
+        assert!(queue.is_requester(1, 10));
+        assert!(!queue.is_requester(1, 20));
+        queue.next_batch(1, runner);
+        assert!(queue.is_requester(1, 10));
+        assert!(!queue.is_requester(1, 20));
+        queue.next_batch(1, runner);
+        assert!(!queue.is_requester(1, 10));
+        assert!(queue.is_requester(1, 20));
+        assert!(!queue.is_requester(2, 20));
+    }
+
+    #[tokio::test]
+    async fn finish_unwedges_a_conversation() {
+        let queue = TurnQueue::new();
+        let runner = push(&queue, 10, "a").unwrap();
+        queue.next_batch(1, runner);
+        // The runner died mid-turn: new messages wait for it forever
+        assert_eq!(push(&queue, 10, "b"), None);
+        assert!(!queue.finish(1, runner + 1));
+        assert!(queue.finish(1, runner));
+        assert!(push(&queue, 10, "c").is_some());
+        // Finishing after a normal end or /stop leaves a new runner alone
+        assert!(!queue.finish(1, runner));
+    }
+}
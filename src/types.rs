@@ -184,3 +184,94 @@ pub struct McpServerRow {
     pub created_at: String,
     pub updated_at: String,
 }
+
+#[derive(Debug, Clone, Default)]
+#[allow(dead_code)]
+pub struct ExpertRow {
+    pub id: i64,
+    pub name: String,
+    pub description: String,
+    pub model: String,
+    pub system_prompt: String,
+    pub max_tokens: i64,
+    pub kind: String,
+    /// Comma-separated chat IDs the expert may be used in; empty means all chats
+    pub allowed_chats: String,
+    pub enabled: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl ExpertRow {
+    pub fn allows_chat(&self, chat_id: i64) -> bool {
+        let mut ids = self
+            .allowed_chats
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .peekable();
+        ids.peek().is_none() || ids.any(|id| id.parse::<i64>().ok() == Some(chat_id))
+    }
+}
+
+/// One sandboxed run, as recorded in `sandbox_audit`
+#[derive(Debug, Clone)]
+pub struct SandboxAuditEntry {
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub tool: String,
+    /// Code, script or query that was run, truncated
+    pub input: String,
+    /// None when the sandbox failed to launch
+    pub exit_code: Option<i32>,
+    pub limit_exceeded: Option<String>,
+    pub error: Option<String>,
+    pub network: String,
+    pub duration_ms: i64,
+}
+
+/// A stored secret, without its value
+#[derive(Debug, Clone)]
+pub struct SecretInfo {
+    pub name: String,
+    /// Comma-separated hosts the value may be sent to in HTTP headers
+    pub allowed_hosts: String,
+    /// Comma-separated MCP servers the value may be sent to in tool arguments
+    pub mcp_servers: String,
+    /// Comma-separated chats whose non-admin turns may use the secret
+    pub shared_chats: String,
+    pub updated_at: String,
+}
+
+/// A cached HTTP response body with its validators
+#[derive(Debug, Clone)]
+pub struct HttpCacheEntry {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_type: String,
+    pub body: Vec<u8>,
+    /// URL after redirects; None for entries stored before it was recorded
+    pub final_url: Option<String>,
+    /// Unix time of the last fetch or revalidation
+    pub fetched_at: i64,
+}
+
+/// A `send_message` call, as recorded in `outbound_messages`
+#[derive(Debug, Clone)]
+pub struct OutboundMessage {
+    /// 0 until recorded
+    pub id: i64,
+    /// Chat and user whose turn asked for the message
+    pub origin_chat_id: i64,
+    pub origin_user_id: i64,
+    pub target_chat_id: i64,
+    /// Forum topic of the target chat
+    pub thread_id: Option<i32>,
+    pub text: String,
+    /// `html` or `markdown`; plain text when None
+    pub parse_mode: Option<String>,
+    pub reply_to_message_id: Option<i32>,
+    pub silent: bool,
+    /// UTC time of a scheduled send, as `YYYY-MM-DD HH:MM:SS`
+    pub send_at: Option<String>,
+}